@@ -21,24 +21,26 @@ use moonlight_common_sys::limelight::{
     LI_CCAP_ACCEL, LI_CCAP_ANALOG_TRIGGERS, LI_CCAP_BATTERY_STATE, LI_CCAP_GYRO, LI_CCAP_RGB_LED,
     LI_CCAP_RUMBLE, LI_CCAP_TOUCHPAD, LI_CCAP_TRIGGER_RUMBLE, LI_CTYPE_NINTENDO, LI_CTYPE_PS,
     LI_CTYPE_UNKNOWN, LI_CTYPE_XBOX, LI_FF_CONTROLLER_TOUCH_EVENTS, LI_FF_PEN_TOUCH_EVENTS,
-    LI_MOTION_TYPE_ACCEL, LI_MOTION_TYPE_GYRO, LI_TOUCH_EVENT_BUTTON_ONLY, LI_TOUCH_EVENT_CANCEL,
-    LI_TOUCH_EVENT_CANCEL_ALL, LI_TOUCH_EVENT_DOWN, LI_TOUCH_EVENT_HOVER,
-    LI_TOUCH_EVENT_HOVER_LEAVE, LI_TOUCH_EVENT_MOVE, LI_TOUCH_EVENT_UP, LS_CLK_FLAG,
-    LiGetStageName, MISC_FLAG, ML_ERROR_FRAME_CONVERSION, ML_ERROR_GRACEFUL_TERMINATION,
-    ML_ERROR_NO_VIDEO_FRAME, ML_ERROR_NO_VIDEO_TRAFFIC, ML_ERROR_PROTECTED_CONTENT,
-    ML_ERROR_UNEXPECTED_EARLY_TERMINATION, MODIFIER_ALT, MODIFIER_CTRL, MODIFIER_META,
-    MODIFIER_SHIFT, PADDLE1_FLAG, PADDLE2_FLAG, PADDLE3_FLAG, PADDLE4_FLAG, PLAY_FLAG, RB_FLAG,
-    RIGHT_FLAG, RS_CLK_FLAG, SCM_AV1_HIGH8_444, SCM_AV1_HIGH10_444, SCM_AV1_MAIN8, SCM_AV1_MAIN10,
-    SCM_H264, SCM_H264_HIGH8_444, SCM_HEVC, SCM_HEVC_MAIN10, SCM_HEVC_REXT8_444,
-    SCM_HEVC_REXT10_444, SPECIAL_FLAG, SS_KBE_FLAG_NON_NORMALIZED, STAGE_AUDIO_STREAM_INIT,
-    STAGE_AUDIO_STREAM_START, STAGE_CONTROL_STREAM_INIT, STAGE_CONTROL_STREAM_START,
-    STAGE_INPUT_STREAM_INIT, STAGE_INPUT_STREAM_START, STAGE_MAX, STAGE_NAME_RESOLUTION,
-    STAGE_NONE, STAGE_PLATFORM_INIT, STAGE_RTSP_HANDSHAKE, STAGE_VIDEO_STREAM_INIT,
-    STAGE_VIDEO_STREAM_START, STREAM_CFG_AUTO, STREAM_CFG_LOCAL, STREAM_CFG_REMOTE, TOUCHPAD_FLAG,
-    UP_FLAG, VIDEO_FORMAT_AV1_HIGH8_444, VIDEO_FORMAT_AV1_HIGH10_444, VIDEO_FORMAT_AV1_MAIN8,
-    VIDEO_FORMAT_AV1_MAIN10, VIDEO_FORMAT_H264, VIDEO_FORMAT_H264_HIGH8_444, VIDEO_FORMAT_H265,
-    VIDEO_FORMAT_H265_MAIN10, VIDEO_FORMAT_H265_REXT8_444, VIDEO_FORMAT_H265_REXT10_444,
-    VIDEO_FORMAT_MASK_10BIT, VIDEO_FORMAT_MASK_AV1, VIDEO_FORMAT_MASK_H264, VIDEO_FORMAT_MASK_H265,
+    LI_MOTION_TYPE_ACCEL, LI_MOTION_TYPE_GYRO, LI_PEN_BUTTON_PRIMARY, LI_PEN_BUTTON_SECONDARY,
+    LI_PEN_BUTTON_TERTIARY, LI_TOOL_TYPE_ERASER, LI_TOOL_TYPE_PEN, LI_TOOL_TYPE_UNKNOWN,
+    LI_TOUCH_EVENT_BUTTON_ONLY, LI_TOUCH_EVENT_CANCEL, LI_TOUCH_EVENT_CANCEL_ALL,
+    LI_TOUCH_EVENT_DOWN, LI_TOUCH_EVENT_HOVER, LI_TOUCH_EVENT_HOVER_LEAVE, LI_TOUCH_EVENT_MOVE,
+    LI_TOUCH_EVENT_UP, LS_CLK_FLAG, LiGetStageName, MISC_FLAG, ML_ERROR_FRAME_CONVERSION,
+    ML_ERROR_GRACEFUL_TERMINATION, ML_ERROR_NO_VIDEO_FRAME, ML_ERROR_NO_VIDEO_TRAFFIC,
+    ML_ERROR_PROTECTED_CONTENT, ML_ERROR_UNEXPECTED_EARLY_TERMINATION, MODIFIER_ALT, MODIFIER_CTRL,
+    MODIFIER_META, MODIFIER_SHIFT, PADDLE1_FLAG, PADDLE2_FLAG, PADDLE3_FLAG, PADDLE4_FLAG,
+    PLAY_FLAG, RB_FLAG, RIGHT_FLAG, RS_CLK_FLAG, SCM_AV1_HIGH8_444, SCM_AV1_HIGH10_444,
+    SCM_AV1_MAIN8, SCM_AV1_MAIN10, SCM_H264, SCM_H264_HIGH8_444, SCM_HEVC, SCM_HEVC_MAIN10,
+    SCM_HEVC_REXT8_444, SCM_HEVC_REXT10_444, SPECIAL_FLAG, SS_KBE_FLAG_NON_NORMALIZED,
+    STAGE_AUDIO_STREAM_INIT, STAGE_AUDIO_STREAM_START, STAGE_CONTROL_STREAM_INIT,
+    STAGE_CONTROL_STREAM_START, STAGE_INPUT_STREAM_INIT, STAGE_INPUT_STREAM_START, STAGE_MAX,
+    STAGE_NAME_RESOLUTION, STAGE_NONE, STAGE_PLATFORM_INIT, STAGE_RTSP_HANDSHAKE,
+    STAGE_VIDEO_STREAM_INIT, STAGE_VIDEO_STREAM_START, STREAM_CFG_AUTO, STREAM_CFG_LOCAL,
+    STREAM_CFG_REMOTE, TOUCHPAD_FLAG, UP_FLAG, VIDEO_FORMAT_AV1_HIGH8_444,
+    VIDEO_FORMAT_AV1_HIGH10_444, VIDEO_FORMAT_AV1_MAIN8, VIDEO_FORMAT_AV1_MAIN10,
+    VIDEO_FORMAT_H264, VIDEO_FORMAT_H264_HIGH8_444, VIDEO_FORMAT_H265, VIDEO_FORMAT_H265_MAIN10,
+    VIDEO_FORMAT_H265_REXT8_444, VIDEO_FORMAT_H265_REXT10_444, VIDEO_FORMAT_MASK_10BIT,
+    VIDEO_FORMAT_MASK_AV1, VIDEO_FORMAT_MASK_H264, VIDEO_FORMAT_MASK_H265,
     VIDEO_FORMAT_MASK_YUV444, X_FLAG, Y_FLAG,
 };
 use num_derive::FromPrimitive;
@@ -167,6 +169,9 @@ bitflags! {
     }
 }
 
+/// The amount of bytes of the effect parameters for each adaptive trigger
+pub const DUAL_SENSE_EFFECT_PAYLOAD_SIZE: usize = DS_EFFECT_PAYLOAD_SIZE as usize;
+
 #[repr(i32)]
 #[derive(Debug, Clone, Copy)]
 pub enum TerminationError {
@@ -532,6 +537,25 @@ pub enum TouchEventType {
     CancelAll = LI_TOUCH_EVENT_CANCEL_ALL,
 }
 
+// --------------- Pen ---------------
+
+#[repr(u32)]
+#[derive(Debug, Clone, Copy, FromPrimitive)]
+pub enum PenToolType {
+    Unknown = LI_TOOL_TYPE_UNKNOWN,
+    Pen = LI_TOOL_TYPE_PEN,
+    Eraser = LI_TOOL_TYPE_ERASER,
+}
+
+bitflags! {
+    #[derive(Debug, Clone, Copy)]
+    pub struct PenButtons: u8 {
+        const PRIMARY   = LI_PEN_BUTTON_PRIMARY as u8;
+        const SECONDARY = LI_PEN_BUTTON_SECONDARY as u8;
+        const TERTIARY  = LI_PEN_BUTTON_TERTIARY as u8;
+    }
+}
+
 // --------------- Controller ---------------
 
 bitflags! {
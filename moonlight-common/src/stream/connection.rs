@@ -7,7 +7,7 @@ use moonlight_common_sys::limelight::_CONNECTION_LISTENER_CALLBACKS;
 use num::FromPrimitive;
 use printf_compat::{format, output};
 
-use crate::stream::bindings::{ConnectionStatus, Stage};
+use crate::stream::bindings::{ConnectionStatus, DUAL_SENSE_EFFECT_PAYLOAD_SIZE, Stage};
 
 pub trait ConnectionListener {
     /// This callback is invoked to indicate that a stage of initialization is about to begin
@@ -88,8 +88,8 @@ pub trait ConnectionListener {
         event_flags: u8,
         type_left: u8,
         type_right: u8,
-        left: &mut u8,
-        right: &mut u8,
+        left: &[u8; DUAL_SENSE_EFFECT_PAYLOAD_SIZE],
+        right: &[u8; DUAL_SENSE_EFFECT_PAYLOAD_SIZE],
     );
 
     /// This callback is invoked to set a controller's RGB LED (if present).
@@ -215,7 +215,14 @@ unsafe extern "C" fn controller_set_adaptive_triggers(
     right: *mut c_uchar,
 ) {
     global_listener(|listener| {
-        let (left, right) = unsafe { (&mut *left, &mut *right) };
+        // # Safety
+        // Both pointers point to DS_EFFECT_PAYLOAD_SIZE bytes of effect parameters
+        let (left, right) = unsafe {
+            (
+                &*(left as *const [u8; DUAL_SENSE_EFFECT_PAYLOAD_SIZE]),
+                &*(right as *const [u8; DUAL_SENSE_EFFECT_PAYLOAD_SIZE]),
+            )
+        };
 
         listener.controller_set_adaptive_triggers(
             controller_number,
@@ -3,8 +3,8 @@ use log::info;
 use crate::stream::{
     audio::AudioDecoder,
     bindings::{
-        AudioConfig, Capabilities, ConnectionStatus, DecodeResult, OpusMultistreamConfig, Stage,
-        SupportedVideoFormats, VideoDecodeUnit,
+        AudioConfig, Capabilities, ConnectionStatus, DUAL_SENSE_EFFECT_PAYLOAD_SIZE, DecodeResult,
+        OpusMultistreamConfig, Stage, SupportedVideoFormats, VideoDecodeUnit,
     },
     connection::ConnectionListener,
     video::{VideoDecoder, VideoSetup},
@@ -114,8 +114,8 @@ impl ConnectionListener for NullHandler {
         event_flags: u8,
         type_left: u8,
         type_right: u8,
-        left: &mut u8,
-        right: &mut u8,
+        left: &[u8; DUAL_SENSE_EFFECT_PAYLOAD_SIZE],
+        right: &[u8; DUAL_SENSE_EFFECT_PAYLOAD_SIZE],
     ) {
         let _ = (
             controller_number,
@@ -192,8 +192,8 @@ impl ConnectionListener for DebugHandler {
         event_flags: u8,
         type_left: u8,
         type_right: u8,
-        left: &mut u8,
-        right: &mut u8,
+        left: &[u8; DUAL_SENSE_EFFECT_PAYLOAD_SIZE],
+        right: &[u8; DUAL_SENSE_EFFECT_PAYLOAD_SIZE],
     ) {
         let _ = (
             controller_number,
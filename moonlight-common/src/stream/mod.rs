@@ -9,15 +9,16 @@ use std::{
 
 use moonlight_common_sys::limelight::{
     _SERVER_INFORMATION, _STREAM_CONFIGURATION, LI_BATTERY_PERCENTAGE_UNKNOWN, LI_ERR_UNSUPPORTED,
-    LI_ROT_UNKNOWN, LiGetEstimatedRttInfo, LiGetHostFeatureFlags, LiGetLaunchUrlQueryParameters,
-    LiInterruptConnection, LiSendControllerArrivalEvent, LiSendControllerBatteryEvent,
-    LiSendControllerEvent, LiSendControllerMotionEvent, LiSendControllerTouchEvent,
-    LiSendHScrollEvent, LiSendHighResHScrollEvent, LiSendHighResScrollEvent, LiSendKeyboardEvent,
-    LiSendKeyboardEvent2, LiSendMouseButtonEvent, LiSendMouseMoveAsMousePositionEvent,
-    LiSendMouseMoveEvent, LiSendMousePositionEvent, LiSendMultiControllerEvent, LiSendScrollEvent,
-    LiSendTouchEvent, LiSendUtf8TextEvent, LiStartConnection, LiStopConnection,
-    PAUDIO_RENDERER_CALLBACKS, PCONNECTION_LISTENER_CALLBACKS, PDECODER_RENDERER_CALLBACKS,
-    PSERVER_INFORMATION, PSTREAM_CONFIGURATION,
+    LI_ROT_UNKNOWN, LI_TILT_UNKNOWN, LiGetEstimatedRttInfo, LiGetHostFeatureFlags,
+    LiGetLaunchUrlQueryParameters, LiInterruptConnection, LiSendControllerArrivalEvent,
+    LiSendControllerBatteryEvent, LiSendControllerEvent, LiSendControllerMotionEvent,
+    LiSendControllerTouchEvent, LiSendHScrollEvent, LiSendHighResHScrollEvent,
+    LiSendHighResScrollEvent, LiSendKeyboardEvent, LiSendKeyboardEvent2, LiSendMouseButtonEvent,
+    LiSendMouseMoveAsMousePositionEvent, LiSendMouseMoveEvent, LiSendMousePositionEvent,
+    LiSendMultiControllerEvent, LiSendPenEvent, LiSendScrollEvent, LiSendTouchEvent,
+    LiSendUtf8TextEvent, LiStartConnection, LiStopConnection, PAUDIO_RENDERER_CALLBACKS,
+    PCONNECTION_LISTENER_CALLBACKS, PDECODER_RENDERER_CALLBACKS, PSERVER_INFORMATION,
+    PSTREAM_CONFIGURATION,
 };
 
 use crate::{
@@ -27,8 +28,8 @@ use crate::{
         bindings::{
             ActiveGamepads, BatteryState, ControllerButtons, ControllerCapabilities,
             ControllerType, EstimatedRttInfo, HostFeatures, KeyAction, KeyFlags, KeyModifiers,
-            MotionType, MouseButton, MouseButtonAction, ServerCodeModeSupport, StreamConfiguration,
-            TouchEventType,
+            MotionType, MouseButton, MouseButtonAction, PenButtons, PenToolType,
+            ServerCodeModeSupport, StreamConfiguration, TouchEventType,
         },
         connection::ConnectionListener,
         video::VideoDecoder,
@@ -412,6 +413,50 @@ impl MoonlightStream {
         Ok(())
     }
 
+    /// This function allows advanced clients to send pen events to a supported host. Pen events
+    /// carry the tool type (pen or eraser), the pressed pen buttons and optionally the tilt of the
+    /// pen in degrees from the vertical. Hover events are sent with the hover event types and use
+    /// the distance from the screen instead of the pressure.
+    ///
+    /// The rotation and tilt are optional and will be sent as unknown if they are not available.
+    ///
+    /// If unsupported by the host, this will return LI_ERR_UNSUPPORTED and the caller should consider
+    /// falling back to other functions to send this input (such as LiSendMousePositionEvent()).
+    ///
+    /// To determine if LiSendPenEvent() is supported without calling it, call LiGetHostFeatureFlags()
+    /// and check for the LI_FF_PEN_TOUCH_EVENTS flag.
+    pub fn send_pen(
+        &self,
+        event_type: TouchEventType,
+        tool_type: PenToolType,
+        pen_buttons: PenButtons,
+        x: f32,
+        y: f32,
+        pressure_or_distance: f32,
+        contact_area_major: f32,
+        contact_area_minor: f32,
+        rotation: Option<u16>,
+        tilt: Option<u8>,
+    ) -> Result<(), MoonlightError> {
+        unsafe {
+            if let Some(err) = Self::send_event_error(LiSendPenEvent(
+                event_type as u32 as u8,
+                tool_type as u32 as u8,
+                pen_buttons.bits(),
+                x,
+                y,
+                pressure_or_distance,
+                contact_area_major,
+                contact_area_minor,
+                rotation.unwrap_or(LI_ROT_UNKNOWN as u16),
+                tilt.unwrap_or(LI_TILT_UNKNOWN as u8),
+            )) {
+                return Err(err);
+            }
+        }
+        Ok(())
+    }
+
     /// This function queues a mouse button event to be sent to the remote server.
     pub fn send_mouse_button(
         &self,
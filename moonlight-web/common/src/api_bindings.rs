@@ -3,8 +3,8 @@ use std::fmt::{Display, Formatter};
 use moonlight_common::{
     ServerState,
     stream::bindings::{
-        Colorspace, ControllerButtons, ControllerCapabilities, KeyModifiers, MouseButton,
-        SupportedVideoFormats,
+        BatteryState, Colorspace, ControllerButtons, ControllerCapabilities, KeyModifiers,
+        MotionType, MouseButton, PenButtons, PenToolType, SupportedVideoFormats,
     },
 };
 use serde::{Deserialize, Serialize};
@@ -19,6 +19,8 @@ const EXPORT_PATH: &str = "../../web-server/web/api_bindings.ts";
 pub struct ConfigJs {
     pub path_prefix: String,
     pub default_settings: Option<TsAny>,
+    /// The name of the OpenID Connect login button, none if it's disabled
+    pub oidc_login: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug, TS, Clone)]
@@ -26,6 +28,9 @@ pub struct ConfigJs {
 pub struct PostLoginRequest {
     pub name: String,
     pub password: String,
+    /// The code of the authenticator app or a recovery code, required if the user has two factor authentication
+    #[serde(default)]
+    pub totp_code: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug, TS, Clone, Copy)]
@@ -66,6 +71,8 @@ impl From<moonlight_common::PairStatus> for PairStatus {
 pub enum HostOwner {
     ThisUser,
     Global,
+    /// Owned by another user and shared with a group this user is a member of
+    Group,
 }
 
 #[derive(Serialize, Deserialize, Debug, TS)]
@@ -248,6 +255,76 @@ pub struct DetailedUser {
     pub name: String,
     pub role: UserRole,
     pub client_unique_id: String,
+    /// If this user can share the clipboard with the host while streaming
+    pub allow_clipboard: bool,
+    pub totp_enabled: bool,
+    /// These don't apply to admins
+    pub permissions: UserPermissions,
+}
+
+/// Restricts what a user can do, admins are never restricted
+#[derive(Serialize, Deserialize, Debug, TS, Clone, PartialEq)]
+#[ts(export, export_to = EXPORT_PATH)]
+pub struct UserPermissions {
+    /// The global hosts this user can use, all of them if this is none
+    #[serde(default)]
+    pub global_hosts: Option<Vec<u32>>,
+    /// The only apps this user can see and launch on a host, all apps are allowed on hosts without an entry
+    #[serde(default)]
+    pub allowed_apps: Vec<HostAllowedApps>,
+    #[serde(default = "default_permission_allowed")]
+    pub can_pair_hosts: bool,
+    #[serde(default = "default_permission_allowed")]
+    pub can_reencode: bool,
+    /// In kbps
+    #[serde(default)]
+    pub max_bitrate: Option<u32>,
+    #[serde(default)]
+    pub max_width: Option<u32>,
+    #[serde(default)]
+    pub max_height: Option<u32>,
+    /// Streams can only run inside of one of these, at any time if this is empty.
+    /// Running streams are stopped shortly after their window closed.
+    #[serde(default)]
+    pub time_windows: Vec<PermissionTimeWindow>,
+}
+
+impl Default for UserPermissions {
+    fn default() -> Self {
+        Self {
+            global_hosts: None,
+            allowed_apps: Vec::new(),
+            can_pair_hosts: default_permission_allowed(),
+            can_reencode: default_permission_allowed(),
+            max_bitrate: None,
+            max_width: None,
+            max_height: None,
+            time_windows: Vec::new(),
+        }
+    }
+}
+
+fn default_permission_allowed() -> bool {
+    true
+}
+
+#[derive(Serialize, Deserialize, Debug, TS, Clone, PartialEq, Eq)]
+#[ts(export, export_to = EXPORT_PATH)]
+pub struct HostAllowedApps {
+    pub host_id: u32,
+    pub app_ids: Vec<u32>,
+}
+
+/// A time range on some days of the week in utc
+#[derive(Serialize, Deserialize, Debug, TS, Clone, PartialEq, Eq)]
+#[ts(export, export_to = EXPORT_PATH)]
+pub struct PermissionTimeWindow {
+    /// 0 is monday and 6 is sunday
+    pub weekdays: Vec<u8>,
+    /// Minutes after midnight
+    pub start_minute: u16,
+    /// Minutes after midnight, the window goes past midnight if this is before the start
+    pub end_minute: u16,
 }
 
 #[derive(Serialize, Deserialize, Debug, TS)]
@@ -267,6 +344,14 @@ pub struct PatchUserRequest {
     pub password: Option<String>,
     pub role: Option<UserRole>,
     pub client_unique_id: Option<String>,
+    #[serde(default)]
+    pub allow_clipboard: Option<bool>,
+    /// Removes the two factor authentication of the user, only admins can do this
+    #[serde(default)]
+    pub reset_totp: Option<bool>,
+    /// Only admins can change these
+    #[serde(default)]
+    pub permissions: Option<UserPermissions>,
 }
 
 #[derive(Serialize, Deserialize, Debug, TS)]
@@ -281,6 +366,366 @@ pub struct GetUsersResponse {
     pub users: Vec<DetailedUser>,
 }
 
+#[derive(Serialize, Deserialize, Debug, TS)]
+#[ts(export, export_to = EXPORT_PATH)]
+pub struct DetailedGroup {
+    pub id: u32,
+    pub name: String,
+    /// The user ids of the members
+    pub members: Vec<u32>,
+    /// The host ids that are shared with the members
+    pub hosts: Vec<u32>,
+}
+
+#[derive(Serialize, Deserialize, Debug, TS)]
+#[ts(export, export_to = EXPORT_PATH)]
+pub struct PostGroupRequest {
+    pub name: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, TS)]
+#[ts(export, export_to = EXPORT_PATH)]
+pub struct PatchGroupRequest {
+    /// The group id of the group to change
+    pub id: u32,
+    pub name: Option<String>,
+    /// Replaces all members
+    pub members: Option<Vec<u32>>,
+    /// Replaces all shared hosts
+    pub hosts: Option<Vec<u32>>,
+}
+
+#[derive(Serialize, Deserialize, Debug, TS)]
+#[ts(export, export_to = EXPORT_PATH)]
+pub struct DeleteGroupRequest {
+    pub id: u32,
+}
+
+#[derive(Serialize, Deserialize, Debug, TS)]
+#[ts(export, export_to = EXPORT_PATH)]
+pub struct GetGroupsResponse {
+    pub groups: Vec<DetailedGroup>,
+}
+
+/// A login of the user, identified without revealing its token
+#[derive(Serialize, Deserialize, Debug, TS)]
+#[ts(export, export_to = EXPORT_PATH)]
+pub struct LoginSession {
+    pub id: String,
+    /// If this is the session the request was made with
+    pub current: bool,
+    /// Unix time in seconds
+    pub created_at: u64,
+    /// Unix time in seconds
+    pub last_seen_at: u64,
+    /// Unix time in seconds
+    pub expires_at: u64,
+    pub user_agent: Option<String>,
+    pub last_seen_ip: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, TS)]
+#[ts(export, export_to = EXPORT_PATH)]
+pub struct GetLoginSessionsResponse {
+    pub sessions: Vec<LoginSession>,
+}
+
+#[derive(Serialize, Deserialize, Debug, TS)]
+#[ts(export, export_to = EXPORT_PATH)]
+pub struct DeleteLoginSessionRequest {
+    pub id: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, TS)]
+#[ts(export, export_to = EXPORT_PATH)]
+pub struct PostTotpResponse {
+    /// The base32 secret for entering it manually into the authenticator app
+    pub secret: String,
+    /// The otpauth uri for a qr code
+    pub provisioning_uri: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, TS)]
+#[ts(export, export_to = EXPORT_PATH)]
+pub struct PostTotpConfirmRequest {
+    pub code: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, TS)]
+#[ts(export, export_to = EXPORT_PATH)]
+pub struct PostTotpConfirmResponse {
+    /// Each can be used once instead of a code, they can't be retrieved again
+    pub recovery_codes: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, TS)]
+#[ts(export, export_to = EXPORT_PATH)]
+pub struct DeleteTotpRequest {
+    /// The code of the authenticator app or a recovery code
+    pub code: String,
+}
+
+/// What an api token is allowed to do, a token can't do anything that isn't listed here
+#[derive(Serialize, Deserialize, Debug, TS, Clone, Copy, PartialEq, Eq)]
+#[ts(export, export_to = EXPORT_PATH)]
+pub enum ApiTokenScope {
+    /// List hosts, get their details and apps
+    ReadHosts,
+    /// Wake hosts with wake on lan
+    WakeHosts,
+    /// Start and cancel apps on hosts
+    StartApps,
+}
+
+#[derive(Serialize, Deserialize, Debug, TS)]
+#[ts(export, export_to = EXPORT_PATH)]
+pub struct ApiTokenInfo {
+    pub id: String,
+    pub name: String,
+    pub scopes: Vec<ApiTokenScope>,
+    /// Unix time in seconds
+    pub created_at: u64,
+    /// Unix time in seconds
+    pub last_used_at: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, TS)]
+#[ts(export, export_to = EXPORT_PATH)]
+pub struct GetApiTokensResponse {
+    pub tokens: Vec<ApiTokenInfo>,
+}
+
+#[derive(Serialize, Deserialize, Debug, TS)]
+#[ts(export, export_to = EXPORT_PATH)]
+pub struct PostApiTokenRequest {
+    pub name: String,
+    pub scopes: Vec<ApiTokenScope>,
+}
+
+#[derive(Serialize, Deserialize, Debug, TS)]
+#[ts(export, export_to = EXPORT_PATH)]
+pub struct PostApiTokenResponse {
+    /// The secret used as the bearer token, it can't be retrieved again
+    pub token: String,
+    pub info: ApiTokenInfo,
+}
+
+#[derive(Serialize, Deserialize, Debug, TS)]
+#[ts(export, export_to = EXPORT_PATH)]
+pub struct DeleteApiTokenRequest {
+    pub id: String,
+}
+
+/// Changes the input of a user before it reaches the host
+#[derive(Serialize, Deserialize, Debug, TS, Clone, PartialEq)]
+#[ts(export, export_to = EXPORT_PATH)]
+pub struct InputProfile {
+    #[serde(default)]
+    pub key_remaps: Vec<KeyRemap>,
+    #[serde(default)]
+    pub key_macros: Vec<KeyMacro>,
+    /// Multiplier for relative mouse movement, above zero and at most [InputProfile::MAX_MOUSE_SENSITIVITY]
+    #[serde(default = "default_mouse_sensitivity")]
+    pub mouse_sensitivity: f32,
+    #[serde(default)]
+    pub gamepad_remaps: Vec<GamepadButtonRemap>,
+}
+
+impl Default for InputProfile {
+    fn default() -> Self {
+        Self {
+            key_remaps: Vec::new(),
+            key_macros: Vec::new(),
+            mouse_sensitivity: default_mouse_sensitivity(),
+            gamepad_remaps: Vec::new(),
+        }
+    }
+}
+
+impl InputProfile {
+    pub const MAX_MOUSE_SENSITIVITY: f32 = 10.0;
+
+    pub fn is_valid(&self) -> bool {
+        self.mouse_sensitivity.is_finite()
+            && self.mouse_sensitivity > 0.0
+            && self.mouse_sensitivity <= Self::MAX_MOUSE_SENSITIVITY
+    }
+}
+
+fn default_mouse_sensitivity() -> f32 {
+    1.0
+}
+
+/// Sends the key `to` when the key `from` is pressed, keys are Windows virtual key codes
+#[derive(Serialize, Deserialize, Debug, TS, Clone, PartialEq, Eq)]
+#[ts(export, export_to = EXPORT_PATH)]
+pub struct KeyRemap {
+    pub from: u16,
+    pub to: u16,
+}
+
+/// Presses all `keys` in order when the `trigger` key is pressed and releases them in reverse, e.g. Ctrl+Alt+Del
+#[derive(Serialize, Deserialize, Debug, TS, Clone, PartialEq, Eq)]
+#[ts(export, export_to = EXPORT_PATH)]
+pub struct KeyMacro {
+    pub trigger: u16,
+    pub keys: Vec<u16>,
+}
+
+/// Sends the gamepad button `to` when the button `from` is pressed, look at the ControllerButtons flags
+#[derive(Serialize, Deserialize, Debug, TS, Clone, PartialEq, Eq)]
+#[ts(export, export_to = EXPORT_PATH)]
+pub struct GamepadButtonRemap {
+    pub from: u32,
+    pub to: u32,
+}
+
+#[derive(Serialize, Deserialize, Debug, TS)]
+#[ts(export, export_to = EXPORT_PATH)]
+pub struct AppInputProfile {
+    pub host_id: u32,
+    pub app_id: u32,
+    pub profile: InputProfile,
+}
+
+#[derive(Serialize, Deserialize, Debug, TS)]
+#[ts(export, export_to = EXPORT_PATH)]
+pub struct GetInputProfilesResponse {
+    /// Used for every app without its own profile
+    pub default: Option<InputProfile>,
+    pub apps: Vec<AppInputProfile>,
+}
+
+#[derive(Serialize, Deserialize, Debug, TS)]
+#[ts(export, export_to = EXPORT_PATH)]
+pub struct PostInputProfileRequest {
+    /// If both are none this sets the default profile
+    pub host_id: Option<u32>,
+    pub app_id: Option<u32>,
+    pub profile: InputProfile,
+}
+
+#[derive(Serialize, Deserialize, Debug, TS)]
+#[ts(export, export_to = EXPORT_PATH)]
+pub struct DeleteInputProfileQuery {
+    /// If both are none this removes the default profile
+    pub host_id: Option<u32>,
+    pub app_id: Option<u32>,
+}
+
+#[derive(Serialize, Deserialize, Debug, TS, Clone)]
+#[ts(export, export_to = EXPORT_PATH)]
+pub struct StreamSessionSettings {
+    pub bitrate: u32,
+    pub width: u32,
+    pub height: u32,
+    pub fps: u32,
+}
+
+#[derive(Serialize, Deserialize, Debug, TS, Clone, Default)]
+#[ts(export, export_to = EXPORT_PATH)]
+pub struct StreamSessionStats {
+    /// Incoming bandwidth from Moonlight host to streamer
+    pub incoming_kbps: Option<f64>,
+    /// Outgoing bandwidth from streamer to browser
+    pub outgoing_kbps: Option<f64>,
+    /// The host to the streamer
+    pub rtt_ms: Option<f64>,
+}
+
+/// A streamer which is currently running
+#[derive(Serialize, Deserialize, Debug, TS)]
+#[ts(export, export_to = EXPORT_PATH)]
+pub struct StreamSession {
+    pub user_id: u32,
+    pub host_id: u32,
+    pub app_id: u32,
+    /// Unix timestamp in seconds
+    pub started_at: u64,
+    pub transport: Option<TransportType>,
+    pub settings: Option<StreamSessionSettings>,
+    pub stats: StreamSessionStats,
+    /// False while the owner is disconnected and the streamer waits for a reconnect
+    pub owner_connected: bool,
+    pub spectator_count: usize,
+}
+
+#[derive(Serialize, Deserialize, Debug, TS)]
+#[ts(export, export_to = EXPORT_PATH)]
+pub struct GetStreamSessionsResponse {
+    pub sessions: Vec<StreamSession>,
+}
+
+#[derive(Serialize, Deserialize, Debug, TS)]
+#[ts(export, export_to = EXPORT_PATH)]
+pub struct DeleteStreamSessionRequest {
+    pub user_id: u32,
+    pub host_id: u32,
+}
+
+#[derive(Serialize, Deserialize, Debug, TS)]
+#[ts(export, export_to = EXPORT_PATH)]
+pub struct GetSpectatorsQuery {
+    pub host_id: u32,
+}
+
+#[derive(Serialize, Deserialize, Debug, TS)]
+#[ts(export, export_to = EXPORT_PATH)]
+pub struct StreamSpectator {
+    pub spectator_id: u32,
+    pub user_id: u32,
+    pub name: String,
+}
+
+/// The input a user is allowed to send into a stream which is owned by someone else
+#[derive(Serialize, Deserialize, Debug, TS, Clone, Default, PartialEq, Eq)]
+#[ts(export, export_to = EXPORT_PATH)]
+pub struct StreamSeat {
+    /// The gamepad slots on the host: the first gamepad of the user will use the first slot, ...
+    pub gamepads: Vec<u8>,
+    pub keyboard_mouse: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, TS)]
+#[ts(export, export_to = EXPORT_PATH)]
+pub struct StreamUserSeat {
+    pub user_id: u32,
+    pub seat: StreamSeat,
+}
+
+#[derive(Serialize, Deserialize, Debug, TS)]
+#[ts(export, export_to = EXPORT_PATH)]
+pub struct GetSpectatorsResponse {
+    /// The user ids which are allowed to spectate
+    pub invited: Vec<u32>,
+    pub spectators: Vec<StreamSpectator>,
+    pub seats: Vec<StreamUserSeat>,
+}
+
+#[derive(Serialize, Deserialize, Debug, TS)]
+#[ts(export, export_to = EXPORT_PATH)]
+pub struct PostSpectatorRequest {
+    pub host_id: u32,
+    pub user_id: u32,
+}
+
+/// Assigns the seat to this user and invites it, an empty seat revokes all input
+#[derive(Serialize, Deserialize, Debug, TS)]
+#[ts(export, export_to = EXPORT_PATH)]
+pub struct PostSeatRequest {
+    pub host_id: u32,
+    pub user_id: u32,
+    pub seat: StreamSeat,
+}
+
+/// Revokes the invite and kicks all connections of this user
+#[derive(Serialize, Deserialize, Debug, TS)]
+#[ts(export, export_to = EXPORT_PATH)]
+pub struct DeleteSpectatorRequest {
+    pub host_id: u32,
+    pub user_id: u32,
+}
+
 // -- Stream
 
 #[derive(Serialize, Deserialize, Debug, TS, Clone, Copy, PartialEq, Eq)]
@@ -321,6 +766,8 @@ ts_consts!(
     pub const CONTROLLER14: u8 = 24;
     pub const CONTROLLER15: u8 = 25;
     pub const RTT: u8 = 26;
+    pub const PEN: u8 = 27;
+    pub const CLIPBOARD: u8 = 28;
 );
 
 #[derive(Serialize, Deserialize, Debug, TS, Clone, Copy, PartialEq, Eq)]
@@ -376,7 +823,7 @@ pub struct ReencodeSettings {
     pub threads: Option<u16>,
 }
 
-#[derive(Serialize, Deserialize, Debug, TS)]
+#[derive(Serialize, Deserialize, Debug, TS, Clone, Copy)]
 #[ts(export, export_to = EXPORT_PATH)]
 pub enum TransportType {
     WebRTC,
@@ -406,10 +853,28 @@ pub enum StreamClientMessage {
         video_color_range_full: bool,
         hdr: bool,
         reencode: Option<ReencodeSettings>,
+        /// Bit mask of the gamepads that are connected when the stream starts
+        #[serde(default)]
+        gamepads_attached: u16,
+        /// The host keeps the virtual gamepads after they got disconnected
+        #[serde(default)]
+        gamepads_persist: bool,
+        /// The horizontal component of scroll events is sent to the host.
+        /// Clients that don't send it scroll in both directions, like before this option existed
+        #[serde(default = "default_horizontal_scroll")]
+        horizontal_scroll: bool,
     },
     UpdateReencode {
         reencode: Option<ReencodeSettings>,
     },
+    /// The first message of a spectator web socket, instead of Init
+    Spectate {
+        host_id: u32,
+    },
+}
+
+fn default_horizontal_scroll() -> bool {
+    true
 }
 
 #[derive(Serialize, Deserialize, Debug, TS, Clone, Default)]
@@ -436,10 +901,16 @@ impl Display for RtcIceServer {
     }
 }
 
-#[derive(Serialize, Deserialize, Debug, TS)]
+#[derive(Serialize, Deserialize, Debug, TS, Clone)]
 #[ts(export, export_to = EXPORT_PATH)]
 pub struct StreamCapabilities {
     pub touch: bool,
+    /// The host accepts touchpad contacts of gamepads (DualShock / DualSense)
+    pub controller_touch: bool,
+    /// The host accepts pen input with pressure, tilt and pen buttons
+    pub pen: bool,
+    /// The user is allowed to share the clipboard on the clipboard channel
+    pub clipboard: bool,
 }
 
 #[derive(Serialize, Deserialize, Debug, TS)]
@@ -490,6 +961,10 @@ pub enum StreamServerMessage {
     ConnectionTerminated {
         error_code: i32,
     },
+    /// Only sent to spectators, the input which they're allowed to send
+    UpdateSeat {
+        seat: StreamSeat,
+    },
 }
 
 #[derive(Serialize, Deserialize, Debug, TS)]
@@ -556,6 +1031,13 @@ pub enum StreamerStatsUpdate {
         /// Outgoing bandwidth from streamer to browser
         outgoing_kbps: f64,
     },
+    /// Input since the last update, coalesced events were merged into others
+    InputCoalescing {
+        mouse_moves: u32,
+        coalesced_mouse_moves: u32,
+        controller_states: u32,
+        coalesced_controller_states: u32,
+    },
 }
 
 // Virtual-Key Codes
@@ -1038,6 +1520,24 @@ ts_consts!(
     pub const X2: i32 = MouseButton::X2 as i32;
 );
 
+// Pen Tool Types
+ts_consts!(
+    pub StreamPenToolType(export_bindings_pen_tool_type: EXPORT_PATH):
+
+    pub const UNKNOWN: u8 = PenToolType::Unknown as u8;
+    pub const PEN: u8 = PenToolType::Pen as u8;
+    pub const ERASER: u8 = PenToolType::Eraser as u8;
+);
+
+// Pen Buttons
+ts_consts!(
+    pub StreamPenButtons(export_bindings_pen_buttons: EXPORT_PATH):
+
+    pub const PRIMARY: u8 = PenButtons::PRIMARY.bits();
+    pub const SECONDARY: u8 = PenButtons::SECONDARY.bits();
+    pub const TERTIARY: u8 = PenButtons::TERTIARY.bits();
+);
+
 // Controller Buttons
 ts_consts!(
     pub StreamControllerButton(export_bindings_controller_buttons: EXPORT_PATH):
@@ -1071,6 +1571,31 @@ ts_consts!(
 
     pub const CAPABILITY_RUMBLE: u16 = ControllerCapabilities::RUMBLE.bits();
     pub const CAPABILITY_TRIGGER_RUMBLE: u16 = ControllerCapabilities::TRIGGER_RUMBLE.bits();
+    pub const CAPABILITY_ACCEL: u16 = ControllerCapabilities::ACCEL.bits();
+    pub const CAPABILITY_GYRO: u16 = ControllerCapabilities::GYRO.bits();
+    pub const CAPABILITY_TOUCHPAD: u16 = ControllerCapabilities::TOUCHPAD.bits();
+    pub const CAPABILITY_BATTERY_STATE: u16 = ControllerCapabilities::BATTERY_STATE.bits();
+    pub const CAPABILITY_RGB_LED: u16 = ControllerCapabilities::RGB_LED.bits();
+);
+
+// Controller Motion Sensors
+ts_consts!(
+    pub StreamMotionType(export_bindings_motion_type: EXPORT_PATH):
+
+    pub const ACCEL: u8 = MotionType::ACCEL.bits();
+    pub const GYRO: u8 = MotionType::GYRO.bits();
+);
+
+// Controller Battery
+ts_consts!(
+    pub StreamBatteryState(export_bindings_battery_state: EXPORT_PATH):
+
+    pub const UNKNOWN: u8 = BatteryState::UNKNOWN.bits();
+    pub const NOT_PRESENT: u8 = BatteryState::NOT_PRESENT.bits();
+    pub const DISCHARGING: u8 = BatteryState::DISCHARGING.bits();
+    pub const CHARGING: u8 = BatteryState::CHARGING.bits();
+    pub const NOT_CHARGING: u8 = BatteryState::NOT_CHARGING.bits();
+    pub const FULL: u8 = BatteryState::FULL.bits();
 );
 
 #[derive(Serialize, Deserialize, Debug, TS)]
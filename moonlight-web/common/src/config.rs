@@ -25,6 +25,12 @@ pub struct Config {
     pub moonlight: MoonlightConfig,
     #[serde(default)]
     pub video: VideoConfig,
+    #[serde(default)]
+    pub stream: StreamConfig,
+    #[serde(default)]
+    pub input: InputConfig,
+    #[serde(default)]
+    pub clipboard: ClipboardConfig,
     #[serde(default = "default_streamer_path")]
     pub streamer_path: String,
     #[serde(default)]
@@ -42,6 +48,9 @@ impl Default for Config {
             moonlight: Default::default(),
             webrtc: Default::default(),
             video: Default::default(),
+            stream: Default::default(),
+            input: Default::default(),
+            clipboard: Default::default(),
             log: Default::default(),
             default_settings: Default::default(),
         }
@@ -78,6 +87,13 @@ pub enum StorageConfig {
         path: String,
         session_expiration_check_interval: Duration,
     },
+    Sqlite {
+        path: String,
+        session_expiration_check_interval: Duration,
+        /// A json data file which is imported when the database is created
+        #[serde(default)]
+        import_json: Option<String>,
+    },
 }
 
 impl Default for StorageConfig {
@@ -289,6 +305,146 @@ fn default_ffmpeg_fps() -> u32 {
     60
 }
 
+// -- Stream Config
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StreamConfig {
+    /// How long a streamer keeps running after the owner disconnected so that a reload can resume it
+    #[serde(default = "default_reconnect_grace_period")]
+    pub reconnect_grace_period: Duration,
+    /// The maximum amount of streams running at the same time on this server
+    #[serde(default)]
+    pub max_streams: Option<usize>,
+    #[serde(default)]
+    pub max_streams_per_user: Option<usize>,
+    #[serde(default)]
+    pub max_streams_per_host: Option<usize>,
+    /// What happens when the server or host stream limit is reached
+    #[serde(default)]
+    pub busy_policy: BusyHostPolicy,
+}
+
+impl Default for StreamConfig {
+    fn default() -> Self {
+        Self {
+            reconnect_grace_period: default_reconnect_grace_period(),
+            max_streams: None,
+            max_streams_per_user: None,
+            max_streams_per_host: None,
+            busy_policy: Default::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum BusyHostPolicy {
+    /// Fail to start the new stream
+    #[default]
+    Reject,
+    /// Wait until a stream stopped
+    Queue,
+    /// Stop the running streams on the host. Falls back to reject for the server limit.
+    TakeOver,
+}
+
+fn default_reconnect_grace_period() -> Duration {
+    Duration::from_secs(30)
+}
+
+// -- Input Config
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InputConfig {
+    /// Relative mouse movement inside this window is summed up and sent once. Zero (the default) disables it.
+    #[serde(default = "default_mouse_coalesce_window")]
+    pub mouse_coalesce_window: Duration,
+    /// Only the latest controller state inside this window is sent, button changes are never delayed. Zero (the default) disables it.
+    #[serde(default = "default_controller_coalesce_window")]
+    pub controller_coalesce_window: Duration,
+    /// Records the input of every stream into a new file in this directory, it can be replayed with `streamer --replay <file>`
+    #[serde(default)]
+    pub record_directory: Option<String>,
+}
+
+impl Default for InputConfig {
+    fn default() -> Self {
+        Self {
+            mouse_coalesce_window: default_mouse_coalesce_window(),
+            controller_coalesce_window: default_controller_coalesce_window(),
+            record_directory: None,
+        }
+    }
+}
+
+fn default_mouse_coalesce_window() -> Duration {
+    Duration::ZERO
+}
+
+fn default_controller_coalesce_window() -> Duration {
+    Duration::ZERO
+}
+
+// -- Clipboard Config
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClipboardConfig {
+    /// The maximum size of clipboard text in bytes, larger text is dropped in both directions
+    #[serde(default = "default_clipboard_max_size")]
+    pub max_size: usize,
+    /// How the clipboard text of the browser gets to the host
+    #[serde(default)]
+    pub host_sink: ClipboardSinkConfig,
+    /// Where the clipboard text of the host comes from. Without a source only the browser shares its clipboard.
+    #[serde(default)]
+    pub host_source: Option<ClipboardSourceConfig>,
+}
+
+impl Default for ClipboardConfig {
+    fn default() -> Self {
+        Self {
+            max_size: default_clipboard_max_size(),
+            host_sink: Default::default(),
+            host_source: None,
+        }
+    }
+}
+
+fn default_clipboard_max_size() -> usize {
+    64 * 1024
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ClipboardSinkConfig {
+    /// Types the text on the host
+    #[default]
+    Type,
+    /// Runs the helper program with the text on stdin, e.g. a script that sets the clipboard on the host
+    Command {
+        program: String,
+        #[serde(default)]
+        args: Vec<String>,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ClipboardSourceConfig {
+    /// Runs the helper program in an interval and sends its stdout when it changed
+    Command {
+        program: String,
+        #[serde(default)]
+        args: Vec<String>,
+        #[serde(default = "default_clipboard_poll_interval")]
+        poll_interval: Duration,
+    },
+}
+
+fn default_clipboard_poll_interval() -> Duration {
+    Duration::from_secs(1)
+}
+
 // -- Web Server Config
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -307,6 +463,13 @@ pub struct WebServerConfig {
     pub first_login_assign_global_hosts: bool,
     pub default_user_id: Option<u32>,
     pub forwarded_header: Option<ForwardedHeaders>,
+    #[serde(default)]
+    pub oidc: Option<OidcConfig>,
+    /// Users that log in with a password must set up two factor authentication before they can do anything else
+    #[serde(default)]
+    pub require_totp: bool,
+    #[serde(default)]
+    pub login_rate_limit: LoginRateLimitConfig,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -327,6 +490,9 @@ impl Default for WebServerConfig {
             first_login_assign_global_hosts: true,
             default_user_id: None,
             forwarded_header: None,
+            oidc: None,
+            require_totp: false,
+            login_rate_limit: Default::default(),
         }
     }
 }
@@ -348,6 +514,9 @@ pub struct ForwardedHeaders {
     pub username_header: String,
     #[serde(default = "default_forwarded_headers_auto_create_user")]
     pub auto_create_missing_user: bool,
+    /// The header that contains the ip of the client, the last ip in it is used
+    #[serde(default = "default_forwarded_headers_client_ip_header")]
+    pub client_ip_header: Option<String>,
 }
 
 impl Default for ForwardedHeaders {
@@ -355,6 +524,7 @@ impl Default for ForwardedHeaders {
         Self {
             username_header: "X-Forwarded-User".to_string(),
             auto_create_missing_user: default_forwarded_headers_auto_create_user(),
+            client_ip_header: default_forwarded_headers_client_ip_header(),
         }
     }
 }
@@ -362,6 +532,113 @@ impl Default for ForwardedHeaders {
 fn default_forwarded_headers_auto_create_user() -> bool {
     true
 }
+fn default_forwarded_headers_client_ip_header() -> Option<String> {
+    Some("X-Forwarded-For".to_string())
+}
+
+/// Slows down and locks out repeated failed password logins per ip and per username
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LoginRateLimitConfig {
+    #[serde(default = "default_login_rate_limit_enabled")]
+    pub enabled: bool,
+    /// Failed logins that are allowed before the backoff starts
+    #[serde(default = "default_login_rate_limit_free_attempts")]
+    pub free_attempts: u32,
+    /// The wait time after the first failed login past the free attempts, this doubles with every further failed login
+    #[serde(default = "default_login_rate_limit_backoff_base")]
+    pub backoff_base: Duration,
+    #[serde(default = "default_login_rate_limit_backoff_max")]
+    pub backoff_max: Duration,
+    /// Failed logins after which the ip or username is locked out
+    #[serde(default = "default_login_rate_limit_lockout_attempts")]
+    pub lockout_attempts: u32,
+    #[serde(default = "default_login_rate_limit_lockout_duration")]
+    pub lockout_duration: Duration,
+    /// Failed logins are forgotten after this long without another one
+    #[serde(default = "default_login_rate_limit_attempt_reset")]
+    pub attempt_reset: Duration,
+}
+
+impl Default for LoginRateLimitConfig {
+    fn default() -> Self {
+        Self {
+            enabled: default_login_rate_limit_enabled(),
+            free_attempts: default_login_rate_limit_free_attempts(),
+            backoff_base: default_login_rate_limit_backoff_base(),
+            backoff_max: default_login_rate_limit_backoff_max(),
+            lockout_attempts: default_login_rate_limit_lockout_attempts(),
+            lockout_duration: default_login_rate_limit_lockout_duration(),
+            attempt_reset: default_login_rate_limit_attempt_reset(),
+        }
+    }
+}
+
+fn default_login_rate_limit_enabled() -> bool {
+    true
+}
+fn default_login_rate_limit_free_attempts() -> u32 {
+    3
+}
+fn default_login_rate_limit_backoff_base() -> Duration {
+    Duration::from_secs(1)
+}
+fn default_login_rate_limit_backoff_max() -> Duration {
+    Duration::from_secs(60)
+}
+fn default_login_rate_limit_lockout_attempts() -> u32 {
+    10
+}
+fn default_login_rate_limit_lockout_duration() -> Duration {
+    Duration::from_secs(15 * 60)
+}
+fn default_login_rate_limit_attempt_reset() -> Duration {
+    Duration::from_secs(60 * 60)
+}
+
+/// Login with OpenID Connect using the authorization code flow with PKCE
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OidcConfig {
+    /// The discovery document is loaded from `<issuer_url>/.well-known/openid-configuration`
+    pub issuer_url: String,
+    pub client_id: String,
+    /// Only required if the issuer treats this server as a confidential client
+    pub client_secret: Option<String>,
+    /// The full url of `/api/oidc/callback` as seen by the browser, this must be registered at the issuer
+    pub redirect_url: String,
+    #[serde(default = "default_oidc_scopes")]
+    pub scopes: Vec<String>,
+    /// The claim of the id token that is used as the username
+    #[serde(default = "default_oidc_username_claim")]
+    pub username_claim: String,
+    /// The claim of the id token that contains the groups or roles of the user, e.g. `groups`.
+    /// If this is set the role of the user is updated on every login.
+    pub role_claim: Option<String>,
+    /// Users that have one of these values in their role claim will be admins
+    #[serde(default)]
+    pub admin_values: Vec<String>,
+    #[serde(default = "default_oidc_auto_create_user")]
+    pub auto_create_missing_user: bool,
+    /// Links the first login to an existing user with the same name that isn't linked yet and has no two factor authentication.
+    /// Only enable this if the issuer doesn't let users choose their username claim freely.
+    #[serde(default)]
+    pub link_existing_users: bool,
+    /// The name that is shown on the login button
+    #[serde(default = "default_oidc_display_name")]
+    pub display_name: String,
+}
+
+fn default_oidc_scopes() -> Vec<String> {
+    vec!["openid".to_string(), "profile".to_string()]
+}
+fn default_oidc_username_claim() -> String {
+    "preferred_username".to_string()
+}
+fn default_oidc_auto_create_user() -> bool {
+    true
+}
+fn default_oidc_display_name() -> String {
+    "Single Sign-On".to_string()
+}
 
 // -- Moonlight
 
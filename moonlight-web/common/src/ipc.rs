@@ -17,14 +17,18 @@ use tokio::{
 };
 
 use crate::{
-    api_bindings::{StreamClientMessage, StreamServerMessage},
-    config::{VideoConfig, WebRtcConfig},
+    api_bindings::{
+        InputProfile, StreamClientMessage, StreamSeat, StreamServerMessage, StreamerStatsUpdate,
+    },
+    config::{ClipboardConfig, InputConfig, VideoConfig, WebRtcConfig},
 };
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct StreamerConfig {
     pub webrtc: WebRtcConfig,
     pub video: VideoConfig,
+    pub input: InputConfig,
+    pub clipboard: ClipboardConfig,
     pub log_level: LevelFilter,
 }
 
@@ -42,9 +46,35 @@ pub enum ServerIpcMessage {
         app_id: u32,
         video_frame_queue_size: usize,
         audio_sample_queue_size: usize,
+        /// The input profile of the user for this app
+        input_profile: InputProfile,
+        allow_clipboard: bool,
     },
     WebSocket(StreamClientMessage),
     WebSocketTransport(Bytes),
+    /// The owner reconnected with a new web socket and will negotiate a new transport
+    Reconnect,
+    /// A spectator connected to the web server and wants to watch this stream
+    SpectatorJoin {
+        spectator_id: u32,
+    },
+    SpectatorWebSocket {
+        spectator_id: u32,
+        message: StreamClientMessage,
+    },
+    SpectatorWebSocketTransport {
+        spectator_id: u32,
+        data: Bytes,
+    },
+    /// The owner changed the input permissions of this spectator
+    SpectatorSeat {
+        spectator_id: u32,
+        seat: StreamSeat,
+    },
+    /// The spectator disconnected or was kicked by the owner
+    SpectatorLeave {
+        spectator_id: u32,
+    },
     Stop,
 }
 
@@ -52,6 +82,20 @@ pub enum ServerIpcMessage {
 pub enum StreamerIpcMessage {
     WebSocket(StreamServerMessage),
     WebSocketTransport(Bytes),
+    SpectatorWebSocket {
+        spectator_id: u32,
+        message: StreamServerMessage,
+    },
+    SpectatorWebSocketTransport {
+        spectator_id: u32,
+        data: Bytes,
+    },
+    /// The transport of this spectator closed
+    SpectatorStop {
+        spectator_id: u32,
+    },
+    /// Stats of the stream for the session overview of the web server
+    Stats(StreamerStatsUpdate),
     Stop,
 }
 
@@ -22,6 +22,11 @@ pub struct StreamSettings {
     pub video_color_range_full: bool,
     pub hdr: bool,
     pub reencode: Option<api_bindings::ReencodeSettings>,
+    /// Bit mask of the gamepads that are connected when the stream starts
+    pub gamepads_attached: u16,
+    pub gamepads_persist: bool,
+    /// The horizontal component of scroll events is sent to the host
+    pub horizontal_scroll: bool,
 }
 
 impl Display for StreamSettings {
@@ -26,10 +26,15 @@ impl AudioDecoder for StreamAudioDecoder {
 
         {
             let mut stream_info = stream.stream_setup.blocking_lock();
+            stream_info.audio_config = Some(audio_config);
             stream_info.audio = Some(stream_config.clone());
         }
 
         stream.runtime.clone().block_on(async move {
+            stream
+                .spectators_setup_audio(audio_config, &stream_config)
+                .await;
+
             let mut sender = stream.transport_sender.lock().await;
             if let Some(sender) = sender.as_mut() {
                 sender.setup_audio(audio_config, stream_config).await
@@ -50,10 +55,12 @@ impl AudioDecoder for StreamAudioDecoder {
         };
 
         stream.runtime.clone().block_on(async move {
-            let mut stream = stream.transport_sender.lock().await;
+            stream.spectators_send_audio_sample(data).await;
+
+            let mut sender = stream.transport_sender.lock().await;
 
-            if let Some(stream) = stream.as_mut() {
-                if let Err(err) = stream.send_audio_sample(data).await {
+            if let Some(sender) = sender.as_mut() {
+                if let Err(err) = sender.send_audio_sample(data).await {
                     warn!("Failed to send audio sample: {err}");
                 }
             } else {
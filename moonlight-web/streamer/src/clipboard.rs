@@ -0,0 +1,95 @@
+use std::{process::Stdio, time::Duration};
+
+use anyhow::anyhow;
+use async_trait::async_trait;
+use common::config::ClipboardSourceConfig;
+use tokio::{io::AsyncWriteExt, process::Command, time::sleep};
+
+/// Provides the clipboard of the host
+#[async_trait]
+pub trait ClipboardSource {
+    /// Waits until the clipboard of the host changed and returns the new text
+    async fn changed(&mut self) -> Result<String, anyhow::Error>;
+}
+
+pub fn create_clipboard_source(
+    config: &ClipboardSourceConfig,
+) -> Box<dyn ClipboardSource + Send + Sync + 'static> {
+    match config {
+        ClipboardSourceConfig::Command {
+            program,
+            args,
+            poll_interval,
+        } => Box::new(CommandClipboardSource {
+            program: program.clone(),
+            args: args.clone(),
+            poll_interval: *poll_interval,
+            last_text: None,
+        }),
+    }
+}
+
+/// Polls a helper program which prints the clipboard of the host to stdout
+pub struct CommandClipboardSource {
+    program: String,
+    args: Vec<String>,
+    poll_interval: Duration,
+    last_text: Option<String>,
+}
+
+#[async_trait]
+impl ClipboardSource for CommandClipboardSource {
+    async fn changed(&mut self) -> Result<String, anyhow::Error> {
+        loop {
+            sleep(self.poll_interval).await;
+
+            let output = Command::new(&self.program)
+                .args(&self.args)
+                .stdin(Stdio::null())
+                .stderr(Stdio::null())
+                .output()
+                .await?;
+
+            if !output.status.success() {
+                return Err(anyhow!(
+                    "clipboard source \"{}\" failed with {}",
+                    self.program,
+                    output.status
+                ));
+            }
+
+            let text = String::from_utf8(output.stdout)?;
+            if self.last_text.as_ref() != Some(&text) {
+                self.last_text = Some(text.clone());
+
+                return Ok(text);
+            }
+        }
+    }
+}
+
+/// Runs a helper program with the clipboard text of the browser on stdin
+pub async fn write_clipboard_command(
+    program: &str,
+    args: &[String],
+    text: &str,
+) -> Result<(), anyhow::Error> {
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()?;
+
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(text.as_bytes()).await?;
+        // Dropping stdin closes it so that the program sees the end of the text
+    }
+
+    let status = child.wait().await?;
+    if !status.success() {
+        return Err(anyhow!("clipboard sink \"{program}\" failed with {status}"));
+    }
+
+    Ok(())
+}
@@ -0,0 +1,394 @@
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use common::{api_bindings::StreamerStatsUpdate, config::InputConfig};
+
+use crate::transport::InboundPacket;
+
+/// Merges relative mouse movement and controller states that arrive faster than the configured windows.
+/// The first event of a window is sent immediately, everything after it is held back until the window ends.
+/// Edges like keys, mouse buttons or changed controller buttons are never delayed, they flush all held back input first.
+pub struct InputCoalescer {
+    mouse_window: Duration,
+    controller_window: Duration,
+    mouse: MouseCoalesce,
+    controllers: HashMap<u8, ControllerCoalesce>,
+    stats: InputCoalesceStats,
+}
+
+#[derive(Default)]
+struct MouseCoalesce {
+    last_sent: Option<Instant>,
+    pending: Option<(i32, i32)>,
+}
+
+struct ControllerCoalesce {
+    last_sent: Option<Instant>,
+    /// The buttons of the latest state, a change is sent immediately
+    buttons: u32,
+    pending: Option<InboundPacket>,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct InputCoalesceStats {
+    mouse_moves: u32,
+    coalesced_mouse_moves: u32,
+    controller_states: u32,
+    coalesced_controller_states: u32,
+}
+
+impl From<InputCoalesceStats> for StreamerStatsUpdate {
+    fn from(stats: InputCoalesceStats) -> Self {
+        Self::InputCoalescing {
+            mouse_moves: stats.mouse_moves,
+            coalesced_mouse_moves: stats.coalesced_mouse_moves,
+            controller_states: stats.controller_states,
+            coalesced_controller_states: stats.coalesced_controller_states,
+        }
+    }
+}
+
+fn window_open(last_sent: Option<Instant>, window: Duration, now: Instant) -> bool {
+    last_sent.is_none_or(|last_sent| last_sent + window <= now)
+}
+
+impl InputCoalescer {
+    pub fn new(config: &InputConfig) -> Self {
+        Self {
+            mouse_window: config.mouse_coalesce_window,
+            controller_window: config.controller_coalesce_window,
+            mouse: Default::default(),
+            controllers: Default::default(),
+            stats: Default::default(),
+        }
+    }
+
+    /// Puts all packets that should be sent now into the output.
+    /// Returns true if a packet was held back and a flush needs to be scheduled.
+    pub fn push(
+        &mut self,
+        now: Instant,
+        packet: InboundPacket,
+        output: &mut Vec<InboundPacket>,
+    ) -> bool {
+        match packet {
+            InboundPacket::MouseMove { delta_x, delta_y } => {
+                self.stats.mouse_moves += 1;
+
+                if let Some((pending_x, pending_y)) = self.mouse.pending.as_mut() {
+                    *pending_x += delta_x as i32;
+                    *pending_y += delta_y as i32;
+
+                    self.stats.coalesced_mouse_moves += 1;
+                    false
+                } else if window_open(self.mouse.last_sent, self.mouse_window, now) {
+                    self.mouse.last_sent = Some(now);
+
+                    output.push(InboundPacket::MouseMove { delta_x, delta_y });
+                    false
+                } else {
+                    self.mouse.pending = Some((delta_x as i32, delta_y as i32));
+                    true
+                }
+            }
+            InboundPacket::ControllerState { id, buttons, .. } => {
+                self.stats.controller_states += 1;
+
+                let controller = self.controllers.entry(id).or_insert(ControllerCoalesce {
+                    last_sent: None,
+                    buttons: buttons.bits(),
+                    pending: None,
+                });
+
+                if controller.buttons != buttons.bits() {
+                    // A button edge: send the held back state first to keep the order
+                    controller.buttons = buttons.bits();
+                    controller.last_sent = Some(now);
+
+                    if let Some(pending) = controller.pending.take() {
+                        output.push(pending);
+                    }
+                    output.push(packet);
+                    false
+                } else if controller.pending.is_some() {
+                    controller.pending = Some(packet);
+
+                    self.stats.coalesced_controller_states += 1;
+                    false
+                } else if window_open(controller.last_sent, self.controller_window, now) {
+                    controller.last_sent = Some(now);
+
+                    output.push(packet);
+                    false
+                } else {
+                    controller.pending = Some(packet);
+                    true
+                }
+            }
+            packet => {
+                self.flush_all(output);
+
+                if let InboundPacket::ControllerDisconnected { id } = &packet {
+                    self.controllers.remove(id);
+                }
+
+                output.push(packet);
+                false
+            }
+        }
+    }
+
+    /// Sends all held back input whose window has ended
+    pub fn flush_due(&mut self, now: Instant, output: &mut Vec<InboundPacket>) {
+        if window_open(self.mouse.last_sent, self.mouse_window, now)
+            && let Some(pending) = self.mouse.pending.take()
+        {
+            self.mouse.last_sent = Some(now);
+            output.push(Self::mouse_move(pending));
+        }
+
+        for controller in self.controllers.values_mut() {
+            if window_open(controller.last_sent, self.controller_window, now)
+                && let Some(pending) = controller.pending.take()
+            {
+                controller.last_sent = Some(now);
+                output.push(pending);
+            }
+        }
+    }
+
+    fn flush_all(&mut self, output: &mut Vec<InboundPacket>) {
+        if let Some(pending) = self.mouse.pending.take() {
+            output.push(Self::mouse_move(pending));
+        }
+
+        for controller in self.controllers.values_mut() {
+            if let Some(pending) = controller.pending.take() {
+                output.push(pending);
+            }
+        }
+    }
+
+    /// The time at which the next held back input needs to be sent
+    pub fn next_flush(&self) -> Option<Instant> {
+        let mouse = self
+            .mouse
+            .pending
+            .and(self.mouse.last_sent)
+            .map(|last_sent| last_sent + self.mouse_window);
+
+        let controllers = self
+            .controllers
+            .values()
+            .filter(|controller| controller.pending.is_some())
+            .filter_map(|controller| controller.last_sent)
+            .map(|last_sent| last_sent + self.controller_window);
+
+        mouse.into_iter().chain(controllers).min()
+    }
+
+    /// Returns the stats since the last call, if there was any input
+    pub fn take_stats(&mut self) -> Option<InputCoalesceStats> {
+        let stats = std::mem::take(&mut self.stats);
+
+        (stats.mouse_moves > 0 || stats.controller_states > 0).then_some(stats)
+    }
+
+    fn mouse_move((delta_x, delta_y): (i32, i32)) -> InboundPacket {
+        InboundPacket::MouseMove {
+            delta_x: delta_x.clamp(i16::MIN as i32, i16::MAX as i32) as i16,
+            delta_y: delta_y.clamp(i16::MIN as i32, i16::MAX as i32) as i16,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use moonlight_common::stream::bindings::{ControllerButtons, MouseButton, MouseButtonAction};
+
+    use super::*;
+
+    const WINDOW: Duration = Duration::from_millis(10);
+
+    fn coalescer() -> InputCoalescer {
+        InputCoalescer::new(&InputConfig {
+            mouse_coalesce_window: WINDOW,
+            controller_coalesce_window: WINDOW,
+            record_directory: None,
+        })
+    }
+
+    fn mouse_move(delta_x: i16, delta_y: i16) -> InboundPacket {
+        InboundPacket::MouseMove { delta_x, delta_y }
+    }
+
+    fn controller_state(buttons: ControllerButtons, left_trigger: u8) -> InboundPacket {
+        InboundPacket::ControllerState {
+            id: 0,
+            buttons,
+            left_trigger,
+            right_trigger: 0,
+            left_stick_x: 0,
+            left_stick_y: 0,
+            right_stick_x: 0,
+            right_stick_y: 0,
+        }
+    }
+
+    #[test]
+    fn test_merge_mouse_moves() {
+        let mut coalescer = coalescer();
+        let start = Instant::now();
+
+        let mut output = Vec::new();
+        assert!(!coalescer.push(start, mouse_move(1, 2), &mut output));
+        assert!(coalescer.push(
+            start + Duration::from_millis(1),
+            mouse_move(3, 4),
+            &mut output
+        ));
+        assert!(!coalescer.push(
+            start + Duration::from_millis(2),
+            mouse_move(-1, 1),
+            &mut output
+        ));
+
+        assert!(matches!(
+            output.as_slice(),
+            [InboundPacket::MouseMove {
+                delta_x: 1,
+                delta_y: 2
+            }]
+        ));
+        assert_eq!(coalescer.next_flush(), Some(start + WINDOW));
+
+        // Nothing is due inside the window
+        output.clear();
+        coalescer.flush_due(start + Duration::from_millis(5), &mut output);
+        assert!(output.is_empty());
+
+        coalescer.flush_due(start + WINDOW, &mut output);
+        assert!(matches!(
+            output.as_slice(),
+            [InboundPacket::MouseMove {
+                delta_x: 2,
+                delta_y: 5
+            }]
+        ));
+        assert_eq!(coalescer.next_flush(), None);
+
+        let stats = coalescer.take_stats().expect("stats after input");
+        assert_eq!(stats.mouse_moves, 3);
+        assert_eq!(stats.coalesced_mouse_moves, 1);
+    }
+
+    #[test]
+    fn test_disabled_by_default() {
+        let mut coalescer = InputCoalescer::new(&InputConfig::default());
+        let start = Instant::now();
+
+        let mut output = Vec::new();
+        assert!(!coalescer.push(start, mouse_move(1, 2), &mut output));
+        assert!(!coalescer.push(start, mouse_move(3, 4), &mut output));
+        assert!(!coalescer.push(
+            start,
+            controller_state(ControllerButtons::empty(), 1),
+            &mut output
+        ));
+        assert!(!coalescer.push(
+            start,
+            controller_state(ControllerButtons::empty(), 2),
+            &mut output
+        ));
+
+        assert_eq!(output.len(), 4);
+        assert_eq!(coalescer.next_flush(), None);
+    }
+
+    #[test]
+    fn test_mouse_button_flushes_mouse_move() {
+        let mut coalescer = coalescer();
+        let start = Instant::now();
+
+        let mut output = Vec::new();
+        coalescer.push(start, mouse_move(1, 1), &mut output);
+        coalescer.push(
+            start + Duration::from_millis(1),
+            mouse_move(5, 5),
+            &mut output,
+        );
+        coalescer.push(
+            start + Duration::from_millis(2),
+            InboundPacket::MouseButton {
+                action: MouseButtonAction::Press,
+                button: MouseButton::Left,
+            },
+            &mut output,
+        );
+
+        // The held back move is sent before the click
+        assert!(matches!(
+            output.as_slice(),
+            [
+                InboundPacket::MouseMove {
+                    delta_x: 1,
+                    delta_y: 1
+                },
+                InboundPacket::MouseMove {
+                    delta_x: 5,
+                    delta_y: 5
+                },
+                InboundPacket::MouseButton {
+                    action: MouseButtonAction::Press,
+                    button: MouseButton::Left
+                },
+            ]
+        ));
+        assert_eq!(coalescer.next_flush(), None);
+    }
+
+    #[test]
+    fn test_controller_button_change_is_not_delayed() {
+        let mut coalescer = coalescer();
+        let start = Instant::now();
+
+        let mut output = Vec::new();
+        coalescer.push(
+            start,
+            controller_state(ControllerButtons::empty(), 1),
+            &mut output,
+        );
+        assert!(coalescer.push(
+            start + Duration::from_millis(1),
+            controller_state(ControllerButtons::empty(), 2),
+            &mut output
+        ));
+        coalescer.push(
+            start + Duration::from_millis(2),
+            controller_state(ControllerButtons::empty(), 3),
+            &mut output,
+        );
+        coalescer.push(
+            start + Duration::from_millis(3),
+            controller_state(ControllerButtons::A, 3),
+            &mut output,
+        );
+
+        // Only the latest held back state is sent, followed by the button change
+        let sent = output
+            .iter()
+            .map(|packet| match packet {
+                InboundPacket::ControllerState {
+                    buttons,
+                    left_trigger,
+                    ..
+                } => (buttons.bits(), *left_trigger),
+                packet => panic!("unexpected packet {packet:?}"),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(sent, vec![(0, 1), (0, 3), (ControllerButtons::A.bits(), 3)]);
+        assert_eq!(coalescer.next_flush(), None);
+    }
+}
@@ -0,0 +1,380 @@
+use std::collections::HashMap;
+
+use common::api_bindings::InputProfile;
+use moonlight_common::stream::bindings::{ControllerButtons, KeyAction, KeyFlags, KeyModifiers};
+
+use crate::transport::InboundPacket;
+
+// Windows virtual key codes of the modifier keys
+const VK_SHIFT: u16 = 0x10;
+const VK_CONTROL: u16 = 0x11;
+const VK_MENU: u16 = 0x12;
+const VK_LWIN: u16 = 0x5B;
+const VK_RWIN: u16 = 0x5C;
+const VK_LSHIFT: u16 = 0xA0;
+const VK_RSHIFT: u16 = 0xA1;
+const VK_LCONTROL: u16 = 0xA2;
+const VK_RCONTROL: u16 = 0xA3;
+const VK_LMENU: u16 = 0xA4;
+const VK_RMENU: u16 = 0xA5;
+
+fn key_modifier(key: u16) -> KeyModifiers {
+    match key {
+        VK_SHIFT | VK_LSHIFT | VK_RSHIFT => KeyModifiers::SHIFT,
+        VK_CONTROL | VK_LCONTROL | VK_RCONTROL => KeyModifiers::CTRL,
+        VK_MENU | VK_LMENU | VK_RMENU => KeyModifiers::ALT,
+        VK_LWIN | VK_RWIN => KeyModifiers::META,
+        _ => KeyModifiers::empty(),
+    }
+}
+
+/// Applies the input profile of the user to the packets of the client
+pub struct InputMapper {
+    key_remaps: HashMap<u16, u16>,
+    key_macros: HashMap<u16, Vec<u16>>,
+    mouse_sensitivity: f32,
+    /// The fractional mouse movement that couldn't be sent yet
+    mouse_remainder: (f32, f32),
+    gamepad_remaps: Vec<(ControllerButtons, ControllerButtons)>,
+    gamepad_remapped_buttons: ControllerButtons,
+}
+
+impl InputMapper {
+    pub fn new(profile: InputProfile) -> Self {
+        // Profiles that were stored before the web server validated them keep the mouse unchanged
+        let mouse_sensitivity = if profile.is_valid() {
+            profile.mouse_sensitivity
+        } else {
+            1.0
+        };
+
+        let gamepad_remaps = profile
+            .gamepad_remaps
+            .iter()
+            .map(|remap| {
+                (
+                    ControllerButtons::from_bits_truncate(remap.from),
+                    ControllerButtons::from_bits_truncate(remap.to),
+                )
+            })
+            .collect::<Vec<_>>();
+
+        let gamepad_remapped_buttons = gamepad_remaps
+            .iter()
+            .fold(ControllerButtons::empty(), |buttons, (from, _)| {
+                buttons | *from
+            });
+
+        Self {
+            key_remaps: profile
+                .key_remaps
+                .into_iter()
+                .map(|remap| (remap.from, remap.to))
+                .collect(),
+            key_macros: profile
+                .key_macros
+                .into_iter()
+                .map(|key_macro| (key_macro.trigger, key_macro.keys))
+                .collect(),
+            mouse_sensitivity,
+            mouse_remainder: (0.0, 0.0),
+            gamepad_remaps,
+            gamepad_remapped_buttons,
+        }
+    }
+
+    pub fn map(&mut self, packet: InboundPacket, output: &mut Vec<InboundPacket>) {
+        match packet {
+            InboundPacket::Key {
+                action,
+                modifiers,
+                key,
+                flags,
+            } if let Some(keys) = self.key_macros.get(&key) => {
+                Self::map_macro(keys, action, modifiers, flags, output);
+            }
+            InboundPacket::Key {
+                action,
+                modifiers,
+                key,
+                flags,
+            } => {
+                output.push(InboundPacket::Key {
+                    action,
+                    modifiers,
+                    key: self.key_remaps.get(&key).copied().unwrap_or(key),
+                    flags,
+                });
+            }
+            InboundPacket::MouseMove { delta_x, delta_y } if self.mouse_sensitivity != 1.0 => {
+                let x = delta_x as f32 * self.mouse_sensitivity + self.mouse_remainder.0;
+                let y = delta_y as f32 * self.mouse_sensitivity + self.mouse_remainder.1;
+
+                let (send_x, send_y) = (x.trunc(), y.trunc());
+                self.mouse_remainder = (x - send_x, y - send_y);
+
+                if send_x != 0.0 || send_y != 0.0 {
+                    output.push(InboundPacket::MouseMove {
+                        delta_x: send_x.clamp(i16::MIN as f32, i16::MAX as f32) as i16,
+                        delta_y: send_y.clamp(i16::MIN as f32, i16::MAX as f32) as i16,
+                    });
+                }
+            }
+            InboundPacket::ControllerState {
+                id,
+                buttons,
+                left_trigger,
+                right_trigger,
+                left_stick_x,
+                left_stick_y,
+                right_stick_x,
+                right_stick_y,
+            } if !self.gamepad_remaps.is_empty() => {
+                let mut mapped_buttons = buttons - self.gamepad_remapped_buttons;
+                for (from, to) in &self.gamepad_remaps {
+                    if buttons.contains(*from) {
+                        mapped_buttons |= *to;
+                    }
+                }
+
+                output.push(InboundPacket::ControllerState {
+                    id,
+                    buttons: mapped_buttons,
+                    left_trigger,
+                    right_trigger,
+                    left_stick_x,
+                    left_stick_y,
+                    right_stick_x,
+                    right_stick_y,
+                });
+            }
+            packet => output.push(packet),
+        }
+    }
+
+    /// Presses the keys in order and releases them in reverse order
+    fn map_macro(
+        keys: &[u16],
+        action: KeyAction,
+        modifiers: KeyModifiers,
+        flags: KeyFlags,
+        output: &mut Vec<InboundPacket>,
+    ) {
+        match action {
+            KeyAction::Down => {
+                let mut modifiers = modifiers;
+                for key in keys {
+                    modifiers |= key_modifier(*key);
+
+                    output.push(InboundPacket::Key {
+                        action: KeyAction::Down,
+                        modifiers,
+                        key: *key,
+                        flags,
+                    });
+                }
+            }
+            KeyAction::Up => {
+                let mut modifiers = keys
+                    .iter()
+                    .fold(modifiers, |modifiers, key| modifiers | key_modifier(*key));
+                for key in keys.iter().rev() {
+                    modifiers -= key_modifier(*key);
+
+                    output.push(InboundPacket::Key {
+                        action: KeyAction::Up,
+                        modifiers,
+                        key: *key,
+                        flags,
+                    });
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use common::api_bindings::{GamepadButtonRemap, KeyMacro, KeyRemap};
+
+    use super::*;
+
+    const VK_A: u16 = 0x41;
+    const VK_B: u16 = 0x42;
+    const VK_DELETE: u16 = 0x2E;
+    const VK_F1: u16 = 0x70;
+
+    fn key(action: KeyAction, key: u16) -> InboundPacket {
+        InboundPacket::Key {
+            action,
+            modifiers: KeyModifiers::empty(),
+            key,
+            flags: KeyFlags::empty(),
+        }
+    }
+
+    /// (is down, key, modifiers) of all key packets
+    fn keys(packets: &[InboundPacket]) -> Vec<(bool, u16, i8)> {
+        packets
+            .iter()
+            .filter_map(|packet| match packet {
+                InboundPacket::Key {
+                    action,
+                    modifiers,
+                    key,
+                    ..
+                } => Some((matches!(action, KeyAction::Down), *key, modifiers.bits())),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn mouse_moves(packets: &[InboundPacket]) -> Vec<(i16, i16)> {
+        packets
+            .iter()
+            .filter_map(|packet| match packet {
+                InboundPacket::MouseMove { delta_x, delta_y } => Some((*delta_x, *delta_y)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_key_remap() {
+        let mut mapper = InputMapper::new(InputProfile {
+            key_remaps: vec![KeyRemap {
+                from: VK_A,
+                to: VK_B,
+            }],
+            ..Default::default()
+        });
+
+        let mut output = Vec::new();
+        mapper.map(key(KeyAction::Down, VK_A), &mut output);
+        mapper.map(key(KeyAction::Up, VK_A), &mut output);
+        mapper.map(key(KeyAction::Down, VK_DELETE), &mut output);
+
+        assert_eq!(
+            keys(&output),
+            vec![(true, VK_B, 0), (false, VK_B, 0), (true, VK_DELETE, 0)]
+        );
+    }
+
+    #[test]
+    fn test_key_macro_chord() {
+        let mut mapper = InputMapper::new(InputProfile {
+            key_macros: vec![KeyMacro {
+                trigger: VK_F1,
+                keys: vec![VK_CONTROL, VK_MENU, VK_DELETE],
+            }],
+            ..Default::default()
+        });
+
+        let ctrl = KeyModifiers::CTRL.bits();
+        let ctrl_alt = (KeyModifiers::CTRL | KeyModifiers::ALT).bits();
+
+        let mut output = Vec::new();
+        mapper.map(key(KeyAction::Down, VK_F1), &mut output);
+        assert_eq!(
+            keys(&output),
+            vec![
+                (true, VK_CONTROL, ctrl),
+                (true, VK_MENU, ctrl_alt),
+                (true, VK_DELETE, ctrl_alt),
+            ]
+        );
+
+        // Released in reverse order
+        output.clear();
+        mapper.map(key(KeyAction::Up, VK_F1), &mut output);
+        assert_eq!(
+            keys(&output),
+            vec![
+                (false, VK_DELETE, ctrl_alt),
+                (false, VK_MENU, ctrl),
+                (false, VK_CONTROL, 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_mouse_sensitivity_keeps_remainder() {
+        let mut mapper = InputMapper::new(InputProfile {
+            mouse_sensitivity: 0.5,
+            ..Default::default()
+        });
+
+        let mut output = Vec::new();
+        mapper.map(
+            InboundPacket::MouseMove {
+                delta_x: 1,
+                delta_y: -1,
+            },
+            &mut output,
+        );
+        assert!(output.is_empty());
+
+        mapper.map(
+            InboundPacket::MouseMove {
+                delta_x: 1,
+                delta_y: -1,
+            },
+            &mut output,
+        );
+        assert_eq!(mouse_moves(&output), vec![(1, -1)]);
+    }
+
+    #[test]
+    fn test_invalid_mouse_sensitivity_is_ignored() {
+        for mouse_sensitivity in [f32::NAN, -1.0, 0.0, 1000.0] {
+            let mut mapper = InputMapper::new(InputProfile {
+                mouse_sensitivity,
+                ..Default::default()
+            });
+
+            let mut output = Vec::new();
+            mapper.map(
+                InboundPacket::MouseMove {
+                    delta_x: 3,
+                    delta_y: -2,
+                },
+                &mut output,
+            );
+            assert_eq!(mouse_moves(&output), vec![(3, -2)]);
+        }
+    }
+
+    #[test]
+    fn test_gamepad_remap() {
+        let mut mapper = InputMapper::new(InputProfile {
+            gamepad_remaps: vec![GamepadButtonRemap {
+                from: ControllerButtons::A.bits(),
+                to: ControllerButtons::B.bits(),
+            }],
+            ..Default::default()
+        });
+
+        let mut output = Vec::new();
+        mapper.map(
+            InboundPacket::ControllerState {
+                id: 0,
+                buttons: ControllerButtons::A | ControllerButtons::X,
+                left_trigger: 0,
+                right_trigger: 0,
+                left_stick_x: 0,
+                left_stick_y: 0,
+                right_stick_x: 0,
+                right_stick_y: 0,
+            },
+            &mut output,
+        );
+
+        let [InboundPacket::ControllerState { buttons, .. }] = output.as_slice() else {
+            panic!("expected one controller state, got {output:?}");
+        };
+        assert_eq!(
+            buttons.bits(),
+            (ControllerButtons::B | ControllerButtons::X).bits()
+        );
+    }
+}
@@ -0,0 +1,294 @@
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, BufWriter, Write},
+    path::Path,
+    sync::{
+        Arc, Mutex,
+        mpsc::{Receiver, Sender, TryRecvError, channel},
+    },
+    thread,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::anyhow;
+use common::{StreamSettings, api_bindings::TransportChannelId};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::transport::TransportChannel;
+
+/// One json line of a recording
+#[derive(Debug, Serialize, Deserialize)]
+enum RecordEntry {
+    /// The stream started, the time of all following input is relative to this
+    Start { settings: StreamSettings },
+    /// A raw message of the client like it's given to `InboundPacket::deserialize`
+    Input {
+        time_us: u64,
+        channel: u8,
+        data: Vec<u8>,
+    },
+}
+
+/// Records the raw input messages of the client into a file so they can be replayed with `streamer --replay`
+#[derive(Clone)]
+pub struct InputRecorder {
+    start: Arc<Mutex<Option<Instant>>>,
+    sender: Sender<RecordEntry>,
+}
+
+impl InputRecorder {
+    /// Creates a new recording in the directory
+    pub fn new(directory: &Path) -> Result<Self, anyhow::Error> {
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let path = directory.join(format!("input-{time}-{}.jsonl", std::process::id()));
+
+        let file = File::create(&path)?;
+        info!("Recording input to {path:?}");
+
+        let (sender, receiver) = channel();
+        thread::spawn(move || write_recording(BufWriter::new(file), receiver));
+
+        Ok(Self {
+            start: Default::default(),
+            sender,
+        })
+    }
+
+    /// Starts the clock of the recording, input before this isn't recorded
+    pub fn start(&self, settings: &StreamSettings) {
+        let Ok(mut start) = self.start.lock() else {
+            return;
+        };
+        *start = Some(Instant::now());
+
+        let _ = self.sender.send(RecordEntry::Start {
+            settings: settings.clone(),
+        });
+    }
+
+    pub fn record(&self, channel: TransportChannel, data: &[u8]) {
+        if !is_input_channel(channel) {
+            return;
+        }
+
+        let Ok(start) = self.start.lock() else {
+            return;
+        };
+        let Some(start) = *start else {
+            return;
+        };
+
+        let _ = self.sender.send(RecordEntry::Input {
+            time_us: start.elapsed().as_micros() as u64,
+            channel: channel.0,
+            data: data.to_vec(),
+        });
+    }
+}
+
+/// Only input is replayed, messages like stop or rtt belong to the recorded session
+fn is_input_channel(channel: TransportChannel) -> bool {
+    !matches!(
+        channel.0,
+        TransportChannelId::GENERAL
+            | TransportChannelId::STATS
+            | TransportChannelId::HOST_VIDEO
+            | TransportChannelId::HOST_AUDIO
+            | TransportChannelId::RTT
+    )
+}
+
+fn write_recording(mut writer: impl Write, receiver: Receiver<RecordEntry>) {
+    while let Ok(mut entry) = receiver.recv() {
+        loop {
+            if let Err(err) = serde_json::to_writer(&mut writer, &entry)
+                .map_err(anyhow::Error::from)
+                .and_then(|_| writer.write_all(b"\n").map_err(anyhow::Error::from))
+            {
+                warn!("Failed to write input recording, stopping it: {err:?}");
+                return;
+            }
+
+            // Flush once all queued input is written
+            match receiver.try_recv() {
+                Ok(next_entry) => entry = next_entry,
+                Err(TryRecvError::Empty | TryRecvError::Disconnected) => break,
+            }
+        }
+
+        if let Err(err) = writer.flush() {
+            warn!("Failed to write input recording, stopping it: {err:?}");
+            return;
+        }
+    }
+}
+
+pub struct RecordedInput {
+    pub time: Duration,
+    pub channel: TransportChannel,
+    pub data: Vec<u8>,
+}
+
+pub struct InputRecording {
+    pub settings: StreamSettings,
+    pub inputs: Vec<RecordedInput>,
+}
+
+impl InputRecording {
+    /// Reads the first stream of a recording
+    pub fn read(path: &Path) -> Result<Self, anyhow::Error> {
+        Self::from_reader(BufReader::new(File::open(path)?))
+    }
+
+    fn from_reader(reader: impl BufRead) -> Result<Self, anyhow::Error> {
+        let mut settings = None;
+        let mut inputs = Vec::new();
+        for line in reader.lines() {
+            let line = line?;
+            if line.is_empty() {
+                continue;
+            }
+
+            match serde_json::from_str(&line)? {
+                RecordEntry::Start { settings: _ } if settings.is_some() => {
+                    warn!("The recording contains multiple streams, only replaying the first one");
+                    break;
+                }
+                RecordEntry::Start {
+                    settings: new_settings,
+                } => {
+                    settings = Some(new_settings);
+                }
+                RecordEntry::Input {
+                    time_us,
+                    channel,
+                    data,
+                } => inputs.push(RecordedInput {
+                    time: Duration::from_micros(time_us),
+                    channel: TransportChannel(channel),
+                    data,
+                }),
+            }
+        }
+
+        let settings = settings.ok_or_else(|| anyhow!("the recording doesn't contain a stream"))?;
+
+        Ok(Self { settings, inputs })
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use moonlight_common::stream::bindings::{Colorspace, SupportedVideoFormats};
+
+    use super::*;
+
+    fn settings(width: u32) -> StreamSettings {
+        StreamSettings {
+            bitrate: 10_000,
+            packet_size: 1024,
+            fps: 60,
+            width,
+            height: 1080,
+            play_audio_local: false,
+            video_supported_formats: SupportedVideoFormats::H264,
+            video_colorspace: Colorspace::Rec709,
+            video_color_range_full: false,
+            hdr: false,
+            reencode: None,
+            gamepads_attached: 0,
+            gamepads_persist: false,
+            horizontal_scroll: false,
+        }
+    }
+
+    fn input(time_us: u64, channel: u8, data: &[u8]) -> RecordEntry {
+        RecordEntry::Input {
+            time_us,
+            channel,
+            data: data.to_vec(),
+        }
+    }
+
+    #[test]
+    fn test_recording_round_trip() {
+        let (sender, receiver) = channel();
+        sender
+            .send(RecordEntry::Start {
+                settings: settings(1920),
+            })
+            .unwrap();
+        sender
+            .send(input(0, TransportChannelId::MOUSE_RELIABLE, &[1, 2]))
+            .unwrap();
+        sender
+            .send(input(1_500, TransportChannelId::KEYBOARD, &[3]))
+            .unwrap();
+        sender
+            .send(input(
+                20_000,
+                TransportChannelId::MOUSE_RELIABLE,
+                &[4, 5, 6],
+            ))
+            .unwrap();
+        // Only the first stream is replayed
+        sender
+            .send(RecordEntry::Start {
+                settings: settings(1280),
+            })
+            .unwrap();
+        sender
+            .send(input(0, TransportChannelId::KEYBOARD, &[7]))
+            .unwrap();
+        drop(sender);
+
+        let mut buffer = Vec::new();
+        write_recording(&mut buffer, receiver);
+
+        let recording = InputRecording::from_reader(buffer.as_slice()).unwrap();
+
+        assert_eq!(recording.settings.width, 1920);
+
+        let inputs = recording
+            .inputs
+            .iter()
+            .map(|input| (input.time, input.channel.0, input.data.clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            inputs,
+            vec![
+                (
+                    Duration::ZERO,
+                    TransportChannelId::MOUSE_RELIABLE,
+                    vec![1, 2]
+                ),
+                (
+                    Duration::from_micros(1_500),
+                    TransportChannelId::KEYBOARD,
+                    vec![3]
+                ),
+                (
+                    Duration::from_millis(20),
+                    TransportChannelId::MOUSE_RELIABLE,
+                    vec![4, 5, 6]
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_only_input_channels_are_recorded() {
+        assert!(is_input_channel(TransportChannel(
+            TransportChannelId::KEYBOARD
+        )));
+        assert!(!is_input_channel(TransportChannel(
+            TransportChannelId::GENERAL
+        )));
+        assert!(!is_input_channel(TransportChannel(TransportChannelId::RTT)));
+    }
+}
@@ -2,11 +2,13 @@
 #![feature(async_fn_traits)]
 
 use std::{
-    panic,
+    collections::HashMap,
+    env, panic,
+    path::{Path, PathBuf},
     process::exit,
     sync::{
         Arc, Weak,
-        atomic::{AtomicBool, Ordering},
+        atomic::{AtomicBool, AtomicU32, Ordering},
     },
     time::{Duration, Instant},
 };
@@ -15,13 +17,15 @@ use common::{
     StreamSettings,
     api_bindings::{
         GeneralClientMessage, GeneralServerMessage, LogMessageType, StreamClientMessage,
-        TransportType,
+        StreamSeat, TransportType,
     },
+    config::ClipboardSinkConfig,
     ipc::{
         IpcReceiver, IpcSender, ServerIpcMessage, StreamerConfig, StreamerIpcMessage,
         create_process_ipc,
     },
 };
+use futures::future::join_all;
 use log::{LevelFilter, debug, error, info, trace, warn};
 use crate::ffmpeg::{FfmpegPipeline, FfmpegPipelineConfig};
 use moonlight_common::{
@@ -32,8 +36,9 @@ use moonlight_common::{
     stream::{
         MoonlightInstance, MoonlightStream,
         bindings::{
-            ActiveGamepads, ColorRange, ConnectionStatus, ControllerButtons, EncryptionFlags,
-            HostFeatures, OpusMultistreamConfig, Stage, VideoFormat,
+            ActiveGamepads, AudioConfig, ColorRange, ConnectionStatus, ControllerButtons,
+            DUAL_SENSE_EFFECT_PAYLOAD_SIZE, DecodeResult, DualSenseEffect, EncryptionFlags,
+            HostFeatures, OpusMultistreamConfig, Stage, VideoDecodeUnit, VideoFormat,
         },
         connection::ConnectionListener,
         video::VideoSetup,
@@ -46,16 +51,23 @@ use tokio::{
     spawn,
     sync::{Mutex, Notify, RwLock},
     task::spawn_blocking,
-    time::sleep,
+    time::{sleep, timeout},
 };
 
 use common::api_bindings::{StreamCapabilities, StreamServerMessage};
 
 use crate::{
     audio::StreamAudioDecoder,
+    clipboard::{ClipboardSource, create_clipboard_source, write_clipboard_command},
+    input_coalesce::InputCoalescer,
+    input_profile::InputMapper,
+    input_record::{InputRecorder, InputRecording},
+    spectator::{SpectatorVideo, SpectatorVideoFrame},
     transport::{
         InboundPacket, OutboundPacket, TransportError, TransportEvent, TransportEvents,
-        TransportSender, web_socket,
+        TransportSender,
+        replay::ReplayTransportSender,
+        web_socket,
         webrtc::{self},
     },
     video::StreamVideoDecoder,
@@ -67,8 +79,13 @@ pub const TIMEOUT_DURATION: Duration = Duration::from_secs(10);
 
 mod audio;
 mod buffer;
+mod clipboard;
 mod convert;
 mod ffmpeg;
+mod input_coalesce;
+mod input_profile;
+mod input_record;
+mod spectator;
 mod transport;
 mod video;
 
@@ -80,6 +97,11 @@ async fn main() {
         exit(0);
     }));
 
+    // `streamer --replay <recording>` plays back a recorded input stream instead of waiting for a client.
+    // The Init message is still read from stdin, e.g. `streamer --replay <recording> < init.json`
+    // where `init.json` is one line with the `ServerIpcMessage::Init` the web server would send.
+    let replay_path = replay_arg();
+
     // At this point we're authenticated
     let (mut ipc_sender, mut ipc_receiver) =
         create_process_ipc::<ServerIpcMessage, StreamerIpcMessage>(stdin(), stdout()).await;
@@ -95,7 +117,7 @@ async fn main() {
         .await;
 
     let (
-        config,
+        mut config,
         host_address,
         host_http_port,
         client_unique_id,
@@ -105,6 +127,8 @@ async fn main() {
         app_id,
         video_frame_queue_size,
         audio_sample_queue_size,
+        input_profile,
+        allow_clipboard,
     ) = loop {
         match ipc_receiver.recv().await {
             Some(ServerIpcMessage::Init {
@@ -118,6 +142,8 @@ async fn main() {
                 app_id,
                 video_frame_queue_size,
                 audio_sample_queue_size,
+                input_profile,
+                allow_clipboard,
             }) => {
                 break (
                     config,
@@ -130,6 +156,8 @@ async fn main() {
                     app_id,
                     video_frame_queue_size,
                     audio_sample_queue_size,
+                    input_profile,
+                    allow_clipboard,
                 );
             }
             _ => continue,
@@ -147,6 +175,22 @@ async fn main() {
     )
     .expect("failed to init logger");
 
+    let recording = match replay_path {
+        Some(path) => match InputRecording::read(&path) {
+            Ok(recording) => {
+                // Don't record the replay again
+                config.input.record_directory = None;
+
+                Some(recording)
+            }
+            Err(err) => {
+                error!("Failed to read the input recording {path:?}: {err:?}");
+                exit(1);
+            }
+        },
+        None => None,
+    };
+
     // Send stage
     ipc_sender
         .send(StreamerIpcMessage::WebSocket(
@@ -180,6 +224,8 @@ async fn main() {
             host: Mutex::new(host),
             app_id,
         },
+        InputMapper::new(input_profile),
+        allow_clipboard,
         ipc_sender.clone(),
         ipc_receiver,
         config,
@@ -189,12 +235,19 @@ async fn main() {
     .await
     .expect("failed to create connection");
 
-    // Send Info for streamer
-    ipc_sender
-        .send(StreamerIpcMessage::WebSocket(StreamServerMessage::Setup {
-            ice_servers: connection.config.webrtc.ice_servers.clone(),
-        }))
-        .await;
+    if let Some(recording) = recording {
+        let connection = connection.clone();
+        spawn(async move {
+            connection.replay(recording).await;
+        });
+    } else {
+        // Send Info for streamer
+        ipc_sender
+            .send(StreamerIpcMessage::WebSocket(StreamServerMessage::Setup {
+                ice_servers: connection.config.webrtc.ice_servers.clone(),
+            }))
+            .await;
+    }
 
     // Wait for termination
     connection.terminate.notified().await;
@@ -203,6 +256,17 @@ async fn main() {
     exit(0);
 }
 
+fn replay_arg() -> Option<PathBuf> {
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--replay" {
+            return args.next().map(PathBuf::from);
+        }
+    }
+
+    None
+}
+
 struct StreamInfo {
     host: Mutex<MoonlightHost<RequestClient>>,
     app_id: u32,
@@ -210,7 +274,69 @@ struct StreamInfo {
 
 struct StreamSetup {
     video: Option<VideoSetup>,
+    audio_config: Option<AudioConfig>,
     audio: Option<OpusMultistreamConfig>,
+    capabilities: Option<StreamCapabilities>,
+}
+
+impl StreamSetup {
+    fn connection_complete(&self) -> Option<StreamServerMessage> {
+        let (Some(video), Some(capabilities)) = (self.video, self.capabilities.clone()) else {
+            return None;
+        };
+        let audio = self.audio.clone().unwrap_or(OpusMultistreamConfig::STEREO);
+
+        Some(StreamServerMessage::ConnectionComplete {
+            capabilities,
+            format: video.format as u32,
+            width: video.width,
+            height: video.height,
+            fps: video.redraw_rate,
+            audio_sample_rate: audio.sample_rate,
+            audio_channel_count: audio.channel_count,
+            audio_streams: audio.streams,
+            audio_coupled_streams: audio.coupled_streams,
+            audio_samples_per_frame: audio.samples_per_frame,
+            audio_mapping: audio.mapping,
+        })
+    }
+}
+
+/// A read only transport which only receives video and audio
+struct Spectator {
+    sender: Arc<dyn TransportSender + Send + Sync + 'static>,
+    /// Incremented every time the spectator selects a new transport
+    generation: u32,
+    /// The spectator requested the stream and the video / audio is setup
+    watching: bool,
+    video: SpectatorVideo,
+}
+
+/// Only lets the packet through if the seat allows it and maps the local gamepad of the spectator to the gamepad slot of the seat
+fn seat_packet(seat: &StreamSeat, mut packet: InboundPacket) -> Option<InboundPacket> {
+    match &mut packet {
+        InboundPacket::ControllerConnected { id, .. }
+        | InboundPacket::ControllerDisconnected { id }
+        | InboundPacket::ControllerState { id, .. }
+        | InboundPacket::ControllerMotion { id, .. }
+        | InboundPacket::ControllerTouch { id, .. }
+        | InboundPacket::ControllerBattery { id, .. } => {
+            *id = *seat.gamepads.get(*id as usize)?;
+        }
+        InboundPacket::MouseMove { .. }
+        | InboundPacket::MousePosition { .. }
+        | InboundPacket::MouseButton { .. }
+        | InboundPacket::HighResScroll { .. }
+        | InboundPacket::Scroll { .. }
+        | InboundPacket::Key { .. }
+        | InboundPacket::Text { .. }
+        | InboundPacket::Touch { .. }
+        | InboundPacket::Pen { .. }
+            if seat.keyboard_mouse => {}
+        _ => return None,
+    }
+
+    Some(packet)
 }
 
 struct StreamConnection {
@@ -227,7 +353,24 @@ struct StreamConnection {
     pub stream: RwLock<Option<MoonlightStream>>,
     pub active_gamepads: RwLock<ActiveGamepads>,
     pub transport_sender: Mutex<Option<Box<dyn TransportSender + Send + Sync + 'static>>>,
+    /// Increased for every new transport, so that the close of a replaced transport doesn't terminate the stream
+    transport_generation: AtomicU32,
+    /// The owner reconnected and the next start of the stream should resume the running stream
+    resuming: AtomicBool,
+    /// The resumed transport of the owner needs an idr frame
+    pub owner_needs_idr: AtomicBool,
+    /// The client didn't turn off horizontal scrolling
+    horizontal_scroll: AtomicBool,
+    pub spectators: Mutex<HashMap<u32, Spectator>>,
+    pub seats: RwLock<HashMap<u32, StreamSeat>>,
     pub reencode_settings: Mutex<Option<common::api_bindings::ReencodeSettings>>,
+    input_mapper: Mutex<InputMapper>,
+    /// Locked while sending so that flushed input can't overtake newer input
+    input_coalescer: Mutex<InputCoalescer>,
+    input_flush: Arc<Notify>,
+    /// The user is allowed to share the clipboard with the host
+    allow_clipboard: bool,
+    input_recorder: Option<InputRecorder>,
     // Timeout / Terminate
     pub timeout_terminate_request: Mutex<Option<Instant>>,
     pub terminate: Notify,
@@ -238,12 +381,27 @@ impl StreamConnection {
     pub async fn new(
         moonlight: MoonlightInstance,
         info: StreamInfo,
+        input_mapper: InputMapper,
+        allow_clipboard: bool,
         ipc_sender: IpcSender<StreamerIpcMessage>,
         mut ipc_receiver: IpcReceiver<ServerIpcMessage>,
         config: StreamerConfig,
         video_frame_queue_size: usize,
         audio_sample_queue_size: usize,
     ) -> Result<Arc<Self>, anyhow::Error> {
+        let input_coalescer = InputCoalescer::new(&config.input);
+
+        let input_recorder = match &config.input.record_directory {
+            Some(directory) => match InputRecorder::new(Path::new(directory)) {
+                Ok(input_recorder) => Some(input_recorder),
+                Err(err) => {
+                    warn!("Failed to start the input recording in {directory}: {err:?}");
+                    None
+                }
+            },
+            None => None,
+        };
+
         let this = Arc::new(Self {
             runtime: Handle::current(),
             moonlight,
@@ -252,14 +410,27 @@ impl StreamConnection {
             ipc_sender,
             stream_setup: Mutex::new(StreamSetup {
                 video: None,
+                audio_config: None,
                 audio: None,
+                capabilities: None,
             }),
             video_frame_queue_size,
             audio_sample_queue_size,
             stream: RwLock::new(None),
             active_gamepads: RwLock::new(ActiveGamepads::empty()),
             transport_sender: Mutex::new(None),
+            transport_generation: AtomicU32::new(0),
+            resuming: AtomicBool::new(false),
+            owner_needs_idr: AtomicBool::new(false),
+            horizontal_scroll: AtomicBool::new(true),
+            spectators: Default::default(),
+            seats: Default::default(),
             reencode_settings: Mutex::new(None),
+            input_mapper: Mutex::new(input_mapper),
+            input_coalescer: Mutex::new(input_coalescer),
+            input_flush: Default::default(),
+            allow_clipboard,
+            input_recorder,
             timeout_terminate_request: Default::default(),
             terminate: Notify::default(),
             is_terminating: AtomicBool::new(false),
@@ -285,6 +456,18 @@ impl StreamConnection {
             }
         });
 
+        spawn(Self::flush_coalesced_input(
+            Arc::downgrade(&this),
+            this.input_flush.clone(),
+        ));
+
+        if allow_clipboard && let Some(source) = &this.config.clipboard.host_source {
+            spawn(Self::sync_host_clipboard(
+                Arc::downgrade(&this),
+                create_clipboard_source(source),
+            ));
+        }
+
         Ok(this)
     }
 
@@ -295,9 +478,11 @@ impl StreamConnection {
     ) {
         let this = self.clone();
 
-        let old_transport = {
+        let (old_transport, generation) = {
             let mut sender = this.transport_sender.lock().await;
-            sender.replace(new_sender)
+            let generation = this.transport_generation.fetch_add(1, Ordering::AcqRel) + 1;
+
+            (sender.replace(new_sender), generation)
         };
 
         spawn({
@@ -349,7 +534,11 @@ impl StreamConnection {
                                 return;
                             };
 
-                            this.request_terminate().await;
+                            if this.transport_generation.load(Ordering::Acquire) == generation {
+                                this.request_terminate().await;
+                            } else {
+                                debug!("Replaced transport closed");
+                            }
 
                             break;
                         }
@@ -399,7 +588,130 @@ impl StreamConnection {
         }
     }
 
+    /// Input of the owner, the input profile of the owner is applied to it
     async fn on_packet(&self, packet: InboundPacket) {
+        let mut packets = Vec::with_capacity(1);
+        {
+            let mut input_mapper = self.input_mapper.lock().await;
+            input_mapper.map(packet, &mut packets);
+        }
+
+        self.send_input(packets).await;
+    }
+
+    async fn send_input(&self, packets: Vec<InboundPacket>) {
+        let mut input_coalescer = self.input_coalescer.lock().await;
+
+        let now = Instant::now();
+        let mut coalesced_packets = Vec::with_capacity(packets.len());
+        let mut schedule_flush = false;
+        for packet in packets {
+            schedule_flush |= input_coalescer.push(now, packet, &mut coalesced_packets);
+        }
+
+        for packet in coalesced_packets {
+            self.send_packet(packet).await;
+        }
+        drop(input_coalescer);
+
+        if schedule_flush {
+            self.input_flush.notify_one();
+        }
+    }
+
+    /// Sends every change of the host clipboard to the client
+    async fn sync_host_clipboard(
+        this: Weak<Self>,
+        mut source: Box<dyn ClipboardSource + Send + Sync + 'static>,
+    ) {
+        loop {
+            let result = source.changed().await;
+
+            let Some(this) = this.upgrade() else {
+                return;
+            };
+
+            let text = match result {
+                Ok(text) => text,
+                Err(err) => {
+                    warn!("Failed to read the host clipboard: {err:?}");
+                    continue;
+                }
+            };
+
+            if text.len() > this.config.clipboard.max_size {
+                debug!(
+                    "Dropping host clipboard of {} bytes because it exceeds the max size",
+                    text.len()
+                );
+                continue;
+            }
+
+            this.try_send_packet(OutboundPacket::Clipboard { text }, "clipboard", false)
+                .await;
+        }
+    }
+
+    /// Sends the input held back by the coalescer once its window ended and reports the coalescing stats
+    async fn flush_coalesced_input(this: Weak<Self>, input_flush: Arc<Notify>) {
+        const STATS_INTERVAL: Duration = Duration::from_secs(1);
+
+        let mut next_stats = Instant::now() + STATS_INTERVAL;
+        loop {
+            let next_flush = {
+                let Some(this) = this.upgrade() else {
+                    return;
+                };
+
+                this.input_coalescer.lock().await.next_flush()
+            };
+
+            let wake = next_flush.map_or(next_stats, |next_flush| next_flush.min(next_stats));
+            // Woken early when new input was held back
+            let _ = timeout(
+                wake.saturating_duration_since(Instant::now()),
+                input_flush.notified(),
+            )
+            .await;
+
+            let Some(this) = this.upgrade() else {
+                return;
+            };
+
+            let now = Instant::now();
+            let mut input_coalescer = this.input_coalescer.lock().await;
+
+            let mut packets = Vec::new();
+            input_coalescer.flush_due(now, &mut packets);
+            for packet in packets {
+                this.send_packet(packet).await;
+            }
+
+            let stats = if next_stats <= now {
+                next_stats = now + STATS_INTERVAL;
+                input_coalescer.take_stats()
+            } else {
+                None
+            };
+            drop(input_coalescer);
+
+            if let Some(stats) = stats {
+                let mut ipc_sender = this.ipc_sender.clone();
+                ipc_sender
+                    .send(StreamerIpcMessage::Stats(stats.into()))
+                    .await;
+
+                this.try_send_packet(
+                    OutboundPacket::Stats(stats.into()),
+                    "input coalescing",
+                    false,
+                )
+                .await;
+            }
+        }
+    }
+
+    async fn send_packet(&self, packet: InboundPacket) {
         let stream_lock = self.stream.read().await;
         let Some(stream) = stream_lock.as_ref() else {
             warn!("Failed to send packet {packet:?} because of missing stream");
@@ -442,8 +754,10 @@ impl StreamConnection {
                 if delta_y != 0 {
                     err = stream.send_high_res_scroll(delta_y).err()
                 }
-                if delta_x != 0 {
-                    err = stream.send_high_res_horizontal_scroll(delta_x).err()
+                // Both axes are sent, the first error is returned
+                if delta_x != 0 && self.horizontal_scroll.load(Ordering::Acquire) {
+                    let horizontal_err = stream.send_high_res_horizontal_scroll(delta_x).err();
+                    err = err.or(horizontal_err);
                 }
                 err
             }
@@ -452,8 +766,10 @@ impl StreamConnection {
                 if delta_y != 0 {
                     err = stream.send_scroll(delta_y).err();
                 }
-                if delta_x != 0 {
-                    err = stream.send_horizontal_scroll(delta_x).err();
+                // Both axes are sent, the first error is returned
+                if delta_x != 0 && self.horizontal_scroll.load(Ordering::Acquire) {
+                    let horizontal_err = stream.send_horizontal_scroll(delta_x).err();
+                    err = err.or(horizontal_err);
                 }
                 err
             }
@@ -466,6 +782,34 @@ impl StreamConnection {
                 .send_keyboard_event_non_standard(key as i16, action, modifiers, flags)
                 .err(),
             InboundPacket::Text { text } => stream.send_text(&text).err(),
+            InboundPacket::Clipboard { text } => {
+                if !self.allow_clipboard {
+                    debug!("Dropping clipboard because the user isn't allowed to share it");
+                    return;
+                }
+                if text.len() > self.config.clipboard.max_size {
+                    debug!(
+                        "Dropping clipboard of {} bytes because it exceeds the max size",
+                        text.len()
+                    );
+                    return;
+                }
+
+                match &self.config.clipboard.host_sink {
+                    ClipboardSinkConfig::Type => stream.send_text(&text).err(),
+                    ClipboardSinkConfig::Command { program, args } => {
+                        let (program, args) = (program.clone(), args.clone());
+                        spawn(async move {
+                            if let Err(err) = write_clipboard_command(&program, &args, &text).await
+                            {
+                                warn!("Failed to write the host clipboard: {err:?}");
+                            }
+                        });
+
+                        None
+                    }
+                }
+            }
             InboundPacket::Touch {
                 pointer_id,
                 x,
@@ -487,6 +831,31 @@ impl StreamConnection {
                     event_type,
                 )
                 .err(),
+            InboundPacket::Pen {
+                event_type,
+                tool_type,
+                buttons,
+                x,
+                y,
+                pressure_or_distance,
+                contact_area_major,
+                contact_area_minor,
+                rotation,
+                tilt,
+            } => stream
+                .send_pen(
+                    event_type,
+                    tool_type,
+                    buttons,
+                    x,
+                    y,
+                    pressure_or_distance,
+                    contact_area_major,
+                    contact_area_minor,
+                    rotation,
+                    tilt,
+                )
+                .err(),
             InboundPacket::ControllerConnected {
                 id,
                 ty,
@@ -573,6 +942,45 @@ impl StreamConnection {
                     )
                     .err()
             }
+            InboundPacket::ControllerMotion {
+                id,
+                motion_type,
+                x,
+                y,
+                z,
+            } => stream
+                .send_controller_motion_event(id, motion_type, x, y, z)
+                .err(),
+            InboundPacket::ControllerTouch {
+                id,
+                event_type,
+                pointer_id,
+                x,
+                y,
+                pressure,
+            } => {
+                let setup = self.stream_setup.lock().await;
+                if !setup
+                    .capabilities
+                    .as_ref()
+                    .is_some_and(|capabilities| capabilities.controller_touch)
+                {
+                    debug!("Dropping controller touch event because the host doesn't support it");
+                    return;
+                }
+                drop(setup);
+
+                stream
+                    .send_controller_touch_event(id, event_type, pointer_id, x, y, pressure)
+                    .err()
+            }
+            InboundPacket::ControllerBattery {
+                id,
+                battery_state,
+                battery_percentage,
+            } => stream
+                .send_controller_battery_event(id, battery_state, battery_percentage)
+                .err(),
             _ => None,
         };
 
@@ -581,44 +989,143 @@ impl StreamConnection {
         }
     }
 
+    async fn create_transport(
+        &self,
+        transport_type: &TransportType,
+        input_recorder: Option<InputRecorder>,
+    ) -> Option<(
+        Box<dyn TransportSender + Send + Sync + 'static>,
+        Box<dyn TransportEvents + Send + Sync + 'static>,
+    )> {
+        match transport_type {
+            TransportType::WebRTC => {
+                info!("Trying WebRTC transport");
+
+                match webrtc::new(
+                    &self.config.webrtc,
+                    self.video_frame_queue_size,
+                    self.audio_sample_queue_size,
+                    input_recorder,
+                )
+                .await
+                {
+                    Ok((sender, events)) => Some((Box::new(sender), Box::new(events))),
+                    Err(err) => {
+                        error!("Failed to start webrtc transport: {err}");
+                        None
+                    }
+                }
+            }
+            TransportType::WebSocket => {
+                info!("Trying Web Socket transport");
+
+                match web_socket::new(input_recorder).await {
+                    Ok((sender, events)) => Some((Box::new(sender), Box::new(events))),
+                    Err(err) => {
+                        error!("Failed to start web socket transport: {err}");
+                        None
+                    }
+                }
+            }
+        }
+    }
+
     async fn on_ipc_message(self: &Arc<StreamConnection>, message: ServerIpcMessage) {
+        let message = match message {
+            ServerIpcMessage::Reconnect => {
+                info!("Owner reconnected, waiting for the new transport");
+
+                self.resuming.store(true, Ordering::Release);
+
+                let mut ipc_sender = self.ipc_sender.clone();
+                ipc_sender
+                    .send(StreamerIpcMessage::WebSocket(StreamServerMessage::Setup {
+                        ice_servers: self.config.webrtc.ice_servers.clone(),
+                    }))
+                    .await;
+                return;
+            }
+            ServerIpcMessage::Stop => {
+                info!("Stopping because the web server requested it");
+
+                self.stop().await;
+                return;
+            }
+            ServerIpcMessage::SpectatorJoin { spectator_id } => {
+                info!("Spectator {spectator_id} joined");
+
+                let mut ipc_sender = self.ipc_sender.clone();
+                ipc_sender
+                    .send(StreamerIpcMessage::SpectatorWebSocket {
+                        spectator_id,
+                        message: StreamServerMessage::Setup {
+                            ice_servers: self.config.webrtc.ice_servers.clone(),
+                        },
+                    })
+                    .await;
+                return;
+            }
+            ServerIpcMessage::SpectatorWebSocket {
+                spectator_id,
+                message: StreamClientMessage::SetTransport(transport_type),
+            } => {
+                // Only the input of the owner is recorded
+                let Some((sender, events)) = self.create_transport(&transport_type, None).await
+                else {
+                    return;
+                };
+                self.add_spectator(spectator_id, sender, events).await;
+                return;
+            }
+            ServerIpcMessage::SpectatorWebSocket {
+                spectator_id,
+                message,
+            } => {
+                self.on_spectator_ipc_message(spectator_id, ServerIpcMessage::WebSocket(message))
+                    .await;
+                return;
+            }
+            ServerIpcMessage::SpectatorWebSocketTransport { spectator_id, data } => {
+                self.on_spectator_ipc_message(
+                    spectator_id,
+                    ServerIpcMessage::WebSocketTransport(data),
+                )
+                .await;
+                return;
+            }
+            ServerIpcMessage::SpectatorSeat { spectator_id, seat } => {
+                debug!("Spectator {spectator_id} got the seat {seat:?}");
+
+                self.set_spectator_seat(spectator_id, Some(seat)).await;
+                return;
+            }
+            ServerIpcMessage::SpectatorLeave { spectator_id } => {
+                info!("Spectator {spectator_id} left");
+
+                self.set_spectator_seat(spectator_id, None).await;
+
+                if let Some(spectator) = self.remove_spectator(spectator_id, None).await
+                    && let Err(err) = spectator.sender.close().await
+                {
+                    warn!("Failed to close transport of spectator {spectator_id}: {err}");
+                }
+                return;
+            }
+            message => message,
+        };
+
         if let ServerIpcMessage::WebSocket(message) = &message {
             match message {
                 StreamClientMessage::SetTransport(transport_type) => {
                     self.clear_terminate_request().await;
 
-                    match transport_type {
-                        TransportType::WebRTC => {
-                            info!("Trying WebRTC transport");
-
-                            let (sender, events) = match webrtc::new(
-                                &self.config.webrtc,
-                                self.video_frame_queue_size,
-                                self.audio_sample_queue_size,
-                            )
-                            .await
-                            {
-                                Ok(value) => value,
-                                Err(err) => {
-                                    error!("Failed to start webrtc transport: {err}");
-                                    return;
-                                }
-                            };
-                            self.set_transport(Box::new(sender), Box::new(events)).await;
-                        }
-                        TransportType::WebSocket => {
-                            info!("Trying Web Socket transport");
-
-                            let (sender, events) = match web_socket::new().await {
-                                Ok(value) => value,
-                                Err(err) => {
-                                    error!("Failed to start web socket transport: {err}");
-                                    return;
-                                }
-                            };
-                            self.set_transport(Box::new(sender), Box::new(events)).await;
-                        }
-                    }
+                    let Some((sender, events)) = self
+                        .create_transport(transport_type, self.input_recorder.clone())
+                        .await
+                    else {
+                        return;
+                    };
+                    self.set_transport(sender, events).await;
                 }
                 StreamClientMessage::UpdateReencode { reencode } => {
                     let mut reencode_settings = self.reencode_settings.lock().await;
@@ -667,6 +1174,14 @@ impl StreamConnection {
 
     // Start Moonlight Stream
     async fn start_stream(self: &Arc<Self>, settings: StreamSettings) -> Result<(), anyhow::Error> {
+        self.horizontal_scroll
+            .store(settings.horizontal_scroll, Ordering::Release);
+
+        if self.resuming.swap(false, Ordering::AcqRel) && self.resume_stream().await {
+            info!("Resumed Moonlight stream on the new transport");
+            return Ok(());
+        }
+
         // We might already be streaming -> remove and wait for connection close firstly
         {
             let mut stream = self.stream.write().await;
@@ -758,6 +1273,13 @@ impl StreamConnection {
             stream: Arc::downgrade(self),
         };
 
+        // The host creates these gamepads directly at launch instead of on their first input
+        let gamepads_attached = ActiveGamepads::from_bits_truncate(settings.gamepads_attached);
+        {
+            let mut active_gamepads = self.active_gamepads.write().await;
+            *active_gamepads = gamepads_attached;
+        }
+
         let stream = match host
             .start_stream(
                 &self.moonlight,
@@ -768,8 +1290,8 @@ impl StreamConnection {
                 settings.hdr,
                 true,
                 settings.play_audio_local,
-                ActiveGamepads::empty(),
-                false,
+                gamepads_attached,
+                settings.gamepads_persist,
                 settings.video_colorspace,
                 if settings.video_color_range_full {
                     ColorRange::Full
@@ -820,10 +1342,14 @@ impl StreamConnection {
 
         let capabilities = StreamCapabilities {
             touch: host_features.contains(HostFeatures::PEN_TOUCH_EVENTS),
+            controller_touch: host_features.contains(HostFeatures::CONTROLLER_TOUCH_EVENTS),
+            pen: host_features.contains(HostFeatures::PEN_TOUCH_EVENTS),
+            clipboard: self.allow_clipboard,
         };
 
         let (video_setup, audio_setup) = {
-            let setup = self.stream_setup.lock().await;
+            let mut setup = self.stream_setup.lock().await;
+            setup.capabilities = Some(capabilities.clone());
 
             let video = setup.video.unwrap_or_else(|| {
                 warn!("failed to query video setup information. Giving the browser guessed information");
@@ -860,12 +1386,512 @@ impl StreamConnection {
                 .await;
         });
 
+        self.spectators_connection_complete().await;
+
         let mut stream_guard = self.stream.write().await;
         stream_guard.replace(stream);
+        drop(stream_guard);
+
+        if let Some(input_recorder) = &self.input_recorder {
+            input_recorder.start(&settings);
+        }
 
         Ok(())
     }
 
+    /// Plays back a recording of the input against the host, used by `streamer --replay`
+    async fn replay(self: &Arc<Self>, recording: InputRecording) {
+        {
+            let mut sender = self.transport_sender.lock().await;
+            *sender = Some(Box::new(ReplayTransportSender));
+        }
+
+        if let Err(err) = self.start_stream(recording.settings).await {
+            error!("Failed to start the stream for the replay: {err:?}");
+            self.stop().await;
+            return;
+        }
+
+        info!("Replaying {} recorded inputs", recording.inputs.len());
+
+        let start = Instant::now();
+        for input in recording.inputs {
+            sleep((start + input.time).saturating_duration_since(Instant::now())).await;
+
+            if let Some(packet) = InboundPacket::deserialize(input.channel, &input.data) {
+                self.on_packet(packet).await;
+            }
+        }
+
+        info!("Replay finished");
+        self.stop().await;
+    }
+
+    // -- Spectators
+    async fn add_spectator(
+        self: &Arc<Self>,
+        spectator_id: u32,
+        sender: Box<dyn TransportSender + Send + Sync + 'static>,
+        mut events: Box<dyn TransportEvents + Send + Sync + 'static>,
+    ) {
+        let (generation, old_spectator) = {
+            let mut spectators = self.spectators.lock().await;
+
+            let old_spectator = spectators.remove(&spectator_id);
+            let generation = old_spectator
+                .as_ref()
+                .map(|spectator| spectator.generation.wrapping_add(1))
+                .unwrap_or_default();
+
+            let sender: Arc<dyn TransportSender + Send + Sync + 'static> = Arc::from(sender);
+            spectators.insert(
+                spectator_id,
+                Spectator {
+                    video: SpectatorVideo::new(sender.clone(), self.video_frame_queue_size),
+                    sender,
+                    generation,
+                    watching: false,
+                },
+            );
+
+            (generation, old_spectator)
+        };
+
+        spawn({
+            let mut ipc_sender = self.ipc_sender.clone();
+            let this = Arc::downgrade(self);
+
+            async move {
+                loop {
+                    let event = events.poll_event().await;
+                    trace!("Polled transport event of spectator {spectator_id}: {event:?}");
+
+                    match event {
+                        Ok(TransportEvent::SendIpc(message)) => {
+                            let message = match message {
+                                StreamerIpcMessage::WebSocket(message) => {
+                                    StreamerIpcMessage::SpectatorWebSocket {
+                                        spectator_id,
+                                        message,
+                                    }
+                                }
+                                StreamerIpcMessage::WebSocketTransport(data) => {
+                                    StreamerIpcMessage::SpectatorWebSocketTransport {
+                                        spectator_id,
+                                        data,
+                                    }
+                                }
+                                message => {
+                                    warn!(
+                                        "Spectator {spectator_id} tried to send an invalid ipc message: {message:?}"
+                                    );
+                                    continue;
+                                }
+                            };
+
+                            ipc_sender.send(message).await;
+                        }
+                        Ok(TransportEvent::StartStream { .. }) => {
+                            let Some(this) = this.upgrade() else {
+                                warn!(
+                                    "Failed to get stream connection, stopping listening to events"
+                                );
+                                return;
+                            };
+
+                            this.start_spectating(spectator_id).await;
+                        }
+                        Ok(TransportEvent::RecvPacket(packet)) => {
+                            let Some(this) = this.upgrade() else {
+                                warn!(
+                                    "Failed to get stream connection, stopping listening to events"
+                                );
+                                return;
+                            };
+
+                            this.on_spectator_packet(spectator_id, packet).await;
+                        }
+                        Err(TransportError::Closed) | Ok(TransportEvent::Closed) => {
+                            break;
+                        }
+                        // It wouldn't make sense to return this
+                        Err(TransportError::ChannelClosed) => unreachable!(),
+                        Err(TransportError::Implementation(err)) => {
+                            info!(
+                                "Removing spectator {spectator_id} because of transport implementation error: {err}"
+                            );
+                            break;
+                        }
+                    }
+                }
+
+                let Some(this) = this.upgrade() else {
+                    return;
+                };
+
+                if let Some(spectator) = this.remove_spectator(spectator_id, Some(generation)).await
+                {
+                    if let Err(err) = spectator.sender.close().await {
+                        warn!("Failed to close transport of spectator {spectator_id}: {err}");
+                    }
+
+                    ipc_sender
+                        .send(StreamerIpcMessage::SpectatorStop { spectator_id })
+                        .await;
+                }
+            }
+        });
+
+        if let Some(old_spectator) = old_spectator {
+            spawn(async move {
+                if let Err(err) = old_spectator.sender.close().await {
+                    warn!("Failed to close old transport of spectator {spectator_id}: {err:?}");
+                }
+            });
+        }
+    }
+
+    /// Only removes the spectator if the generation matches, if one is specified
+    async fn remove_spectator(
+        &self,
+        spectator_id: u32,
+        generation: Option<u32>,
+    ) -> Option<Spectator> {
+        let mut spectators = self.spectators.lock().await;
+
+        match (spectators.get(&spectator_id), generation) {
+            (Some(spectator), Some(generation)) if spectator.generation != generation => None,
+            _ => spectators.remove(&spectator_id),
+        }
+    }
+
+    async fn on_spectator_ipc_message(&self, spectator_id: u32, message: ServerIpcMessage) {
+        let spectators = self.spectators.lock().await;
+
+        if let Some(spectator) = spectators.get(&spectator_id) {
+            if let Err(err) = spectator.sender.on_ipc_message(message).await {
+                warn!("Failed to send ipc message to spectator {spectator_id}: {err}");
+            }
+        } else {
+            warn!(
+                "Failed to process ipc message because of missing spectator transport {spectator_id}: {message:?}"
+            );
+        }
+    }
+
+    /// Attaches the already running stream to the new transport of the owner.
+    /// Returns false if there's no running stream to resume.
+    async fn resume_stream(&self) -> bool {
+        if self.stream.read().await.is_none() {
+            return false;
+        }
+
+        let setup = self.stream_setup.lock().await;
+        let Some(connection_complete) = setup.connection_complete() else {
+            return false;
+        };
+
+        {
+            let mut sender = self.transport_sender.lock().await;
+            let Some(sender) = sender.as_mut() else {
+                warn!("Failed to resume stream because of missing transport");
+                return false;
+            };
+
+            if let Some(video) = setup.video
+                && sender.setup_video(video).await != 0
+            {
+                warn!("Failed to setup video of the resumed transport");
+            }
+            if let Some(audio_config) = setup.audio_config
+                && let Some(audio) = setup.audio.clone()
+                && sender.setup_audio(audio_config, audio).await != 0
+            {
+                warn!("Failed to setup audio of the resumed transport");
+            }
+        }
+
+        self.owner_needs_idr.store(true, Ordering::Release);
+
+        let mut ipc_sender = self.ipc_sender.clone();
+        ipc_sender
+            .send(StreamerIpcMessage::WebSocket(connection_complete))
+            .await;
+
+        true
+    }
+
+    /// Spectators are read only unless the owner gave them a seat
+    async fn on_spectator_packet(&self, spectator_id: u32, packet: InboundPacket) {
+        let packet = {
+            let seats = self.seats.read().await;
+
+            let Some(seat) = seats.get(&spectator_id) else {
+                trace!("Ignoring packet of spectator {spectator_id} without a seat: {packet:?}");
+                return;
+            };
+
+            let Some(packet) = seat_packet(seat, packet) else {
+                trace!("Ignoring packet of spectator {spectator_id} not allowed by its seat");
+                return;
+            };
+
+            packet
+        };
+
+        // The input profile belongs to the owner, so it doesn't apply to spectators
+        self.send_input(vec![packet]).await;
+    }
+
+    async fn set_spectator_seat(&self, spectator_id: u32, seat: Option<StreamSeat>) {
+        let old_seat = {
+            let mut seats = self.seats.write().await;
+
+            match seat.clone() {
+                Some(seat) => seats.insert(spectator_id, seat),
+                None => seats.remove(&spectator_id),
+            }
+        };
+
+        // Disconnect all gamepads which moved or were revoked
+        if let Some(old_seat) = old_seat {
+            for (index, slot) in old_seat.gamepads.iter().enumerate() {
+                let still_assigned = seat
+                    .as_ref()
+                    .is_some_and(|seat| seat.gamepads.get(index) == Some(slot));
+
+                let is_active = match ActiveGamepads::from_id(*slot) {
+                    Some(gamepad) => self.active_gamepads.read().await.contains(gamepad),
+                    None => false,
+                };
+
+                if !still_assigned && is_active {
+                    self.send_input(vec![InboundPacket::ControllerDisconnected { id: *slot }])
+                        .await;
+                }
+            }
+        }
+
+        if let Some(seat) = seat {
+            let mut ipc_sender = self.ipc_sender.clone();
+            ipc_sender
+                .send(StreamerIpcMessage::SpectatorWebSocket {
+                    spectator_id,
+                    message: StreamServerMessage::UpdateSeat { seat },
+                })
+                .await;
+        }
+    }
+
+    /// Sends the packet to the spectator whose seat contains this gamepad or else to the owner.
+    /// The packet gets created with the gamepad number that the receiver knows.
+    async fn send_controller_packet(
+        &self,
+        controller_number: u8,
+        create_packet: impl FnOnce(u8) -> OutboundPacket,
+        packet_ty: &str,
+    ) {
+        let seat_holder = {
+            let seats = self.seats.read().await;
+
+            seats.iter().find_map(|(spectator_id, seat)| {
+                seat.gamepads
+                    .iter()
+                    .position(|slot| *slot == controller_number)
+                    .map(|local_number| (*spectator_id, local_number as u8))
+            })
+        };
+
+        let Some((spectator_id, local_number)) = seat_holder else {
+            self.try_send_packet(create_packet(controller_number), packet_ty, true)
+                .await;
+            return;
+        };
+
+        let spectators = self.spectators.lock().await;
+        if let Some(spectator) = spectators.get(&spectator_id) {
+            if let Err(err) = spectator.sender.send(create_packet(local_number)).await {
+                debug!(
+                    "Failed to send outbound packet to spectator {spectator_id}: {packet_ty}, {err:?}"
+                );
+            }
+        } else {
+            debug!("Dropping packet {packet_ty} because spectator {spectator_id} has no transport");
+        }
+    }
+
+    /// Setup the video and audio of this spectator.
+    /// If the stream isn't running yet this will happen once it starts.
+    async fn start_spectating(&self, spectator_id: u32) {
+        let setup = self.stream_setup.lock().await;
+        let mut spectators = self.spectators.lock().await;
+
+        let Some(spectator) = spectators.get_mut(&spectator_id) else {
+            warn!("Failed to start spectating because of missing spectator {spectator_id}");
+            return;
+        };
+
+        spectator.watching = true;
+        spectator.video.request_idr();
+
+        // Only setup when the stream is already running
+        let Some(connection_complete) = setup.connection_complete() else {
+            return;
+        };
+
+        if let Some(video) = setup.video
+            && spectator.sender.setup_video(video).await != 0
+        {
+            warn!("Failed to setup video of spectator {spectator_id}");
+        }
+        if let Some(audio_config) = setup.audio_config
+            && let Some(audio) = setup.audio.clone()
+            && spectator.sender.setup_audio(audio_config, audio).await != 0
+        {
+            warn!("Failed to setup audio of spectator {spectator_id}");
+        }
+
+        let mut ipc_sender = self.ipc_sender.clone();
+        ipc_sender
+            .send(StreamerIpcMessage::SpectatorWebSocket {
+                spectator_id,
+                message: connection_complete,
+            })
+            .await;
+    }
+
+    async fn spectators_connection_complete(&self) {
+        let setup = self.stream_setup.lock().await;
+        let spectators = self.spectators.lock().await;
+
+        let mut ipc_sender = self.ipc_sender.clone();
+        for (spectator_id, _) in spectators
+            .iter()
+            .filter(|(_, spectator)| spectator.watching)
+        {
+            let Some(message) = setup.connection_complete() else {
+                return;
+            };
+
+            ipc_sender
+                .send(StreamerIpcMessage::SpectatorWebSocket {
+                    spectator_id: *spectator_id,
+                    message,
+                })
+                .await;
+        }
+    }
+
+    async fn spectators_setup_video(&self, setup: VideoSetup) {
+        let spectators = self.spectators.lock().await;
+
+        for (spectator_id, spectator) in spectators
+            .iter()
+            .filter(|(_, spectator)| spectator.watching)
+        {
+            if spectator.sender.setup_video(setup).await != 0 {
+                warn!("Failed to setup video of spectator {spectator_id}");
+            }
+        }
+    }
+
+    /// Clones the senders of all watching spectators so that the lock isn't held while sending to them
+    async fn watching_spectator_senders(
+        &self,
+    ) -> Vec<(u32, Arc<dyn TransportSender + Send + Sync + 'static>)> {
+        let spectators = self.spectators.lock().await;
+
+        spectators
+            .iter()
+            .filter(|(_, spectator)| spectator.watching)
+            .map(|(spectator_id, spectator)| (*spectator_id, spectator.sender.clone()))
+            .collect()
+    }
+
+    /// Queues the frame for every watching spectator without waiting for them to send it
+    async fn spectators_push_video(
+        &self,
+        create_frame: impl FnOnce() -> SpectatorVideoFrame,
+    ) -> DecodeResult {
+        let now = Instant::now();
+        let mut spectators = self.spectators.lock().await;
+
+        if !spectators.values().any(|spectator| spectator.watching) {
+            return DecodeResult::Ok;
+        }
+        let frame = Arc::new(create_frame());
+
+        let mut result = DecodeResult::Ok;
+        for (spectator_id, spectator) in spectators
+            .iter_mut()
+            .filter(|(_, spectator)| spectator.watching)
+        {
+            if !spectator.video.push(&frame) {
+                trace!("Dropped a video frame of spectator {spectator_id}");
+            }
+
+            if spectator.video.take_needs_idr(now) {
+                debug!("Spectator {spectator_id} requested an idr frame");
+                result = DecodeResult::NeedIdr;
+            }
+        }
+
+        result
+    }
+
+    /// Returns NeedIdr if one of the spectators requires an idr frame
+    async fn spectators_send_video_unit(&self, unit: &VideoDecodeUnit<'_>) -> DecodeResult {
+        self.spectators_push_video(|| SpectatorVideoFrame::from_unit(unit))
+            .await
+    }
+
+    async fn spectators_send_h264_annexb(
+        &self,
+        data: &[u8],
+        rtp_timestamp: u32,
+        is_keyframe: bool,
+    ) {
+        self.spectators_push_video(|| SpectatorVideoFrame::H264AnnexB {
+            data: data.to_vec(),
+            rtp_timestamp,
+            is_keyframe,
+        })
+        .await;
+    }
+
+    async fn spectators_setup_audio(
+        &self,
+        audio_config: AudioConfig,
+        stream_config: &OpusMultistreamConfig,
+    ) {
+        let spectators = self.spectators.lock().await;
+
+        for (spectator_id, spectator) in spectators
+            .iter()
+            .filter(|(_, spectator)| spectator.watching)
+        {
+            if spectator
+                .sender
+                .setup_audio(audio_config, stream_config.clone())
+                .await
+                != 0
+            {
+                warn!("Failed to setup audio of spectator {spectator_id}");
+            }
+        }
+    }
+
+    async fn spectators_send_audio_sample(&self, data: &[u8]) {
+        let senders = self.watching_spectator_senders().await;
+
+        join_all(senders.iter().map(|(spectator_id, sender)| async move {
+            if let Err(err) = sender.send_audio_sample(data).await {
+                debug!("Failed to send audio sample to spectator {spectator_id}: {err}");
+            }
+        }))
+        .await;
+    }
+
     // -- Termination
     async fn request_terminate(self: &Arc<Self>) {
         let this = self.clone();
@@ -924,6 +1950,14 @@ impl StreamConnection {
             drop(transport);
         }
 
+        let mut spectators = self.spectators.lock().await;
+        for (spectator_id, spectator) in spectators.drain() {
+            if let Err(err) = spectator.sender.close().await {
+                warn!("Error whilst closing transport of spectator {spectator_id}: {err}");
+            }
+        }
+        drop(spectators);
+
         let mut ipc_sender = self.ipc_sender.clone();
         ipc_sender.send(StreamerIpcMessage::Stop).await;
 
@@ -1078,14 +2112,14 @@ impl ConnectionListener for StreamConnectionListener {
 
         stream.runtime.clone().block_on(async move {
             stream
-                .try_send_packet(
-                    OutboundPacket::ControllerRumble {
-                        controller_number: controller_number as u8,
+                .send_controller_packet(
+                    controller_number as u8,
+                    |controller_number| OutboundPacket::ControllerRumble {
+                        controller_number,
                         low_frequency_motor,
                         high_frequency_motor,
                     },
                     "controller rumble",
-                    true,
                 )
                 .await;
         });
@@ -1104,14 +2138,14 @@ impl ConnectionListener for StreamConnectionListener {
 
         stream.runtime.clone().block_on(async move {
             stream
-                .try_send_packet(
-                    OutboundPacket::ControllerTriggerRumble {
-                        controller_number: controller_number as u8,
+                .send_controller_packet(
+                    controller_number as u8,
+                    |controller_number| OutboundPacket::ControllerTriggerRumble {
+                        controller_number,
                         left_trigger_motor,
                         right_trigger_motor,
                     },
                     "controller rumble triggers",
-                    true,
                 )
                 .await;
         });
@@ -1119,26 +2153,84 @@ impl ConnectionListener for StreamConnectionListener {
 
     fn controller_set_motion_event_state(
         &mut self,
-        _controller_number: u16,
-        _motion_type: u8,
-        _report_rate_hz: u16,
+        controller_number: u16,
+        motion_type: u8,
+        report_rate_hz: u16,
     ) {
-        // unsupported: https://github.com/w3c/gamepad/issues/211
+        let Some(stream) = self.stream.upgrade() else {
+            warn!("Failed to get stream because it is already deallocated");
+            return;
+        };
+
+        stream.runtime.clone().block_on(async move {
+            stream
+                .send_controller_packet(
+                    controller_number as u8,
+                    |controller_number| OutboundPacket::ControllerMotionEventState {
+                        controller_number,
+                        motion_type,
+                        report_rate_hz,
+                    },
+                    "controller motion event state",
+                )
+                .await;
+        });
     }
 
     fn controller_set_adaptive_triggers(
         &mut self,
-        _controller_number: u16,
-        _event_flags: u8,
-        _type_left: u8,
-        _type_right: u8,
-        _left: &mut u8,
-        _right: &mut u8,
+        controller_number: u16,
+        event_flags: u8,
+        type_left: u8,
+        type_right: u8,
+        left: &[u8; DUAL_SENSE_EFFECT_PAYLOAD_SIZE],
+        right: &[u8; DUAL_SENSE_EFFECT_PAYLOAD_SIZE],
     ) {
-        // unsupported
+        let Some(stream) = self.stream.upgrade() else {
+            warn!("Failed to get stream because it is already deallocated");
+            return;
+        };
+
+        let event_flags = DualSenseEffect::from_bits_truncate(event_flags as u32);
+        let (left, right) = (*left, *right);
+
+        stream.runtime.clone().block_on(async move {
+            stream
+                .send_controller_packet(
+                    controller_number as u8,
+                    |controller_number| OutboundPacket::ControllerAdaptiveTriggers {
+                        controller_number,
+                        event_flags,
+                        type_left,
+                        type_right,
+                        left,
+                        right,
+                    },
+                    "controller adaptive triggers",
+                )
+                .await;
+        });
     }
 
-    fn controller_set_led(&mut self, _controller_number: u16, _r: u8, _g: u8, _b: u8) {
-        // unsupported
+    fn controller_set_led(&mut self, controller_number: u16, r: u8, g: u8, b: u8) {
+        let Some(stream) = self.stream.upgrade() else {
+            warn!("Failed to get stream because it is already deallocated");
+            return;
+        };
+
+        stream.runtime.clone().block_on(async move {
+            stream
+                .send_controller_packet(
+                    controller_number as u8,
+                    |controller_number| OutboundPacket::ControllerLed {
+                        controller_number,
+                        r,
+                        g,
+                        b,
+                    },
+                    "controller led",
+                )
+                .await;
+        });
     }
 }
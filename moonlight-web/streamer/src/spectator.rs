@@ -0,0 +1,287 @@
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+use log::debug;
+use moonlight_common::stream::bindings::{
+    BufferType, Colorspace, DecodeResult, FrameType, VideoDataBuffer, VideoDecodeUnit,
+};
+use tokio::{
+    spawn,
+    sync::mpsc::{self, Receiver, Sender, error::TrySendError},
+};
+
+use crate::transport::{TransportError, TransportSender};
+
+/// A spectator can request an idr frame from the host at most this often.
+/// The idr frame also goes to the owner, so a lagging spectator shouldn't be able to flood the stream with them.
+const SPECTATOR_IDR_REQUEST_INTERVAL: Duration = Duration::from_secs(1);
+
+/// A copy of a video frame that can wait in the queue of a spectator
+pub enum SpectatorVideoFrame {
+    DecodeUnit {
+        frame_number: i32,
+        frame_type: FrameType,
+        frame_processing_latency: Option<Duration>,
+        receive_time: Duration,
+        enqueue_time: Duration,
+        presentation_time: Duration,
+        rtp_timestamp: u32,
+        hdr_active: bool,
+        color_space: Colorspace,
+        buffers: Vec<(BufferType, Vec<u8>)>,
+    },
+    H264AnnexB {
+        data: Vec<u8>,
+        rtp_timestamp: u32,
+        is_keyframe: bool,
+    },
+}
+
+impl SpectatorVideoFrame {
+    pub fn from_unit(unit: &VideoDecodeUnit<'_>) -> Self {
+        Self::DecodeUnit {
+            frame_number: unit.frame_number,
+            frame_type: unit.frame_type,
+            frame_processing_latency: unit.frame_processing_latency,
+            receive_time: unit.receive_time,
+            enqueue_time: unit.enqueue_time,
+            presentation_time: unit.presentation_time,
+            rtp_timestamp: unit.rtp_timestamp,
+            hdr_active: unit.hdr_active,
+            color_space: unit.color_space,
+            buffers: unit
+                .buffers
+                .iter()
+                .map(|buffer| (buffer.ty, buffer.data.to_vec()))
+                .collect(),
+        }
+    }
+
+    fn is_keyframe(&self) -> bool {
+        match self {
+            Self::DecodeUnit { frame_type, .. } => matches!(frame_type, FrameType::Idr),
+            Self::H264AnnexB { is_keyframe, .. } => *is_keyframe,
+        }
+    }
+
+    async fn send(
+        &self,
+        sender: &(dyn TransportSender + Send + Sync + 'static),
+    ) -> Result<DecodeResult, TransportError> {
+        match self {
+            Self::DecodeUnit {
+                frame_number,
+                frame_type,
+                frame_processing_latency,
+                receive_time,
+                enqueue_time,
+                presentation_time,
+                rtp_timestamp,
+                hdr_active,
+                color_space,
+                buffers,
+            } => {
+                let buffers = buffers
+                    .iter()
+                    .map(|(ty, data)| VideoDataBuffer { ty: *ty, data })
+                    .collect::<Vec<_>>();
+
+                let unit = VideoDecodeUnit {
+                    frame_number: *frame_number,
+                    frame_type: *frame_type,
+                    frame_processing_latency: *frame_processing_latency,
+                    receive_time: *receive_time,
+                    enqueue_time: *enqueue_time,
+                    presentation_time: *presentation_time,
+                    rtp_timestamp: *rtp_timestamp,
+                    hdr_active: *hdr_active,
+                    color_space: *color_space,
+                    buffers: &buffers,
+                };
+
+                sender.send_video_unit(&unit).await
+            }
+            Self::H264AnnexB {
+                data,
+                rtp_timestamp,
+                is_keyframe,
+            } => sender
+                .send_h264_annexb(data, *rtp_timestamp, *is_keyframe)
+                .await
+                .map(|()| DecodeResult::Ok),
+        }
+    }
+}
+
+#[derive(Default)]
+struct SpectatorVideoState {
+    /// The spectator missed a frame and can't decode anything until the next idr frame
+    waiting_for_idr: AtomicBool,
+    /// The spectator wants the host to send an idr frame
+    needs_idr: AtomicBool,
+}
+
+impl SpectatorVideoState {
+    fn request_idr(&self) {
+        self.waiting_for_idr.store(true, Ordering::Release);
+        self.needs_idr.store(true, Ordering::Release);
+    }
+}
+
+/// Every spectator gets its video frames through its own bounded queue, so a slow spectator can't hold up the owner or other spectators.
+/// Sends are never cancelled: when the queue is full the whole frame is dropped and only this spectator skips frames until the next idr frame.
+pub struct SpectatorVideo {
+    queue: Sender<Arc<SpectatorVideoFrame>>,
+    state: Arc<SpectatorVideoState>,
+    last_idr_request: Option<Instant>,
+}
+
+impl SpectatorVideo {
+    pub fn new(
+        sender: Arc<dyn TransportSender + Send + Sync + 'static>,
+        queue_size: usize,
+    ) -> Self {
+        let (video, mut receiver) = Self::channel(queue_size);
+
+        spawn({
+            let state = video.state.clone();
+
+            async move {
+                while let Some(frame) = receiver.recv().await {
+                    match frame.send(sender.as_ref()).await {
+                        Ok(DecodeResult::NeedIdr) => state.request_idr(),
+                        Ok(DecodeResult::Ok) => {}
+                        Err(err) => {
+                            debug!("Failed to send video frame to spectator: {err}");
+                        }
+                    }
+                }
+            }
+        });
+
+        video
+    }
+
+    fn channel(queue_size: usize) -> (Self, Receiver<Arc<SpectatorVideoFrame>>) {
+        let (queue, receiver) = mpsc::channel(queue_size.max(1));
+
+        let video = Self {
+            queue,
+            state: Default::default(),
+            last_idr_request: None,
+        };
+        // A new spectator can only start decoding with an idr frame
+        video.state.request_idr();
+
+        (video, receiver)
+    }
+
+    /// The spectator (re)started watching and needs an idr frame before it can decode anything
+    pub fn request_idr(&self) {
+        self.state.request_idr();
+    }
+
+    /// Returns false if the frame was dropped for this spectator
+    pub fn push(&self, frame: &Arc<SpectatorVideoFrame>) -> bool {
+        if frame.is_keyframe() {
+            self.state.waiting_for_idr.store(false, Ordering::Release);
+        } else if self.state.waiting_for_idr.load(Ordering::Acquire) {
+            return false;
+        }
+
+        match self.queue.try_send(frame.clone()) {
+            Ok(()) => true,
+            Err(TrySendError::Full(_)) => {
+                self.state.request_idr();
+                false
+            }
+            Err(TrySendError::Closed(_)) => false,
+        }
+    }
+
+    /// Returns if the spectator needs an idr frame from the host and resets it.
+    /// Requests within [SPECTATOR_IDR_REQUEST_INTERVAL] of the last one are kept for later.
+    pub fn take_needs_idr(&mut self, now: Instant) -> bool {
+        if self
+            .last_idr_request
+            .is_some_and(|last| now < last + SPECTATOR_IDR_REQUEST_INTERVAL)
+        {
+            return false;
+        }
+
+        if !self.state.needs_idr.swap(false, Ordering::AcqRel) {
+            return false;
+        }
+
+        self.last_idr_request = Some(now);
+        true
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    fn frame(is_keyframe: bool) -> Arc<SpectatorVideoFrame> {
+        Arc::new(SpectatorVideoFrame::H264AnnexB {
+            data: vec![0, 0, 0, 1],
+            rtp_timestamp: 0,
+            is_keyframe,
+        })
+    }
+
+    #[test]
+    fn test_waits_for_idr_frame() {
+        let (video, mut receiver) = SpectatorVideo::channel(4);
+
+        assert!(!video.push(&frame(false)));
+        assert!(video.push(&frame(true)));
+        assert!(video.push(&frame(false)));
+
+        assert!(receiver.try_recv().unwrap().is_keyframe());
+        assert!(!receiver.try_recv().unwrap().is_keyframe());
+        assert!(receiver.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_full_queue_drops_until_idr_frame() {
+        let (mut video, mut receiver) = SpectatorVideo::channel(2);
+        let now = Instant::now();
+        assert!(video.take_needs_idr(now));
+
+        assert!(video.push(&frame(true)));
+        assert!(video.push(&frame(false)));
+        // The queue is full, so the frame is dropped without waiting for the sender
+        assert!(!video.push(&frame(false)));
+        assert!(video.take_needs_idr(now + SPECTATOR_IDR_REQUEST_INTERVAL));
+
+        receiver.try_recv().unwrap();
+        receiver.try_recv().unwrap();
+
+        // The spectator can't decode frames after the dropped one
+        assert!(!video.push(&frame(false)));
+        assert!(video.push(&frame(true)));
+        assert!(receiver.try_recv().unwrap().is_keyframe());
+    }
+
+    #[test]
+    fn test_idr_requests_are_rate_limited() {
+        let (mut video, _receiver) = SpectatorVideo::channel(1);
+        let now = Instant::now();
+
+        assert!(video.take_needs_idr(now));
+        assert!(!video.take_needs_idr(now));
+
+        video.request_idr();
+        assert!(!video.take_needs_idr(now + SPECTATOR_IDR_REQUEST_INTERVAL / 2));
+        // The request is kept until the interval passed
+        assert!(video.take_needs_idr(now + SPECTATOR_IDR_REQUEST_INTERVAL));
+        assert!(!video.take_needs_idr(now + SPECTATOR_IDR_REQUEST_INTERVAL * 3));
+    }
+}
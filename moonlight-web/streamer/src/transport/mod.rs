@@ -11,9 +11,10 @@ use common::{
 use log::warn;
 use moonlight_common::stream::{
     bindings::{
-        AudioConfig, ControllerButtons, ControllerCapabilities, ControllerType, DecodeResult,
-        KeyAction, KeyFlags, KeyModifiers, MouseButton, MouseButtonAction, OpusMultistreamConfig,
-        TouchEventType, VideoDecodeUnit,
+        AudioConfig, BatteryState, ControllerButtons, ControllerCapabilities, ControllerType,
+        DUAL_SENSE_EFFECT_PAYLOAD_SIZE, DecodeResult, DualSenseEffect, KeyAction, KeyFlags,
+        KeyModifiers, MotionType, MouseButton, MouseButtonAction, OpusMultistreamConfig,
+        PenButtons, PenToolType, TouchEventType, VideoDecodeUnit,
     },
     video::VideoSetup,
 };
@@ -22,6 +23,7 @@ use thiserror::Error;
 
 use crate::buffer::ByteBuffer;
 
+pub mod replay;
 pub mod web_socket;
 pub mod webrtc;
 
@@ -75,6 +77,10 @@ pub enum InboundPacket {
     Text {
         text: String,
     },
+    /// The clipboard of the browser changed
+    Clipboard {
+        text: String,
+    },
     ControllerConnected {
         id: u8,
         ty: ControllerType,
@@ -94,6 +100,26 @@ pub enum InboundPacket {
         right_stick_x: i16,
         right_stick_y: i16,
     },
+    ControllerMotion {
+        id: u8,
+        motion_type: MotionType,
+        x: f32,
+        y: f32,
+        z: f32,
+    },
+    ControllerTouch {
+        id: u8,
+        event_type: TouchEventType,
+        pointer_id: u32,
+        x: f32,
+        y: f32,
+        pressure: f32,
+    },
+    ControllerBattery {
+        id: u8,
+        battery_state: BatteryState,
+        battery_percentage: Option<u8>,
+    },
     Touch {
         pointer_id: u32,
         x: f32,
@@ -104,6 +130,19 @@ pub enum InboundPacket {
         rotation: Option<u16>,
         event_type: TouchEventType,
     },
+    Pen {
+        event_type: TouchEventType,
+        tool_type: PenToolType,
+        buttons: PenButtons,
+        x: f32,
+        y: f32,
+        pressure_or_distance: f32,
+        contact_area_major: f32,
+        contact_area_minor: f32,
+        rotation: Option<u16>,
+        /// Degrees from the vertical
+        tilt: Option<u8>,
+    },
     Rtt {
         sequence_number: u16,
     },
@@ -360,6 +399,75 @@ impl InboundPacket {
                     event_type,
                 })
             }
+            TransportChannel(TransportChannelId::PEN) => {
+                if buffer.remaining() < 26 {
+                    warn!("[InboudPacket]: failed to read pen message");
+                    return None;
+                }
+
+                let event_type = match buffer.get_u8() {
+                    0 => TouchEventType::Down,
+                    1 => TouchEventType::Move,
+                    2 => TouchEventType::Cancel,
+                    3 => TouchEventType::Up,
+                    4 => TouchEventType::Hover,
+                    5 => TouchEventType::HoverLeave,
+                    _ => {
+                        warn!("[InboundPacket]: received invalid pen event type");
+                        return None;
+                    }
+                };
+                let Some(tool_type) = PenToolType::from_u8(buffer.get_u8()) else {
+                    warn!("[InboundPacket]: received invalid pen tool type");
+                    return None;
+                };
+                let buttons = PenButtons::from_bits_truncate(buffer.get_u8());
+                let x = buffer.get_f32();
+                let y = buffer.get_f32();
+                let pressure_or_distance = buffer.get_f32();
+                let contact_area_major = buffer.get_f32();
+                let contact_area_minor = buffer.get_f32();
+                // 0xFFFF / 0xFF: unknown
+                let rotation = Some(buffer.get_u16()).filter(|rotation| *rotation != u16::MAX);
+                let tilt = Some(buffer.get_u8()).filter(|tilt| *tilt != u8::MAX);
+
+                Some(InboundPacket::Pen {
+                    event_type,
+                    tool_type,
+                    buttons,
+                    x,
+                    y,
+                    pressure_or_distance,
+                    contact_area_major,
+                    contact_area_minor,
+                    rotation,
+                    tilt,
+                })
+            }
+            TransportChannel(TransportChannelId::CLIPBOARD) => {
+                if buffer.remaining() < 1 {
+                    warn!("[InboudPacket]: failed to read clipboard message");
+                    return None;
+                }
+
+                let ty = buffer.get_u8();
+                if ty == 0 {
+                    // Text, the rest of the message
+                    let Ok(text) = str::from_utf8(&bytes[1..]) else {
+                        warn!("[InboundPacket]: received invalid clipboard text");
+                        return None;
+                    };
+
+                    Some(InboundPacket::Clipboard {
+                        text: text.to_owned(),
+                    })
+                } else {
+                    warn!(
+                        "[InboundPacket]: tried to deserialize clipboard packet with type {ty}, this shouldn't happen"
+                    );
+                    None
+                }
+            }
             TransportChannel(TransportChannelId::CONTROLLERS) => {
                 if buffer.remaining() < 1 {
                     warn!("[InboudPacket]: failed to read controller message");
@@ -460,6 +568,95 @@ impl InboundPacket {
                         right_stick_x,
                         right_stick_y,
                     })
+                } else if ty == 1 {
+                    // Motion (accelerometer / gyroscope)
+                    if buffer.remaining() < 13 {
+                        warn!(
+                            "[InboudPacket]: failed to read controller motion message {channel_id}, gamepad: {gamepad_id}"
+                        );
+                        return None;
+                    }
+
+                    let Some(motion_type) = MotionType::from_bits(buffer.get_u8())
+                        .filter(|motion_type| !motion_type.is_empty())
+                    else {
+                        warn!(
+                            "[InboundPacket]: received invalid motion type for controller {gamepad_id}"
+                        );
+                        return None;
+                    };
+
+                    let x = buffer.get_f32();
+                    let y = buffer.get_f32();
+                    let z = buffer.get_f32();
+
+                    Some(InboundPacket::ControllerMotion {
+                        id: gamepad_id as u8,
+                        motion_type,
+                        x,
+                        y,
+                        z,
+                    })
+                } else if ty == 2 {
+                    // Touchpad contact
+                    if buffer.remaining() < 17 {
+                        warn!(
+                            "[InboudPacket]: failed to read controller touch message {channel_id}, gamepad: {gamepad_id}"
+                        );
+                        return None;
+                    }
+
+                    let event_type = match buffer.get_u8() {
+                        0 => TouchEventType::Down,
+                        1 => TouchEventType::Move,
+                        2 => TouchEventType::Cancel,
+                        3 => TouchEventType::Up,
+                        _ => {
+                            warn!(
+                                "[InboundPacket]: received invalid touch event type for controller {gamepad_id}"
+                            );
+                            return None;
+                        }
+                    };
+                    let pointer_id = buffer.get_u32();
+                    let x = buffer.get_f32();
+                    let y = buffer.get_f32();
+                    let pressure = buffer.get_f32();
+
+                    Some(InboundPacket::ControllerTouch {
+                        id: gamepad_id as u8,
+                        event_type,
+                        pointer_id,
+                        x,
+                        y,
+                        pressure,
+                    })
+                } else if ty == 3 {
+                    // Battery
+                    if buffer.remaining() < 2 {
+                        warn!(
+                            "[InboudPacket]: failed to read controller battery message {channel_id}, gamepad: {gamepad_id}"
+                        );
+                        return None;
+                    }
+
+                    let Some(battery_state) = BatteryState::from_bits(buffer.get_u8()) else {
+                        warn!(
+                            "[InboundPacket]: received invalid battery state for controller {gamepad_id}"
+                        );
+                        return None;
+                    };
+                    // 0xFF: the percentage is unknown
+                    let battery_percentage = match buffer.get_u8() {
+                        percentage @ 0..=100 => Some(percentage),
+                        _ => None,
+                    };
+
+                    Some(InboundPacket::ControllerBattery {
+                        id: gamepad_id as u8,
+                        battery_state,
+                        battery_percentage,
+                    })
                 } else {
                     warn!(
                         "[InboundPacket]: tried to deserialize controller {gamepad_id} packet with type {ty}, this shouldn't happen"
@@ -505,6 +702,31 @@ pub enum OutboundPacket {
         left_trigger_motor: u16,
         right_trigger_motor: u16,
     },
+    /// The host wants motion sensor reports of this type at the rate, a rate of 0 stops the reports
+    ControllerMotionEventState {
+        controller_number: u8,
+        motion_type: u8,
+        report_rate_hz: u16,
+    },
+    /// DualSense adaptive trigger effects, the flags tell which triggers get updated
+    ControllerAdaptiveTriggers {
+        controller_number: u8,
+        event_flags: DualSenseEffect,
+        type_left: u8,
+        type_right: u8,
+        left: [u8; DUAL_SENSE_EFFECT_PAYLOAD_SIZE],
+        right: [u8; DUAL_SENSE_EFFECT_PAYLOAD_SIZE],
+    },
+    ControllerLed {
+        controller_number: u8,
+        r: u8,
+        g: u8,
+        b: u8,
+    },
+    /// The clipboard of the host changed
+    Clipboard {
+        text: String,
+    },
     Rtt {
         sequence_number: u16,
     },
@@ -587,6 +809,87 @@ impl OutboundPacket {
                     buffer.into_raw().1,
                 ))
             }
+            Self::ControllerMotionEventState {
+                controller_number,
+                motion_type,
+                report_rate_hz,
+            } => {
+                raw_buffer.resize(5, 0);
+                let mut buffer = ByteBuffer::new(raw_buffer as &mut [u8]);
+
+                // Requires 5 bytes
+                buffer.put_u8(2);
+                buffer.put_u8(*controller_number);
+                buffer.put_u8(*motion_type);
+                buffer.put_u16(*report_rate_hz);
+
+                buffer.flip();
+                Some((
+                    TransportChannel(TransportChannelId::CONTROLLER0 + controller_number),
+                    buffer.into_raw().1,
+                ))
+            }
+            Self::ControllerAdaptiveTriggers {
+                controller_number,
+                event_flags,
+                type_left,
+                type_right,
+                left,
+                right,
+            } => {
+                raw_buffer.resize(5 + 2 * DUAL_SENSE_EFFECT_PAYLOAD_SIZE, 0);
+                let mut buffer = ByteBuffer::new(raw_buffer as &mut [u8]);
+
+                // Requires 25 bytes
+                buffer.put_u8(3);
+                buffer.put_u8(*controller_number);
+                buffer.put_u8(event_flags.bits() as u8);
+                buffer.put_u8(*type_left);
+                buffer.put_u8(*type_right);
+                buffer.put_u8_array(left);
+                buffer.put_u8_array(right);
+
+                buffer.flip();
+                Some((
+                    TransportChannel(TransportChannelId::CONTROLLER0 + controller_number),
+                    buffer.into_raw().1,
+                ))
+            }
+            Self::ControllerLed {
+                controller_number,
+                r,
+                g,
+                b,
+            } => {
+                raw_buffer.resize(5, 0);
+                let mut buffer = ByteBuffer::new(raw_buffer as &mut [u8]);
+
+                // Requires 5 bytes
+                buffer.put_u8(4);
+                buffer.put_u8(*controller_number);
+                buffer.put_u8(*r);
+                buffer.put_u8(*g);
+                buffer.put_u8(*b);
+
+                buffer.flip();
+                Some((
+                    TransportChannel(TransportChannelId::CONTROLLER0 + controller_number),
+                    buffer.into_raw().1,
+                ))
+            }
+            Self::Clipboard { text } => {
+                raw_buffer.resize(text.len() + 1, 0);
+                let mut buffer = ByteBuffer::new(raw_buffer as &mut [u8]);
+
+                buffer.put_u8(0);
+                buffer.put_u8_array(text.as_bytes());
+
+                buffer.flip();
+                Some((
+                    TransportChannel(TransportChannelId::CLIPBOARD),
+                    buffer.into_raw().1,
+                ))
+            }
             Self::Rtt { sequence_number } => {
                 raw_buffer.resize(3, 0);
                 let mut buffer = ByteBuffer::new(raw_buffer as &mut [u8]);
@@ -0,0 +1,56 @@
+use async_trait::async_trait;
+use common::ipc::ServerIpcMessage;
+use moonlight_common::stream::{
+    bindings::{AudioConfig, DecodeResult, OpusMultistreamConfig, VideoDecodeUnit},
+    video::VideoSetup,
+};
+
+use crate::transport::{OutboundPacket, TransportError, TransportSender};
+
+/// Used by `streamer --replay`: there's no client, so the video, audio and packets of the host are dropped
+pub struct ReplayTransportSender;
+
+#[async_trait]
+impl TransportSender for ReplayTransportSender {
+    async fn setup_video(&self, _setup: VideoSetup) -> i32 {
+        0
+    }
+    async fn send_video_unit<'a>(
+        &'a self,
+        _unit: &'a VideoDecodeUnit<'a>,
+    ) -> Result<DecodeResult, TransportError> {
+        Ok(DecodeResult::Ok)
+    }
+
+    async fn send_h264_annexb(
+        &self,
+        _data: &[u8],
+        _rtp_timestamp: u32,
+        _is_keyframe: bool,
+    ) -> Result<(), TransportError> {
+        Ok(())
+    }
+
+    async fn setup_audio(
+        &self,
+        _audio_config: AudioConfig,
+        _stream_config: OpusMultistreamConfig,
+    ) -> i32 {
+        0
+    }
+    async fn send_audio_sample(&self, _data: &[u8]) -> Result<(), TransportError> {
+        Ok(())
+    }
+
+    async fn send(&self, _packet: OutboundPacket) -> Result<(), TransportError> {
+        Ok(())
+    }
+
+    async fn on_ipc_message(&self, _message: ServerIpcMessage) -> Result<(), TransportError> {
+        Ok(())
+    }
+
+    async fn close(&self) -> Result<(), TransportError> {
+        Ok(())
+    }
+}
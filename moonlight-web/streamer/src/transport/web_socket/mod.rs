@@ -32,13 +32,16 @@ use tokio::{
 
 use crate::{
     buffer::ByteBuffer,
+    input_record::InputRecorder,
     transport::{
         InboundPacket, OutboundPacket, TransportChannel, TransportError, TransportEvent,
         TransportEvents, TransportSender,
     },
 };
 
-pub async fn new() -> Result<(WebSocketTransportSender, WebSocketTransportEvents), anyhow::Error> {
+pub async fn new(
+    input_recorder: Option<InputRecorder>,
+) -> Result<(WebSocketTransportSender, WebSocketTransportEvents), anyhow::Error> {
     let (event_sender, event_receiver) = channel::<TransportEvent>(20);
 
     // TODO: use the video_frame_queue_size with packet rtt info to estimate latency of pictures and request idr if too big
@@ -47,6 +50,7 @@ pub async fn new() -> Result<(WebSocketTransportSender, WebSocketTransportEvents
         event_sender,
         rtt: Arc::new(Mutex::new((Instant::now(), 0))),
         needs_idr: AtomicBool::new(false),
+        input_recorder,
     };
 
     // This will start the loop of sending / receiving
@@ -75,6 +79,7 @@ pub struct WebSocketTransportSender {
     /// Time when it was sent, sequence_number
     rtt: Arc<Mutex<(Instant, u16)>>,
     needs_idr: AtomicBool,
+    input_recorder: Option<InputRecorder>,
 }
 
 async fn send_packet(
@@ -259,6 +264,10 @@ impl TransportSender for WebSocketTransportSender {
 
                 let channel_id = message[0];
 
+                if let Some(input_recorder) = &self.input_recorder {
+                    input_recorder.record(TransportChannel(channel_id), &message[1..]);
+                }
+
                 let Some(packet) =
                     InboundPacket::deserialize(TransportChannel(channel_id), &message[1..])
                 else {
@@ -299,6 +308,9 @@ impl TransportSender for WebSocketTransportSender {
                 video_color_range_full,
                 hdr,
                 reencode,
+                gamepads_attached,
+                gamepads_persist,
+                horizontal_scroll,
             }) => {
                 let video_supported_formats = SupportedVideoFormats::from_bits(video_supported_formats).unwrap_or_else(|| {
                     warn!("Failed to deserialize SupportedVideoFormats: {video_supported_formats}, falling back to only H264");
@@ -320,6 +332,9 @@ impl TransportSender for WebSocketTransportSender {
                             play_audio_local,
                             hdr,
                             reencode,
+                            gamepads_attached,
+                            gamepads_persist,
+                            horizontal_scroll,
                         },
                     })
                     .await
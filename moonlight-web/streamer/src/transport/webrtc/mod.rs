@@ -1,4 +1,5 @@
 use std::{
+    collections::HashMap,
     future::ready,
     pin::Pin,
     sync::{Arc, Weak},
@@ -57,6 +58,7 @@ use crate::{
     convert::{
         from_webrtc_sdp, into_webrtc_ice, into_webrtc_ice_candidate, into_webrtc_network_type,
     },
+    input_record::InputRecorder,
     transport::{
         InboundPacket, OutboundPacket, TransportChannel, TransportError, TransportEvent,
         TransportEvents, TransportSender,
@@ -77,6 +79,10 @@ struct WebRtcInner {
     event_sender: Sender<TransportEvent>,
     general_channel: Arc<RTCDataChannel>,
     stats_channel: Mutex<Option<Arc<RTCDataChannel>>>,
+    /// The per gamepad channels, used to send rumble and sensor requests back to the gamepad
+    controller_channels: Mutex<HashMap<u8, Arc<RTCDataChannel>>>,
+    clipboard_channel: Mutex<Option<Arc<RTCDataChannel>>>,
+    input_recorder: Option<InputRecorder>,
     video: Mutex<WebRtcVideo>,
     audio: Mutex<WebRtcAudio>,
     // Timeout / Terminate
@@ -87,6 +93,7 @@ pub async fn new(
     config: &WebRtcConfig,
     video_frame_queue_size: usize,
     audio_sample_queue_size: usize,
+    input_recorder: Option<InputRecorder>,
 ) -> Result<(WebRTCTransportSender, WebRTCTransportEvents), anyhow::Error> {
     // -- Configure WebRTC
     let rtc_config = RTCConfiguration {
@@ -159,6 +166,9 @@ pub async fn new(
         event_sender,
         general_channel: general_channel.clone(),
         stats_channel: Mutex::new(None),
+        controller_channels: Mutex::new(HashMap::new()),
+        clipboard_channel: Mutex::new(None),
+        input_recorder,
         video: Mutex::new(WebRtcVideo::new(
             runtime.clone(),
             Arc::downgrade(&peer),
@@ -264,6 +274,10 @@ fn create_channel_message_handler(
 > {
     debug!("setting up channel {:?}", channel);
     create_event_handler(inner, async move |inner, message: DataChannelMessage| {
+        if let Some(input_recorder) = &inner.input_recorder {
+            input_recorder.record(channel, &message.data);
+        }
+
         let Some(packet) = InboundPacket::deserialize(channel, &message.data) else {
             return;
         };
@@ -394,6 +408,9 @@ impl WebRtcInner {
                 video_color_range_full,
                 hdr,
                 reencode,
+                gamepads_attached,
+                gamepads_persist,
+                horizontal_scroll,
             } => {
                 let video_supported_formats = SupportedVideoFormats::from_bits(video_supported_formats).unwrap_or_else(|| {
                     warn!("Failed to deserialize SupportedVideoFormats: {video_supported_formats}, falling back to only H264");
@@ -421,6 +438,9 @@ impl WebRtcInner {
                             play_audio_local,
                             hdr,
                             reencode,
+                            gamepads_attached,
+                            gamepads_persist,
+                            horizontal_scroll,
                         },
                     })
                     .await
@@ -561,6 +581,21 @@ impl WebRtcInner {
                     TransportChannel(TransportChannelId::TOUCH),
                 ));
             }
+            "pen" => {
+                channel.on_message(create_channel_message_handler(
+                    inner,
+                    TransportChannel(TransportChannelId::PEN),
+                ));
+            }
+            "clipboard" => {
+                channel.on_message(create_channel_message_handler(
+                    inner,
+                    TransportChannel(TransportChannelId::CLIPBOARD),
+                ));
+
+                let mut clipboard = self.clipboard_channel.lock().await;
+                *clipboard = Some(channel);
+            }
             "keyboard" => {
                 channel.on_message(create_channel_message_handler(
                     inner,
@@ -577,10 +612,15 @@ impl WebRtcInner {
                 && let Ok(id) = number.parse::<usize>()
                 && id < InboundPacket::CONTROLLER_CHANNELS.len() =>
             {
+                let channel_id = InboundPacket::CONTROLLER_CHANNELS[id];
+
                 channel.on_message(create_channel_message_handler(
                     inner,
-                    TransportChannel(InboundPacket::CONTROLLER_CHANNELS[id]),
+                    TransportChannel(channel_id),
                 ));
+
+                let mut controller_channels = self.controller_channels.lock().await;
+                controller_channels.insert(channel_id, channel);
             }
             _ => {}
         };
@@ -719,6 +759,34 @@ impl TransportSender for WebRTCTransportSender {
                     return Err(TransportError::ChannelClosed);
                 }
             }
+            TransportChannelId::CLIPBOARD => {
+                let clipboard = self.inner.clipboard_channel.lock().await;
+                let Some(clipboard) = clipboard.as_ref() else {
+                    return Err(TransportError::ChannelClosed);
+                };
+
+                match clipboard.send(&bytes).await {
+                    Ok(_) => {}
+                    Err(webrtc::Error::ErrDataChannelNotOpen) => {
+                        return Err(TransportError::ChannelClosed);
+                    }
+                    _ => {}
+                }
+            }
+            channel_id if InboundPacket::CONTROLLER_CHANNELS.contains(&channel_id) => {
+                let controller_channels = self.inner.controller_channels.lock().await;
+                let Some(controller_channel) = controller_channels.get(&channel_id) else {
+                    return Err(TransportError::ChannelClosed);
+                };
+
+                match controller_channel.send(&bytes).await {
+                    Ok(_) => {}
+                    Err(webrtc::Error::ErrDataChannelNotOpen) => {
+                        return Err(TransportError::ChannelClosed);
+                    }
+                    _ => {}
+                }
+            }
             _ => {
                 warn!("Cannot send data on channel {channel:?}");
                 return Err(TransportError::ChannelClosed);
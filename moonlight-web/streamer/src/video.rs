@@ -1,9 +1,12 @@
 use std::{
-    sync::{Arc, Weak},
+    sync::{Arc, Weak, atomic::Ordering},
     time::{Duration, Instant},
 };
 
-use common::api_bindings::{ReencodeCodec, StatsHostProcessingLatency, StreamerStatsUpdate};
+use common::{
+    api_bindings::{ReencodeCodec, StatsHostProcessingLatency, StreamerStatsUpdate},
+    ipc::StreamerIpcMessage,
+};
 use log::{debug, error, info, warn};
 use moonlight_common::stream::{
     bindings::{
@@ -110,6 +113,8 @@ impl VideoDecoder for StreamVideoDecoder {
         if tokio::runtime::Handle::try_current().is_ok() {
             tokio::task::block_in_place(|| {
                 stream.runtime.clone().block_on(async move {
+                    stream.spectators_setup_video(setup).await;
+
                     let mut sender = stream.transport_sender.lock().await;
 
                     if let Some(sender) = sender.as_mut() {
@@ -122,6 +127,8 @@ impl VideoDecoder for StreamVideoDecoder {
             })
         } else {
             stream.runtime.clone().block_on(async move {
+                stream.spectators_setup_video(setup).await;
+
                 let mut sender = stream.transport_sender.lock().await;
 
                 if let Some(sender) = sender.as_mut() {
@@ -270,6 +277,13 @@ impl VideoDecoder for StreamVideoDecoder {
                                                     {
                                                         warn!("Failed to send transcoded frame: {err}");
                                                     }
+                                                    stream
+                                                        .spectators_send_h264_annexb(
+                                                            &data,
+                                                            unit.rtp_timestamp,
+                                                            keyframe || matches!(unit.frame_type, FrameType::Idr),
+                                                        )
+                                                        .await;
                                                 }
                                                 did_transcode = true;
                                             }
@@ -285,6 +299,7 @@ impl VideoDecoder for StreamVideoDecoder {
 
                                 if !did_transcode {
                                     // Fallback to passthrough if transcode not ready
+                                    stream.spectators_send_video_unit(&unit).await;
                                     match sender.send_video_unit(&unit).await {
                                         Err(err) => {
                                             warn!("Failed to send video decode unit: {err}");
@@ -298,12 +313,18 @@ impl VideoDecoder for StreamVideoDecoder {
                                 (DecodeResult::Ok, outgoing_bytes)
                             }
                         } else {
+                            let spectators_result = stream.spectators_send_video_unit(&unit).await;
+
                             match sender.send_video_unit(&unit).await {
                                 Err(err) => {
                                     warn!("Failed to send video decode unit: {err}");
-                                    (DecodeResult::Ok, incoming_bytes)
+                                    (spectators_result, incoming_bytes)
                                 }
-                                Ok(value) => (value, incoming_bytes),
+                                Ok(DecodeResult::NeedIdr) => (DecodeResult::NeedIdr, incoming_bytes),
+                                Ok(_) if stream.owner_needs_idr.swap(false, Ordering::AcqRel) => {
+                                    (DecodeResult::NeedIdr, incoming_bytes)
+                                }
+                                Ok(_) => (spectators_result, incoming_bytes),
                             }
                         };
 
@@ -444,6 +465,13 @@ impl VideoDecoder for StreamVideoDecoder {
                                                 {
                                                     warn!("Failed to send transcoded frame: {err}");
                                                 }
+                                                stream
+                                                    .spectators_send_h264_annexb(
+                                                        &data,
+                                                        unit.rtp_timestamp,
+                                                        keyframe || matches!(unit.frame_type, FrameType::Idr),
+                                                    )
+                                                    .await;
                                             }
                                             did_transcode = true;
                                         }
@@ -459,6 +487,7 @@ impl VideoDecoder for StreamVideoDecoder {
 
                             if !did_transcode {
                                 // Fallback to passthrough if transcode not ready
+                                stream.spectators_send_video_unit(&unit).await;
                                 match sender.send_video_unit(&unit).await {
                                     Err(err) => {
                                         warn!("Failed to send video decode unit: {err}");
@@ -472,12 +501,18 @@ impl VideoDecoder for StreamVideoDecoder {
                             (DecodeResult::Ok, outgoing_bytes)
                         }
                     } else {
+                        let spectators_result = stream.spectators_send_video_unit(&unit).await;
+
                         match sender.send_video_unit(&unit).await {
                             Err(err) => {
                                 warn!("Failed to send video decode unit: {err}");
-                                (DecodeResult::Ok, incoming_bytes)
+                                (spectators_result, incoming_bytes)
+                            }
+                            Ok(DecodeResult::NeedIdr) => (DecodeResult::NeedIdr, incoming_bytes),
+                            Ok(_) if stream.owner_needs_idr.swap(false, Ordering::AcqRel) => {
+                                (DecodeResult::NeedIdr, incoming_bytes)
                             }
-                            Ok(value) => (value, incoming_bytes),
+                            Ok(_) => (spectators_result, incoming_bytes),
                         }
                     };
 
@@ -672,6 +707,14 @@ impl VideoStats {
                     )
                     .await;
 
+                let mut ipc_sender = stream.ipc_sender.clone();
+                ipc_sender
+                    .send(StreamerIpcMessage::Stats(StreamerStatsUpdate::Bandwidth {
+                        incoming_kbps,
+                        outgoing_kbps,
+                    }))
+                    .await;
+
                 // Send RTT info
                 let ml_stream_lock = stream.stream.read().await;
                 if let Some(ml_stream) = ml_stream_lock.as_ref() {
@@ -680,6 +723,13 @@ impl VideoStats {
 
                     match rtt {
                         Ok(EstimatedRttInfo { rtt, rtt_variance }) => {
+                            ipc_sender
+                                .send(StreamerIpcMessage::Stats(StreamerStatsUpdate::Rtt {
+                                    rtt_ms: rtt.as_secs_f64() * 1000.0,
+                                    rtt_variance_ms: rtt_variance.as_secs_f64() * 1000.0,
+                                }))
+                                .await;
+
                             stream
                                 .try_send_packet(
                                     OutboundPacket::Stats(StreamerStatsUpdate::Rtt {
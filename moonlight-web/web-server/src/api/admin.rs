@@ -3,15 +3,19 @@ use actix_web::{
     web::{Data, Json},
 };
 use common::api_bindings::{
-    DeleteUserRequest, DetailedUser, GetUsersResponse, PatchUserRequest, PostUserRequest,
+    DeleteGroupRequest, DeleteStreamSessionRequest, DeleteUserRequest, DetailedGroup, DetailedUser,
+    GetGroupsResponse, GetStreamSessionsResponse, GetUsersResponse, PatchGroupRequest,
+    PatchUserRequest, PostGroupRequest, PostUserRequest,
 };
 use futures::future::join_all;
 use log::warn;
 
 use crate::app::{
     App, AppError,
+    group::GroupId,
+    host::HostId,
     password::StoragePassword,
-    storage::{StorageUserAdd, StorageUserModify},
+    storage::{StorageGroupAdd, StorageGroupModify, StorageUserAdd, StorageUserModify},
     user::{Admin, AuthenticatedUser, Role, UserId},
 };
 
@@ -29,6 +33,8 @@ pub async fn add_user(
                 password: Some(StoragePassword::new(&request.password)?),
                 role: request.role.into(),
                 client_unique_id: request.client_unique_id,
+                allow_clipboard: false,
+                oidc: None,
             },
         )
         .await?;
@@ -56,16 +62,30 @@ pub async fn patch_user(
                 None
             };
 
+            // Whoever was logged in with the old credentials or privileges must log in again
+            let revoke_sessions = new_password.is_some() || request.role.is_some();
+
             target_user
                 .modify(
                     &admin,
                     StorageUserModify {
-                        password: Some(new_password),
+                        password: new_password.map(Some),
                         role: request.role.map(Role::from),
                         client_unique_id: request.client_unique_id,
+                        allow_clipboard: request.allow_clipboard,
+                        totp: None,
+                        permissions: request.permissions,
                     },
                 )
                 .await?;
+
+            if request.reset_totp == Some(true) {
+                target_user.reset_totp(&admin).await?;
+            }
+
+            if revoke_sessions {
+                target_user.revoke_login_sessions(&admin).await?;
+            }
         }
         Err(mut user) => {
             if user.id() != target_user_id {
@@ -78,8 +98,16 @@ pub async fn patch_user(
                 password: _,
                 role,
                 client_unique_id,
+                allow_clipboard,
+                reset_totp,
+                permissions,
             } = &request;
-            if role.is_some() || client_unique_id.is_some() {
+            if role.is_some()
+                || client_unique_id.is_some()
+                || allow_clipboard.is_some()
+                || reset_totp.is_some()
+                || permissions.is_some()
+            {
                 return Err(AppError::Forbidden);
             }
 
@@ -128,3 +156,85 @@ pub async fn list_users(app: Data<App>, admin: Admin) -> Result<Json<GetUsersRes
 
     Ok(Json(GetUsersResponse { users: out_users }))
 }
+
+#[post("/group")]
+pub async fn add_group(
+    app: Data<App>,
+    admin: Admin,
+    Json(request): Json<PostGroupRequest>,
+) -> Result<Json<DetailedGroup>, AppError> {
+    let group = app
+        .add_group(&admin, StorageGroupAdd { name: request.name })
+        .await?;
+
+    Ok(Json(group.into()))
+}
+
+#[patch("/group")]
+pub async fn patch_group(
+    app: Data<App>,
+    admin: Admin,
+    Json(request): Json<PatchGroupRequest>,
+) -> Result<HttpResponse, AppError> {
+    app.modify_group(
+        &admin,
+        GroupId(request.id),
+        StorageGroupModify {
+            name: request.name,
+            members: request
+                .members
+                .map(|members| members.into_iter().map(UserId).collect()),
+            hosts: request
+                .hosts
+                .map(|hosts| hosts.into_iter().map(HostId).collect()),
+        },
+    )
+    .await?;
+
+    Ok(HttpResponse::Ok().finish())
+}
+
+#[delete("/group")]
+pub async fn delete_group(
+    app: Data<App>,
+    admin: Admin,
+    Json(request): Json<DeleteGroupRequest>,
+) -> Result<HttpResponse, AppError> {
+    app.remove_group(&admin, GroupId(request.id)).await?;
+
+    Ok(HttpResponse::Ok().finish())
+}
+
+#[get("/groups")]
+pub async fn list_groups(
+    app: Data<App>,
+    admin: Admin,
+) -> Result<Json<GetGroupsResponse>, AppError> {
+    let groups = app.all_groups(&admin).await?;
+
+    Ok(Json(GetGroupsResponse {
+        groups: groups.into_iter().map(DetailedGroup::from).collect(),
+    }))
+}
+
+#[get("/admin/sessions")]
+pub async fn list_stream_sessions(
+    app: Data<App>,
+    admin: Admin,
+) -> Result<Json<GetStreamSessionsResponse>, AppError> {
+    let sessions = app.stream_sessions(&admin).await;
+
+    Ok(Json(GetStreamSessionsResponse { sessions }))
+}
+
+#[delete("/admin/session")]
+pub async fn delete_stream_session(
+    app: Data<App>,
+    admin: Admin,
+    Json(request): Json<DeleteStreamSessionRequest>,
+) -> Result<HttpResponse, AppError> {
+    app.stop_stream(&admin, UserId(request.user_id), HostId(request.host_id))
+        .await?;
+
+    Ok(HttpResponse::Ok().finish())
+}
@@ -2,20 +2,30 @@ use actix_web::{
     Error, FromRequest, HttpRequest, HttpResponse,
     body::MessageBody,
     cookie::{Cookie, Expiration, SameSite, time::OffsetDateTime},
+    delete,
     dev::{Payload, ServiceRequest, ServiceResponse},
     get,
+    http::header::{LOCATION, USER_AGENT},
     middleware::Next,
     post,
-    web::{Data, Json},
+    web::{Data, Json, Query},
+};
+use common::api_bindings::{
+    ApiTokenInfo, DeleteApiTokenRequest, DeleteLoginSessionRequest, DeleteTotpRequest,
+    GetApiTokensResponse, GetLoginSessionsResponse, LoginSession, PostApiTokenRequest,
+    PostApiTokenResponse, PostLoginRequest, PostTotpConfirmRequest, PostTotpConfirmResponse,
+    PostTotpResponse,
 };
-use common::api_bindings::PostLoginRequest;
 use futures::future::{Ready, ready};
-use std::{pin::Pin, time::Duration};
+use log::warn;
+use serde::Deserialize;
+use std::{net::IpAddr, pin::Pin, time::Duration};
 
 use crate::app::{
     App, AppError,
-    auth::{SessionToken, UserAuth},
-    user::{Admin, AuthenticatedUser},
+    auth::{API_TOKEN_PREFIX, ApiToken, SessionToken, UserAuth},
+    storage::{StorageApiToken, StorageApiTokenAdd, StorageSessionAdd},
+    user::{Admin, AuthenticatedUser, RequiredScope, ScopedUser},
 };
 
 pub const COOKIE_SESSION_TOKEN_NAME: &str = "mlSession";
@@ -56,19 +66,57 @@ fn extract_user_auth(req: &HttpRequest) -> Result<UserAuth, AppError> {
             .ok_or(AppError::AuthorizationNotBearer)?
             .trim();
 
+        if let Some(token_str) = token_str.strip_prefix(API_TOKEN_PREFIX) {
+            let token = ApiToken::decode(token_str)?;
+
+            return Ok(UserAuth::ApiToken { token });
+        }
+
         let token = SessionToken::decode(token_str)?;
 
-        Ok(UserAuth::Session(token))
+        Ok(UserAuth::Session {
+            token,
+            ip: client_ip(req),
+        })
     } else if let Some(cookie) = req.cookie(COOKIE_SESSION_TOKEN_NAME) {
         // Look for cookie
         let token = SessionToken::decode(cookie.value())?;
 
-        Ok(UserAuth::Session(token))
+        Ok(UserAuth::Session {
+            token,
+            ip: client_ip(req),
+        })
     } else {
         Ok(UserAuth::None)
     }
 }
 
+/// The ip of the client, behind a reverse proxy this is the ip the proxy forwarded
+fn client_ip(req: &HttpRequest) -> Option<IpAddr> {
+    let forwarded_ip = req
+        .app_data::<Data<App>>()
+        .and_then(|app| app.config().web_server.forwarded_header.as_ref())
+        .and_then(|header_auth| header_auth.client_ip_header.as_ref())
+        .and_then(|client_ip_header| req.headers().get(client_ip_header))
+        .and_then(|value| value.to_str().ok())
+        // The proxy appends the ip it sees, everything before that could be sent by the client
+        .and_then(|value| value.rsplit(',').next())
+        .and_then(|ip| ip.trim().parse().ok());
+
+    forwarded_ip.or_else(|| req.peer_addr().map(|addr| addr.ip()))
+}
+
+fn session_add(req: &HttpRequest) -> StorageSessionAdd {
+    StorageSessionAdd {
+        user_agent: req
+            .headers()
+            .get(USER_AGENT)
+            .and_then(|user_agent| user_agent.to_str().ok())
+            .map(str::to_string),
+        ip: client_ip(req),
+    }
+}
+
 impl FromRequest for AuthenticatedUser {
     type Error = AppError;
 
@@ -86,13 +134,50 @@ impl FromRequest for AuthenticatedUser {
         Box::pin(async move {
             let auth = auth_future.await?;
 
-            let user = app.user_by_auth(auth).await?;
+            let mut user = app.user_by_auth(auth).await?;
+            user.check_totp_required().await?;
 
             Ok(user)
         })
     }
 }
 
+impl<S: RequiredScope + 'static> FromRequest for ScopedUser<S> {
+    type Error = AppError;
+
+    type Future = Pin<Box<dyn Future<Output = Result<Self, Self::Error>>>>;
+
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        let app = match req.app_data::<Data<App>>() {
+            None => return Box::pin(ready(Err(AppError::AppDestroyed))),
+            Some(value) => value,
+        };
+
+        let auth_future = UserAuth::from_request(req, payload);
+
+        let app = app.clone();
+        Box::pin(async move {
+            let auth = auth_future.await?;
+
+            match auth {
+                UserAuth::ApiToken { token } => {
+                    let (mut user, scopes) = app.user_by_api_token(token).await?;
+                    // Tokens that were created before two factor authentication was required stop working until it's set up
+                    user.check_totp_required().await?;
+
+                    ScopedUser::new(user, Some(&scopes))
+                }
+                auth => {
+                    let mut user = app.user_by_auth(auth).await?;
+                    user.check_totp_required().await?;
+
+                    ScopedUser::new(user, None)
+                }
+            }
+        })
+    }
+}
+
 impl FromRequest for Admin {
     type Error = AppError;
 
@@ -112,34 +197,23 @@ impl FromRequest for Admin {
 #[post("/login")]
 async fn login(
     app: Data<App>,
+    req: HttpRequest,
     Json(request): Json<PostLoginRequest>,
 ) -> Result<HttpResponse, Error> {
-    let user = if app.config().web_server.first_login_create_admin {
-        match app
-            .try_add_first_login(request.name.clone(), request.password.clone())
-            .await
-        {
-            Ok(user) => user,
-            Err(AppError::FirstUserAlreadyExists) => {
-                app.user_by_auth(UserAuth::UserPassword {
-                    username: request.name,
-                    password: request.password,
-                })
-                .await?
-            }
-            Err(err) => return Err(err.into()),
-        }
-    } else {
-        app.user_by_auth(UserAuth::UserPassword {
-            username: request.name,
-            password: request.password,
-        })
-        .await?
-    };
+    let user = app
+        .user_by_login(
+            request.name,
+            request.password,
+            request.totp_code,
+            client_ip(&req),
+        )
+        .await?;
 
     let session_expiration = app.config().web_server.session_cookie_expiration;
 
-    let session = user.new_session(session_expiration).await?;
+    let session = user
+        .new_session(session_expiration, session_add(&req))
+        .await?;
     let mut session_bytes = [0; _];
     let session_str = session.encode(&mut session_bytes);
 
@@ -148,10 +222,61 @@ async fn login(
         .finish())
 }
 
+/// Redirects the browser to the openid connect issuer
+#[get("/oidc/login")]
+async fn oidc_login(app: Data<App>) -> Result<HttpResponse, AppError> {
+    let url = app.oidc_login_url().await?;
+
+    Ok(HttpResponse::Found()
+        .insert_header((LOCATION, url))
+        .finish())
+}
+
+#[derive(Deserialize)]
+struct OidcCallbackQuery {
+    state: String,
+    code: Option<String>,
+    error: Option<String>,
+}
+
+/// The issuer redirects the browser back to this after the login
+#[get("/oidc/callback")]
+async fn oidc_callback(
+    app: Data<App>,
+    req: HttpRequest,
+    Query(query): Query<OidcCallbackQuery>,
+) -> Result<HttpResponse, Error> {
+    let Some(code) = query.code else {
+        warn!(
+            "openid connect login was rejected by the issuer: {}",
+            query.error.as_deref().unwrap_or("unknown error")
+        );
+        return Err(AppError::Unauthorized.into());
+    };
+
+    let user = app.user_by_oidc(&query.state, &code).await?;
+
+    let session_expiration = app.config().web_server.session_cookie_expiration;
+
+    let session = user
+        .new_session(session_expiration, session_add(&req))
+        .await?;
+    let mut session_bytes = [0; _];
+    let session_str = session.encode(&mut session_bytes);
+
+    Ok(HttpResponse::Found()
+        .cookie(build_cookie(&app, session_expiration, session_str))
+        .insert_header((
+            LOCATION,
+            format!("{}/", app.config().web_server.url_path_prefix),
+        ))
+        .finish())
+}
+
 #[post("/logout")]
 async fn logout(app: Data<App>, auth: UserAuth, req: HttpRequest) -> Result<HttpResponse, Error> {
     let session = match auth {
-        UserAuth::Session(session) => session,
+        UserAuth::Session { token, .. } => token,
         _ => return Ok(HttpResponse::BadRequest().finish()),
     };
 
@@ -166,6 +291,158 @@ async fn logout(app: Data<App>, auth: UserAuth, req: HttpRequest) -> Result<Http
     Ok(response)
 }
 
+#[get("/user/sessions")]
+async fn list_login_sessions(
+    user: AuthenticatedUser,
+    auth: UserAuth,
+) -> Result<Json<GetLoginSessionsResponse>, AppError> {
+    let current_hash = match auth {
+        UserAuth::Session { token, .. } => Some(token.hash()),
+        _ => None,
+    };
+
+    let sessions = user.login_sessions().await?;
+
+    Ok(Json(GetLoginSessionsResponse {
+        sessions: sessions
+            .into_iter()
+            .map(|session| LoginSession {
+                id: hex::encode(session.token_hash),
+                current: Some(session.token_hash) == current_hash,
+                created_at: session.created_at,
+                last_seen_at: session.last_seen_at,
+                expires_at: session.expires_at,
+                user_agent: session.user_agent,
+                last_seen_ip: session.last_seen_ip.map(|ip| ip.to_string()),
+            })
+            .collect(),
+    }))
+}
+
+#[delete("/user/session")]
+async fn delete_login_session(
+    user: AuthenticatedUser,
+    Json(request): Json<DeleteLoginSessionRequest>,
+) -> Result<HttpResponse, AppError> {
+    let mut token_hash = [0; 32];
+    hex::decode_to_slice(&request.id, &mut token_hash)?;
+
+    user.remove_login_session(token_hash).await?;
+
+    Ok(HttpResponse::Ok().finish())
+}
+
+/// Logs the user out everywhere, including the session of this request
+#[delete("/user/sessions")]
+async fn delete_all_login_sessions(
+    app: Data<App>,
+    user: AuthenticatedUser,
+    req: HttpRequest,
+) -> Result<HttpResponse, Error> {
+    user.remove_all_login_sessions().await?;
+
+    let mut response = HttpResponse::Ok().finish();
+
+    if req.cookie(COOKIE_SESSION_TOKEN_NAME).is_some() {
+        response.add_removal_cookie(&build_cookie(&app, Duration::ZERO, ""))?;
+    }
+
+    Ok(response)
+}
+
+// The two factor endpoints don't use AuthenticatedUser because it rejects users that must set it up first
+
+/// Starts the two factor setup, this replaces a setup that wasn't confirmed yet
+#[post("/user/totp")]
+async fn post_totp(app: Data<App>, auth: UserAuth) -> Result<Json<PostTotpResponse>, AppError> {
+    let mut user = app.user_by_auth(auth).await?;
+
+    let (totp, provisioning_uri) = user.start_totp().await?;
+
+    Ok(Json(PostTotpResponse {
+        secret: totp.secret_base32(),
+        provisioning_uri,
+    }))
+}
+
+#[post("/user/totp/confirm")]
+async fn confirm_totp(
+    app: Data<App>,
+    auth: UserAuth,
+    req: HttpRequest,
+    Json(request): Json<PostTotpConfirmRequest>,
+) -> Result<Json<PostTotpConfirmResponse>, AppError> {
+    let mut user = app.user_by_auth(auth).await?;
+
+    let recovery_codes = user.confirm_totp(&request.code, client_ip(&req)).await?;
+
+    Ok(Json(PostTotpConfirmResponse { recovery_codes }))
+}
+
+#[delete("/user/totp")]
+async fn delete_totp(
+    app: Data<App>,
+    auth: UserAuth,
+    req: HttpRequest,
+    Json(request): Json<DeleteTotpRequest>,
+) -> Result<HttpResponse, AppError> {
+    let mut user = app.user_by_auth(auth).await?;
+
+    user.disable_totp(&request.code, client_ip(&req)).await?;
+
+    Ok(HttpResponse::Ok().finish())
+}
+
+fn api_token_info(api_token: StorageApiToken) -> ApiTokenInfo {
+    ApiTokenInfo {
+        id: hex::encode(api_token.token_hash),
+        name: api_token.name,
+        scopes: api_token.scopes,
+        created_at: api_token.created_at,
+        last_used_at: api_token.last_used_at,
+    }
+}
+
+#[get("/user/api_tokens")]
+async fn list_api_tokens(user: AuthenticatedUser) -> Result<Json<GetApiTokensResponse>, AppError> {
+    let api_tokens = user.api_tokens().await?;
+
+    Ok(Json(GetApiTokensResponse {
+        tokens: api_tokens.into_iter().map(api_token_info).collect(),
+    }))
+}
+
+#[post("/user/api_token")]
+async fn post_api_token(
+    user: AuthenticatedUser,
+    Json(request): Json<PostApiTokenRequest>,
+) -> Result<Json<PostApiTokenResponse>, AppError> {
+    let (token, api_token) = user
+        .new_api_token(StorageApiTokenAdd {
+            name: request.name,
+            scopes: request.scopes,
+        })
+        .await?;
+
+    Ok(Json(PostApiTokenResponse {
+        token: token.encode(),
+        info: api_token_info(api_token),
+    }))
+}
+
+#[delete("/user/api_token")]
+async fn delete_api_token(
+    user: AuthenticatedUser,
+    Json(request): Json<DeleteApiTokenRequest>,
+) -> Result<HttpResponse, AppError> {
+    let mut token_hash = [0; 32];
+    hex::decode_to_slice(&request.id, &mut token_hash)?;
+
+    user.remove_api_token(token_hash).await?;
+
+    Ok(HttpResponse::Ok().finish())
+}
+
 pub async fn auth_middleware(
     req: ServiceRequest,
     next: Next<impl MessageBody>,
@@ -200,3 +477,114 @@ pub fn build_cookie<'a>(app: &'a App, expiration: Duration, session_str: &'a str
 async fn authenticate(_user: AuthenticatedUser) -> HttpResponse {
     HttpResponse::Ok().finish()
 }
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use actix_web::test::TestRequest;
+    use common::{
+        api_bindings::ApiTokenScope,
+        config::{Config, StorageConfig},
+    };
+
+    use crate::app::user::{ReadHosts, StartApps};
+
+    use super::*;
+
+    async fn app() -> Data<App> {
+        let app = App::new(Config {
+            data_storage: StorageConfig::Sqlite {
+                path: ":memory:".to_string(),
+                session_expiration_check_interval: Duration::from_secs(3600),
+                import_json: None,
+            },
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+        Data::new(app)
+    }
+
+    /// Creates the first user with an api token that only has the scopes
+    async fn api_token(
+        app: &App,
+        scopes: Vec<ApiTokenScope>,
+    ) -> (AuthenticatedUser, ApiToken, [u8; 32]) {
+        let user = app
+            .try_add_first_login("alice".to_string(), "password".to_string())
+            .await
+            .unwrap();
+
+        let (token, api_token) = user
+            .new_api_token(StorageApiTokenAdd {
+                name: "automation".to_string(),
+                scopes,
+            })
+            .await
+            .unwrap();
+
+        (user, token, api_token.token_hash)
+    }
+
+    fn bearer_request(app: &Data<App>, token: &ApiToken) -> HttpRequest {
+        TestRequest::default()
+            .app_data(app.clone())
+            .insert_header(("Authorization", format!("Bearer {}", token.encode())))
+            .to_http_request()
+    }
+
+    #[actix_web::test]
+    async fn test_api_token_scopes() {
+        let app = app().await;
+        let (_, token, _) = api_token(&app, vec![ApiTokenScope::ReadHosts]).await;
+        let req = bearer_request(&app, &token);
+
+        assert!(ScopedUser::<ReadHosts>::extract(&req).await.is_ok());
+        assert!(matches!(
+            ScopedUser::<StartApps>::extract(&req).await,
+            Err(AppError::ApiTokenNotAllowed)
+        ));
+    }
+
+    #[actix_web::test]
+    async fn test_api_token_rejected_without_scope() {
+        let app = app().await;
+        let (_, token, _) = api_token(
+            &app,
+            vec![
+                ApiTokenScope::ReadHosts,
+                ApiTokenScope::WakeHosts,
+                ApiTokenScope::StartApps,
+            ],
+        )
+        .await;
+        let req = bearer_request(&app, &token);
+
+        // Endpoints that don't opt in to api tokens reject them even with every scope
+        assert!(matches!(
+            AuthenticatedUser::extract(&req).await,
+            Err(AppError::ApiTokenNotAllowed)
+        ));
+        assert!(matches!(
+            Admin::extract(&req).await,
+            Err(AppError::ApiTokenNotAllowed)
+        ));
+    }
+
+    #[actix_web::test]
+    async fn test_api_token_revocation() {
+        let app = app().await;
+        let (user, token, token_hash) = api_token(&app, vec![ApiTokenScope::ReadHosts]).await;
+        let req = bearer_request(&app, &token);
+
+        assert!(ScopedUser::<ReadHosts>::extract(&req).await.is_ok());
+
+        user.remove_api_token(token_hash).await.unwrap();
+        assert!(matches!(
+            ScopedUser::<ReadHosts>::extract(&req).await,
+            Err(AppError::Unauthorized)
+        ));
+        assert!(user.api_tokens().await.unwrap().is_empty());
+    }
+}
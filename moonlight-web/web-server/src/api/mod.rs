@@ -15,7 +15,10 @@ use sha2::{Digest, Sha256};
 
 use crate::{
     api::{
-        admin::{add_user, delete_user, list_users, patch_user},
+        admin::{
+            add_group, add_user, delete_group, delete_stream_session, delete_user, list_groups,
+            list_stream_sessions, list_users, patch_group, patch_user,
+        },
         auth::auth_middleware,
         response_streaming::StreamedResponse,
     },
@@ -23,14 +26,15 @@ use crate::{
         App, AppError,
         host::{AppId, HostId},
         storage::StorageHostModify,
-        user::{AuthenticatedUser, Role, UserId},
+        user::{AuthenticatedUser, ReadHosts, Role, ScopedUser, UserId, WakeHosts},
     },
 };
 use common::api_bindings::{
-    self, DeleteHostQuery, DetailedUser, GetAppImageQuery, GetAppsQuery, GetAppsResponse,
-    GetHostQuery, GetHostResponse, GetHostsResponse, GetUserQuery, PatchHostRequest,
-    PostHostRequest, PostHostResponse, PostPairRequest, PostPairResponse1, PostPairResponse2,
-    PostWakeUpRequest, UndetailedHost,
+    self, AppInputProfile, DeleteHostQuery, DeleteInputProfileQuery, DetailedUser,
+    GetAppImageQuery, GetAppsQuery, GetAppsResponse, GetHostQuery, GetHostResponse,
+    GetHostsResponse, GetInputProfilesResponse, GetUserQuery, PatchHostRequest, PostHostRequest,
+    PostHostResponse, PostInputProfileRequest, PostPairRequest, PostPairResponse1,
+    PostPairResponse2, PostWakeUpRequest, UndetailedHost,
 };
 
 pub mod admin;
@@ -71,10 +75,67 @@ async fn get_user(
     }
 }
 
+fn input_profile_app(
+    host_id: Option<u32>,
+    app_id: Option<u32>,
+) -> Result<Option<(HostId, AppId)>, AppError> {
+    match (host_id, app_id) {
+        (None, None) => Ok(None),
+        (Some(host_id), Some(app_id)) => Ok(Some((HostId(host_id), AppId(app_id)))),
+        _ => Err(AppError::BadRequest),
+    }
+}
+
+#[get("/user/input_profiles")]
+async fn get_input_profiles(
+    user: AuthenticatedUser,
+) -> Result<Json<GetInputProfilesResponse>, AppError> {
+    let profiles = user.input_profiles().await?;
+
+    Ok(Json(GetInputProfilesResponse {
+        default: profiles.default,
+        apps: profiles
+            .apps
+            .into_iter()
+            .map(|app| AppInputProfile {
+                host_id: app.host_id.0,
+                app_id: app.app_id.0,
+                profile: app.profile,
+            })
+            .collect(),
+    }))
+}
+
+#[post("/user/input_profile")]
+async fn post_input_profile(
+    mut user: AuthenticatedUser,
+    Json(request): Json<PostInputProfileRequest>,
+) -> Result<HttpResponse, AppError> {
+    let app = input_profile_app(request.host_id, request.app_id)?;
+
+    user.set_input_profile(app, Some(request.profile)).await?;
+
+    Ok(HttpResponse::Ok().finish())
+}
+
+#[delete("/user/input_profile")]
+async fn delete_input_profile(
+    mut user: AuthenticatedUser,
+    Query(query): Query<DeleteInputProfileQuery>,
+) -> Result<HttpResponse, AppError> {
+    let app = input_profile_app(query.host_id, query.app_id)?;
+
+    user.set_input_profile(app, None).await?;
+
+    Ok(HttpResponse::Ok().finish())
+}
+
 #[get("/hosts")]
 async fn list_hosts(
-    mut user: AuthenticatedUser,
+    user: ScopedUser<ReadHosts>,
 ) -> Result<StreamedResponse<GetHostsResponse, UndetailedHost>, AppError> {
+    let mut user = user.into_inner();
+
     let (mut stream_response, stream_sender) =
         StreamedResponse::new(GetHostsResponse { hosts: Vec::new() });
 
@@ -122,9 +183,11 @@ async fn list_hosts(
 
 #[get("/host")]
 async fn get_host(
-    mut user: AuthenticatedUser,
+    user: ScopedUser<ReadHosts>,
     Query(query): Query<GetHostQuery>,
 ) -> Result<Json<GetHostResponse>, AppError> {
+    let mut user = user.into_inner();
+
     let host_id = HostId(query.host_id);
 
     let mut host = user.host(host_id).await?;
@@ -239,9 +302,11 @@ async fn pair_host(
 
 #[post("/host/wake")]
 async fn wake_host(
-    mut user: AuthenticatedUser,
+    user: ScopedUser<WakeHosts>,
     Json(request): Json<PostWakeUpRequest>,
 ) -> Result<HttpResponse, AppError> {
+    let mut user = user.into_inner();
+
     let host_id = HostId(request.host_id);
 
     let host = user.host(host_id).await?;
@@ -253,9 +318,11 @@ async fn wake_host(
 
 #[get("/apps")]
 async fn get_apps(
-    mut user: AuthenticatedUser,
+    user: ScopedUser<ReadHosts>,
     Query(query): Query<GetAppsQuery>,
 ) -> Result<Json<GetAppsResponse>, AppError> {
+    let mut user = user.into_inner();
+
     let host_id = HostId(query.host_id);
 
     let mut host = user.host(host_id).await?;
@@ -276,10 +343,12 @@ async fn get_apps(
 
 #[get("/app/image")]
 async fn get_app_image(
-    mut user: AuthenticatedUser,
+    user: ScopedUser<ReadHosts>,
     Query(query): Query<GetAppImageQuery>,
     req: HttpRequest,
 ) -> Result<HttpResponse, AppError> {
+    let mut user = user.into_inner();
+
     let host_id = HostId(query.host_id);
     let app_id = AppId(query.app_id);
 
@@ -320,8 +389,19 @@ pub fn api_service() -> impl HttpServiceFactory {
         .service(services![
             // -- Auth
             auth::login,
+            auth::oidc_login,
+            auth::oidc_callback,
             auth::logout,
-            auth::authenticate
+            auth::authenticate,
+            auth::list_login_sessions,
+            auth::delete_login_session,
+            auth::delete_all_login_sessions,
+            auth::post_totp,
+            auth::confirm_totp,
+            auth::delete_totp,
+            auth::list_api_tokens,
+            auth::post_api_token,
+            auth::delete_api_token,
         ])
         .service(services![
             // -- Host
@@ -336,9 +416,20 @@ pub fn api_service() -> impl HttpServiceFactory {
             get_apps,
             get_app_image,
         ])
+        .service(services![
+            // -- Input Profiles
+            get_input_profiles,
+            post_input_profile,
+            delete_input_profile,
+        ])
         .service(services![
             // -- Stream
             stream::start_host,
+            stream::spectate_host,
+            stream::list_spectators,
+            stream::invite_spectator,
+            stream::kick_spectator,
+            stream::set_seat,
             stream::cancel_host,
             stream::client_log,
         ])
@@ -347,6 +438,12 @@ pub fn api_service() -> impl HttpServiceFactory {
             add_user,
             patch_user,
             delete_user,
-            list_users
+            list_users,
+            add_group,
+            patch_group,
+            delete_group,
+            list_groups,
+            list_stream_sessions,
+            delete_stream_session,
         ])
 }
@@ -1,22 +1,27 @@
 use std::{
     path::PathBuf,
+    pin::pin,
     process::Stdio,
+    sync::Arc,
+    time::Duration,
 };
 
 use actix_web::{
-    Error, HttpRequest, HttpResponse, get, post, rt as actix_rt,
-    web::{Data, Json, Payload},
+    Error, HttpRequest, HttpResponse, delete, get, post, rt as actix_rt,
+    web::{Data, Json, Payload, Query},
 };
-use actix_ws::{Closed, Message, Session};
+use actix_ws::{Closed, Message, MessageStream, Session};
 use common::{
     api_bindings::{
-        LogMessageType, PostCancelRequest, PostCancelResponse, StreamClientMessage,
-        StreamServerMessage,
+        DeleteSpectatorRequest, GetSpectatorsQuery, GetSpectatorsResponse, LogMessageType,
+        PostCancelRequest, PostCancelResponse, PostSeatRequest, PostSpectatorRequest,
+        StreamClientMessage, StreamServerMessage, UserPermissions,
     },
-    config::StorageConfig,
+    config::{BusyHostPolicy, StorageConfig},
     ipc::{ServerIpcMessage, StreamerConfig, StreamerIpcMessage, create_child_ipc},
     serialize_json,
 };
+use futures::future::{Either, select};
 use log::{debug, error, info, warn};
 use serde::Deserialize;
 use tokio::{
@@ -24,21 +29,31 @@ use tokio::{
     io::AsyncWriteExt,
     process::Command,
     spawn,
+    time::{sleep, timeout},
 };
 
 use crate::app::{
     App, AppError,
     host::{AppId, HostId},
-    user::AuthenticatedUser,
+    permissions::{is_stream_time_allowed, restrict_stream_message},
+    stream::{ActiveStream, SpectatorId, StreamLimitReached},
+    user::{AuthenticatedUser, ScopedUser, StartApps, UserId},
 };
 
+/// How often a queued stream checks the stream limits again
+const QUEUE_RECHECK_INTERVAL: Duration = Duration::from_secs(5);
+/// How often a running stream checks if the time windows of the owner still allow it
+const TIME_WINDOW_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
 #[get("/host/stream")]
 pub async fn start_host(
     web_app: Data<App>,
-    mut user: AuthenticatedUser,
+    user: ScopedUser<StartApps>,
     request: HttpRequest,
     payload: Payload,
 ) -> Result<HttpResponse, Error> {
+    let mut user = user.into_inner();
+
     let (response, mut session, mut stream) = actix_ws::handle(&request, payload)?;
 
     let client_unique_id = user.host_unique_id().await?;
@@ -189,6 +204,46 @@ pub async fn start_host(
             }
         };
 
+        let input_profile = match user.input_profile(host_id, app_id).await {
+            Ok(input_profile) => input_profile,
+            Err(err) => {
+                warn!(
+                    "failed to get the input profile of {:?}, using none: {err}",
+                    user.id()
+                );
+                Default::default()
+            }
+        };
+
+        let permissions = match user.permissions().await {
+            Ok(permissions) => permissions,
+            Err(err) => {
+                warn!("failed to start stream for host {host_id:?} (at permissions): {err}");
+
+                let _ = send_ws_message(
+                    &mut session,
+                    StreamServerMessage::DebugLog {
+                        message: "Failed to start stream because of a server error".to_string(),
+                        ty: Some(LogMessageType::FatalDescription),
+                    },
+                )
+                .await;
+                let _ = session.close(None).await;
+                return;
+            }
+        };
+
+        let allow_clipboard = match user.allow_clipboard().await {
+            Ok(allow_clipboard) => allow_clipboard,
+            Err(err) => {
+                warn!(
+                    "failed to get the clipboard permission of {:?}, denying it: {err}",
+                    user.id()
+                );
+                false
+            }
+        };
+
         // -- Send App info
         let _ = send_ws_message(
             &mut session,
@@ -196,6 +251,114 @@ pub async fn start_host(
         )
         .await;
 
+        if !is_stream_time_allowed(&permissions) {
+            info!(
+                "[Stream]: {:?} is not allowed to start a stream at this time",
+                user.id()
+            );
+
+            let _ = send_ws_message(
+                &mut session,
+                StreamServerMessage::DebugLog {
+                    message: "Failed to start stream because streaming is not allowed at this time"
+                        .to_string(),
+                    ty: Some(LogMessageType::FatalDescription),
+                },
+            )
+            .await;
+            let _ = session.close(None).await;
+            return;
+        }
+
+        // -- Resume the stream if it's still running
+        if let Some(active_stream) = web_app.resumable_stream(&user, host_id, app_id).await {
+            info!(
+                "[Stream]: resuming stream of {:?} on host {host_id:?}",
+                user.id()
+            );
+
+            let _ = send_ws_message(
+                &mut session,
+                StreamServerMessage::DebugLog {
+                    message: "Resuming stream".to_string(),
+                    ty: None,
+                },
+            )
+            .await;
+
+            let generation = active_stream.attach_owner(session).await;
+            active_stream.send_ipc(ServerIpcMessage::Reconnect).await;
+
+            forward_owner_ws(&web_app, &active_stream, &permissions, generation, stream).await;
+            return;
+        }
+
+        // -- Check the stream limits
+        let busy_policy = web_app.config().stream.busy_policy;
+        let mut queued = false;
+        let start_guard = loop {
+            let start_guard = web_app.lock_stream_start().await;
+
+            let err = match web_app.check_stream_limits(&user, host_id).await {
+                Ok(()) => break start_guard,
+                Err(err) => err,
+            };
+            drop(start_guard);
+
+            match (busy_policy, err) {
+                (BusyHostPolicy::TakeOver, StreamLimitReached::Host) => {
+                    web_app.take_over_host(&user, host_id).await;
+                }
+                (BusyHostPolicy::Queue, StreamLimitReached::Server | StreamLimitReached::Host) => {
+                    if !queued {
+                        queued = true;
+
+                        let _ = send_ws_message(
+                            &mut session,
+                            StreamServerMessage::DebugLog {
+                                message: format!("Waiting in queue because {err}"),
+                                ty: None,
+                            },
+                        )
+                        .await;
+                    }
+
+                    // Wait for a stream to stop but leave the queue when the client disconnects
+                    let stream_stopped = pin!(web_app.stream_stopped());
+                    let client_message = pin!(stream.recv());
+                    if let Ok(Either::Right((
+                        None | Some(Err(_)) | Some(Ok(Message::Close(_))),
+                        _,
+                    ))) = timeout(
+                        QUEUE_RECHECK_INTERVAL,
+                        select(stream_stopped, client_message),
+                    )
+                    .await
+                    {
+                        info!("[Stream]: client left the queue for host {host_id:?}");
+                        return;
+                    }
+                }
+                _ => {
+                    info!("[Stream]: failed to start stream for host {host_id:?}: {err}");
+
+                    let _ = send_ws_message(
+                        &mut session,
+                        StreamServerMessage::DebugLog {
+                            message: format!("Failed to start stream because {err}"),
+                            ty: Some(LogMessageType::FatalDescription),
+                        },
+                    )
+                    .await;
+                    let _ = session.close(None).await;
+                    return;
+                }
+            }
+        };
+
+        // -- Stop the stream of another app, it would otherwise race the new streamer on the host
+        web_app.stop_own_stream(&user, host_id).await;
+
         // -- Starting stage: launch streamer
         let _ = send_ws_message(
             &mut session,
@@ -264,11 +427,32 @@ pub async fn start_host(
         )
         .await;
 
+        let active_stream = web_app
+            .register_stream(&user, host_id, app_id, child, ipc_sender.clone())
+            .await;
+        drop(start_guard);
+        let generation = active_stream.attach_owner(session).await;
+
+        spawn(stop_outside_time_windows(
+            web_app.clone(),
+            user.clone(),
+            active_stream.clone(),
+        ));
+
         // Redirect ipc message into ws
+        let ipc_web_app = web_app.clone();
+        let ipc_active_stream = active_stream.clone();
         spawn(async move {
+            let active_stream = ipc_active_stream;
+
             while let Some(message) = ipc_receiver.recv().await {
                 match message {
                     StreamerIpcMessage::WebSocket(message) => {
+                        let Some(mut session) = active_stream.owner_session().await else {
+                            debug!("[Ipc]: dropping message because the owner is disconnected");
+                            continue;
+                        };
+
                         if let Err(Closed) = send_ws_message(&mut session, message).await {
                             warn!(
                                 "[Ipc]: Tried to send a ws message (text) but the socket is already closed"
@@ -276,13 +460,63 @@ pub async fn start_host(
                         }
                     }
                     StreamerIpcMessage::WebSocketTransport(data) => {
+                        let Some(mut session) = active_stream.owner_session().await else {
+                            debug!("[Ipc]: dropping message because the owner is disconnected");
+                            continue;
+                        };
+
                         if let Err(Closed) = session.binary(data).await {
                             warn!(
                                 "[Ipc]: Tried to send a ws message (binary) but the socket is already closed"
                             );
-                            break;
                         }
                     }
+                    StreamerIpcMessage::SpectatorWebSocket {
+                        spectator_id,
+                        message,
+                    } => {
+                        let Some(mut spectator_session) = active_stream
+                            .spectator_session(SpectatorId(spectator_id))
+                            .await
+                        else {
+                            debug!(
+                                "[Ipc]: dropping message of already removed spectator {spectator_id}"
+                            );
+                            continue;
+                        };
+
+                        if let Err(Closed) = send_ws_message(&mut spectator_session, message).await
+                        {
+                            warn!(
+                                "[Ipc]: Tried to send a ws message (text) to spectator {spectator_id} but the socket is already closed"
+                            );
+                        }
+                    }
+                    StreamerIpcMessage::SpectatorWebSocketTransport { spectator_id, data } => {
+                        let Some(mut spectator_session) = active_stream
+                            .spectator_session(SpectatorId(spectator_id))
+                            .await
+                        else {
+                            debug!(
+                                "[Ipc]: dropping message of already removed spectator {spectator_id}"
+                            );
+                            continue;
+                        };
+
+                        if let Err(Closed) = spectator_session.binary(data).await {
+                            warn!(
+                                "[Ipc]: Tried to send a ws message (binary) to spectator {spectator_id} but the socket is already closed"
+                            );
+                        }
+                    }
+                    StreamerIpcMessage::SpectatorStop { spectator_id } => {
+                        active_stream
+                            .remove_spectator(SpectatorId(spectator_id))
+                            .await;
+                    }
+                    StreamerIpcMessage::Stats(stats) => {
+                        active_stream.on_stats(stats).await;
+                    }
                     StreamerIpcMessage::Stop => {
                         debug!("[Ipc]: ipc receiver stopped by streamer");
                         break;
@@ -291,15 +525,11 @@ pub async fn start_host(
             }
             info!("[Ipc]: ipc receiver is closed");
 
-            // close the websocket when the streamer crashed / disconnected / whatever
-            if let Err(err) = session.close(None).await {
-                warn!("failed to close streamer web socket: {err}");
-            }
+            // close the websockets when the streamer crashed / disconnected / whatever
+            ipc_web_app.unregister_stream(&active_stream).await;
 
             // kill the streamer
-            if let Err(err) = child.kill().await {
-                warn!("failed to kill streamer child: {err}");
-            }
+            active_stream.kill().await;
         });
 
         // Send init into ipc
@@ -308,6 +538,8 @@ pub async fn start_host(
                 config: StreamerConfig {
                     webrtc: web_app.config().webrtc.clone(),
                     video: web_app.config().video.clone(),
+                    input: web_app.config().input.clone(),
+                    clipboard: web_app.config().clipboard.clone(),
                     log_level: web_app.config().log.level_filter,
                 },
                 host_address: address,
@@ -319,33 +551,288 @@ pub async fn start_host(
                 app_id: app_id.0,
                 video_frame_queue_size,
                 audio_sample_queue_size,
+                input_profile,
+                allow_clipboard,
             })
             .await;
 
+        forward_owner_ws(&web_app, &active_stream, &permissions, generation, stream).await;
+    });
+
+    Ok(response)
+}
+
+/// Redirects the ws messages of the owner into ipc until the ws closes.
+/// The streamer keeps running for the reconnect grace period afterwards.
+async fn forward_owner_ws(
+    web_app: &App,
+    active_stream: &Arc<ActiveStream>,
+    permissions: &UserPermissions,
+    generation: u32,
+    mut stream: MessageStream,
+) {
+    while let Some(Ok(message)) = stream.recv().await {
+        match message {
+            Message::Text(text) => {
+                let Ok(mut message) = serde_json::from_str::<StreamClientMessage>(&text) else {
+                    warn!("[Stream]: failed to deserialize from json");
+                    break;
+                };
+
+                restrict_stream_message(permissions, &mut message);
+
+                active_stream.on_owner_message(&message).await;
+                active_stream
+                    .send_ipc(ServerIpcMessage::WebSocket(message))
+                    .await;
+            }
+            Message::Binary(binary) => {
+                active_stream
+                    .send_ipc(ServerIpcMessage::WebSocketTransport(binary))
+                    .await;
+            }
+            _ => {}
+        }
+    }
+
+    active_stream
+        .detach_owner(generation, web_app.config().stream.reconnect_grace_period)
+        .await;
+}
+
+/// Stops the stream once the time windows of the owner don't allow streaming anymore
+async fn stop_outside_time_windows(
+    web_app: Data<App>,
+    mut user: AuthenticatedUser,
+    active_stream: Arc<ActiveStream>,
+) {
+    loop {
+        sleep(TIME_WINDOW_CHECK_INTERVAL).await;
+
+        // The stream already stopped or was replaced
+        match web_app.owned_stream(&user, active_stream.host_id()).await {
+            Ok(stream) if Arc::ptr_eq(&stream, &active_stream) => {}
+            _ => return,
+        }
+
+        let permissions = match user.permissions().await {
+            Ok(permissions) => permissions,
+            Err(err) => {
+                warn!(
+                    "[Stream]: failed to get the permissions of {:?} to check the time windows: {err}",
+                    user.id()
+                );
+                continue;
+            }
+        };
+        if is_stream_time_allowed(&permissions) {
+            continue;
+        }
+
+        info!(
+            "[Stream]: stopping the stream of {:?} because streaming isn't allowed at this time anymore",
+            user.id()
+        );
+
+        let _ = active_stream
+            .send_owner_message(StreamServerMessage::DebugLog {
+                message: "The stream was stopped because streaming is not allowed at this time"
+                    .to_string(),
+                ty: Some(LogMessageType::FatalDescription),
+            })
+            .await;
+        active_stream.stop().await;
+
+        return;
+    }
+}
+
+#[get("/host/stream/spectate")]
+pub async fn spectate_host(
+    web_app: Data<App>,
+    mut user: AuthenticatedUser,
+    request: HttpRequest,
+    payload: Payload,
+) -> Result<HttpResponse, Error> {
+    let (response, mut session, mut stream) = actix_ws::handle(&request, payload)?;
+
+    let web_app = web_app.clone();
+    actix_rt::spawn(async move {
+        let message;
+        loop {
+            message = match stream.recv().await {
+                Some(Ok(Message::Text(text))) => text,
+                Some(Ok(Message::Binary(_))) => {
+                    return;
+                }
+                Some(Ok(_)) => continue,
+                Some(Err(_)) => {
+                    return;
+                }
+                None => {
+                    return;
+                }
+            };
+            break;
+        }
+
+        let Ok(StreamClientMessage::Spectate { host_id }) =
+            serde_json::from_str::<StreamClientMessage>(&message)
+        else {
+            let _ = session.close(None).await;
+
+            warn!("WebSocket didn't send spectate as first message, closing it");
+            return;
+        };
+
+        let host_id = HostId(host_id);
+
+        let active_stream = match web_app.spectatable_stream(&user, host_id).await {
+            Ok(active_stream) => active_stream,
+            Err(err) => {
+                info!("failed to spectate host {host_id:?}: {err}");
+
+                let _ = send_ws_message(
+                    &mut session,
+                    StreamServerMessage::DebugLog {
+                        message: "Failed to spectate because there is no stream you're invited to"
+                            .to_string(),
+                        ty: Some(LogMessageType::FatalDescription),
+                    },
+                )
+                .await;
+                let _ = session.close(None).await;
+                return;
+            }
+        };
+
+        let name = match user.detailed_user().await {
+            Ok(detailed_user) => detailed_user.name,
+            Err(err) => {
+                warn!("failed to spectate host {host_id:?} (at detailed_user): {err}");
+
+                let _ = send_ws_message(
+                    &mut session,
+                    StreamServerMessage::DebugLog {
+                        message: "Failed to spectate because of a server error".to_string(),
+                        ty: Some(LogMessageType::FatalDescription),
+                    },
+                )
+                .await;
+                let _ = session.close(None).await;
+                return;
+            }
+        };
+
+        let spectator_id = active_stream
+            .add_spectator(user.id(), name, session.clone())
+            .await;
+
         // Redirect ws message into ipc
         while let Some(Ok(message)) = stream.recv().await {
             match message {
                 Message::Text(text) => {
                     let Ok(message) = serde_json::from_str::<StreamClientMessage>(&text) else {
                         warn!("[Stream]: failed to deserialize from json");
-                        return;
+                        break;
                     };
 
-                    ipc_sender.send(ServerIpcMessage::WebSocket(message)).await;
+                    active_stream
+                        .send_ipc(ServerIpcMessage::SpectatorWebSocket {
+                            spectator_id: spectator_id.0,
+                            message,
+                        })
+                        .await;
                 }
-                Message::Binary(binary) => {
-                    ipc_sender
-                        .send(ServerIpcMessage::WebSocketTransport(binary))
+                Message::Binary(data) => {
+                    active_stream
+                        .send_ipc(ServerIpcMessage::SpectatorWebSocketTransport {
+                            spectator_id: spectator_id.0,
+                            data,
+                        })
                         .await;
                 }
                 _ => {}
             }
         }
+
+        active_stream.remove_spectator(spectator_id).await;
     });
 
     Ok(response)
 }
 
+#[get("/host/stream/spectators")]
+pub async fn list_spectators(
+    app: Data<App>,
+    user: AuthenticatedUser,
+    Query(query): Query<GetSpectatorsQuery>,
+) -> Result<Json<GetSpectatorsResponse>, AppError> {
+    let active_stream = app.owned_stream(&user, HostId(query.host_id)).await?;
+
+    Ok(Json(GetSpectatorsResponse {
+        invited: active_stream
+            .invited()
+            .await
+            .into_iter()
+            .map(|user_id| user_id.0)
+            .collect(),
+        spectators: active_stream.spectators().await,
+        seats: active_stream.seats().await,
+    }))
+}
+
+#[post("/host/stream/spectator")]
+pub async fn invite_spectator(
+    app: Data<App>,
+    user: AuthenticatedUser,
+    Json(request): Json<PostSpectatorRequest>,
+) -> Result<HttpResponse, AppError> {
+    let active_stream = app.owned_stream(&user, HostId(request.host_id)).await?;
+
+    let target_user = app.user_by_id(UserId(request.user_id)).await?;
+    if target_user.id() == user.id() {
+        return Err(AppError::BadRequest);
+    }
+
+    active_stream.invite(target_user.id()).await;
+
+    Ok(HttpResponse::Ok().finish())
+}
+
+#[delete("/host/stream/spectator")]
+pub async fn kick_spectator(
+    app: Data<App>,
+    user: AuthenticatedUser,
+    Json(request): Json<DeleteSpectatorRequest>,
+) -> Result<HttpResponse, AppError> {
+    let active_stream = app.owned_stream(&user, HostId(request.host_id)).await?;
+
+    active_stream.kick(UserId(request.user_id)).await;
+
+    Ok(HttpResponse::Ok().finish())
+}
+
+#[post("/host/stream/seat")]
+pub async fn set_seat(
+    app: Data<App>,
+    user: AuthenticatedUser,
+    Json(request): Json<PostSeatRequest>,
+) -> Result<HttpResponse, AppError> {
+    let active_stream = app.owned_stream(&user, HostId(request.host_id)).await?;
+
+    let target_user = app.user_by_id(UserId(request.user_id)).await?;
+    if target_user.id() == user.id() {
+        return Err(AppError::BadRequest);
+    }
+
+    active_stream
+        .set_seat(target_user.id(), request.seat)
+        .await?;
+
+    Ok(HttpResponse::Ok().finish())
+}
+
 async fn send_ws_message(sender: &mut Session, message: StreamServerMessage) -> Result<(), Closed> {
     let Some(json) = serialize_json(&message) else {
         return Ok(());
@@ -356,9 +843,11 @@ async fn send_ws_message(sender: &mut Session, message: StreamServerMessage) ->
 
 #[post("/host/cancel")]
 pub async fn cancel_host(
-    mut user: AuthenticatedUser,
+    user: ScopedUser<StartApps>,
     Json(request): Json<PostCancelRequest>,
 ) -> Result<Json<PostCancelResponse>, AppError> {
+    let mut user = user.into_inner();
+
     let host_id = HostId(request.host_id);
 
     let mut host = user.host(host_id).await?;
@@ -380,7 +869,9 @@ pub async fn client_log(
     Json(request): Json<ClientLogRequest>,
 ) -> Result<HttpResponse, AppError> {
     let base_path = match app.config().data_storage.clone() {
-        StorageConfig::Json { path, .. } => PathBuf::from(path),
+        StorageConfig::Json { path, .. } | StorageConfig::Sqlite { path, .. } => {
+            PathBuf::from(path)
+        }
     };
 
     let log_path = base_path
@@ -1,7 +1,7 @@
-use std::fmt;
+use std::{fmt, net::IpAddr};
 
 use hex::FromHexError;
-use openssl::rand::rand_bytes;
+use openssl::{rand::rand_bytes, sha::sha256};
 use serde::{
     Deserialize, Deserializer, Serialize, Serializer,
     de::{self, Visitor},
@@ -11,9 +11,21 @@ use crate::app::AppError;
 
 pub enum UserAuth {
     None,
-    UserPassword { username: String, password: String },
-    Session(SessionToken),
-    ForwardedHeaders { username: String },
+    UserPassword {
+        username: String,
+        password: String,
+        totp_code: Option<String>,
+    },
+    Session {
+        token: SessionToken,
+        ip: Option<IpAddr>,
+    },
+    ForwardedHeaders {
+        username: String,
+    },
+    ApiToken {
+        token: ApiToken,
+    },
 }
 
 const SESSION_TOKEN_SIZE: usize = 32;
@@ -29,6 +41,11 @@ impl SessionToken {
         Ok(Self(bytes))
     }
 
+    /// The hash under which the token is stored, a leaked data file doesn't leak usable tokens
+    pub fn hash(&self) -> [u8; 32] {
+        sha256(&self.0)
+    }
+
     pub fn encode<'a>(&self, bytes: &'a mut [u8; SESSION_TOKEN_SIZE * 2]) -> &'a str {
         hex::encode_to_slice(self.0.as_slice(), bytes).expect("failed to hex encode bytes");
 
@@ -77,3 +94,36 @@ impl<'de> Deserialize<'de> for SessionToken {
         deserializer.deserialize_str(SessionTokenVisitor)
     }
 }
+
+/// Api tokens are prefixed so that they can't be confused with session tokens
+pub const API_TOKEN_PREFIX: &str = "mlt_";
+const API_TOKEN_SIZE: usize = 32;
+
+/// A long lived token for automation, only a hash of it is stored
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ApiToken([u8; API_TOKEN_SIZE]);
+
+impl ApiToken {
+    pub fn new() -> Result<Self, AppError> {
+        let mut bytes = [0; API_TOKEN_SIZE];
+
+        rand_bytes(&mut bytes)?;
+
+        Ok(Self(bytes))
+    }
+
+    pub fn hash(&self) -> [u8; 32] {
+        sha256(&self.0)
+    }
+
+    pub fn encode(&self) -> String {
+        format!("{API_TOKEN_PREFIX}{}", hex::encode(self.0))
+    }
+
+    /// Decodes the hex part of the token, without the prefix
+    pub fn decode(str: &str) -> Result<Self, FromHexError> {
+        let mut arr = [0u8; API_TOKEN_SIZE];
+        hex::decode_to_slice(str.as_bytes(), &mut arr)?;
+        Ok(ApiToken(arr))
+    }
+}
@@ -0,0 +1,17 @@
+use common::api_bindings::DetailedGroup;
+
+use crate::app::storage::StorageGroup;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct GroupId(pub u32);
+
+impl From<StorageGroup> for DetailedGroup {
+    fn from(value: StorageGroup) -> Self {
+        Self {
+            id: value.id.0,
+            name: value.name,
+            members: value.members.into_iter().map(|user_id| user_id.0).collect(),
+            hosts: value.hosts.into_iter().map(|host_id| host_id.0).collect(),
+        }
+    }
+}
@@ -20,6 +20,7 @@ use uuid::Uuid;
 
 use crate::app::{
     AppError, AppInner, AppRef, MoonlightClient,
+    permissions::{is_app_allowed, is_global_host_allowed},
     storage::{StorageHost, StorageHostModify, StorageHostPairInfo},
     user::{AuthenticatedUser, Role, UserId},
 };
@@ -76,20 +77,46 @@ impl Host {
 
     async fn can_use(&self, user: &mut AuthenticatedUser) -> Result<(), AppError> {
         let owner = self.owner().await?;
-        if owner.is_none() || owner == Some(user.id()) || matches!(user.role().await?, Role::Admin)
-        {
+        if owner == Some(user.id()) || matches!(user.role().await?, Role::Admin) {
+            return Ok(());
+        }
+
+        let allowed = match owner {
+            None => is_global_host_allowed(&user.permissions().await?, self.id),
+            Some(_) => {
+                let app = self.app.access()?;
+
+                app.storage
+                    .is_host_shared_with_user(self.id, user.id())
+                    .await?
+            }
+        };
+
+        if allowed {
             Ok(())
         } else {
             Err(AppError::Forbidden)
         }
     }
 
+    /// Hosts that are shared with a group can only be used by the members, not changed
+    async fn can_manage(&self, user: &mut AuthenticatedUser) -> Result<(), AppError> {
+        self.can_use(user).await?;
+
+        match self.owner().await? {
+            Some(owner) if owner != user.id() && !matches!(user.role().await?, Role::Admin) => {
+                Err(AppError::Forbidden)
+            }
+            _ => Ok(()),
+        }
+    }
+
     pub async fn modify(
         &mut self,
         user: &mut AuthenticatedUser,
         modify: StorageHostModify,
     ) -> Result<(), AppError> {
-        self.can_use(user).await?;
+        self.can_manage(user).await?;
 
         let app = self.app.access()?;
 
@@ -115,7 +142,7 @@ impl Host {
         Ok(match this.owner {
             None => HostOwner::Global,
             Some(user_id) if user.id() == user_id => HostOwner::ThisUser,
-            _ => unreachable!(),
+            Some(_) => HostOwner::Group,
         })
     }
 
@@ -455,7 +482,11 @@ impl Host {
         user: &mut AuthenticatedUser,
         pin: PairPin,
     ) -> Result<(), AppError> {
-        self.can_use(user).await?;
+        self.can_manage(user).await?;
+
+        if !user.permissions().await?.can_pair_hosts {
+            return Err(AppError::Forbidden);
+        }
 
         let user_id = user.id();
         let app = self.app.access()?;
@@ -532,7 +563,7 @@ impl Host {
 
     #[allow(dead_code)]
     pub async fn unpair(&self, user: &mut AuthenticatedUser) -> Result<Host, AppError> {
-        self.can_use(user).await?;
+        self.can_manage(user).await?;
 
         todo!()
     }
@@ -555,6 +586,7 @@ impl Host {
     pub async fn list_apps(&mut self, user: &mut AuthenticatedUser) -> Result<Vec<App>, AppError> {
         self.can_use(user).await?;
 
+        let permissions = user.permissions().await?;
         let app = self.app.access()?;
 
         let info = self
@@ -566,7 +598,7 @@ impl Host {
             &app,
             user,
             false,
-            async |_this, https_capable, client, host, _port, client_info| {
+            async |this, https_capable, client, host, _port, client_info| {
                 if !https_capable {
                     return Err(AppError::HostNotPaired);
                 }
@@ -578,7 +610,12 @@ impl Host {
                 )
                 .await?;
 
-                let apps = apps.apps.into_iter().map(App::from).collect::<Vec<_>>();
+                let apps = apps
+                    .apps
+                    .into_iter()
+                    .map(App::from)
+                    .filter(|app| is_app_allowed(&permissions, this.id, app.id))
+                    .collect::<Vec<_>>();
 
                 Ok(apps)
             },
@@ -699,3 +736,140 @@ impl Host {
         Ok(())
     }
 }
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use std::time::Duration;
+
+    use common::config::{Config, StorageConfig};
+
+    use crate::app::{
+        App,
+        storage::{
+            StorageGroupAdd, StorageGroupModify, StorageHostAdd, StorageHostCache, StorageUserAdd,
+        },
+    };
+
+    use super::*;
+
+    async fn app() -> App {
+        App::new(Config {
+            data_storage: StorageConfig::Sqlite {
+                path: ":memory:".to_string(),
+                session_expiration_check_interval: Duration::from_secs(3600),
+                import_json: None,
+            },
+            ..Default::default()
+        })
+        .await
+        .unwrap()
+    }
+
+    async fn add_user(app: &App, name: &str) -> AuthenticatedUser {
+        app.add_user_no_auth(StorageUserAdd {
+            role: Role::User,
+            name: name.to_string(),
+            password: None,
+            client_unique_id: name.to_string(),
+            allow_clipboard: false,
+            oidc: None,
+        })
+        .await
+        .unwrap()
+    }
+
+    /// A host of the owner that is shared with the member
+    async fn shared_host(app: &App, owner: UserId, member: UserId) -> HostId {
+        let storage = &app.inner.storage;
+
+        let host = storage
+            .add_host(StorageHostAdd {
+                owner: Some(owner),
+                address: "127.0.0.1".to_string(),
+                http_port: 47989,
+                pair_info: None,
+                cache: StorageHostCache {
+                    name: "host".to_string(),
+                    mac: None,
+                },
+            })
+            .await
+            .unwrap();
+
+        let group = storage
+            .add_group(StorageGroupAdd {
+                name: "friends".to_string(),
+            })
+            .await
+            .unwrap();
+        storage
+            .modify_group(
+                group.id,
+                StorageGroupModify {
+                    members: Some(vec![owner, member]),
+                    hosts: Some(vec![host.id]),
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+
+        host.id
+    }
+
+    #[actix_web::test]
+    async fn test_group_member_cannot_manage_host() {
+        let app = app().await;
+        let mut owner = add_user(&app, "alice").await;
+        let mut member = add_user(&app, "bob").await;
+        let host_id = shared_host(&app, owner.id(), member.id()).await;
+
+        // Sharing allows using the host
+        let mut host = member.host(host_id).await.unwrap();
+        assert!(matches!(
+            host.undetailed_host_cached(&mut member)
+                .await
+                .unwrap()
+                .owner,
+            HostOwner::Group
+        ));
+
+        assert!(matches!(
+            host.modify(
+                &mut member,
+                StorageHostModify {
+                    address: Some("10.0.0.1".to_string()),
+                    ..Default::default()
+                },
+            )
+            .await,
+            Err(AppError::Forbidden)
+        ));
+        assert!(matches!(
+            host.pair(&mut member, PairPin::from_array([1, 2, 3, 4]).unwrap())
+                .await,
+            Err(AppError::Forbidden)
+        ));
+        assert!(matches!(
+            member.host_delete(host_id).await,
+            Err(AppError::Forbidden)
+        ));
+
+        // The owner still can
+        let mut host = owner.host(host_id).await.unwrap();
+        host.modify(
+            &mut owner,
+            StorageHostModify {
+                address: Some("10.0.0.1".to_string()),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            host.address_port(&mut owner).await.unwrap(),
+            ("10.0.0.1".to_string(), 47989)
+        );
+    }
+}
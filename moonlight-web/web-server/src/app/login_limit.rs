@@ -0,0 +1,279 @@
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    time::{Duration, Instant},
+};
+
+use common::config::LoginRateLimitConfig;
+use log::warn;
+use tokio::sync::Mutex;
+
+use crate::app::AppError;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum LoginAttemptKey {
+    Ip(IpAddr),
+    Username(String),
+}
+
+struct FailedLogins {
+    count: u32,
+    last_failed: Instant,
+}
+
+/// Tracks failed password logins per ip and per username.
+///
+/// After the free attempts every failed login doubles the time until the next attempt is allowed
+/// and after too many the ip or username is locked out for a while.
+pub struct LoginLimiter {
+    config: LoginRateLimitConfig,
+    failed: Mutex<HashMap<LoginAttemptKey, FailedLogins>>,
+}
+
+impl LoginLimiter {
+    pub fn new(config: LoginRateLimitConfig) -> Self {
+        Self {
+            config,
+            failed: Default::default(),
+        }
+    }
+
+    fn keys(ip: Option<IpAddr>, username: &str) -> impl Iterator<Item = LoginAttemptKey> {
+        ip.map(LoginAttemptKey::Ip)
+            .into_iter()
+            .chain([LoginAttemptKey::Username(username.to_string())])
+    }
+
+    /// How long after the last failed login no new attempt is allowed
+    fn blocked_for(&self, failed: &FailedLogins) -> Duration {
+        if failed.count >= self.config.lockout_attempts {
+            self.config.lockout_duration
+        } else if failed.count > self.config.free_attempts {
+            let exponent = (failed.count - self.config.free_attempts - 1).min(31);
+
+            self.config
+                .backoff_base
+                .saturating_mul(1 << exponent)
+                .min(self.config.backoff_max)
+        } else {
+            Duration::ZERO
+        }
+    }
+
+    fn is_expired(&self, failed: &FailedLogins) -> bool {
+        let elapsed = failed.last_failed.elapsed();
+
+        elapsed >= self.blocked_for(failed) && elapsed >= self.config.attempt_reset
+    }
+
+    /// Fails if the ip or the username has to wait before trying to log in again.
+    ///
+    /// An allowed attempt is counted as failed right away, so parallel attempts can't all pass
+    /// before the password is verified. Call `login_succeeded` or `login_not_counted` to take it back.
+    pub async fn check(&self, ip: Option<IpAddr>, username: &str) -> Result<(), AppError> {
+        if !self.config.enabled {
+            return Ok(());
+        }
+
+        let mut failed = self.failed.lock().await;
+
+        failed.retain(|_, failed| !self.is_expired(failed));
+
+        let retry_after = Self::keys(ip, username)
+            .filter_map(|key| failed.get(&key))
+            .map(|failed| {
+                self.blocked_for(failed)
+                    .saturating_sub(failed.last_failed.elapsed())
+            })
+            .max()
+            .unwrap_or(Duration::ZERO);
+
+        if !retry_after.is_zero() {
+            warn!(
+                "rejected login attempt for user \"{username}\" from {ip:?}, retry allowed in {}s",
+                retry_after.as_secs() + 1
+            );
+
+            return Err(AppError::TooManyLoginAttempts {
+                retry_after_secs: retry_after.as_secs() + 1,
+            });
+        }
+
+        for key in Self::keys(ip, username) {
+            let failed = failed.entry(key).or_insert(FailedLogins {
+                count: 0,
+                last_failed: Instant::now(),
+            });
+
+            failed.count += 1;
+            failed.last_failed = Instant::now();
+        }
+
+        Ok(())
+    }
+
+    /// The attempt was already counted by `check`, this only logs it
+    pub async fn login_failed(&self, ip: Option<IpAddr>, username: &str, err: &AppError) {
+        if !self.config.enabled {
+            warn!("failed login attempt for user \"{username}\" from {ip:?}: {err}");
+            return;
+        }
+
+        let failed = self.failed.lock().await;
+
+        let max_count = Self::keys(ip, username)
+            .filter_map(|key| failed.get(&key))
+            .map(|failed| failed.count)
+            .max()
+            .unwrap_or(0);
+
+        warn!("failed login attempt {max_count} for user \"{username}\" from {ip:?}: {err}");
+        if max_count >= self.config.lockout_attempts {
+            warn!(
+                "locked out login attempts for user \"{username}\" or from {ip:?} for {}s",
+                self.config.lockout_duration.as_secs()
+            );
+        }
+    }
+
+    /// Forgets the failed logins of the username.
+    /// The ip keeps its earlier failed logins, else one known password would allow guessing others from it.
+    pub async fn login_succeeded(&self, ip: Option<IpAddr>, username: &str) {
+        if !self.config.enabled {
+            return;
+        }
+
+        let mut failed = self.failed.lock().await;
+
+        failed.remove(&LoginAttemptKey::Username(username.to_string()));
+        if let Some(ip) = ip {
+            Self::take_back(&mut failed, LoginAttemptKey::Ip(ip));
+        }
+    }
+
+    /// Takes back the attempt of `check` if it failed for a reason that isn't about the credentials
+    pub async fn login_not_counted(&self, ip: Option<IpAddr>, username: &str) {
+        if !self.config.enabled {
+            return;
+        }
+
+        let mut failed = self.failed.lock().await;
+
+        for key in Self::keys(ip, username) {
+            Self::take_back(&mut failed, key);
+        }
+    }
+
+    fn take_back(failed: &mut HashMap<LoginAttemptKey, FailedLogins>, key: LoginAttemptKey) {
+        if let Some(entry) = failed.get_mut(&key) {
+            entry.count = entry.count.saturating_sub(1);
+
+            if entry.count == 0 {
+                failed.remove(&key);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use super::*;
+
+    const IP: IpAddr = IpAddr::V4(Ipv4Addr::new(192, 168, 0, 2));
+
+    fn limiter() -> LoginLimiter {
+        LoginLimiter::new(LoginRateLimitConfig {
+            enabled: true,
+            free_attempts: 2,
+            backoff_base: Duration::from_secs(1),
+            backoff_max: Duration::from_secs(8),
+            lockout_attempts: 10,
+            lockout_duration: Duration::from_secs(3600),
+            attempt_reset: Duration::from_secs(600),
+        })
+    }
+
+    fn failed_ago(count: u32, ago: Duration) -> FailedLogins {
+        FailedLogins {
+            count,
+            last_failed: Instant::now().checked_sub(ago).unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_blocked_for_growth() {
+        let limiter = limiter();
+
+        let blocked_for = (0..=11)
+            .map(|count| {
+                limiter
+                    .blocked_for(&failed_ago(count, Duration::ZERO))
+                    .as_secs()
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(blocked_for, vec![0, 0, 0, 1, 2, 4, 8, 8, 8, 8, 3600, 3600]);
+    }
+
+    #[test]
+    fn test_lockout_expiry() {
+        let limiter = limiter();
+
+        // Still locked out even though the attempts would be reset
+        assert!(!limiter.is_expired(&failed_ago(10, Duration::from_secs(1800))));
+        assert!(limiter.is_expired(&failed_ago(10, Duration::from_secs(3601))));
+
+        // Without a lockout the attempts are forgotten after the reset time
+        assert!(!limiter.is_expired(&failed_ago(1, Duration::from_secs(599))));
+        assert!(limiter.is_expired(&failed_ago(1, Duration::from_secs(601))));
+    }
+
+    #[actix_web::test]
+    async fn test_check_counts_attempt() {
+        let limiter = limiter();
+
+        // The free attempts are used up by checking alone
+        for _ in 0..3 {
+            limiter.check(Some(IP), "alice").await.unwrap();
+        }
+        assert!(matches!(
+            limiter.check(Some(IP), "alice").await,
+            Err(AppError::TooManyLoginAttempts { .. })
+        ));
+    }
+
+    #[actix_web::test]
+    async fn test_login_succeeded_keeps_ip() {
+        let limiter = limiter();
+
+        for _ in 0..2 {
+            limiter.check(Some(IP), "alice").await.unwrap();
+        }
+        limiter.check(Some(IP), "bob").await.unwrap();
+        limiter.login_succeeded(Some(IP), "bob").await;
+
+        let failed = limiter.failed.lock().await;
+        assert!(!failed.contains_key(&LoginAttemptKey::Username("bob".to_string())));
+        assert_eq!(
+            failed
+                .get(&LoginAttemptKey::Username("alice".to_string()))
+                .unwrap()
+                .count,
+            2
+        );
+        assert_eq!(failed.get(&LoginAttemptKey::Ip(IP)).unwrap().count, 2);
+    }
+
+    #[actix_web::test]
+    async fn test_login_not_counted() {
+        let limiter = limiter();
+
+        limiter.check(Some(IP), "alice").await.unwrap();
+        limiter.login_not_counted(Some(IP), "alice").await;
+
+        assert!(limiter.failed.lock().await.is_empty());
+    }
+}
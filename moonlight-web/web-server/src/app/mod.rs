@@ -1,34 +1,56 @@
 use std::{
     collections::HashMap,
     io,
+    net::IpAddr,
     ops::Deref,
     sync::{Arc, Weak},
 };
 
 use actix_web::{ResponseError, http::StatusCode, web::Bytes};
-use common::config::Config;
+use common::{
+    api_bindings::{ApiTokenScope, LogMessageType, StreamServerMessage, StreamSession},
+    config::Config,
+    ipc::{IpcSender, ServerIpcMessage},
+};
+use futures::future::join_all;
 use hex::FromHexError;
-use log::{error, warn};
+use log::{error, info, warn};
 use moonlight_common::{
     network::{ApiError, backend::reqwest::ReqwestClient, request_client::RequestClient},
     pair::PairError,
 };
 use openssl::error::ErrorStack;
 use thiserror::Error;
-use tokio::sync::RwLock;
+use tokio::{
+    process::Child,
+    sync::{Mutex, MutexGuard, Notify, RwLock},
+};
 
 use crate::app::{
-    auth::{SessionToken, UserAuth},
+    auth::{ApiToken, SessionToken, UserAuth},
+    group::GroupId,
     host::{AppId, HostId},
+    login_limit::LoginLimiter,
+    oidc::Oidc,
     password::StoragePassword,
-    storage::{Either, Storage, StorageHostModify, StorageUserAdd, create_storage},
+    storage::{
+        Either, Storage, StorageGroup, StorageGroupAdd, StorageGroupModify, StorageHostModify,
+        StorageUserAdd, StorageUserModify, create_storage,
+    },
+    stream::{ActiveStream, StreamLimitReached},
     user::{Admin, AuthenticatedUser, Role, User, UserId},
 };
 
 pub mod auth;
+pub mod group;
 pub mod host;
+pub mod login_limit;
+pub mod oidc;
 pub mod password;
+pub mod permissions;
 pub mod storage;
+pub mod stream;
+pub mod totp;
 pub mod user;
 
 #[derive(Debug, Error)]
@@ -51,6 +73,16 @@ pub enum AppError {
     HostNotPaired,
     #[error("the host was offline, but the action requires that the host is online")]
     HostOffline,
+    #[error("the stream was not found")]
+    StreamNotFound,
+    #[error("the login session was not found")]
+    LoginSessionNotFound,
+    #[error("the api token was not found")]
+    ApiTokenNotFound,
+    #[error("the group was not found")]
+    GroupNotFound,
+    #[error("the group already exists")]
+    GroupAlreadyExists,
     // -- Unauthorized
     #[error("the credentials don't exists")]
     CredentialsWrong,
@@ -60,9 +92,22 @@ pub enum AppError {
     Unauthorized,
     #[error("using a custom header for authorization is disabled")]
     HeaderAuthDisabled,
+    #[error("the id token of the openid connect issuer is invalid")]
+    OidcIdTokenInvalid,
+    #[error("the user has two factor authentication enabled, but no code was sent")]
+    TotpRequired,
     // --
     #[error("the action is not allowed with the current privileges, 403")]
     Forbidden,
+    #[error("the api token doesn't allow this action")]
+    ApiTokenNotAllowed,
+    #[error("two factor authentication must be set up before doing anything else")]
+    TotpSetupRequired,
+    // --
+    #[error("two factor authentication is already enabled")]
+    TotpAlreadyEnabled,
+    #[error("too many failed login attempts, try again in {retry_after_secs} seconds")]
+    TooManyLoginAttempts { retry_after_secs: u64 },
     // -- Bad Request
     #[error("the authorization header is not a bearer")]
     AuthorizationNotBearer,
@@ -76,13 +121,28 @@ pub enum AppError {
     NameEmpty,
     #[error("the authorization header is not a bearer")]
     BadRequest,
+    #[error("the openid connect login was not started or took too long")]
+    OidcStateInvalid,
+    #[error("the two factor code is wrong")]
+    TotpCodeWrong,
+    // --
+    #[error("openid connect login is not configured")]
+    OidcDisabled,
+    #[error("the openid connect issuer sent an invalid response")]
+    OidcResponseInvalid,
     // --
     #[error("openssl error occured: {0}")]
     OpenSSL(#[from] ErrorStack),
+    #[error("argon2 error occured: {0}")]
+    Argon2(#[from] argon2::Error),
     #[error("hex error occured: {0}")]
     Hex(#[from] FromHexError),
     #[error("io error: {0}")]
     Io(#[from] io::Error),
+    #[error("sqlite error: {0}")]
+    Sqlite(#[from] rusqlite::Error),
+    #[error("openid connect request error: {0}")]
+    OidcRequest(#[from] reqwest::Error),
     #[error("moonlight api error: {0}")]
     MoonlightApi(#[from] ApiError<<MoonlightClient as RequestClient>::Error>),
     #[error("pairing error: {0}")]
@@ -99,14 +159,31 @@ impl ResponseError for AppError {
             Self::HostNotPaired => StatusCode::FORBIDDEN,
             Self::HostPaired => StatusCode::NOT_MODIFIED,
             Self::HostOffline => StatusCode::GATEWAY_TIMEOUT,
+            Self::StreamNotFound => StatusCode::NOT_FOUND,
+            Self::LoginSessionNotFound => StatusCode::NOT_FOUND,
+            Self::ApiTokenNotFound => StatusCode::NOT_FOUND,
+            Self::GroupNotFound => StatusCode::NOT_FOUND,
+            Self::GroupAlreadyExists => StatusCode::CONFLICT,
             Self::UserNotFound => StatusCode::NOT_FOUND,
             Self::UserAlreadyExists => StatusCode::CONFLICT,
             Self::CredentialsWrong => StatusCode::UNAUTHORIZED,
             Self::SessionTokenNotFound => StatusCode::UNAUTHORIZED,
             Self::Unauthorized => StatusCode::UNAUTHORIZED,
             Self::Forbidden => StatusCode::FORBIDDEN,
+            Self::ApiTokenNotAllowed => StatusCode::FORBIDDEN,
             Self::OpenSSL(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            Self::Argon2(_) => StatusCode::INTERNAL_SERVER_ERROR,
             Self::HeaderAuthDisabled => StatusCode::UNAUTHORIZED,
+            Self::OidcIdTokenInvalid => StatusCode::UNAUTHORIZED,
+            Self::TotpRequired => StatusCode::UNAUTHORIZED,
+            Self::TotpSetupRequired => StatusCode::FORBIDDEN,
+            Self::TotpAlreadyEnabled => StatusCode::CONFLICT,
+            Self::TooManyLoginAttempts { .. } => StatusCode::TOO_MANY_REQUESTS,
+            Self::TotpCodeWrong => StatusCode::BAD_REQUEST,
+            Self::OidcStateInvalid => StatusCode::BAD_REQUEST,
+            Self::OidcDisabled => StatusCode::NOT_FOUND,
+            Self::OidcResponseInvalid => StatusCode::BAD_GATEWAY,
+            Self::OidcRequest(_) => StatusCode::BAD_GATEWAY,
             Self::Hex(_) => StatusCode::BAD_REQUEST,
             Self::AuthorizationNotBearer => StatusCode::BAD_REQUEST,
             Self::HeaderAuthMalformed => StatusCode::BAD_REQUEST,
@@ -116,6 +193,7 @@ impl ResponseError for AppError {
             Self::BadRequest => StatusCode::BAD_REQUEST,
             Self::MoonlightApi(_) => StatusCode::INTERNAL_SERVER_ERROR,
             Self::Io(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            Self::Sqlite(_) => StatusCode::INTERNAL_SERVER_ERROR,
             Self::Pairing(_) => StatusCode::INTERNAL_SERVER_ERROR,
         }
     }
@@ -136,6 +214,11 @@ struct AppInner {
     config: Config,
     storage: Arc<dyn Storage + Send + Sync>,
     app_image_cache: RwLock<HashMap<(UserId, HostId, AppId), Bytes>>,
+    streams: RwLock<HashMap<(UserId, HostId), Arc<ActiveStream>>>,
+    stream_start_lock: Mutex<()>,
+    stream_stopped: Notify,
+    oidc: Option<Oidc>,
+    login_limiter: LoginLimiter,
 }
 
 pub type MoonlightClient = ReqwestClient;
@@ -146,10 +229,18 @@ pub struct App {
 
 impl App {
     pub async fn new(config: Config) -> Result<Self, anyhow::Error> {
+        let oidc = config.web_server.oidc.clone().map(Oidc::new).transpose()?;
+        let login_limiter = LoginLimiter::new(config.web_server.login_rate_limit.clone());
+
         let app = AppInner {
             storage: create_storage(config.data_storage.clone()).await?,
+            oidc,
+            login_limiter,
             config,
             app_image_cache: Default::default(),
+            streams: Default::default(),
+            stream_start_lock: Default::default(),
+            stream_stopped: Default::default(),
         };
 
         Ok(Self {
@@ -190,6 +281,8 @@ impl App {
                 password: Some(StoragePassword::new(&password)?),
                 role: Role::Admin,
                 client_unique_id: username,
+                allow_clipboard: false,
+                oidc: None,
             })
             .await?;
 
@@ -222,6 +315,47 @@ impl App {
         Ok(user)
     }
 
+    /// Logs in with a username and password, this also creates the first user if allowed.
+    ///
+    /// Repeated failed logins from the same ip or for the same username are slowed down and locked out.
+    pub async fn user_by_login(
+        &self,
+        username: String,
+        password: String,
+        totp_code: Option<String>,
+        ip: Option<IpAddr>,
+    ) -> Result<AuthenticatedUser, AppError> {
+        let limiter = &self.inner.login_limiter;
+
+        limiter.check(ip, &username).await?;
+
+        let result = match self
+            .try_add_first_login(username.clone(), password.clone())
+            .await
+        {
+            Err(AppError::FirstUserAlreadyExists | AppError::FirstLoginCreateAdminNotSet) => {
+                self.user_by_auth(UserAuth::UserPassword {
+                    username: username.clone(),
+                    password,
+                    totp_code,
+                })
+                .await
+            }
+            result => result,
+        };
+
+        match &result {
+            Ok(_) => limiter.login_succeeded(ip, &username).await,
+            Err(
+                err
+                @ (AppError::CredentialsWrong | AppError::UserNotFound | AppError::Unauthorized),
+            ) => limiter.login_failed(ip, &username, err).await,
+            Err(_) => limiter.login_not_counted(ip, &username).await,
+        }
+
+        result
+    }
+
     /// admin: The admin that tries to do this action
     pub async fn add_user(
         &self,
@@ -271,8 +405,8 @@ impl App {
 
                 user.authenticate(&auth).await
             }
-            UserAuth::Session(session) => {
-                let user = self.user_by_session(session).await?;
+            UserAuth::Session { token, ip } => {
+                let user = self.user_by_session(token, ip).await?;
 
                 Ok(user)
             }
@@ -296,6 +430,8 @@ impl App {
                                 name: username.clone(),
                                 password: None,
                                 client_unique_id: username.clone(),
+                                allow_clipboard: false,
+                                oidc: None,
                             })
                             .await?;
 
@@ -306,7 +442,122 @@ impl App {
 
                 user.authenticate(&auth).await
             }
+            // Api tokens only work for the actions their scopes allow, see user_by_api_token
+            UserAuth::ApiToken { .. } => Err(AppError::ApiTokenNotAllowed),
+        }
+    }
+
+    /// Returns the url of the openid connect issuer where the user logs in
+    pub async fn oidc_login_url(&self) -> Result<String, AppError> {
+        let oidc = self.inner.oidc.as_ref().ok_or(AppError::OidcDisabled)?;
+
+        oidc.start_login().await
+    }
+
+    /// Finishes the openid connect login when the issuer redirected back to us.
+    ///
+    /// Users are found by the issuer and subject of the id token. Existing users with the same name are only linked
+    /// if that's enabled in the config, missing users are created like with forwarded headers.
+    /// The role is updated if a role claim is configured.
+    pub async fn user_by_oidc(
+        &self,
+        state: &str,
+        code: &str,
+    ) -> Result<AuthenticatedUser, AppError> {
+        let oidc = self.inner.oidc.as_ref().ok_or(AppError::OidcDisabled)?;
+
+        let oidc_user = oidc.finish_login(state, code).await?;
+
+        let user_id = match self
+            .inner
+            .storage
+            .get_user_by_oidc(&oidc_user.identity)
+            .await
+        {
+            Ok((user_id, _)) => user_id,
+            Err(AppError::UserNotFound) => {
+                let existing_user = if oidc.link_existing_users() {
+                    match self
+                        .inner
+                        .storage
+                        .get_user_by_name(&oidc_user.username)
+                        .await
+                    {
+                        Ok((user_id, _)) => Some(user_id),
+                        Err(AppError::UserNotFound) => None,
+                        Err(err) => return Err(err),
+                    }
+                } else {
+                    None
+                };
+
+                let Some(user_id) = existing_user else {
+                    if !oidc.auto_create_missing_user() {
+                        return Err(AppError::Unauthorized);
+                    }
+
+                    return self
+                        .add_user_no_auth(StorageUserAdd {
+                            role: oidc_user.role.unwrap_or(Role::User),
+                            name: oidc_user.username.clone(),
+                            password: None,
+                            client_unique_id: oidc_user.username,
+                            allow_clipboard: false,
+                            oidc: Some(oidc_user.identity),
+                        })
+                        .await;
+                };
+
+                // Never take over a user that belongs to another account.
+                // Users with two factor authentication would otherwise skip it with every openid connect login.
+                let existing_user = self.inner.storage.get_user(user_id).await?;
+                if existing_user.oidc.is_some()
+                    || existing_user.totp.is_some_and(|totp| totp.confirmed)
+                {
+                    return Err(AppError::Unauthorized);
+                }
+
+                self.inner
+                    .storage
+                    .modify_user(
+                        user_id,
+                        StorageUserModify {
+                            oidc: Some(Some(oidc_user.identity.clone())),
+                            ..Default::default()
+                        },
+                    )
+                    .await?;
+                info!(
+                    "Linked the user {} to the openid connect subject {} of {}",
+                    oidc_user.username, oidc_user.identity.subject, oidc_user.identity.issuer
+                );
+
+                user_id
+            }
+            Err(err) => return Err(err),
+        };
+
+        let mut user = AuthenticatedUser {
+            inner: self.user_by_id(user_id).await?,
+        };
+
+        if let Some(role) = oidc_user.role
+            && user.role().await? != role
+        {
+            self.inner
+                .storage
+                .modify_user(
+                    user.id(),
+                    StorageUserModify {
+                        role: Some(role),
+                        ..Default::default()
+                    },
+                )
+                .await?;
+            user.cache_storage = None;
         }
+
+        Ok(user)
     }
 
     pub async fn user_by_id(&self, user_id: UserId) -> Result<User, AppError> {
@@ -330,11 +581,12 @@ impl App {
     pub async fn user_by_session(
         &self,
         session: SessionToken,
+        ip: Option<IpAddr>,
     ) -> Result<AuthenticatedUser, AppError> {
         let (user_id, user) = self
             .inner
             .storage
-            .get_user_by_session_token(session)
+            .get_user_by_session_token(session, ip)
             .await?;
 
         Ok(AuthenticatedUser {
@@ -346,6 +598,24 @@ impl App {
         })
     }
 
+    /// The returned user is only allowed to do what the scopes allow
+    pub async fn user_by_api_token(
+        &self,
+        token: ApiToken,
+    ) -> Result<(AuthenticatedUser, Vec<ApiTokenScope>), AppError> {
+        let (user_id, scopes) = self.inner.storage.get_user_by_api_token(token).await?;
+
+        let user = AuthenticatedUser {
+            inner: User {
+                app: self.new_ref(),
+                id: user_id,
+                cache_storage: None,
+            },
+        };
+
+        Ok((user, scopes))
+    }
+
     pub async fn all_users(&self, _: Admin) -> Result<Vec<User>, AppError> {
         let users = self.inner.storage.list_users().await?;
 
@@ -371,7 +641,302 @@ impl App {
         Ok(users)
     }
 
+    pub async fn add_group(
+        &self,
+        _: &Admin,
+        group: StorageGroupAdd,
+    ) -> Result<StorageGroup, AppError> {
+        if group.name.is_empty() {
+            return Err(AppError::NameEmpty);
+        }
+
+        self.inner.storage.add_group(group).await
+    }
+
+    pub async fn modify_group(
+        &self,
+        _: &Admin,
+        group_id: GroupId,
+        group: StorageGroupModify,
+    ) -> Result<(), AppError> {
+        if group.name.as_ref().is_some_and(|name| name.is_empty()) {
+            return Err(AppError::NameEmpty);
+        }
+
+        // Only existing users and hosts can be added
+        for user_id in group.members.iter().flatten() {
+            self.inner.storage.get_user(*user_id).await?;
+        }
+        for host_id in group.hosts.iter().flatten() {
+            self.inner.storage.get_host(*host_id).await?;
+        }
+
+        self.inner.storage.modify_group(group_id, group).await
+    }
+
+    pub async fn remove_group(&self, _: &Admin, group_id: GroupId) -> Result<(), AppError> {
+        self.inner.storage.remove_group(group_id).await
+    }
+
+    pub async fn all_groups(&self, _: &Admin) -> Result<Vec<StorageGroup>, AppError> {
+        self.inner.storage.list_groups().await
+    }
+
     pub async fn delete_session(&self, session: SessionToken) -> Result<(), AppError> {
         self.inner.storage.remove_session_token(session).await
     }
+
+    // -- Streams
+    pub async fn register_stream(
+        &self,
+        owner: &AuthenticatedUser,
+        host_id: HostId,
+        app_id: AppId,
+        child: Child,
+        ipc_sender: IpcSender<ServerIpcMessage>,
+    ) -> Arc<ActiveStream> {
+        let stream = Arc::new(ActiveStream::new(
+            owner.id(),
+            host_id,
+            app_id,
+            child,
+            ipc_sender,
+        ));
+
+        let mut streams = self.inner.streams.write().await;
+        if let Some(old_stream) = streams.insert((owner.id(), host_id), stream.clone()) {
+            warn!(
+                "registered a new stream for {:?} on host {host_id:?} while another one was still running",
+                owner.id()
+            );
+            old_stream.send_ipc(ServerIpcMessage::Stop).await;
+            old_stream.close_spectators().await;
+        }
+
+        stream
+    }
+
+    /// Called once the streamer stopped
+    pub async fn unregister_stream(&self, stream: &Arc<ActiveStream>) {
+        {
+            let mut streams = self.inner.streams.write().await;
+
+            let key = (stream.owner(), stream.host_id());
+            if streams
+                .get(&key)
+                .is_some_and(|registered| Arc::ptr_eq(registered, stream))
+            {
+                streams.remove(&key);
+            }
+        }
+        self.inner.stream_stopped.notify_waiters();
+
+        stream.close_owner().await;
+        stream.close_spectators().await;
+    }
+
+    /// Held from checking the stream limits until the new stream is registered
+    pub async fn lock_stream_start(&self) -> MutexGuard<'_, ()> {
+        self.inner.stream_start_lock.lock().await
+    }
+
+    /// Checks if the user is allowed to start a new stream on this host.
+    /// A running stream of the user on this host doesn't count because the new stream replaces it.
+    pub async fn check_stream_limits(
+        &self,
+        user: &AuthenticatedUser,
+        host_id: HostId,
+    ) -> Result<(), StreamLimitReached> {
+        let config = &self.inner.config.stream;
+        let streams = self.inner.streams.read().await;
+
+        let (mut total, mut user_count, mut host_count) = (0, 0, 0);
+        for (stream_user_id, stream_host_id) in streams.keys() {
+            if (*stream_user_id, *stream_host_id) == (user.id(), host_id) {
+                continue;
+            }
+
+            total += 1;
+            if *stream_user_id == user.id() {
+                user_count += 1;
+            }
+            if *stream_host_id == host_id {
+                host_count += 1;
+            }
+        }
+
+        if config
+            .max_streams_per_user
+            .is_some_and(|max| user_count >= max)
+        {
+            return Err(StreamLimitReached::User);
+        }
+        if config
+            .max_streams_per_host
+            .is_some_and(|max| host_count >= max)
+        {
+            return Err(StreamLimitReached::Host);
+        }
+        if config.max_streams.is_some_and(|max| total >= max) {
+            return Err(StreamLimitReached::Server);
+        }
+
+        Ok(())
+    }
+
+    /// Resolves once a stream stopped
+    pub async fn stream_stopped(&self) {
+        self.inner.stream_stopped.notified().await;
+    }
+
+    /// Stops the running stream of the user on this host and waits until its streamer exited.
+    /// Used when the user starts a different app, so the old and the new streamer don't both use the host.
+    pub async fn stop_own_stream(&self, user: &AuthenticatedUser, host_id: HostId) {
+        let old_stream = {
+            let mut streams = self.inner.streams.write().await;
+
+            streams.remove(&(user.id(), host_id))
+        };
+
+        if let Some(old_stream) = old_stream {
+            info!(
+                "{:?} starts a different app on host {host_id:?}, stopping the running stream",
+                user.id()
+            );
+
+            old_stream.close_spectators().await;
+            old_stream.stop().await;
+        }
+    }
+
+    /// Stops the streams of all other users on this host and waits until their streamers exited
+    pub async fn take_over_host(&self, user: &AuthenticatedUser, host_id: HostId) {
+        let taken_over = {
+            let mut streams = self.inner.streams.write().await;
+
+            let keys = streams
+                .keys()
+                .filter(|(stream_user_id, stream_host_id)| {
+                    *stream_host_id == host_id && *stream_user_id != user.id()
+                })
+                .copied()
+                .collect::<Vec<_>>();
+
+            keys.into_iter()
+                .filter_map(|key| streams.remove(&key))
+                .collect::<Vec<_>>()
+        };
+
+        for stream in &taken_over {
+            info!(
+                "{:?} takes over the host {host_id:?} from {:?}",
+                user.id(),
+                stream.owner()
+            );
+
+            let _ = stream
+                .send_owner_message(StreamServerMessage::DebugLog {
+                    message: "The stream was stopped because another user took over the host"
+                        .to_string(),
+                    ty: Some(LogMessageType::FatalDescription),
+                })
+                .await;
+        }
+
+        // The host would otherwise still run the old session when the new one launches
+        join_all(taken_over.iter().map(|stream| stream.stop())).await;
+    }
+
+    /// The stream that the user owns on this host
+    pub async fn owned_stream(
+        &self,
+        user: &AuthenticatedUser,
+        host_id: HostId,
+    ) -> Result<Arc<ActiveStream>, AppError> {
+        let streams = self.inner.streams.read().await;
+
+        streams
+            .get(&(user.id(), host_id))
+            .cloned()
+            .ok_or(AppError::StreamNotFound)
+    }
+
+    /// The stream of this app that the user owns on this host which the user can reconnect to
+    pub async fn resumable_stream(
+        &self,
+        user: &AuthenticatedUser,
+        host_id: HostId,
+        app_id: AppId,
+    ) -> Option<Arc<ActiveStream>> {
+        let streams = self.inner.streams.read().await;
+
+        streams
+            .get(&(user.id(), host_id))
+            .filter(|stream| stream.app_id() == app_id)
+            .cloned()
+    }
+
+    pub async fn stream_sessions(&self, _: &Admin) -> Vec<StreamSession> {
+        let streams = self.all_streams().await;
+
+        join_all(streams.iter().map(|stream| stream.session())).await
+    }
+
+    pub async fn stop_stream(
+        &self,
+        _: &Admin,
+        user_id: UserId,
+        host_id: HostId,
+    ) -> Result<(), AppError> {
+        let stream = {
+            let streams = self.inner.streams.read().await;
+
+            streams
+                .get(&(user_id, host_id))
+                .cloned()
+                .ok_or(AppError::StreamNotFound)?
+        };
+
+        stream.stop().await;
+
+        Ok(())
+    }
+
+    /// Stops all streamers, called when the web server shuts down
+    pub async fn stop_all_streams(&self) {
+        let streams = self.all_streams().await;
+
+        join_all(streams.iter().map(|stream| stream.stop())).await;
+    }
+
+    async fn all_streams(&self) -> Vec<Arc<ActiveStream>> {
+        let streams = self.inner.streams.read().await;
+
+        streams.values().cloned().collect()
+    }
+
+    /// A stream on this host that the user was invited to spectate
+    pub async fn spectatable_stream(
+        &self,
+        user: &AuthenticatedUser,
+        host_id: HostId,
+    ) -> Result<Arc<ActiveStream>, AppError> {
+        let streams = self
+            .inner
+            .streams
+            .read()
+            .await
+            .values()
+            .filter(|stream| stream.host_id() == host_id)
+            .cloned()
+            .collect::<Vec<_>>();
+
+        for stream in streams {
+            if stream.is_invited(user.id()).await {
+                return Ok(stream);
+            }
+        }
+
+        Err(AppError::StreamNotFound)
+    }
 }
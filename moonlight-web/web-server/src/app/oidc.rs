@@ -0,0 +1,460 @@
+use std::{
+    collections::HashMap,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use common::config::OidcConfig;
+use openssl::{rand::rand_bytes, sha::sha256};
+use reqwest::Client;
+use serde::Deserialize;
+use serde_json::Value;
+use tokio::sync::{Mutex, RwLock};
+use url::Url;
+
+use crate::app::{
+    AppError,
+    storage::{StorageOidcIdentity, unix_now},
+    user::Role,
+};
+
+/// How long the user has to log in at the issuer
+const PENDING_LOGIN_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+/// Starting a login doesn't need authentication, so the oldest pending logins are dropped above this
+const MAX_PENDING_LOGINS: usize = 1024;
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Deserialize)]
+struct OidcDiscovery {
+    issuer: String,
+    authorization_endpoint: String,
+    token_endpoint: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    id_token: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum Audience {
+    One(String),
+    Many(Vec<String>),
+}
+
+#[derive(Debug, Deserialize)]
+struct IdTokenClaims {
+    iss: String,
+    sub: String,
+    aud: Audience,
+    exp: u64,
+    nonce: Option<String>,
+    #[serde(flatten)]
+    other: HashMap<String, Value>,
+}
+
+struct PendingLogin {
+    code_verifier: String,
+    nonce: String,
+    created_at: Instant,
+}
+
+/// The user that the issuer authenticated
+pub struct OidcUser {
+    pub identity: StorageOidcIdentity,
+    /// The name for a new user
+    pub username: String,
+    /// None if no role claim is configured
+    pub role: Option<Role>,
+}
+
+pub struct Oidc {
+    config: OidcConfig,
+    client: Client,
+    discovery: RwLock<Option<Arc<OidcDiscovery>>>,
+    /// Logins that were started but didn't come back from the issuer yet, by their state
+    pending: Mutex<HashMap<String, PendingLogin>>,
+}
+
+fn random_string(len: usize) -> Result<String, AppError> {
+    let mut bytes = vec![0; len];
+    rand_bytes(&mut bytes)?;
+
+    Ok(URL_SAFE_NO_PAD.encode(bytes))
+}
+
+impl Oidc {
+    pub fn new(config: OidcConfig) -> Result<Self, anyhow::Error> {
+        let client = Client::builder().timeout(REQUEST_TIMEOUT).build()?;
+
+        Ok(Self {
+            config,
+            client,
+            discovery: Default::default(),
+            pending: Default::default(),
+        })
+    }
+
+    async fn discovery(&self) -> Result<Arc<OidcDiscovery>, AppError> {
+        if let Some(discovery) = self.discovery.read().await.as_ref() {
+            return Ok(discovery.clone());
+        }
+
+        let url = format!(
+            "{}/.well-known/openid-configuration",
+            self.config.issuer_url.trim_end_matches('/')
+        );
+        let response = self
+            .client
+            .get(url)
+            .send()
+            .await?
+            .error_for_status()?
+            .bytes()
+            .await?;
+
+        let discovery = serde_json::from_slice::<OidcDiscovery>(&response)
+            .map_err(|_| AppError::OidcResponseInvalid)?;
+        let discovery = Arc::new(discovery);
+
+        *self.discovery.write().await = Some(discovery.clone());
+
+        Ok(discovery)
+    }
+
+    /// Returns the url of the issuer the browser should be redirected to
+    pub async fn start_login(&self) -> Result<String, AppError> {
+        let discovery = self.discovery().await?;
+
+        let state = random_string(16)?;
+        let nonce = random_string(16)?;
+        let code_verifier = random_string(32)?;
+        let code_challenge = URL_SAFE_NO_PAD.encode(sha256(code_verifier.as_bytes()));
+
+        let url = Url::parse_with_params(
+            &discovery.authorization_endpoint,
+            &[
+                ("response_type", "code"),
+                ("client_id", self.config.client_id.as_str()),
+                ("redirect_uri", self.config.redirect_url.as_str()),
+                ("scope", self.config.scopes.join(" ").as_str()),
+                ("state", state.as_str()),
+                ("nonce", nonce.as_str()),
+                ("code_challenge", code_challenge.as_str()),
+                ("code_challenge_method", "S256"),
+            ],
+        )
+        .map_err(|_| AppError::OidcResponseInvalid)?;
+
+        let mut pending = self.pending.lock().await;
+
+        pending.retain(|_, login| login.created_at.elapsed() < PENDING_LOGIN_TIMEOUT);
+        while pending.len() >= MAX_PENDING_LOGINS {
+            let Some(oldest) = pending
+                .iter()
+                .min_by_key(|(_, login)| login.created_at)
+                .map(|(state, _)| state.clone())
+            else {
+                break;
+            };
+            pending.remove(&oldest);
+        }
+        pending.insert(
+            state,
+            PendingLogin {
+                code_verifier,
+                nonce,
+                created_at: Instant::now(),
+            },
+        );
+
+        Ok(url.into())
+    }
+
+    /// Exchanges the code the issuer sent back for the id token and reads the user from it
+    pub async fn finish_login(&self, state: &str, code: &str) -> Result<OidcUser, AppError> {
+        let login = self
+            .pending
+            .lock()
+            .await
+            .remove(state)
+            .filter(|login| login.created_at.elapsed() < PENDING_LOGIN_TIMEOUT)
+            .ok_or(AppError::OidcStateInvalid)?;
+
+        let discovery = self.discovery().await?;
+
+        let mut form = vec![
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("redirect_uri", self.config.redirect_url.as_str()),
+            ("client_id", self.config.client_id.as_str()),
+            ("code_verifier", login.code_verifier.as_str()),
+        ];
+        if let Some(client_secret) = &self.config.client_secret {
+            form.push(("client_secret", client_secret.as_str()));
+        }
+
+        let response = self
+            .client
+            .post(&discovery.token_endpoint)
+            .form(&form)
+            .send()
+            .await?
+            .error_for_status()?
+            .bytes()
+            .await?;
+
+        let token = serde_json::from_slice::<TokenResponse>(&response)
+            .map_err(|_| AppError::OidcResponseInvalid)?;
+
+        // The id token came directly from the token endpoint over tls, so the tls validation of the issuer
+        // replaces the signature check (OpenID Connect Core 3.1.3.7)
+        let claims = token
+            .id_token
+            .split('.')
+            .nth(1)
+            .and_then(|payload| URL_SAFE_NO_PAD.decode(payload).ok())
+            .and_then(|payload| serde_json::from_slice::<IdTokenClaims>(&payload).ok())
+            .ok_or(AppError::OidcIdTokenInvalid)?;
+
+        let audience_valid = match &claims.aud {
+            Audience::One(audience) => *audience == self.config.client_id,
+            Audience::Many(audiences) => audiences.contains(&self.config.client_id),
+        };
+        if claims.iss != discovery.issuer
+            || !audience_valid
+            || claims.exp <= unix_now()
+            || claims.nonce.as_deref() != Some(login.nonce.as_str())
+        {
+            return Err(AppError::OidcIdTokenInvalid);
+        }
+
+        if claims.sub.is_empty() {
+            return Err(AppError::OidcIdTokenInvalid);
+        }
+
+        // The subject isn't part of the other claims
+        let username = if self.config.username_claim == "sub" {
+            Some(claims.sub.as_str())
+        } else {
+            claims
+                .other
+                .get(&self.config.username_claim)
+                .and_then(Value::as_str)
+        }
+        .filter(|username| !username.is_empty())
+        .ok_or(AppError::OidcIdTokenInvalid)?
+        .to_string();
+
+        let role = self.config.role_claim.as_ref().map(|role_claim| {
+            let is_admin = match claims.other.get(role_claim) {
+                Some(Value::String(value)) => self.config.admin_values.contains(value),
+                Some(Value::Array(values)) => values.iter().any(|value| {
+                    value
+                        .as_str()
+                        .is_some_and(|value| self.config.admin_values.iter().any(|v| v == value))
+                }),
+                _ => false,
+            };
+
+            if is_admin { Role::Admin } else { Role::User }
+        });
+
+        Ok(OidcUser {
+            identity: StorageOidcIdentity {
+                issuer: claims.iss,
+                subject: claims.sub,
+            },
+            username,
+            role,
+        })
+    }
+
+    pub fn auto_create_missing_user(&self) -> bool {
+        self.config.auto_create_missing_user
+    }
+
+    pub fn link_existing_users(&self) -> bool {
+        self.config.link_existing_users
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use std::{net::TcpListener, sync::Mutex as StdMutex};
+
+    use actix_web::{
+        App, HttpResponse, HttpServer,
+        web::{self, Data},
+    };
+    use serde_json::json;
+
+    use super::*;
+
+    /// Serves the discovery document and hands out an id token with the claims of the test
+    struct MockIssuer {
+        url: String,
+        claims: StdMutex<Value>,
+    }
+
+    async fn mock_discovery(issuer: Data<MockIssuer>) -> HttpResponse {
+        HttpResponse::Ok().json(json!({
+            "issuer": issuer.url,
+            "authorization_endpoint": format!("{}/authorize", issuer.url),
+            "token_endpoint": format!("{}/token", issuer.url),
+        }))
+    }
+
+    async fn mock_token(issuer: Data<MockIssuer>) -> HttpResponse {
+        let claims = issuer.claims.lock().unwrap().to_string();
+        let id_token = format!(
+            "{}.{}.",
+            URL_SAFE_NO_PAD.encode(r#"{"alg":"none"}"#),
+            URL_SAFE_NO_PAD.encode(claims)
+        );
+
+        HttpResponse::Ok().json(json!({ "id_token": id_token }))
+    }
+
+    fn start_issuer() -> Data<MockIssuer> {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let issuer = Data::new(MockIssuer {
+            url: format!("http://{}", listener.local_addr().unwrap()),
+            claims: StdMutex::new(Value::Null),
+        });
+
+        let server = HttpServer::new({
+            let issuer = issuer.clone();
+            move || {
+                App::new()
+                    .app_data(issuer.clone())
+                    .route(
+                        "/.well-known/openid-configuration",
+                        web::get().to(mock_discovery),
+                    )
+                    .route("/token", web::post().to(mock_token))
+            }
+        })
+        .workers(1)
+        .listen(listener)
+        .unwrap()
+        .run();
+        actix_web::rt::spawn(server);
+
+        issuer
+    }
+
+    fn config(issuer: &MockIssuer) -> OidcConfig {
+        OidcConfig {
+            issuer_url: issuer.url.clone(),
+            client_id: "moonlight-web".to_string(),
+            client_secret: None,
+            redirect_url: "https://moonlight.test/api/oidc/callback".to_string(),
+            scopes: vec!["openid".to_string()],
+            username_claim: "preferred_username".to_string(),
+            role_claim: Some("groups".to_string()),
+            admin_values: vec!["admins".to_string()],
+            auto_create_missing_user: true,
+            link_existing_users: false,
+            display_name: "Single Sign-On".to_string(),
+        }
+    }
+
+    fn claims(issuer: &MockIssuer, nonce: &str) -> Value {
+        json!({
+            "iss": issuer.url,
+            "sub": "subject-1",
+            "aud": "moonlight-web",
+            "exp": unix_now() + 60,
+            "nonce": nonce,
+            "preferred_username": "alice",
+            "groups": ["users", "admins"],
+        })
+    }
+
+    /// Returns the state and nonce that the browser would take to the issuer
+    async fn start_login(oidc: &Oidc) -> (String, String) {
+        let url = Url::parse(&oidc.start_login().await.unwrap()).unwrap();
+        let param = |name: &str| {
+            url.query_pairs()
+                .find(|(key, _)| key == name)
+                .map(|(_, value)| value.into_owned())
+                .unwrap()
+        };
+
+        (param("state"), param("nonce"))
+    }
+
+    #[actix_web::test]
+    async fn test_login() {
+        let issuer = start_issuer();
+        let oidc = Oidc::new(config(&issuer)).unwrap();
+
+        let (state, nonce) = start_login(&oidc).await;
+        *issuer.claims.lock().unwrap() = claims(&issuer, &nonce);
+
+        let user = oidc.finish_login(&state, "code").await.unwrap();
+        assert_eq!(
+            user.identity,
+            StorageOidcIdentity {
+                issuer: issuer.url.clone(),
+                subject: "subject-1".to_string(),
+            }
+        );
+        assert_eq!(user.username, "alice");
+        assert_eq!(user.role, Some(Role::Admin));
+
+        // The state can only be used once
+        assert!(matches!(
+            oidc.finish_login(&state, "code").await,
+            Err(AppError::OidcStateInvalid)
+        ));
+    }
+
+    #[actix_web::test]
+    async fn test_reject_invalid_id_token() {
+        let issuer = start_issuer();
+        let oidc = Oidc::new(config(&issuer)).unwrap();
+
+        for (claim, value) in [
+            ("iss", json!("https://other-issuer.test")),
+            ("aud", json!("other-client")),
+            ("exp", json!(0)),
+            ("nonce", json!("other-nonce")),
+            ("sub", json!("")),
+            ("preferred_username", json!(null)),
+        ] {
+            let (state, nonce) = start_login(&oidc).await;
+
+            let mut claims = claims(&issuer, &nonce);
+            claims[claim] = value;
+            *issuer.claims.lock().unwrap() = claims;
+
+            assert!(
+                matches!(
+                    oidc.finish_login(&state, "code").await,
+                    Err(AppError::OidcIdTokenInvalid)
+                ),
+                "the id token with the claim {claim} was accepted"
+            );
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_pending_logins_are_capped() {
+        let issuer = start_issuer();
+        let oidc = Oidc::new(config(&issuer)).unwrap();
+
+        let (first_state, _) = start_login(&oidc).await;
+        for _ in 0..MAX_PENDING_LOGINS {
+            oidc.start_login().await.unwrap();
+        }
+
+        let pending = oidc.pending.lock().await;
+        assert_eq!(pending.len(), MAX_PENDING_LOGINS);
+        assert!(!pending.contains_key(&first_state));
+    }
+}
@@ -1,28 +1,70 @@
-use openssl::{hash::MessageDigest, pkcs5, rand::rand_bytes};
+use argon2::{Algorithm, Argon2, Params, Version};
+use openssl::{hash::MessageDigest, memcmp, pkcs5, rand::rand_bytes};
 
 use crate::app::AppError;
 
-const HASH_ITERATIONS: usize = 150_000;
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PasswordAlgorithm {
+    /// Passwords set before Argon2id was used, these are rehashed on the next login
+    Pbkdf2Sha256 { iterations: u32 },
+    Argon2id {
+        memory_kib: u32,
+        iterations: u32,
+        parallelism: u32,
+    },
+}
+
+impl PasswordAlgorithm {
+    /// The algorithm new passwords are hashed with, these are the parameters recommended by OWASP
+    pub const CURRENT: Self = Self::Argon2id {
+        memory_kib: 19 * 1024,
+        iterations: 2,
+        parallelism: 1,
+    };
+}
 
 #[derive(Clone)]
 pub struct StoragePassword {
+    pub algorithm: PasswordAlgorithm,
     pub salt: [u8; 16],
     pub hash: [u8; 32],
 }
 
 impl StoragePassword {
-    fn hash(salt: &[u8; 16], password: &str, out: &mut [u8; 32]) -> Result<(), AppError> {
+    fn hash(
+        algorithm: PasswordAlgorithm,
+        salt: &[u8; 16],
+        password: &str,
+        out: &mut [u8; 32],
+    ) -> Result<(), AppError> {
         if password.is_empty() {
             return Err(AppError::PasswordEmpty);
         }
 
-        pkcs5::pbkdf2_hmac(
-            password.as_bytes(),
-            salt,
-            HASH_ITERATIONS,
-            MessageDigest::sha256(),
-            out,
-        )?;
+        match algorithm {
+            PasswordAlgorithm::Pbkdf2Sha256 { iterations } => {
+                pkcs5::pbkdf2_hmac(
+                    password.as_bytes(),
+                    salt,
+                    iterations as usize,
+                    MessageDigest::sha256(),
+                    out,
+                )?;
+            }
+            PasswordAlgorithm::Argon2id {
+                memory_kib,
+                iterations,
+                parallelism,
+            } => {
+                let params = Params::new(memory_kib, iterations, parallelism, Some(out.len()))?;
+
+                Argon2::new(Algorithm::Argon2id, Version::V0x13, params).hash_password_into(
+                    password.as_bytes(),
+                    salt,
+                    out,
+                )?;
+            }
+        }
 
         Ok(())
     }
@@ -34,15 +76,80 @@ impl StoragePassword {
 
         let mut hash = [0u8; 32];
 
-        Self::hash(&salt, password, &mut hash)?;
+        Self::hash(PasswordAlgorithm::CURRENT, &salt, password, &mut hash)?;
 
-        Ok(Self { salt, hash })
+        Ok(Self {
+            algorithm: PasswordAlgorithm::CURRENT,
+            salt,
+            hash,
+        })
     }
 
     pub fn verify(&self, password: &str) -> Result<bool, AppError> {
         let mut hash = [0u8; 32];
-        Self::hash(&self.salt, password, &mut hash)?;
+        Self::hash(self.algorithm, &self.salt, password, &mut hash)?;
+
+        Ok(memcmp::eq(&self.hash, &hash))
+    }
+
+    /// Whether the password should be hashed again with the current algorithm after it was verified
+    pub fn needs_rehash(&self) -> bool {
+        self.algorithm != PasswordAlgorithm::CURRENT
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    /// PBKDF2-HMAC-SHA256 with 150000 iterations like v2 of the json storage
+    fn legacy_password() -> StoragePassword {
+        StoragePassword {
+            algorithm: PasswordAlgorithm::Pbkdf2Sha256 {
+                iterations: 150_000,
+            },
+            salt: std::array::from_fn(|index| index as u8),
+            hash: hex::decode("b88bf3ac2789dae566df22e7c0a9d89d7a817dfc3269138ef18dce9775b89181")
+                .unwrap()
+                .try_into()
+                .unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_verify_legacy_pbkdf2() {
+        let password = legacy_password();
+
+        assert!(password.verify("hunter2").unwrap());
+        assert!(!password.verify("hunter3").unwrap());
+        assert!(password.needs_rehash());
+    }
+
+    #[test]
+    fn test_new_password() {
+        let password = StoragePassword::new("hunter2").unwrap();
+
+        assert_eq!(password.algorithm, PasswordAlgorithm::CURRENT);
+        assert!(password.verify("hunter2").unwrap());
+        assert!(!password.verify("hunter3").unwrap());
+        assert!(!password.needs_rehash());
+
+        assert!(matches!(
+            StoragePassword::new(""),
+            Err(AppError::PasswordEmpty)
+        ));
+    }
+
+    #[test]
+    fn test_needs_rehash_with_other_params() {
+        let mut password = StoragePassword::new("hunter2").unwrap();
+        password.algorithm = PasswordAlgorithm::Argon2id {
+            memory_kib: 8 * 1024,
+            iterations: 2,
+            parallelism: 1,
+        };
 
-        Ok(self.hash == hash)
+        assert!(password.needs_rehash());
     }
 }
@@ -0,0 +1,256 @@
+use common::api_bindings::{
+    PermissionTimeWindow, ReencodeSettings, StreamClientMessage, UserPermissions,
+};
+
+use crate::app::{
+    host::{AppId, HostId},
+    storage::unix_now,
+};
+
+const DAY_SECONDS: u64 = 24 * 60 * 60;
+
+pub fn is_global_host_allowed(permissions: &UserPermissions, host_id: HostId) -> bool {
+    permissions
+        .global_hosts
+        .as_ref()
+        .is_none_or(|global_hosts| global_hosts.contains(&host_id.0))
+}
+
+pub fn is_app_allowed(permissions: &UserPermissions, host_id: HostId, app_id: AppId) -> bool {
+    permissions
+        .allowed_apps
+        .iter()
+        .find(|allowed_apps| allowed_apps.host_id == host_id.0)
+        .is_none_or(|allowed_apps| allowed_apps.app_ids.contains(&app_id.0))
+}
+
+fn time_window_contains(time_window: &PermissionTimeWindow, weekday: u8, minute: u16) -> bool {
+    let PermissionTimeWindow {
+        weekdays,
+        start_minute,
+        end_minute,
+    } = time_window;
+
+    if start_minute <= end_minute {
+        weekdays.contains(&weekday) && (*start_minute..*end_minute).contains(&minute)
+    } else {
+        // The window started on the day before and goes past midnight
+        let previous_weekday = (weekday + 6) % 7;
+
+        (weekdays.contains(&weekday) && minute >= *start_minute)
+            || (weekdays.contains(&previous_weekday) && minute < *end_minute)
+    }
+}
+
+/// If the permissions allow to start a stream right now
+pub fn is_stream_time_allowed(permissions: &UserPermissions) -> bool {
+    if permissions.time_windows.is_empty() {
+        return true;
+    }
+
+    let now = unix_now();
+    // The unix epoch was a thursday
+    let weekday = ((now / DAY_SECONDS + 3) % 7) as u8;
+    let minute = ((now % DAY_SECONDS) / 60) as u16;
+
+    permissions
+        .time_windows
+        .iter()
+        .any(|time_window| time_window_contains(time_window, weekday, minute))
+}
+
+fn restrict_reencode(permissions: &UserPermissions, reencode: &mut Option<ReencodeSettings>) {
+    if !permissions.can_reencode {
+        *reencode = None;
+    }
+
+    if let Some(reencode) = reencode
+        && let Some(max_bitrate) = permissions.max_bitrate
+    {
+        reencode.bitrate_kbps = reencode.bitrate_kbps.min(max_bitrate);
+    }
+}
+
+/// Applies the bitrate, resolution and reencode limits to the stream settings the client requested
+pub fn restrict_stream_message(permissions: &UserPermissions, message: &mut StreamClientMessage) {
+    match message {
+        StreamClientMessage::StartStream {
+            bitrate,
+            width,
+            height,
+            reencode,
+            ..
+        } => {
+            if let Some(max_bitrate) = permissions.max_bitrate {
+                *bitrate = (*bitrate).min(max_bitrate);
+            }
+
+            // Scale the resolution down and keep the aspect ratio
+            if let Some(max_width) = permissions.max_width
+                && *width > max_width
+            {
+                *height = (*height as u64 * max_width as u64 / *width as u64) as u32;
+                *width = max_width;
+            }
+            if let Some(max_height) = permissions.max_height
+                && *height > max_height
+            {
+                *width = (*width as u64 * max_height as u64 / *height as u64) as u32;
+                *height = max_height;
+            }
+
+            restrict_reencode(permissions, reencode);
+        }
+        StreamClientMessage::UpdateReencode { reencode } => {
+            restrict_reencode(permissions, reencode);
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use common::api_bindings::{ReencodeCodec, StreamColorspace};
+
+    use super::*;
+
+    const MONDAY: u8 = 0;
+    const TUESDAY: u8 = 1;
+    const SUNDAY: u8 = 6;
+
+    fn start_stream(bitrate: u32, width: u32, height: u32) -> StreamClientMessage {
+        StreamClientMessage::StartStream {
+            bitrate,
+            packet_size: 1024,
+            fps: 60,
+            width,
+            height,
+            play_audio_local: false,
+            video_supported_formats: 1,
+            video_colorspace: StreamColorspace::Rec709,
+            video_color_range_full: false,
+            hdr: false,
+            reencode: Some(ReencodeSettings {
+                enabled: true,
+                codec: ReencodeCodec::H264,
+                bitrate_kbps: bitrate,
+                preset: None,
+                threads: None,
+            }),
+            gamepads_attached: 0,
+            gamepads_persist: false,
+            horizontal_scroll: false,
+        }
+    }
+
+    /// The bitrate, resolution and reencode bitrate after the restriction
+    fn restricted(
+        permissions: &UserPermissions,
+        mut message: StreamClientMessage,
+    ) -> (u32, u32, u32, Option<u32>) {
+        restrict_stream_message(permissions, &mut message);
+
+        let StreamClientMessage::StartStream {
+            bitrate,
+            width,
+            height,
+            reencode,
+            ..
+        } = message
+        else {
+            unreachable!()
+        };
+
+        (
+            bitrate,
+            width,
+            height,
+            reencode.map(|reencode| reencode.bitrate_kbps),
+        )
+    }
+
+    #[test]
+    fn test_time_window_same_day() {
+        let time_window = PermissionTimeWindow {
+            weekdays: vec![MONDAY],
+            start_minute: 8 * 60,
+            end_minute: 17 * 60,
+        };
+
+        assert!(time_window_contains(&time_window, MONDAY, 8 * 60));
+        assert!(time_window_contains(&time_window, MONDAY, 17 * 60 - 1));
+        assert!(!time_window_contains(&time_window, MONDAY, 17 * 60));
+        assert!(!time_window_contains(&time_window, MONDAY, 7 * 60));
+        assert!(!time_window_contains(&time_window, TUESDAY, 12 * 60));
+    }
+
+    #[test]
+    fn test_time_window_across_midnight() {
+        // From sunday 22:00 until monday 02:00
+        let time_window = PermissionTimeWindow {
+            weekdays: vec![SUNDAY],
+            start_minute: 22 * 60,
+            end_minute: 2 * 60,
+        };
+
+        assert!(time_window_contains(&time_window, SUNDAY, 23 * 60));
+        assert!(time_window_contains(&time_window, MONDAY, 60));
+        assert!(!time_window_contains(&time_window, MONDAY, 2 * 60));
+        assert!(!time_window_contains(&time_window, MONDAY, 23 * 60));
+        assert!(!time_window_contains(&time_window, SUNDAY, 60));
+        assert!(!time_window_contains(&time_window, SUNDAY, 12 * 60));
+    }
+
+    #[test]
+    fn test_no_time_windows_always_allowed() {
+        assert!(is_stream_time_allowed(&UserPermissions::default()));
+    }
+
+    #[test]
+    fn test_resolution_clamping_keeps_aspect_ratio() {
+        let permissions = UserPermissions {
+            max_width: Some(1280),
+            ..Default::default()
+        };
+        assert_eq!(
+            restricted(&permissions, start_stream(10_000, 1920, 1080)),
+            (10_000, 1280, 720, Some(10_000))
+        );
+        // Smaller resolutions stay the same
+        assert_eq!(
+            restricted(&permissions, start_stream(10_000, 1024, 768)),
+            (10_000, 1024, 768, Some(10_000))
+        );
+
+        let permissions = UserPermissions {
+            max_width: Some(1280),
+            max_height: Some(600),
+            ..Default::default()
+        };
+        assert_eq!(
+            restricted(&permissions, start_stream(10_000, 1920, 1080)),
+            (10_000, 1066, 600, Some(10_000))
+        );
+    }
+
+    #[test]
+    fn test_bitrate_and_reencode() {
+        let permissions = UserPermissions {
+            max_bitrate: Some(5_000),
+            ..Default::default()
+        };
+        assert_eq!(
+            restricted(&permissions, start_stream(10_000, 1920, 1080)),
+            (5_000, 1920, 1080, Some(5_000))
+        );
+
+        let permissions = UserPermissions {
+            can_reencode: false,
+            ..Default::default()
+        };
+        assert_eq!(
+            restricted(&permissions, start_stream(10_000, 1920, 1080)),
+            (10_000, 1920, 1080, None)
+        );
+    }
+}
@@ -1,13 +1,15 @@
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     io::ErrorKind,
-    path::PathBuf,
+    net::IpAddr,
+    path::{Path, PathBuf},
     sync::Arc,
-    time::{Duration, Instant},
+    time::Duration,
 };
 
 use anyhow::anyhow;
 use async_trait::async_trait;
+use common::api_bindings::{ApiTokenScope, InputProfile};
 use futures::future::join_all;
 use log::{debug, error};
 use openssl::rand::rand_bytes;
@@ -24,30 +26,41 @@ use tokio::{
 
 use crate::app::{
     AppError,
-    auth::SessionToken,
-    host::HostId,
-    password::StoragePassword,
+    auth::{ApiToken, SessionToken},
+    group::GroupId,
+    host::{AppId, HostId},
+    password::{PasswordAlgorithm, StoragePassword},
     storage::{
-        Either, Storage, StorageHost, StorageHostAdd, StorageHostCache, StorageHostModify,
-        StorageHostPairInfo, StorageQueryHosts, StorageUser, StorageUserAdd, StorageUserModify,
+        Either, LAST_SEEN_UPDATE_INTERVAL, Storage, StorageApiToken, StorageApiTokenAdd,
+        StorageAppInputProfile, StorageGroup, StorageGroupAdd, StorageGroupModify, StorageHost,
+        StorageHostAdd, StorageHostCache, StorageHostModify, StorageHostPairInfo,
+        StorageInputProfiles, StorageOidcIdentity, StorageQueryHosts, StorageSession,
+        StorageSessionAdd, StorageUser, StorageUserAdd, StorageUserModify,
         json::versions::{
-            Json, V2, V2Host, V2HostCache, V2HostPairInfo, V2User, V2UserPassword,
-            migrate_to_latest,
+            Json, V2ApiToken, V2AppInputProfile, V2Host, V2HostCache, V2HostPairInfo,
+            V2RecoveryCode, V2Session, V2UserOidc, V2UserTotp, V3, V3Group, V3PasswordAlgorithm,
+            V3User, V3UserPassword, migrate_to_latest,
         },
+        unix_now,
     },
+    totp::StorageTotp,
     user::UserId,
 };
 
 mod serde_helpers;
-mod versions;
+pub(super) mod versions;
 
 pub struct JsonStorage {
     file: PathBuf,
     store_sender: Sender<()>,
     session_expiration_checker: JoinHandle<()>,
-    users: RwLock<HashMap<u32, RwLock<V2User>>>,
+    users: RwLock<HashMap<u32, RwLock<V3User>>>,
     hosts: RwLock<HashMap<u32, RwLock<V2Host>>>,
-    sessions: RwLock<HashMap<SessionToken, Session>>,
+    /// The sessions by the hash of their token
+    sessions: RwLock<HashMap<[u8; 32], V2Session>>,
+    /// The api tokens by the hash of their token
+    api_tokens: RwLock<HashMap<[u8; 32], V2ApiToken>>,
+    groups: RwLock<HashMap<u32, V3Group>>,
 }
 
 impl Drop for JsonStorage {
@@ -56,12 +69,6 @@ impl Drop for JsonStorage {
     }
 }
 
-struct Session {
-    created_at: Instant,
-    expiration: Duration,
-    user_id: u32,
-}
-
 impl JsonStorage {
     pub async fn load(
         file: PathBuf,
@@ -88,12 +95,15 @@ impl JsonStorage {
 
                 let mut sessions = this.sessions.write().await;
 
-                let now = Instant::now();
-                sessions.retain(|_, session| {
-                    let current_session_length = now - session.created_at;
+                let now = unix_now();
+                let session_count = sessions.len();
+                sessions.retain(|_, session| now < session.expires_at);
 
-                    current_session_length < session.expiration
-                });
+                if sessions.len() != session_count {
+                    drop(sessions);
+
+                    this.force_write();
+                }
             }
         });
 
@@ -104,6 +114,8 @@ impl JsonStorage {
             hosts: Default::default(),
             users: Default::default(),
             sessions: Default::default(),
+            api_tokens: Default::default(),
+            groups: Default::default(),
         };
         let this = Arc::new(this);
 
@@ -131,28 +143,16 @@ impl JsonStorage {
     }
 
     async fn load_internal(&self) -> Result<(), anyhow::Error> {
-        let text = match fs::read_to_string(&self.file).await {
-            Ok(text) => text,
-            Err(err) if err.kind() == ErrorKind::NotFound => {
-                return Ok(());
-            }
-            Err(err) => {
-                return Err(anyhow!("Failed to read data: {err:?}"));
-            }
+        let Some(data) = read_json(&self.file).await? else {
+            return Ok(());
         };
 
-        let json = match serde_json::from_str::<Json>(&text) {
-            Ok(value) => value,
-            Err(err) => {
-                return Err(anyhow!("Failed to deserialize data as json: {err:?}"));
-            }
-        };
-
-        let data = migrate_to_latest(json)?;
-
         {
             let mut users = self.users.write().await;
             let mut hosts = self.hosts.write().await;
+            let mut sessions = self.sessions.write().await;
+            let mut api_tokens = self.api_tokens.write().await;
+            let mut groups = self.groups.write().await;
 
             *users = data
                 .users
@@ -164,6 +164,17 @@ impl JsonStorage {
                 .into_iter()
                 .map(|(id, host)| (id, RwLock::new(host)))
                 .collect();
+            *sessions = data
+                .sessions
+                .into_iter()
+                .map(|session| (session.token_hash, session))
+                .collect();
+            *api_tokens = data
+                .api_tokens
+                .into_iter()
+                .map(|api_token| (api_token.token_hash, api_token))
+                .collect();
+            *groups = data.groups;
         }
 
         Ok(())
@@ -187,9 +198,16 @@ impl JsonStorage {
                 hosts_json.insert(*key, (*value).clone());
             }
 
-            Json::V2(V2 {
+            let sessions_json = self.sessions.read().await.values().cloned().collect();
+            let api_tokens_json = self.api_tokens.read().await.values().cloned().collect();
+            let groups_json = self.groups.read().await.clone();
+
+            Json::V3(V3 {
                 users: users_json,
                 hosts: hosts_json,
+                sessions: sessions_json,
+                api_tokens: api_tokens_json,
+                groups: groups_json,
             })
         };
 
@@ -207,6 +225,28 @@ impl JsonStorage {
     }
 }
 
+/// Reads the data file and migrates it to the latest version, returns none if the file doesn't exist
+pub(super) async fn read_json(file: &Path) -> Result<Option<V3>, anyhow::Error> {
+    let text = match fs::read_to_string(file).await {
+        Ok(text) => text,
+        Err(err) if err.kind() == ErrorKind::NotFound => {
+            return Ok(None);
+        }
+        Err(err) => {
+            return Err(anyhow!("Failed to read data: {err:?}"));
+        }
+    };
+
+    let json = match serde_json::from_str::<Json>(&text) {
+        Ok(value) => value,
+        Err(err) => {
+            return Err(anyhow!("Failed to deserialize data as json: {err:?}"));
+        }
+    };
+
+    migrate_to_latest(json).map(Some)
+}
+
 async fn file_writer(mut store_receiver: Receiver<()>, json: Arc<JsonStorage>) {
     loop {
         if store_receiver.recv().await.is_none() {
@@ -217,16 +257,89 @@ async fn file_writer(mut store_receiver: Receiver<()>, json: Arc<JsonStorage>) {
     }
 }
 
-fn user_from_json(user_id: UserId, user: &V2User) -> StorageUser {
+fn user_from_json(user_id: UserId, user: &V3User) -> StorageUser {
     StorageUser {
         id: user_id,
         name: user.name.clone(),
-        password: user.password.as_ref().map(|password| StoragePassword {
-            salt: password.salt,
-            hash: password.hash,
-        }),
+        password: user.password.as_ref().map(password_from_json),
         role: user.role,
         client_unique_id: user.client_unique_id.clone(),
+        allow_clipboard: user.allow_clipboard,
+        totp: user.totp.as_ref().map(|totp| StorageTotp {
+            secret: totp.secret,
+            confirmed: totp.confirmed,
+            recovery_codes: totp
+                .recovery_codes
+                .iter()
+                .map(|recovery_code| recovery_code.0)
+                .collect(),
+            last_used_step: totp.last_used_step,
+        }),
+        permissions: user.permissions.clone(),
+        oidc: user.oidc.as_ref().map(|oidc| StorageOidcIdentity {
+            issuer: oidc.issuer.clone(),
+            subject: oidc.subject.clone(),
+        }),
+    }
+}
+
+pub(super) fn password_from_json(password: &V3UserPassword) -> StoragePassword {
+    StoragePassword {
+        algorithm: match password.algorithm {
+            V3PasswordAlgorithm::Pbkdf2Sha256 { iterations } => {
+                PasswordAlgorithm::Pbkdf2Sha256 { iterations }
+            }
+            V3PasswordAlgorithm::Argon2id {
+                memory_kib,
+                iterations,
+                parallelism,
+            } => PasswordAlgorithm::Argon2id {
+                memory_kib,
+                iterations,
+                parallelism,
+            },
+        },
+        salt: password.salt,
+        hash: password.hash,
+    }
+}
+fn password_to_json(password: &StoragePassword) -> V3UserPassword {
+    V3UserPassword {
+        algorithm: match password.algorithm {
+            PasswordAlgorithm::Pbkdf2Sha256 { iterations } => {
+                V3PasswordAlgorithm::Pbkdf2Sha256 { iterations }
+            }
+            PasswordAlgorithm::Argon2id {
+                memory_kib,
+                iterations,
+                parallelism,
+            } => V3PasswordAlgorithm::Argon2id {
+                memory_kib,
+                iterations,
+                parallelism,
+            },
+        },
+        salt: password.salt,
+        hash: password.hash,
+    }
+}
+
+fn api_token_from_json(api_token: &V2ApiToken) -> StorageApiToken {
+    StorageApiToken {
+        token_hash: api_token.token_hash,
+        name: api_token.name.clone(),
+        scopes: api_token.scopes.clone(),
+        created_at: api_token.created_at,
+        last_used_at: api_token.last_used_at,
+    }
+}
+
+fn group_from_json(group_id: GroupId, group: &V3Group) -> StorageGroup {
+    StorageGroup {
+        id: group_id,
+        name: group.name.clone(),
+        members: group.members.iter().copied().map(UserId).collect(),
+        hosts: group.hosts.iter().copied().map(HostId).collect(),
     }
 }
 
@@ -251,14 +364,19 @@ fn host_from_json(host_id: HostId, host: &V2Host) -> StorageHost {
 #[async_trait]
 impl Storage for JsonStorage {
     async fn add_user(&self, user: StorageUserAdd) -> Result<StorageUser, AppError> {
-        let user = V2User {
+        let user = V3User {
             role: user.role,
             name: user.name,
-            password: user.password.map(|password| V2UserPassword {
-                salt: password.salt,
-                hash: password.hash,
-            }),
+            password: user.password.as_ref().map(password_to_json),
             client_unique_id: user.client_unique_id,
+            input_profiles: Default::default(),
+            allow_clipboard: user.allow_clipboard,
+            totp: None,
+            permissions: Default::default(),
+            oidc: user.oidc.map(|oidc| V2UserOidc {
+                issuer: oidc.issuer,
+                subject: oidc.subject,
+            }),
         };
 
         {
@@ -292,12 +410,16 @@ impl Storage for JsonStorage {
         Ok(StorageUser {
             id: UserId(id),
             name: user.name,
-            password: user.password.map(|password| StoragePassword {
-                salt: password.salt,
-                hash: password.hash,
-            }),
+            password: user.password.as_ref().map(password_from_json),
             role: user.role,
             client_unique_id: user.client_unique_id,
+            allow_clipboard: user.allow_clipboard,
+            totp: None,
+            permissions: user.permissions,
+            oidc: user.oidc.map(|oidc| StorageOidcIdentity {
+                issuer: oidc.issuer,
+                subject: oidc.subject,
+            }),
         })
     }
     async fn modify_user(
@@ -311,10 +433,7 @@ impl Storage for JsonStorage {
         let mut user = user_lock.write().await;
 
         if let Some(password) = modify.password {
-            user.password = password.map(|password| V2UserPassword {
-                salt: password.salt,
-                hash: password.hash,
-            });
+            user.password = password.as_ref().map(password_to_json);
         }
         if let Some(role) = modify.role {
             user.role = role;
@@ -322,6 +441,30 @@ impl Storage for JsonStorage {
         if let Some(client_unique_id) = modify.client_unique_id {
             user.client_unique_id = client_unique_id;
         }
+        if let Some(allow_clipboard) = modify.allow_clipboard {
+            user.allow_clipboard = allow_clipboard;
+        }
+        if let Some(totp) = modify.totp {
+            user.totp = totp.map(|totp| V2UserTotp {
+                secret: totp.secret,
+                confirmed: totp.confirmed,
+                recovery_codes: totp
+                    .recovery_codes
+                    .into_iter()
+                    .map(V2RecoveryCode)
+                    .collect(),
+                last_used_step: totp.last_used_step,
+            });
+        }
+        if let Some(permissions) = modify.permissions {
+            user.permissions = permissions;
+        }
+        if let Some(oidc) = modify.oidc {
+            user.oidc = oidc.map(|oidc| V2UserOidc {
+                issuer: oidc.issuer,
+                subject: oidc.subject,
+            });
+        }
 
         drop(user);
         drop(users);
@@ -358,6 +501,32 @@ impl Storage for JsonStorage {
 
         user.ok_or(AppError::UserNotFound)
     }
+    async fn get_user_by_oidc(
+        &self,
+        identity: &StorageOidcIdentity,
+    ) -> Result<(UserId, Option<StorageUser>), AppError> {
+        let users = self.users.read().await;
+
+        let results = join_all(users.iter().map(|(user_id, user)| async move {
+            let user = user.read().await;
+
+            let user_id = UserId(*user_id);
+            let user = user
+                .oidc
+                .as_ref()
+                .is_some_and(|oidc| {
+                    oidc.issuer == identity.issuer && oidc.subject == identity.subject
+                })
+                .then(|| user_from_json(user_id, &user));
+
+            (user_id, user)
+        }))
+        .await;
+
+        let user = results.into_iter().find(|(_, user)| user.is_some());
+
+        user.ok_or(AppError::UserNotFound)
+    }
     async fn remove_user(&self, user_id: UserId) -> Result<(), AppError> {
         let mut users = self.users.write().await;
 
@@ -368,6 +537,15 @@ impl Storage for JsonStorage {
 
         drop(users);
 
+        self.api_tokens
+            .write()
+            .await
+            .retain(|_, api_token| UserId(api_token.user_id) != user_id);
+
+        for group in self.groups.write().await.values_mut() {
+            group.members.retain(|member| UserId(*member) != user_id);
+        }
+
         self.force_write();
 
         result
@@ -396,36 +574,67 @@ impl Storage for JsonStorage {
         &self,
         user_id: UserId,
         expiration: Duration,
+        session: StorageSessionAdd,
     ) -> Result<SessionToken, AppError> {
-        let mut token;
-        {
-            let sessions = self.sessions.read().await;
+        let mut sessions = self.sessions.write().await;
 
-            loop {
-                token = SessionToken::new()?;
-                if !sessions.contains_key(&token) {
-                    break;
-                }
+        let mut token;
+        loop {
+            token = SessionToken::new()?;
+            if !sessions.contains_key(&token.hash()) {
+                break;
             }
-        };
-
-        let mut sessions = self.sessions.write().await;
+        }
 
+        let now = unix_now();
         sessions.insert(
-            token,
-            Session {
-                created_at: Instant::now(),
-                expiration,
+            token.hash(),
+            V2Session {
+                token_hash: token.hash(),
                 user_id: user_id.0,
+                created_at: now,
+                expires_at: now.saturating_add(expiration.as_secs()),
+                last_seen_at: now,
+                user_agent: session.user_agent,
+                last_seen_ip: session.ip,
             },
         );
 
+        drop(sessions);
+
+        self.force_write();
+
         Ok(token)
     }
     async fn remove_session_token(&self, session: SessionToken) -> Result<(), AppError> {
         let mut sessions = self.sessions.write().await;
 
-        sessions.remove(&session);
+        if sessions.remove(&session.hash()).is_some() {
+            drop(sessions);
+
+            self.force_write();
+        }
+
+        Ok(())
+    }
+    async fn remove_user_session_token(
+        &self,
+        user_id: UserId,
+        token_hash: [u8; 32],
+    ) -> Result<(), AppError> {
+        let mut sessions = self.sessions.write().await;
+
+        if sessions
+            .get(&token_hash)
+            .is_none_or(|session| UserId(session.user_id) != user_id)
+        {
+            return Err(AppError::LoginSessionNotFound);
+        }
+        sessions.remove(&token_hash);
+
+        drop(sessions);
+
+        self.force_write();
 
         Ok(())
     }
@@ -434,18 +643,208 @@ impl Storage for JsonStorage {
 
         sessions.retain(|_, session| UserId(session.user_id) != user_id);
 
+        drop(sessions);
+
+        self.force_write();
+
         Ok(())
     }
+    async fn list_user_session_tokens(
+        &self,
+        user_id: UserId,
+    ) -> Result<Vec<StorageSession>, AppError> {
+        let sessions = self.sessions.read().await;
+
+        let now = unix_now();
+        Ok(sessions
+            .values()
+            .filter(|session| UserId(session.user_id) == user_id && now < session.expires_at)
+            .map(|session| StorageSession {
+                token_hash: session.token_hash,
+                created_at: session.created_at,
+                expires_at: session.expires_at,
+                last_seen_at: session.last_seen_at,
+                user_agent: session.user_agent.clone(),
+                last_seen_ip: session.last_seen_ip,
+            })
+            .collect())
+    }
     async fn get_user_by_session_token(
         &self,
         session: SessionToken,
+        ip: Option<IpAddr>,
     ) -> Result<(UserId, Option<StorageUser>), AppError> {
-        let sessions = self.sessions.read().await;
+        let mut sessions = self.sessions.write().await;
+
+        let now = unix_now();
+        let session = sessions
+            .get_mut(&session.hash())
+            .filter(|session| now < session.expires_at)
+            .ok_or(AppError::SessionTokenNotFound)?;
+
+        let user_id = UserId(session.user_id);
+
+        if session.last_seen_ip != ip || session.last_seen_at + LAST_SEEN_UPDATE_INTERVAL <= now {
+            session.last_seen_at = now;
+            session.last_seen_ip = ip;
+
+            drop(sessions);
+
+            self.force_write();
+        }
+
+        Ok((user_id, None))
+    }
+
+    async fn create_api_token(
+        &self,
+        user_id: UserId,
+        api_token: StorageApiTokenAdd,
+    ) -> Result<(ApiToken, StorageApiToken), AppError> {
+        let mut api_tokens = self.api_tokens.write().await;
+
+        let mut token;
+        loop {
+            token = ApiToken::new()?;
+            if !api_tokens.contains_key(&token.hash()) {
+                break;
+            }
+        }
+
+        let api_token = V2ApiToken {
+            token_hash: token.hash(),
+            user_id: user_id.0,
+            name: api_token.name,
+            scopes: api_token.scopes,
+            created_at: unix_now(),
+            last_used_at: None,
+        };
+        let storage_api_token = api_token_from_json(&api_token);
+
+        api_tokens.insert(token.hash(), api_token);
+
+        drop(api_tokens);
+
+        self.force_write();
+
+        Ok((token, storage_api_token))
+    }
+    async fn remove_user_api_token(
+        &self,
+        user_id: UserId,
+        token_hash: [u8; 32],
+    ) -> Result<(), AppError> {
+        let mut api_tokens = self.api_tokens.write().await;
+
+        if api_tokens
+            .get(&token_hash)
+            .is_none_or(|api_token| UserId(api_token.user_id) != user_id)
+        {
+            return Err(AppError::ApiTokenNotFound);
+        }
+        api_tokens.remove(&token_hash);
+
+        drop(api_tokens);
+
+        self.force_write();
+
+        Ok(())
+    }
+    async fn list_user_api_tokens(
+        &self,
+        user_id: UserId,
+    ) -> Result<Vec<StorageApiToken>, AppError> {
+        let api_tokens = self.api_tokens.read().await;
+
+        Ok(api_tokens
+            .values()
+            .filter(|api_token| UserId(api_token.user_id) == user_id)
+            .map(api_token_from_json)
+            .collect())
+    }
+    async fn get_user_by_api_token(
+        &self,
+        token: ApiToken,
+    ) -> Result<(UserId, Vec<ApiTokenScope>), AppError> {
+        let mut api_tokens = self.api_tokens.write().await;
+
+        let api_token = api_tokens
+            .get_mut(&token.hash())
+            .ok_or(AppError::Unauthorized)?;
+
+        let user_id = UserId(api_token.user_id);
+        let scopes = api_token.scopes.clone();
+
+        let now = unix_now();
+        if api_token
+            .last_used_at
+            .is_none_or(|last_used_at| last_used_at + LAST_SEEN_UPDATE_INTERVAL <= now)
+        {
+            api_token.last_used_at = Some(now);
+
+            drop(api_tokens);
+
+            self.force_write();
+        }
+
+        Ok((user_id, scopes))
+    }
+
+    async fn get_input_profiles(&self, user_id: UserId) -> Result<StorageInputProfiles, AppError> {
+        let users = self.users.read().await;
+
+        let user_lock = users.get(&user_id.0).ok_or(AppError::UserNotFound)?;
+        let user = user_lock.read().await;
+
+        Ok(StorageInputProfiles {
+            default: user.input_profiles.default.clone(),
+            apps: user
+                .input_profiles
+                .apps
+                .iter()
+                .map(|app| StorageAppInputProfile {
+                    host_id: HostId(app.host_id),
+                    app_id: AppId(app.app_id),
+                    profile: app.profile.clone(),
+                })
+                .collect(),
+        })
+    }
+    async fn set_input_profile(
+        &self,
+        user_id: UserId,
+        app: Option<(HostId, AppId)>,
+        profile: Option<InputProfile>,
+    ) -> Result<(), AppError> {
+        let users = self.users.read().await;
+
+        let user_lock = users.get(&user_id.0).ok_or(AppError::UserNotFound)?;
+        let mut user = user_lock.write().await;
+
+        match app {
+            None => {
+                user.input_profiles.default = profile;
+            }
+            Some((host_id, app_id)) => {
+                let apps = &mut user.input_profiles.apps;
+                apps.retain(|app| app.host_id != host_id.0 || app.app_id != app_id.0);
+
+                if let Some(profile) = profile {
+                    apps.push(V2AppInputProfile {
+                        host_id: host_id.0,
+                        app_id: app_id.0,
+                        profile,
+                    });
+                }
+            }
+        }
+
+        drop(user);
+        drop(users);
+
+        self.force_write();
 
-        sessions
-            .get(&session)
-            .map(|session| (UserId(session.user_id), None))
-            .ok_or(AppError::SessionTokenNotFound)
+        Ok(())
     }
 
     async fn add_host(&self, host: StorageHostAdd) -> Result<StorageHost, AppError> {
@@ -548,6 +947,12 @@ impl Storage for JsonStorage {
             return Err(AppError::HostNotFound);
         }
 
+        drop(hosts);
+
+        for group in self.groups.write().await.values_mut() {
+            group.hosts.retain(|host| HostId(*host) != host_id);
+        }
+
         self.force_write();
 
         Ok(())
@@ -557,18 +962,137 @@ impl Storage for JsonStorage {
         &self,
         query: StorageQueryHosts,
     ) -> Result<Vec<(HostId, Option<StorageHost>)>, AppError> {
+        let group_hosts = self
+            .groups
+            .read()
+            .await
+            .values()
+            .filter(|group| group.members.contains(&query.user_id.0))
+            .flat_map(|group| group.hosts.iter().copied())
+            .collect::<HashSet<_>>();
+
         let hosts = self.hosts.read().await;
 
         let mut user_hosts = Vec::new();
         for (host_id, host) in &*hosts {
+            let shared = group_hosts.contains(host_id);
             let host_id = HostId(*host_id);
             let host = host.read().await;
 
-            if host.owner.is_none() || host.owner.map(UserId) == Some(query.user_id) {
+            if host.owner.is_none() || host.owner.map(UserId) == Some(query.user_id) || shared {
                 user_hosts.push((host_id, Some(host_from_json(host_id, &host))));
             }
         }
 
         Ok(user_hosts)
     }
+
+    async fn add_group(&self, group: StorageGroupAdd) -> Result<StorageGroup, AppError> {
+        let mut groups = self.groups.write().await;
+
+        if groups.values().any(|other| other.name == group.name) {
+            return Err(AppError::GroupAlreadyExists);
+        }
+
+        let mut id;
+        loop {
+            let mut id_bytes = [0u8; 4];
+            rand_bytes(&mut id_bytes)?;
+            id = u32::from_be_bytes(id_bytes);
+
+            if !groups.contains_key(&id) {
+                break;
+            }
+        }
+
+        let group = V3Group {
+            name: group.name,
+            members: Vec::new(),
+            hosts: Vec::new(),
+        };
+        groups.insert(id, group.clone());
+
+        drop(groups);
+
+        self.force_write();
+
+        Ok(group_from_json(GroupId(id), &group))
+    }
+    async fn modify_group(
+        &self,
+        group_id: GroupId,
+        modify: StorageGroupModify,
+    ) -> Result<(), AppError> {
+        let mut groups = self.groups.write().await;
+
+        if let Some(name) = &modify.name
+            && groups
+                .iter()
+                .any(|(id, other)| *id != group_id.0 && other.name == *name)
+        {
+            return Err(AppError::GroupAlreadyExists);
+        }
+
+        let group = groups.get_mut(&group_id.0).ok_or(AppError::GroupNotFound)?;
+
+        if let Some(name) = modify.name {
+            group.name = name;
+        }
+        if let Some(members) = modify.members {
+            group.members = members.into_iter().map(|user_id| user_id.0).collect();
+            group.members.sort_unstable();
+            group.members.dedup();
+        }
+        if let Some(hosts) = modify.hosts {
+            group.hosts = hosts.into_iter().map(|host_id| host_id.0).collect();
+            group.hosts.sort_unstable();
+            group.hosts.dedup();
+        }
+
+        drop(groups);
+
+        self.force_write();
+
+        Ok(())
+    }
+    async fn get_group(&self, group_id: GroupId) -> Result<StorageGroup, AppError> {
+        let groups = self.groups.read().await;
+
+        let group = groups.get(&group_id.0).ok_or(AppError::GroupNotFound)?;
+
+        Ok(group_from_json(group_id, group))
+    }
+    async fn remove_group(&self, group_id: GroupId) -> Result<(), AppError> {
+        let mut groups = self.groups.write().await;
+
+        if groups.remove(&group_id.0).is_none() {
+            return Err(AppError::GroupNotFound);
+        }
+
+        drop(groups);
+
+        self.force_write();
+
+        Ok(())
+    }
+    async fn list_groups(&self) -> Result<Vec<StorageGroup>, AppError> {
+        let groups = self.groups.read().await;
+
+        Ok(groups
+            .iter()
+            .map(|(id, group)| group_from_json(GroupId(*id), group))
+            .collect())
+    }
+
+    async fn is_host_shared_with_user(
+        &self,
+        host_id: HostId,
+        user_id: UserId,
+    ) -> Result<bool, AppError> {
+        let groups = self.groups.read().await;
+
+        Ok(groups
+            .values()
+            .any(|group| group.members.contains(&user_id.0) && group.hosts.contains(&host_id.0)))
+    }
 }
@@ -1,5 +1,6 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, net::IpAddr};
 
+use common::api_bindings::{ApiTokenScope, InputProfile, UserPermissions};
 use log::error;
 use moonlight_common::mac::MacAddress;
 use pem::Pem;
@@ -10,6 +11,8 @@ use crate::app::user::Role;
 #[derive(Serialize, Deserialize)]
 #[serde(tag = "version")]
 pub enum Json {
+    #[serde(rename = "3")]
+    V3(V3),
     #[serde(rename = "2")]
     V2(V2),
     #[serde(untagged)]
@@ -82,6 +85,8 @@ pub fn migrate_v1_to_v2(old: V1) -> V2 {
     V2 {
         users: Default::default(),
         hosts: v2_hosts,
+        sessions: Default::default(),
+        api_tokens: Default::default(),
     }
 }
 
@@ -95,6 +100,10 @@ pub struct V2 {
     pub users: HashMap<u32, V2User>,
     #[serde(deserialize_with = "de_int_key")]
     pub hosts: HashMap<u32, V2Host>,
+    #[serde(default)]
+    pub sessions: Vec<V2Session>,
+    #[serde(default)]
+    pub api_tokens: Vec<V2ApiToken>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -103,6 +112,19 @@ pub struct V2User {
     pub name: String,
     pub password: Option<V2UserPassword>,
     pub client_unique_id: String,
+    #[serde(default)]
+    pub input_profiles: V2InputProfiles,
+    #[serde(default)]
+    pub allow_clipboard: bool,
+    #[serde(default)]
+    pub totp: Option<V2UserTotp>,
+    #[serde(default)]
+    pub oidc: Option<V2UserOidc>,
+}
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct V2UserOidc {
+    pub issuer: String,
+    pub subject: String,
 }
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct V2UserPassword {
@@ -111,6 +133,29 @@ pub struct V2UserPassword {
     #[serde(with = "hex_array")]
     pub hash: [u8; 32],
 }
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct V2UserTotp {
+    #[serde(with = "hex_array")]
+    pub secret: [u8; 20],
+    pub confirmed: bool,
+    pub recovery_codes: Vec<V2RecoveryCode>,
+    pub last_used_step: u64,
+}
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct V2RecoveryCode(#[serde(with = "hex_array")] pub [u8; 32]);
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct V2InputProfiles {
+    pub default: Option<InputProfile>,
+    pub apps: Vec<V2AppInputProfile>,
+}
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct V2AppInputProfile {
+    pub host_id: u32,
+    pub app_id: u32,
+    pub profile: InputProfile,
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct V2Host {
@@ -134,9 +179,199 @@ pub struct V2HostCache {
     pub mac: Option<MacAddress>,
 }
 
-pub fn migrate_to_latest(json: Json) -> Result<V2, anyhow::Error> {
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct V2Session {
+    #[serde(with = "hex_array")]
+    pub token_hash: [u8; 32],
+    pub user_id: u32,
+    /// Unix time in seconds
+    pub created_at: u64,
+    /// Unix time in seconds
+    pub expires_at: u64,
+    /// Unix time in seconds
+    pub last_seen_at: u64,
+    pub user_agent: Option<String>,
+    pub last_seen_ip: Option<IpAddr>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct V2ApiToken {
+    #[serde(with = "hex_array")]
+    pub token_hash: [u8; 32],
+    pub user_id: u32,
+    pub name: String,
+    pub scopes: Vec<ApiTokenScope>,
+    /// Unix time in seconds
+    pub created_at: u64,
+    /// Unix time in seconds
+    pub last_used_at: Option<u64>,
+}
+
+/// V2 only supported PBKDF2-HMAC-SHA256 with this many iterations
+const V2_PASSWORD_ITERATIONS: u32 = 150_000;
+
+pub fn migrate_v2_to_v3(old: V2) -> V3 {
+    let users = old
+        .users
+        .into_iter()
+        .map(|(id, user)| {
+            let user = V3User {
+                role: user.role,
+                name: user.name,
+                password: user.password.map(|password| V3UserPassword {
+                    algorithm: V3PasswordAlgorithm::Pbkdf2Sha256 {
+                        iterations: V2_PASSWORD_ITERATIONS,
+                    },
+                    salt: password.salt,
+                    hash: password.hash,
+                }),
+                client_unique_id: user.client_unique_id,
+                input_profiles: user.input_profiles,
+                allow_clipboard: user.allow_clipboard,
+                totp: user.totp,
+                permissions: Default::default(),
+                oidc: user.oidc,
+            };
+
+            (id, user)
+        })
+        .collect();
+
+    V3 {
+        users,
+        hosts: old.hosts,
+        sessions: old.sessions,
+        api_tokens: old.api_tokens,
+        groups: Default::default(),
+    }
+}
+
+// -- V3: The password stores the algorithm it was hashed with
+
+#[derive(Serialize, Deserialize)]
+pub struct V3 {
+    #[serde(deserialize_with = "de_int_key")]
+    pub users: HashMap<u32, V3User>,
+    #[serde(deserialize_with = "de_int_key")]
+    pub hosts: HashMap<u32, V2Host>,
+    #[serde(default)]
+    pub sessions: Vec<V2Session>,
+    #[serde(default)]
+    pub api_tokens: Vec<V2ApiToken>,
+    #[serde(default, deserialize_with = "de_int_key")]
+    pub groups: HashMap<u32, V3Group>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct V3User {
+    pub role: Role,
+    pub name: String,
+    pub password: Option<V3UserPassword>,
+    pub client_unique_id: String,
+    #[serde(default)]
+    pub input_profiles: V2InputProfiles,
+    #[serde(default)]
+    pub allow_clipboard: bool,
+    #[serde(default)]
+    pub totp: Option<V2UserTotp>,
+    #[serde(default)]
+    pub permissions: UserPermissions,
+    #[serde(default)]
+    pub oidc: Option<V2UserOidc>,
+}
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct V3UserPassword {
+    #[serde(flatten)]
+    pub algorithm: V3PasswordAlgorithm,
+    #[serde(with = "hex_array")]
+    pub salt: [u8; 16],
+    #[serde(with = "hex_array")]
+    pub hash: [u8; 32],
+}
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "algorithm", rename_all = "snake_case")]
+pub enum V3PasswordAlgorithm {
+    Pbkdf2Sha256 {
+        iterations: u32,
+    },
+    Argon2id {
+        memory_kib: u32,
+        iterations: u32,
+        parallelism: u32,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct V3Group {
+    pub name: String,
+    pub members: Vec<u32>,
+    pub hosts: Vec<u32>,
+}
+
+pub fn migrate_to_latest(json: Json) -> Result<V3, anyhow::Error> {
     match json {
-        Json::V1(v1) => Ok(migrate_v1_to_v2(v1)),
-        Json::V2(v2) => Ok(v2),
+        Json::V1(v1) => Ok(migrate_v2_to_v3(migrate_v1_to_v2(v1))),
+        Json::V2(v2) => Ok(migrate_v2_to_v3(v2)),
+        Json::V3(v3) => Ok(v3),
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use crate::app::storage::json::password_from_json;
+
+    use super::*;
+
+    const V2_JSON: &str = r#"{
+        "version": "2",
+        "users": {
+            "1": {
+                "role": "Admin",
+                "name": "alice",
+                "password": {
+                    "salt": "000102030405060708090a0b0c0d0e0f",
+                    "hash": "b88bf3ac2789dae566df22e7c0a9d89d7a817dfc3269138ef18dce9775b89181"
+                },
+                "client_unique_id": "alice"
+            },
+            "2": {
+                "role": "User",
+                "name": "bob",
+                "password": null,
+                "client_unique_id": "bob",
+                "allow_clipboard": true
+            }
+        },
+        "hosts": {}
+    }"#;
+
+    #[test]
+    fn test_migrate_v2_to_v3() {
+        let json = serde_json::from_str::<Json>(V2_JSON).unwrap();
+        assert!(matches!(json, Json::V2(_)));
+
+        let v3 = migrate_to_latest(json).unwrap();
+        assert!(v3.groups.is_empty());
+
+        let alice = v3.users.get(&1).unwrap();
+        assert_eq!(alice.role, Role::Admin);
+        assert_eq!(alice.permissions, UserPermissions::default());
+
+        let password = alice.password.as_ref().unwrap();
+        assert!(matches!(
+            password.algorithm,
+            V3PasswordAlgorithm::Pbkdf2Sha256 {
+                iterations: V2_PASSWORD_ITERATIONS
+            }
+        ));
+        // The migrated password still works and is upgraded on the next login
+        let password = password_from_json(password);
+        assert!(password.verify("hunter2").unwrap());
+        assert!(password.needs_rehash());
+
+        let bob = v3.users.get(&2).unwrap();
+        assert!(bob.password.is_none());
+        assert!(bob.allow_clipboard);
     }
 }
@@ -1,20 +1,31 @@
-use std::{sync::Arc, time::Duration};
+use std::{
+    net::IpAddr,
+    path::PathBuf,
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
 
 use async_trait::async_trait;
-use common::config::StorageConfig;
+use common::{
+    api_bindings::{ApiTokenScope, InputProfile, UserPermissions},
+    config::StorageConfig,
+};
 use moonlight_common::mac::MacAddress;
 use pem::Pem;
 
 use crate::app::{
     AppError,
-    auth::SessionToken,
-    host::HostId,
+    auth::{ApiToken, SessionToken},
+    group::GroupId,
+    host::{AppId, HostId},
     password::StoragePassword,
-    storage::json::JsonStorage,
+    storage::{json::JsonStorage, sqlite::SqliteStorage},
+    totp::StorageTotp,
     user::{Role, UserId},
 };
 
 pub mod json;
+pub mod sqlite;
 
 pub async fn create_storage(
     config: StorageConfig,
@@ -26,6 +37,20 @@ pub async fn create_storage(
         } => {
             let storage = JsonStorage::load(path.into(), session_expiration_check_interval).await?;
 
+            Ok(storage)
+        }
+        StorageConfig::Sqlite {
+            path,
+            session_expiration_check_interval,
+            import_json,
+        } => {
+            let storage = SqliteStorage::open(
+                path.into(),
+                session_expiration_check_interval,
+                import_json.map(PathBuf::from),
+            )
+            .await?;
+
             Ok(storage)
         }
     }
@@ -41,6 +66,10 @@ pub struct StorageUser {
     pub password: Option<StoragePassword>,
     pub role: Role,
     pub client_unique_id: String,
+    pub allow_clipboard: bool,
+    pub totp: Option<StorageTotp>,
+    pub permissions: UserPermissions,
+    pub oidc: Option<StorageOidcIdentity>,
 }
 #[derive(Clone)]
 pub struct StorageUserAdd {
@@ -48,12 +77,25 @@ pub struct StorageUserAdd {
     pub name: String,
     pub password: Option<StoragePassword>,
     pub client_unique_id: String,
+    pub allow_clipboard: bool,
+    pub oidc: Option<StorageOidcIdentity>,
 }
 #[derive(Default, Clone)]
 pub struct StorageUserModify {
     pub role: Option<Role>,
     pub password: Option<Option<StoragePassword>>,
     pub client_unique_id: Option<String>,
+    pub allow_clipboard: Option<bool>,
+    pub totp: Option<Option<StorageTotp>>,
+    pub permissions: Option<UserPermissions>,
+    pub oidc: Option<Option<StorageOidcIdentity>>,
+}
+
+/// The openid connect account a user is linked to, the subject is only unique per issuer
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StorageOidcIdentity {
+    pub issuer: String,
+    pub subject: String,
 }
 
 #[derive(Clone)]
@@ -95,6 +137,82 @@ pub struct StorageHostModify {
     pub cache_mac: Option<Option<MacAddress>>,
 }
 
+#[derive(Clone)]
+pub struct StorageGroup {
+    pub id: GroupId,
+    pub name: String,
+    pub members: Vec<UserId>,
+    /// The hosts every member can use
+    pub hosts: Vec<HostId>,
+}
+#[derive(Clone)]
+pub struct StorageGroupAdd {
+    pub name: String,
+}
+#[derive(Default, Clone)]
+pub struct StorageGroupModify {
+    pub name: Option<String>,
+    pub members: Option<Vec<UserId>>,
+    pub hosts: Option<Vec<HostId>>,
+}
+
+#[derive(Default, Clone)]
+pub struct StorageInputProfiles {
+    pub default: Option<InputProfile>,
+    pub apps: Vec<StorageAppInputProfile>,
+}
+#[derive(Clone)]
+pub struct StorageAppInputProfile {
+    pub host_id: HostId,
+    pub app_id: AppId,
+    pub profile: InputProfile,
+}
+
+#[derive(Clone)]
+pub struct StorageSession {
+    pub token_hash: [u8; 32],
+    /// Unix time in seconds
+    pub created_at: u64,
+    /// Unix time in seconds
+    pub expires_at: u64,
+    /// Unix time in seconds
+    pub last_seen_at: u64,
+    pub user_agent: Option<String>,
+    pub last_seen_ip: Option<IpAddr>,
+}
+#[derive(Default, Clone)]
+pub struct StorageSessionAdd {
+    pub user_agent: Option<String>,
+    pub ip: Option<IpAddr>,
+}
+
+#[derive(Clone)]
+pub struct StorageApiToken {
+    pub token_hash: [u8; 32],
+    pub name: String,
+    pub scopes: Vec<ApiTokenScope>,
+    /// Unix time in seconds
+    pub created_at: u64,
+    /// Unix time in seconds
+    pub last_used_at: Option<u64>,
+}
+#[derive(Clone)]
+pub struct StorageApiTokenAdd {
+    pub name: String,
+    pub scopes: Vec<ApiTokenScope>,
+}
+
+/// The last seen time of a session or api token is only written again after this many seconds or if the ip changed
+const LAST_SEEN_UPDATE_INTERVAL: u64 = 60;
+
+/// Sessions are stored with wall clock times so they stay valid across restarts
+pub(super) fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
 #[derive(Clone)]
 pub struct StorageQueryHosts {
     pub user_id: UserId,
@@ -115,35 +233,103 @@ pub trait Storage {
     /// The returned tuple can contain a StorageUser if the Storage thinks it's more efficient to query all data directly
     async fn get_user_by_name(&self, name: &str)
     -> Result<(UserId, Option<StorageUser>), AppError>;
+    /// The returned tuple can contain a StorageUser if the Storage thinks it's more efficient to query all data directly
+    async fn get_user_by_oidc(
+        &self,
+        identity: &StorageOidcIdentity,
+    ) -> Result<(UserId, Option<StorageUser>), AppError>;
     async fn remove_user(&self, user_id: UserId) -> Result<(), AppError>;
     /// The returned tuple can contain a Vec<UserId> or Vec<StorageUser> if the Storage thinks it's more efficient to query all data directly
     async fn list_users(&self) -> Result<Either<Vec<UserId>, Vec<StorageUser>>, AppError>;
     async fn any_user_exists(&self) -> Result<bool, AppError>;
 
+    /// Only a hash of the token is stored
     async fn create_session_token(
         &self,
         user_id: UserId,
         expires_after: Duration,
+        session: StorageSessionAdd,
     ) -> Result<SessionToken, AppError>;
     async fn remove_session_token(&self, session: SessionToken) -> Result<(), AppError>;
-    #[allow(dead_code)]
+    /// Removes the session with this token hash if it belongs to the user
+    async fn remove_user_session_token(
+        &self,
+        user_id: UserId,
+        token_hash: [u8; 32],
+    ) -> Result<(), AppError>;
     async fn remove_all_user_session_tokens(&self, user_id: UserId) -> Result<(), AppError>;
+    /// Returns all sessions of the user that aren't expired
+    async fn list_user_session_tokens(
+        &self,
+        user_id: UserId,
+    ) -> Result<Vec<StorageSession>, AppError>;
+    /// Also updates when and from which ip the session was last seen.
+    ///
     /// The returned tuple can contain a StorageUser if the Storage thinks it's more efficient to query all data directly
     async fn get_user_by_session_token(
         &self,
         session: SessionToken,
+        ip: Option<IpAddr>,
     ) -> Result<(UserId, Option<StorageUser>), AppError>;
 
+    /// Only a hash of the token is stored
+    async fn create_api_token(
+        &self,
+        user_id: UserId,
+        token: StorageApiTokenAdd,
+    ) -> Result<(ApiToken, StorageApiToken), AppError>;
+    /// Removes the api token with this hash if it belongs to the user
+    async fn remove_user_api_token(
+        &self,
+        user_id: UserId,
+        token_hash: [u8; 32],
+    ) -> Result<(), AppError>;
+    async fn list_user_api_tokens(&self, user_id: UserId)
+    -> Result<Vec<StorageApiToken>, AppError>;
+    /// Also updates when the token was last used
+    async fn get_user_by_api_token(
+        &self,
+        token: ApiToken,
+    ) -> Result<(UserId, Vec<ApiTokenScope>), AppError>;
+
+    async fn get_input_profiles(&self, user_id: UserId) -> Result<StorageInputProfiles, AppError>;
+    /// Sets the profile of this app or the default profile if app is none. A profile of none removes it.
+    async fn set_input_profile(
+        &self,
+        user_id: UserId,
+        app: Option<(HostId, AppId)>,
+        profile: Option<InputProfile>,
+    ) -> Result<(), AppError>;
+
     async fn add_host(&self, host: StorageHostAdd) -> Result<StorageHost, AppError>;
     async fn modify_host(&self, host_id: HostId, host: StorageHostModify) -> Result<(), AppError>;
     async fn get_host(&self, host_id: HostId) -> Result<StorageHost, AppError>;
     async fn remove_host(&self, host_id: HostId) -> Result<(), AppError>;
 
-    /// Returns all hosts that either have no owner (global) or have the specified user_id as an owner
+    /// Returns all hosts that either have no owner (global), have the specified user_id as an owner or are shared with a group the user is a member of
     ///
     /// The returned tuple in the Vec can contain a StorageHost if the Storage thinks it's more efficient to query all data directly
     async fn list_user_hosts(
         &self,
         query: StorageQueryHosts,
     ) -> Result<Vec<(HostId, Option<StorageHost>)>, AppError>;
+
+    /// No duplicate names are allowed!
+    async fn add_group(&self, group: StorageGroupAdd) -> Result<StorageGroup, AppError>;
+    /// The members and hosts must exist
+    async fn modify_group(
+        &self,
+        group_id: GroupId,
+        group: StorageGroupModify,
+    ) -> Result<(), AppError>;
+    async fn get_group(&self, group_id: GroupId) -> Result<StorageGroup, AppError>;
+    async fn remove_group(&self, group_id: GroupId) -> Result<(), AppError>;
+    async fn list_groups(&self) -> Result<Vec<StorageGroup>, AppError>;
+
+    /// If the host is shared with any group the user is a member of
+    async fn is_host_shared_with_user(
+        &self,
+        host_id: HostId,
+        user_id: UserId,
+    ) -> Result<bool, AppError>;
 }
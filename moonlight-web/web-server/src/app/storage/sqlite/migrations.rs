@@ -0,0 +1,460 @@
+use std::collections::HashSet;
+
+use anyhow::anyhow;
+use rusqlite::{Connection, Transaction, params};
+
+use crate::app::storage::{
+    json::{password_from_json, versions::V3},
+    sqlite::{
+        api_token_scopes_to_sql, input_profile_to_sql, password_algorithm_to_sql,
+        permissions_to_sql, recovery_codes_to_sql, role_to_sql,
+    },
+};
+
+/// Every entry migrates the schema from the version of its index to the next one.
+/// The current version is stored in the `user_version` pragma, 0 means the database was just created.
+const MIGRATIONS: &[&str] = &[
+    // -- V1
+    "
+    CREATE TABLE users (
+        id INTEGER PRIMARY KEY NOT NULL,
+        name TEXT NOT NULL UNIQUE,
+        password_salt BLOB,
+        password_hash BLOB,
+        role TEXT NOT NULL,
+        client_unique_id TEXT NOT NULL,
+        allow_clipboard INTEGER NOT NULL DEFAULT 0,
+        default_input_profile TEXT
+    );
+
+    CREATE TABLE user_app_input_profiles (
+        user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+        host_id INTEGER NOT NULL,
+        app_id INTEGER NOT NULL,
+        profile TEXT NOT NULL,
+        PRIMARY KEY (user_id, host_id, app_id)
+    );
+
+    CREATE TABLE hosts (
+        id INTEGER PRIMARY KEY NOT NULL,
+        owner INTEGER,
+        address TEXT NOT NULL,
+        http_port INTEGER NOT NULL,
+        client_private_key TEXT,
+        client_certificate TEXT,
+        server_certificate TEXT,
+        cache_name TEXT NOT NULL,
+        cache_mac BLOB
+    );
+
+    CREATE TABLE sessions (
+        token BLOB PRIMARY KEY NOT NULL,
+        user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+        created_at INTEGER NOT NULL,
+        expires_at INTEGER NOT NULL
+    );
+    ",
+    // -- V2: Only the hash of session tokens is stored, the old sessions are dropped because they contain the plain tokens
+    "
+    DROP TABLE sessions;
+
+    CREATE TABLE sessions (
+        token_hash BLOB PRIMARY KEY NOT NULL,
+        user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+        created_at INTEGER NOT NULL,
+        expires_at INTEGER NOT NULL,
+        last_seen_at INTEGER NOT NULL,
+        user_agent TEXT,
+        last_seen_ip TEXT
+    );
+    ",
+    // -- V3
+    "
+    CREATE TABLE api_tokens (
+        token_hash BLOB PRIMARY KEY NOT NULL,
+        user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+        name TEXT NOT NULL,
+        scopes TEXT NOT NULL,
+        created_at INTEGER NOT NULL,
+        last_used_at INTEGER
+    );
+    ",
+    // -- V4: Users that log in with openid connect are linked by the issuer and subject of their id token
+    "
+    ALTER TABLE users ADD COLUMN oidc_issuer TEXT;
+    ALTER TABLE users ADD COLUMN oidc_subject TEXT;
+
+    CREATE UNIQUE INDEX users_oidc ON users (oidc_issuer, oidc_subject);
+    ",
+    // -- V5: The recovery codes are the concatenated sha256 hashes
+    "
+    ALTER TABLE users ADD COLUMN totp_secret BLOB;
+    ALTER TABLE users ADD COLUMN totp_confirmed INTEGER NOT NULL DEFAULT 0;
+    ALTER TABLE users ADD COLUMN totp_recovery_codes BLOB;
+    ALTER TABLE users ADD COLUMN totp_last_used_step INTEGER NOT NULL DEFAULT 0;
+    ",
+    // -- V6: Passwords store the algorithm they were hashed with, before only PBKDF2-HMAC-SHA256 with 150000 iterations existed
+    "
+    ALTER TABLE users ADD COLUMN password_algorithm TEXT;
+    ALTER TABLE users ADD COLUMN password_iterations INTEGER;
+    ALTER TABLE users ADD COLUMN password_memory_kib INTEGER;
+    ALTER TABLE users ADD COLUMN password_parallelism INTEGER;
+
+    UPDATE users SET password_algorithm = 'pbkdf2_sha256', password_iterations = 150000 WHERE password_hash IS NOT NULL;
+    ",
+    // -- V7: The permissions are stored as json, null means no restrictions
+    "
+    ALTER TABLE users ADD COLUMN permissions TEXT;
+    ",
+    // -- V8: Groups of users that share hosts
+    "
+    CREATE TABLE user_groups (
+        id INTEGER PRIMARY KEY NOT NULL,
+        name TEXT NOT NULL UNIQUE
+    );
+
+    CREATE TABLE user_group_members (
+        group_id INTEGER NOT NULL REFERENCES user_groups(id) ON DELETE CASCADE,
+        user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+        PRIMARY KEY (group_id, user_id)
+    );
+
+    CREATE TABLE user_group_hosts (
+        group_id INTEGER NOT NULL REFERENCES user_groups(id) ON DELETE CASCADE,
+        host_id INTEGER NOT NULL REFERENCES hosts(id) ON DELETE CASCADE,
+        PRIMARY KEY (group_id, host_id)
+    );
+    ",
+];
+
+/// Migrates the schema to the latest version and returns the version the database had before
+pub fn migrate_to_latest(connection: &mut Connection) -> Result<usize, anyhow::Error> {
+    let version: usize = connection.pragma_query_value(None, "user_version", |row| row.get(0))?;
+
+    if version > MIGRATIONS.len() {
+        return Err(anyhow!(
+            "the database has the schema version {version} but this version of the web server only supports up to {}",
+            MIGRATIONS.len()
+        ));
+    }
+
+    for (index, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+        let transaction = connection.transaction()?;
+
+        transaction.execute_batch(migration)?;
+        transaction.pragma_update(None, "user_version", index + 1)?;
+
+        transaction.commit()?;
+    }
+
+    Ok(version)
+}
+
+pub fn import_json(transaction: &Transaction, data: V3) -> Result<(), anyhow::Error> {
+    let user_ids = data.users.keys().copied().collect::<HashSet<_>>();
+    let host_ids = data.hosts.keys().copied().collect::<HashSet<_>>();
+
+    for (id, user) in data.users {
+        let password = user.password.as_ref().map(password_from_json);
+        let password_algorithm = password
+            .as_ref()
+            .map(|password| password_algorithm_to_sql(password.algorithm));
+
+        transaction.execute(
+            "INSERT INTO users (id, name, password_salt, password_hash, role, client_unique_id, allow_clipboard, default_input_profile, totp_secret, totp_confirmed, totp_recovery_codes, totp_last_used_step, password_algorithm, password_iterations, password_memory_kib, password_parallelism, permissions, oidc_issuer, oidc_subject)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?19)",
+            params![
+                id,
+                user.name,
+                password.as_ref().map(|password| password.salt),
+                password.as_ref().map(|password| password.hash),
+                role_to_sql(user.role),
+                user.client_unique_id,
+                user.allow_clipboard,
+                user.input_profiles
+                    .default
+                    .as_ref()
+                    .map(input_profile_to_sql)
+                    .transpose()?,
+                user.totp.as_ref().map(|totp| totp.secret),
+                user.totp.as_ref().is_some_and(|totp| totp.confirmed),
+                user.totp.as_ref().map(|totp| {
+                    recovery_codes_to_sql(
+                        totp.recovery_codes
+                            .iter()
+                            .map(|recovery_code| &recovery_code.0),
+                    )
+                }),
+                user.totp.as_ref().map_or(0, |totp| totp.last_used_step),
+                password_algorithm.map(|algorithm| algorithm.0),
+                password_algorithm.map(|algorithm| algorithm.1),
+                password_algorithm.and_then(|algorithm| algorithm.2),
+                password_algorithm.and_then(|algorithm| algorithm.3),
+                permissions_to_sql(&user.permissions)?,
+                user.oidc.as_ref().map(|oidc| &oidc.issuer),
+                user.oidc.as_ref().map(|oidc| &oidc.subject),
+            ],
+        )?;
+
+        for app in user.input_profiles.apps {
+            transaction.execute(
+                "INSERT OR REPLACE INTO user_app_input_profiles (user_id, host_id, app_id, profile) VALUES (?1, ?2, ?3, ?4)",
+                params![
+                    id,
+                    app.host_id,
+                    app.app_id,
+                    input_profile_to_sql(&app.profile)?
+                ],
+            )?;
+        }
+    }
+
+    for session in data.sessions {
+        // Sessions of removed users are still in the json file until they expire
+        if !user_ids.contains(&session.user_id) {
+            continue;
+        }
+
+        transaction.execute(
+            "INSERT INTO sessions (token_hash, user_id, created_at, expires_at, last_seen_at, user_agent, last_seen_ip)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            params![
+                session.token_hash,
+                session.user_id,
+                session.created_at,
+                session.expires_at,
+                session.last_seen_at,
+                session.user_agent,
+                session.last_seen_ip.map(|ip| ip.to_string()),
+            ],
+        )?;
+    }
+
+    for api_token in data.api_tokens {
+        if !user_ids.contains(&api_token.user_id) {
+            continue;
+        }
+
+        transaction.execute(
+            "INSERT INTO api_tokens (token_hash, user_id, name, scopes, created_at, last_used_at)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                api_token.token_hash,
+                api_token.user_id,
+                api_token.name,
+                api_token_scopes_to_sql(&api_token.scopes)?,
+                api_token.created_at,
+                api_token.last_used_at,
+            ],
+        )?;
+    }
+
+    for (id, host) in data.hosts {
+        transaction.execute(
+            "INSERT INTO hosts (id, owner, address, http_port, client_private_key, client_certificate, server_certificate, cache_name, cache_mac)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            params![
+                id,
+                host.owner,
+                host.address,
+                host.http_port,
+                host.pair_info
+                    .as_ref()
+                    .map(|pair_info| pem::encode(&pair_info.client_private_key)),
+                host.pair_info
+                    .as_ref()
+                    .map(|pair_info| pem::encode(&pair_info.client_certificate)),
+                host.pair_info
+                    .as_ref()
+                    .map(|pair_info| pem::encode(&pair_info.server_certificate)),
+                host.cache.name,
+                host.cache.mac.map(|mac| mac.to_bytes()),
+            ],
+        )?;
+    }
+
+    for (id, group) in data.groups {
+        transaction.execute(
+            "INSERT INTO user_groups (id, name) VALUES (?1, ?2)",
+            params![id, group.name],
+        )?;
+
+        for user_id in group.members {
+            if !user_ids.contains(&user_id) {
+                continue;
+            }
+
+            transaction.execute(
+                "INSERT OR IGNORE INTO user_group_members (group_id, user_id) VALUES (?1, ?2)",
+                params![id, user_id],
+            )?;
+        }
+        for host_id in group.hosts {
+            if !host_ids.contains(&host_id) {
+                continue;
+            }
+
+            transaction.execute(
+                "INSERT OR IGNORE INTO user_group_hosts (group_id, host_id) VALUES (?1, ?2)",
+                params![id, host_id],
+            )?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use std::collections::HashMap;
+
+    use common::api_bindings::ApiTokenScope;
+
+    use crate::app::{
+        storage::json::versions::{V2ApiToken, V2Host, V2HostCache, V2Session, V3Group, V3User},
+        user::Role,
+    };
+
+    use super::*;
+
+    fn open() -> Connection {
+        let connection = Connection::open_in_memory().unwrap();
+        connection
+            .pragma_update(None, "foreign_keys", true)
+            .unwrap();
+        connection
+    }
+
+    fn user_version(connection: &Connection) -> usize {
+        connection
+            .pragma_query_value(None, "user_version", |row| row.get(0))
+            .unwrap()
+    }
+
+    fn count(connection: &Connection, table: &str) -> u32 {
+        connection
+            .query_row(&format!("SELECT COUNT(*) FROM {table}"), [], |row| {
+                row.get(0)
+            })
+            .unwrap()
+    }
+
+    fn user(name: &str) -> V3User {
+        V3User {
+            role: Role::User,
+            name: name.to_string(),
+            password: None,
+            client_unique_id: format!("{name}-client"),
+            input_profiles: Default::default(),
+            allow_clipboard: false,
+            totp: None,
+            permissions: Default::default(),
+            oidc: None,
+        }
+    }
+
+    fn session(token: u8, user_id: u32) -> V2Session {
+        V2Session {
+            token_hash: [token; 32],
+            user_id,
+            created_at: 0,
+            expires_at: 1_000,
+            last_seen_at: 0,
+            user_agent: None,
+            last_seen_ip: None,
+        }
+    }
+
+    fn api_token(token: u8, user_id: u32) -> V2ApiToken {
+        V2ApiToken {
+            token_hash: [token; 32],
+            user_id,
+            name: "token".to_string(),
+            scopes: vec![ApiTokenScope::ReadHosts],
+            created_at: 0,
+            last_used_at: None,
+        }
+    }
+
+    #[test]
+    fn test_migrate_from_empty() {
+        let mut connection = open();
+
+        assert_eq!(migrate_to_latest(&mut connection).unwrap(), 0);
+        assert_eq!(user_version(&connection), MIGRATIONS.len());
+
+        // Migrating again doesn't change anything
+        assert_eq!(
+            migrate_to_latest(&mut connection).unwrap(),
+            MIGRATIONS.len()
+        );
+        assert_eq!(user_version(&connection), MIGRATIONS.len());
+    }
+
+    #[test]
+    fn test_reject_newer_version() {
+        let mut connection = open();
+        connection
+            .pragma_update(None, "user_version", MIGRATIONS.len() + 1)
+            .unwrap();
+
+        assert!(migrate_to_latest(&mut connection).is_err());
+        assert_eq!(user_version(&connection), MIGRATIONS.len() + 1);
+    }
+
+    #[test]
+    fn test_import_json_drops_orphans() {
+        let mut connection = open();
+        migrate_to_latest(&mut connection).unwrap();
+
+        let data = V3 {
+            users: HashMap::from([(1, user("alice")), (2, user("bob"))]),
+            hosts: HashMap::from([(
+                10,
+                V2Host {
+                    owner: Some(1),
+                    address: "localhost".to_string(),
+                    http_port: 47989,
+                    pair_info: None,
+                    cache: V2HostCache {
+                        name: "host".to_string(),
+                        mac: None,
+                    },
+                },
+            )]),
+            // User 3 was removed
+            sessions: vec![session(1, 1), session(2, 3), session(3, 2)],
+            api_tokens: vec![api_token(1, 3), api_token(2, 2)],
+            groups: HashMap::from([(
+                5,
+                V3Group {
+                    name: "friends".to_string(),
+                    members: vec![1, 2, 3],
+                    hosts: vec![10, 11],
+                },
+            )]),
+        };
+
+        let transaction = connection.transaction().unwrap();
+        import_json(&transaction, data).unwrap();
+        transaction.commit().unwrap();
+
+        assert_eq!(count(&connection, "users"), 2);
+        assert_eq!(count(&connection, "hosts"), 1);
+        assert_eq!(count(&connection, "sessions"), 2);
+        assert_eq!(count(&connection, "api_tokens"), 1);
+        assert_eq!(count(&connection, "user_groups"), 1);
+        assert_eq!(count(&connection, "user_group_members"), 2);
+        assert_eq!(count(&connection, "user_group_hosts"), 1);
+
+        let orphaned_sessions: u32 = connection
+            .query_row(
+                "SELECT COUNT(*) FROM sessions WHERE user_id NOT IN (SELECT id FROM users)",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(orphaned_sessions, 0);
+    }
+}
@@ -0,0 +1,1487 @@
+use std::{
+    io,
+    net::IpAddr,
+    path::PathBuf,
+    sync::{Arc, Mutex, PoisonError},
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use common::api_bindings::{ApiTokenScope, InputProfile, UserPermissions};
+use log::{debug, error, info};
+use moonlight_common::mac::MacAddress;
+use openssl::rand::rand_bytes;
+use rusqlite::{Connection, OptionalExtension, Row, Transaction, params, types::Type as SqlType};
+use tokio::{
+    spawn,
+    task::{JoinHandle, spawn_blocking},
+    time::sleep,
+};
+
+use crate::app::{
+    AppError,
+    auth::{ApiToken, SessionToken},
+    group::GroupId,
+    host::{AppId, HostId},
+    password::{PasswordAlgorithm, StoragePassword},
+    storage::{
+        Either, LAST_SEEN_UPDATE_INTERVAL, Storage, StorageApiToken, StorageApiTokenAdd,
+        StorageAppInputProfile, StorageGroup, StorageGroupAdd, StorageGroupModify, StorageHost,
+        StorageHostAdd, StorageHostCache, StorageHostModify, StorageHostPairInfo,
+        StorageInputProfiles, StorageOidcIdentity, StorageQueryHosts, StorageSession,
+        StorageSessionAdd, StorageUser, StorageUserAdd, StorageUserModify,
+        json::read_json,
+        sqlite::migrations::{import_json, migrate_to_latest},
+        unix_now,
+    },
+    totp::StorageTotp,
+    user::{Role, UserId},
+};
+
+mod migrations;
+
+const USER_COLUMNS: &str = "id, name, password_salt, password_hash, role, client_unique_id, allow_clipboard, totp_secret, totp_confirmed, totp_recovery_codes, totp_last_used_step, password_algorithm, password_iterations, password_memory_kib, password_parallelism, permissions, oidc_issuer, oidc_subject";
+const HOST_COLUMNS: &str = "id, owner, address, http_port, client_private_key, client_certificate, server_certificate, cache_name, cache_mac";
+
+pub struct SqliteStorage {
+    connection: Arc<Mutex<Connection>>,
+    session_expiration_checker: JoinHandle<()>,
+}
+
+impl Drop for SqliteStorage {
+    fn drop(&mut self) {
+        self.session_expiration_checker.abort();
+    }
+}
+
+impl SqliteStorage {
+    pub async fn open(
+        file: PathBuf,
+        session_expiration_check_interval: Duration,
+        import_json_file: Option<PathBuf>,
+    ) -> Result<Arc<Self>, anyhow::Error> {
+        let (connection, previous_version) = spawn_blocking(move || {
+            let mut connection = Connection::open(&file)?;
+            connection.pragma_update(None, "foreign_keys", true)?;
+
+            let previous_version = migrate_to_latest(&mut connection)?;
+
+            Ok::<_, anyhow::Error>((connection, previous_version))
+        })
+        .await??;
+        let connection = Arc::new(Mutex::new(connection));
+
+        if previous_version == 0
+            && let Some(import_json_file) = import_json_file
+            && let Some(data) = read_json(&import_json_file).await?
+        {
+            let user_count = data.users.len();
+            let host_count = data.hosts.len();
+
+            let connection = connection.clone();
+            spawn_blocking(move || {
+                let mut connection = connection.lock().unwrap_or_else(PoisonError::into_inner);
+
+                let transaction = connection.transaction()?;
+                import_json(&transaction, data)?;
+                transaction.commit()?;
+
+                Ok::<_, anyhow::Error>(())
+            })
+            .await??;
+
+            info!(
+                "Imported {user_count} users and {host_count} hosts from {import_json_file:?} into the database"
+            );
+        }
+
+        let session_expiration_checker = spawn({
+            let connection = connection.clone();
+
+            async move {
+                loop {
+                    sleep(session_expiration_check_interval).await;
+                    debug!("Clearing all expired sessions!");
+
+                    let connection = connection.clone();
+                    let result = spawn_blocking(move || {
+                        let connection = connection.lock().unwrap_or_else(PoisonError::into_inner);
+
+                        connection.execute(
+                            "DELETE FROM sessions WHERE expires_at <= ?1",
+                            params![unix_now()],
+                        )
+                    })
+                    .await;
+
+                    match result {
+                        Ok(Ok(_)) => {}
+                        Ok(Err(err)) => error!("Failed to clear expired sessions: {err:?}"),
+                        Err(err) => error!("Failed to clear expired sessions: {err:?}"),
+                    }
+                }
+            }
+        });
+
+        Ok(Arc::new(Self {
+            connection,
+            session_expiration_checker,
+        }))
+    }
+
+    /// Runs the function on a blocking thread with exclusive access to the connection
+    async fn run<T, F>(&self, f: F) -> Result<T, AppError>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut Connection) -> Result<T, AppError> + Send + 'static,
+    {
+        let connection = self.connection.clone();
+
+        spawn_blocking(move || {
+            let mut connection = connection.lock().unwrap_or_else(PoisonError::into_inner);
+
+            f(&mut connection)
+        })
+        .await
+        .map_err(io::Error::other)?
+    }
+}
+
+/// Generates a random id which isn't used in the table yet
+fn new_id(transaction: &Transaction, table: &str) -> Result<u32, AppError> {
+    loop {
+        let mut id_bytes = [0u8; 4];
+        rand_bytes(&mut id_bytes)?;
+        let id = u32::from_be_bytes(id_bytes);
+
+        let exists: bool = transaction.query_row(
+            &format!("SELECT EXISTS(SELECT 1 FROM {table} WHERE id = ?1)"),
+            params![id],
+            |row| row.get(0),
+        )?;
+        if !exists {
+            return Ok(id);
+        }
+    }
+}
+
+fn user_exists(connection: &Connection, user_id: UserId) -> Result<bool, rusqlite::Error> {
+    connection.query_row(
+        "SELECT EXISTS(SELECT 1 FROM users WHERE id = ?1)",
+        params![user_id.0],
+        |row| row.get(0),
+    )
+}
+
+pub(super) fn role_to_sql(role: Role) -> &'static str {
+    match role {
+        Role::User => "user",
+        Role::Admin => "admin",
+    }
+}
+fn role_from_sql(index: usize, role: &str) -> Result<Role, rusqlite::Error> {
+    match role {
+        "user" => Ok(Role::User),
+        "admin" => Ok(Role::Admin),
+        _ => Err(rusqlite::Error::FromSqlConversionFailure(
+            index,
+            SqlType::Text,
+            format!("invalid role \"{role}\"").into(),
+        )),
+    }
+}
+
+pub(super) fn input_profile_to_sql(profile: &InputProfile) -> Result<String, rusqlite::Error> {
+    serde_json::to_string(profile)
+        .map_err(|err| rusqlite::Error::ToSqlConversionFailure(err.into()))
+}
+fn input_profile_from_sql(index: usize, profile: &str) -> Result<InputProfile, rusqlite::Error> {
+    serde_json::from_str(profile)
+        .map_err(|err| rusqlite::Error::FromSqlConversionFailure(index, SqlType::Text, err.into()))
+}
+
+pub(super) fn permissions_to_sql(permissions: &UserPermissions) -> Result<String, rusqlite::Error> {
+    serde_json::to_string(permissions)
+        .map_err(|err| rusqlite::Error::ToSqlConversionFailure(err.into()))
+}
+fn permissions_from_sql(
+    index: usize,
+    permissions: &str,
+) -> Result<UserPermissions, rusqlite::Error> {
+    serde_json::from_str(permissions)
+        .map_err(|err| rusqlite::Error::FromSqlConversionFailure(index, SqlType::Text, err.into()))
+}
+
+pub(super) fn api_token_scopes_to_sql(scopes: &[ApiTokenScope]) -> Result<String, rusqlite::Error> {
+    serde_json::to_string(scopes).map_err(|err| rusqlite::Error::ToSqlConversionFailure(err.into()))
+}
+fn api_token_scopes_from_sql(
+    index: usize,
+    scopes: &str,
+) -> Result<Vec<ApiTokenScope>, rusqlite::Error> {
+    serde_json::from_str(scopes)
+        .map_err(|err| rusqlite::Error::FromSqlConversionFailure(index, SqlType::Text, err.into()))
+}
+
+pub(super) fn recovery_codes_to_sql<'a>(
+    recovery_codes: impl IntoIterator<Item = &'a [u8; 32]>,
+) -> Vec<u8> {
+    recovery_codes.into_iter().flatten().copied().collect()
+}
+fn recovery_codes_from_sql(recovery_codes: &[u8]) -> Vec<[u8; 32]> {
+    recovery_codes
+        .chunks_exact(32)
+        .filter_map(|recovery_code| recovery_code.try_into().ok())
+        .collect()
+}
+
+/// The algorithm name, iterations, memory in KiB and parallelism of the password hash
+pub(super) fn password_algorithm_to_sql(
+    algorithm: PasswordAlgorithm,
+) -> (&'static str, u32, Option<u32>, Option<u32>) {
+    match algorithm {
+        PasswordAlgorithm::Pbkdf2Sha256 { iterations } => ("pbkdf2_sha256", iterations, None, None),
+        PasswordAlgorithm::Argon2id {
+            memory_kib,
+            iterations,
+            parallelism,
+        } => ("argon2id", iterations, Some(memory_kib), Some(parallelism)),
+    }
+}
+fn password_algorithm_from_sql(
+    index: usize,
+    algorithm: &str,
+    iterations: u32,
+    memory_kib: Option<u32>,
+    parallelism: Option<u32>,
+) -> Result<PasswordAlgorithm, rusqlite::Error> {
+    match (algorithm, memory_kib, parallelism) {
+        ("pbkdf2_sha256", _, _) => Ok(PasswordAlgorithm::Pbkdf2Sha256 { iterations }),
+        ("argon2id", Some(memory_kib), Some(parallelism)) => Ok(PasswordAlgorithm::Argon2id {
+            memory_kib,
+            iterations,
+            parallelism,
+        }),
+        _ => Err(rusqlite::Error::FromSqlConversionFailure(
+            index,
+            SqlType::Text,
+            format!("invalid password algorithm \"{algorithm}\"").into(),
+        )),
+    }
+}
+
+fn totp_to_sql(totp: Option<&StorageTotp>) -> (Option<[u8; 20]>, bool, Option<Vec<u8>>, u64) {
+    (
+        totp.map(|totp| totp.secret),
+        totp.is_some_and(|totp| totp.confirmed),
+        totp.map(|totp| recovery_codes_to_sql(&totp.recovery_codes)),
+        totp.map_or(0, |totp| totp.last_used_step),
+    )
+}
+
+fn pem_from_sql(index: usize, pem: &str) -> Result<pem::Pem, rusqlite::Error> {
+    pem::parse(pem)
+        .map_err(|err| rusqlite::Error::FromSqlConversionFailure(index, SqlType::Text, err.into()))
+}
+
+fn user_from_row(row: &Row) -> Result<StorageUser, rusqlite::Error> {
+    let password_salt: Option<[u8; 16]> = row.get(2)?;
+    let password_hash: Option<[u8; 32]> = row.get(3)?;
+    let password_algorithm: Option<String> = row.get(11)?;
+
+    let password = match (password_salt, password_hash, password_algorithm) {
+        (Some(salt), Some(hash), Some(algorithm)) => Some(StoragePassword {
+            algorithm: password_algorithm_from_sql(
+                11,
+                &algorithm,
+                row.get(12)?,
+                row.get(13)?,
+                row.get(14)?,
+            )?,
+            salt,
+            hash,
+        }),
+        _ => None,
+    };
+
+    Ok(StorageUser {
+        id: UserId(row.get(0)?),
+        name: row.get(1)?,
+        password,
+        role: role_from_sql(4, &row.get::<_, String>(4)?)?,
+        client_unique_id: row.get(5)?,
+        allow_clipboard: row.get(6)?,
+        totp: match row.get::<_, Option<[u8; 20]>>(7)? {
+            Some(secret) => Some(StorageTotp {
+                secret,
+                confirmed: row.get(8)?,
+                recovery_codes: recovery_codes_from_sql(
+                    &row.get::<_, Option<Vec<u8>>>(9)?.unwrap_or_default(),
+                ),
+                last_used_step: row.get(10)?,
+            }),
+            None => None,
+        },
+        permissions: match row.get::<_, Option<String>>(15)? {
+            Some(permissions) => permissions_from_sql(15, &permissions)?,
+            None => Default::default(),
+        },
+        oidc: match (
+            row.get::<_, Option<String>>(16)?,
+            row.get::<_, Option<String>>(17)?,
+        ) {
+            (Some(issuer), Some(subject)) => Some(StorageOidcIdentity { issuer, subject }),
+            _ => None,
+        },
+    })
+}
+
+fn host_from_row(row: &Row) -> Result<StorageHost, rusqlite::Error> {
+    let client_private_key: Option<String> = row.get(4)?;
+    let client_certificate: Option<String> = row.get(5)?;
+    let server_certificate: Option<String> = row.get(6)?;
+
+    let pair_info = match (client_private_key, client_certificate, server_certificate) {
+        (Some(client_private_key), Some(client_certificate), Some(server_certificate)) => {
+            Some(StorageHostPairInfo {
+                client_private_key: pem_from_sql(4, &client_private_key)?,
+                client_certificate: pem_from_sql(5, &client_certificate)?,
+                server_certificate: pem_from_sql(6, &server_certificate)?,
+            })
+        }
+        _ => None,
+    };
+
+    Ok(StorageHost {
+        id: HostId(row.get(0)?),
+        owner: row.get::<_, Option<u32>>(1)?.map(UserId),
+        address: row.get(2)?,
+        http_port: row.get(3)?,
+        pair_info,
+        cache: StorageHostCache {
+            name: row.get(7)?,
+            mac: row
+                .get::<_, Option<[u8; 6]>>(8)?
+                .map(MacAddress::from_bytes),
+        },
+    })
+}
+
+/// Queries the members and hosts of the group
+fn group_from_sql(
+    connection: &Connection,
+    group_id: u32,
+    name: String,
+) -> Result<StorageGroup, rusqlite::Error> {
+    let members = connection
+        .prepare("SELECT user_id FROM user_group_members WHERE group_id = ?1")?
+        .query_map(params![group_id], |row| row.get(0).map(UserId))?
+        .collect::<Result<Vec<_>, _>>()?;
+    let hosts = connection
+        .prepare("SELECT host_id FROM user_group_hosts WHERE group_id = ?1")?
+        .query_map(params![group_id], |row| row.get(0).map(HostId))?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(StorageGroup {
+        id: GroupId(group_id),
+        name,
+        members,
+        hosts,
+    })
+}
+
+#[async_trait]
+impl Storage for SqliteStorage {
+    async fn add_user(&self, user: StorageUserAdd) -> Result<StorageUser, AppError> {
+        self.run(move |connection| {
+            let transaction = connection.transaction()?;
+
+            let name_taken: bool = transaction.query_row(
+                "SELECT EXISTS(SELECT 1 FROM users WHERE name = ?1)",
+                params![user.name],
+                |row| row.get(0),
+            )?;
+            if name_taken {
+                return Err(AppError::UserAlreadyExists);
+            }
+
+            let id = new_id(&transaction, "users")?;
+            let password_algorithm = user
+                .password
+                .as_ref()
+                .map(|password| password_algorithm_to_sql(password.algorithm));
+            transaction.execute(
+                "INSERT INTO users (id, name, password_salt, password_hash, role, client_unique_id, allow_clipboard, password_algorithm, password_iterations, password_memory_kib, password_parallelism, oidc_issuer, oidc_subject)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)",
+                params![
+                    id,
+                    user.name,
+                    user.password.as_ref().map(|password| password.salt),
+                    user.password.as_ref().map(|password| password.hash),
+                    role_to_sql(user.role),
+                    user.client_unique_id,
+                    user.allow_clipboard,
+                    password_algorithm.map(|algorithm| algorithm.0),
+                    password_algorithm.map(|algorithm| algorithm.1),
+                    password_algorithm.and_then(|algorithm| algorithm.2),
+                    password_algorithm.and_then(|algorithm| algorithm.3),
+                    user.oidc.as_ref().map(|oidc| &oidc.issuer),
+                    user.oidc.as_ref().map(|oidc| &oidc.subject),
+                ],
+            )?;
+
+            transaction.commit()?;
+
+            Ok(StorageUser {
+                id: UserId(id),
+                name: user.name,
+                password: user.password,
+                role: user.role,
+                client_unique_id: user.client_unique_id,
+                allow_clipboard: user.allow_clipboard,
+                totp: None,
+                permissions: Default::default(),
+                oidc: user.oidc,
+            })
+        })
+        .await
+    }
+    async fn modify_user(
+        &self,
+        user_id: UserId,
+        modify: StorageUserModify,
+    ) -> Result<(), AppError> {
+        self.run(move |connection| {
+            let transaction = connection.transaction()?;
+
+            if !user_exists(&transaction, user_id)? {
+                return Err(AppError::UserNotFound);
+            }
+
+            if let Some(password) = modify.password {
+                let password_algorithm = password
+                    .as_ref()
+                    .map(|password| password_algorithm_to_sql(password.algorithm));
+                transaction.execute(
+                    "UPDATE users SET password_salt = ?2, password_hash = ?3, password_algorithm = ?4, password_iterations = ?5, password_memory_kib = ?6, password_parallelism = ?7 WHERE id = ?1",
+                    params![
+                        user_id.0,
+                        password.as_ref().map(|password| password.salt),
+                        password.as_ref().map(|password| password.hash),
+                        password_algorithm.map(|algorithm| algorithm.0),
+                        password_algorithm.map(|algorithm| algorithm.1),
+                        password_algorithm.and_then(|algorithm| algorithm.2),
+                        password_algorithm.and_then(|algorithm| algorithm.3),
+                    ],
+                )?;
+            }
+            if let Some(role) = modify.role {
+                transaction.execute(
+                    "UPDATE users SET role = ?2 WHERE id = ?1",
+                    params![user_id.0, role_to_sql(role)],
+                )?;
+            }
+            if let Some(client_unique_id) = modify.client_unique_id {
+                transaction.execute(
+                    "UPDATE users SET client_unique_id = ?2 WHERE id = ?1",
+                    params![user_id.0, client_unique_id],
+                )?;
+            }
+            if let Some(allow_clipboard) = modify.allow_clipboard {
+                transaction.execute(
+                    "UPDATE users SET allow_clipboard = ?2 WHERE id = ?1",
+                    params![user_id.0, allow_clipboard],
+                )?;
+            }
+            if let Some(totp) = modify.totp {
+                let (secret, confirmed, recovery_codes, last_used_step) =
+                    totp_to_sql(totp.as_ref());
+
+                transaction.execute(
+                    "UPDATE users SET totp_secret = ?2, totp_confirmed = ?3, totp_recovery_codes = ?4, totp_last_used_step = ?5 WHERE id = ?1",
+                    params![user_id.0, secret, confirmed, recovery_codes, last_used_step],
+                )?;
+            }
+            if let Some(permissions) = modify.permissions {
+                transaction.execute(
+                    "UPDATE users SET permissions = ?2 WHERE id = ?1",
+                    params![user_id.0, permissions_to_sql(&permissions)?],
+                )?;
+            }
+            if let Some(oidc) = modify.oidc {
+                transaction.execute(
+                    "UPDATE users SET oidc_issuer = ?2, oidc_subject = ?3 WHERE id = ?1",
+                    params![
+                        user_id.0,
+                        oidc.as_ref().map(|oidc| &oidc.issuer),
+                        oidc.as_ref().map(|oidc| &oidc.subject),
+                    ],
+                )?;
+            }
+
+            transaction.commit()?;
+
+            Ok(())
+        })
+        .await
+    }
+    async fn get_user(&self, user_id: UserId) -> Result<StorageUser, AppError> {
+        self.run(move |connection| {
+            connection
+                .query_row(
+                    &format!("SELECT {USER_COLUMNS} FROM users WHERE id = ?1"),
+                    params![user_id.0],
+                    user_from_row,
+                )
+                .optional()?
+                .ok_or(AppError::UserNotFound)
+        })
+        .await
+    }
+    async fn get_user_by_name(
+        &self,
+        name: &str,
+    ) -> Result<(UserId, Option<StorageUser>), AppError> {
+        let name = name.to_string();
+
+        self.run(move |connection| {
+            let user = connection
+                .query_row(
+                    &format!("SELECT {USER_COLUMNS} FROM users WHERE name = ?1"),
+                    params![name],
+                    user_from_row,
+                )
+                .optional()?
+                .ok_or(AppError::UserNotFound)?;
+
+            Ok((user.id, Some(user)))
+        })
+        .await
+    }
+    async fn get_user_by_oidc(
+        &self,
+        identity: &StorageOidcIdentity,
+    ) -> Result<(UserId, Option<StorageUser>), AppError> {
+        let identity = identity.clone();
+
+        self.run(move |connection| {
+            let user = connection
+                .query_row(
+                    &format!(
+                        "SELECT {USER_COLUMNS} FROM users WHERE oidc_issuer = ?1 AND oidc_subject = ?2"
+                    ),
+                    params![identity.issuer, identity.subject],
+                    user_from_row,
+                )
+                .optional()?
+                .ok_or(AppError::UserNotFound)?;
+
+            Ok((user.id, Some(user)))
+        })
+        .await
+    }
+    async fn remove_user(&self, user_id: UserId) -> Result<(), AppError> {
+        self.run(move |connection| {
+            let removed =
+                connection.execute("DELETE FROM users WHERE id = ?1", params![user_id.0])?;
+
+            if removed == 0 {
+                return Err(AppError::UserNotFound);
+            }
+
+            Ok(())
+        })
+        .await
+    }
+    async fn list_users(&self) -> Result<Either<Vec<UserId>, Vec<StorageUser>>, AppError> {
+        self.run(|connection| {
+            let mut statement = connection.prepare(&format!("SELECT {USER_COLUMNS} FROM users"))?;
+
+            let users = statement
+                .query_map([], user_from_row)?
+                .collect::<Result<Vec<_>, _>>()?;
+
+            Ok(Either::Right(users))
+        })
+        .await
+    }
+    async fn any_user_exists(&self) -> Result<bool, AppError> {
+        self.run(|connection| {
+            let exists =
+                connection.query_row("SELECT EXISTS(SELECT 1 FROM users)", [], |row| row.get(0))?;
+
+            Ok(exists)
+        })
+        .await
+    }
+
+    async fn create_session_token(
+        &self,
+        user_id: UserId,
+        expiration: Duration,
+        session: StorageSessionAdd,
+    ) -> Result<SessionToken, AppError> {
+        self.run(move |connection| {
+            let transaction = connection.transaction()?;
+
+            let mut token;
+            loop {
+                token = SessionToken::new()?;
+
+                let exists: bool = transaction.query_row(
+                    "SELECT EXISTS(SELECT 1 FROM sessions WHERE token_hash = ?1)",
+                    params![token.hash()],
+                    |row| row.get(0),
+                )?;
+                if !exists {
+                    break;
+                }
+            }
+
+            let now = unix_now();
+            transaction.execute(
+                "INSERT INTO sessions (token_hash, user_id, created_at, expires_at, last_seen_at, user_agent, last_seen_ip)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                params![
+                    token.hash(),
+                    user_id.0,
+                    now,
+                    now.saturating_add(expiration.as_secs()),
+                    now,
+                    session.user_agent,
+                    session.ip.map(|ip| ip.to_string()),
+                ],
+            )?;
+
+            transaction.commit()?;
+
+            Ok(token)
+        })
+        .await
+    }
+    async fn remove_session_token(&self, session: SessionToken) -> Result<(), AppError> {
+        self.run(move |connection| {
+            connection.execute(
+                "DELETE FROM sessions WHERE token_hash = ?1",
+                params![session.hash()],
+            )?;
+
+            Ok(())
+        })
+        .await
+    }
+    async fn remove_user_session_token(
+        &self,
+        user_id: UserId,
+        token_hash: [u8; 32],
+    ) -> Result<(), AppError> {
+        self.run(move |connection| {
+            let removed = connection.execute(
+                "DELETE FROM sessions WHERE token_hash = ?1 AND user_id = ?2",
+                params![token_hash, user_id.0],
+            )?;
+
+            if removed == 0 {
+                return Err(AppError::LoginSessionNotFound);
+            }
+
+            Ok(())
+        })
+        .await
+    }
+    async fn remove_all_user_session_tokens(&self, user_id: UserId) -> Result<(), AppError> {
+        self.run(move |connection| {
+            connection.execute(
+                "DELETE FROM sessions WHERE user_id = ?1",
+                params![user_id.0],
+            )?;
+
+            Ok(())
+        })
+        .await
+    }
+    async fn list_user_session_tokens(
+        &self,
+        user_id: UserId,
+    ) -> Result<Vec<StorageSession>, AppError> {
+        self.run(move |connection| {
+            let mut statement = connection.prepare(
+                "SELECT token_hash, created_at, expires_at, last_seen_at, user_agent, last_seen_ip FROM sessions
+                WHERE user_id = ?1 AND expires_at > ?2",
+            )?;
+
+            let sessions = statement
+                .query_map(params![user_id.0, unix_now()], |row| {
+                    let last_seen_ip: Option<String> = row.get(5)?;
+
+                    Ok(StorageSession {
+                        token_hash: row.get(0)?,
+                        created_at: row.get(1)?,
+                        expires_at: row.get(2)?,
+                        last_seen_at: row.get(3)?,
+                        user_agent: row.get(4)?,
+                        // An unparsable ip only loses information for displaying
+                        last_seen_ip: last_seen_ip.and_then(|ip| ip.parse().ok()),
+                    })
+                })?
+                .collect::<Result<Vec<_>, _>>()?;
+
+            Ok(sessions)
+        })
+        .await
+    }
+    async fn get_user_by_session_token(
+        &self,
+        session: SessionToken,
+        ip: Option<IpAddr>,
+    ) -> Result<(UserId, Option<StorageUser>), AppError> {
+        self.run(move |connection| {
+            let transaction = connection.transaction()?;
+
+            let now = unix_now();
+            let user = transaction
+                .query_row(
+                    &format!(
+                        "SELECT {USER_COLUMNS} FROM users WHERE id = (SELECT user_id FROM sessions WHERE token_hash = ?1 AND expires_at > ?2)"
+                    ),
+                    params![session.hash(), now],
+                    user_from_row,
+                )
+                .optional()?
+                .ok_or(AppError::SessionTokenNotFound)?;
+
+            transaction.execute(
+                "UPDATE sessions SET last_seen_at = ?2, last_seen_ip = ?3
+                WHERE token_hash = ?1 AND (last_seen_ip IS NOT ?3 OR last_seen_at + ?4 <= ?2)",
+                params![
+                    session.hash(),
+                    now,
+                    ip.map(|ip| ip.to_string()),
+                    LAST_SEEN_UPDATE_INTERVAL
+                ],
+            )?;
+
+            transaction.commit()?;
+
+            Ok((user.id, Some(user)))
+        })
+        .await
+    }
+
+    async fn create_api_token(
+        &self,
+        user_id: UserId,
+        api_token: StorageApiTokenAdd,
+    ) -> Result<(ApiToken, StorageApiToken), AppError> {
+        self.run(move |connection| {
+            let transaction = connection.transaction()?;
+
+            if !user_exists(&transaction, user_id)? {
+                return Err(AppError::UserNotFound);
+            }
+
+            let mut token;
+            loop {
+                token = ApiToken::new()?;
+
+                let exists: bool = transaction.query_row(
+                    "SELECT EXISTS(SELECT 1 FROM api_tokens WHERE token_hash = ?1)",
+                    params![token.hash()],
+                    |row| row.get(0),
+                )?;
+                if !exists {
+                    break;
+                }
+            }
+
+            let api_token = StorageApiToken {
+                token_hash: token.hash(),
+                name: api_token.name,
+                scopes: api_token.scopes,
+                created_at: unix_now(),
+                last_used_at: None,
+            };
+
+            transaction.execute(
+                "INSERT INTO api_tokens (token_hash, user_id, name, scopes, created_at, last_used_at)
+                VALUES (?1, ?2, ?3, ?4, ?5, NULL)",
+                params![
+                    api_token.token_hash,
+                    user_id.0,
+                    api_token.name,
+                    api_token_scopes_to_sql(&api_token.scopes)?,
+                    api_token.created_at,
+                ],
+            )?;
+
+            transaction.commit()?;
+
+            Ok((token, api_token))
+        })
+        .await
+    }
+    async fn remove_user_api_token(
+        &self,
+        user_id: UserId,
+        token_hash: [u8; 32],
+    ) -> Result<(), AppError> {
+        self.run(move |connection| {
+            let removed = connection.execute(
+                "DELETE FROM api_tokens WHERE token_hash = ?1 AND user_id = ?2",
+                params![token_hash, user_id.0],
+            )?;
+
+            if removed == 0 {
+                return Err(AppError::ApiTokenNotFound);
+            }
+
+            Ok(())
+        })
+        .await
+    }
+    async fn list_user_api_tokens(
+        &self,
+        user_id: UserId,
+    ) -> Result<Vec<StorageApiToken>, AppError> {
+        self.run(move |connection| {
+            let mut statement = connection.prepare(
+                "SELECT token_hash, name, scopes, created_at, last_used_at FROM api_tokens WHERE user_id = ?1",
+            )?;
+
+            let api_tokens = statement
+                .query_map(params![user_id.0], |row| {
+                    Ok(StorageApiToken {
+                        token_hash: row.get(0)?,
+                        name: row.get(1)?,
+                        scopes: api_token_scopes_from_sql(2, &row.get::<_, String>(2)?)?,
+                        created_at: row.get(3)?,
+                        last_used_at: row.get(4)?,
+                    })
+                })?
+                .collect::<Result<Vec<_>, _>>()?;
+
+            Ok(api_tokens)
+        })
+        .await
+    }
+    async fn get_user_by_api_token(
+        &self,
+        token: ApiToken,
+    ) -> Result<(UserId, Vec<ApiTokenScope>), AppError> {
+        self.run(move |connection| {
+            let transaction = connection.transaction()?;
+
+            let now = unix_now();
+            let (user_id, scopes) = transaction
+                .query_row(
+                    "SELECT user_id, scopes FROM api_tokens WHERE token_hash = ?1",
+                    params![token.hash()],
+                    |row| {
+                        Ok((
+                            UserId(row.get(0)?),
+                            api_token_scopes_from_sql(1, &row.get::<_, String>(1)?)?,
+                        ))
+                    },
+                )
+                .optional()?
+                .ok_or(AppError::Unauthorized)?;
+
+            transaction.execute(
+                "UPDATE api_tokens SET last_used_at = ?2
+                WHERE token_hash = ?1 AND (last_used_at IS NULL OR last_used_at + ?3 <= ?2)",
+                params![token.hash(), now, LAST_SEEN_UPDATE_INTERVAL],
+            )?;
+
+            transaction.commit()?;
+
+            Ok((user_id, scopes))
+        })
+        .await
+    }
+
+    async fn get_input_profiles(&self, user_id: UserId) -> Result<StorageInputProfiles, AppError> {
+        self.run(move |connection| {
+            let transaction = connection.transaction()?;
+
+            let default = transaction
+                .query_row(
+                    "SELECT default_input_profile FROM users WHERE id = ?1",
+                    params![user_id.0],
+                    |row| {
+                        row.get::<_, Option<String>>(0)?
+                            .map(|profile| input_profile_from_sql(0, &profile))
+                            .transpose()
+                    },
+                )
+                .optional()?
+                .ok_or(AppError::UserNotFound)?;
+
+            let mut statement = transaction.prepare(
+                "SELECT host_id, app_id, profile FROM user_app_input_profiles WHERE user_id = ?1",
+            )?;
+            let apps = statement
+                .query_map(params![user_id.0], |row| {
+                    Ok(StorageAppInputProfile {
+                        host_id: HostId(row.get(0)?),
+                        app_id: AppId(row.get(1)?),
+                        profile: input_profile_from_sql(2, &row.get::<_, String>(2)?)?,
+                    })
+                })?
+                .collect::<Result<Vec<_>, _>>()?;
+
+            Ok(StorageInputProfiles { default, apps })
+        })
+        .await
+    }
+    async fn set_input_profile(
+        &self,
+        user_id: UserId,
+        app: Option<(HostId, AppId)>,
+        profile: Option<InputProfile>,
+    ) -> Result<(), AppError> {
+        self.run(move |connection| {
+            let transaction = connection.transaction()?;
+
+            if !user_exists(&transaction, user_id)? {
+                return Err(AppError::UserNotFound);
+            }
+
+            let profile = profile.as_ref().map(input_profile_to_sql).transpose()?;
+
+            match (app, profile) {
+                (None, profile) => {
+                    transaction.execute(
+                        "UPDATE users SET default_input_profile = ?2 WHERE id = ?1",
+                        params![user_id.0, profile],
+                    )?;
+                }
+                (Some((host_id, app_id)), Some(profile)) => {
+                    transaction.execute(
+                        "INSERT OR REPLACE INTO user_app_input_profiles (user_id, host_id, app_id, profile) VALUES (?1, ?2, ?3, ?4)",
+                        params![user_id.0, host_id.0, app_id.0, profile],
+                    )?;
+                }
+                (Some((host_id, app_id)), None) => {
+                    transaction.execute(
+                        "DELETE FROM user_app_input_profiles WHERE user_id = ?1 AND host_id = ?2 AND app_id = ?3",
+                        params![user_id.0, host_id.0, app_id.0],
+                    )?;
+                }
+            }
+
+            transaction.commit()?;
+
+            Ok(())
+        })
+        .await
+    }
+
+    async fn add_host(&self, host: StorageHostAdd) -> Result<StorageHost, AppError> {
+        self.run(move |connection| {
+            let transaction = connection.transaction()?;
+
+            let id = new_id(&transaction, "hosts")?;
+            transaction.execute(
+                "INSERT INTO hosts (id, owner, address, http_port, client_private_key, client_certificate, server_certificate, cache_name, cache_mac)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+                params![
+                    id,
+                    host.owner.map(|user_id| user_id.0),
+                    host.address,
+                    host.http_port,
+                    host.pair_info
+                        .as_ref()
+                        .map(|pair_info| pem::encode(&pair_info.client_private_key)),
+                    host.pair_info
+                        .as_ref()
+                        .map(|pair_info| pem::encode(&pair_info.client_certificate)),
+                    host.pair_info
+                        .as_ref()
+                        .map(|pair_info| pem::encode(&pair_info.server_certificate)),
+                    host.cache.name,
+                    host.cache.mac.map(|mac| mac.to_bytes()),
+                ],
+            )?;
+
+            transaction.commit()?;
+
+            Ok(StorageHost {
+                id: HostId(id),
+                owner: host.owner,
+                address: host.address,
+                http_port: host.http_port,
+                pair_info: host.pair_info,
+                cache: host.cache,
+            })
+        })
+        .await
+    }
+    async fn modify_host(
+        &self,
+        host_id: HostId,
+        modify: StorageHostModify,
+    ) -> Result<(), AppError> {
+        self.run(move |connection| {
+            let transaction = connection.transaction()?;
+
+            let exists: bool = transaction.query_row(
+                "SELECT EXISTS(SELECT 1 FROM hosts WHERE id = ?1)",
+                params![host_id.0],
+                |row| row.get(0),
+            )?;
+            if !exists {
+                return Err(AppError::HostNotFound);
+            }
+
+            if let Some(new_owner) = modify.owner {
+                transaction.execute(
+                    "UPDATE hosts SET owner = ?2 WHERE id = ?1",
+                    params![host_id.0, new_owner.map(|user_id| user_id.0)],
+                )?;
+            }
+            if let Some(new_address) = modify.address {
+                transaction.execute(
+                    "UPDATE hosts SET address = ?2 WHERE id = ?1",
+                    params![host_id.0, new_address],
+                )?;
+            }
+            if let Some(new_http_port) = modify.http_port {
+                transaction.execute(
+                    "UPDATE hosts SET http_port = ?2 WHERE id = ?1",
+                    params![host_id.0, new_http_port],
+                )?;
+            }
+            if let Some(new_pair_info) = modify.pair_info {
+                transaction.execute(
+                    "UPDATE hosts SET client_private_key = ?2, client_certificate = ?3, server_certificate = ?4 WHERE id = ?1",
+                    params![
+                        host_id.0,
+                        new_pair_info
+                            .as_ref()
+                            .map(|pair_info| pem::encode(&pair_info.client_private_key)),
+                        new_pair_info
+                            .as_ref()
+                            .map(|pair_info| pem::encode(&pair_info.client_certificate)),
+                        new_pair_info
+                            .as_ref()
+                            .map(|pair_info| pem::encode(&pair_info.server_certificate)),
+                    ],
+                )?;
+            }
+            if let Some(new_cache_name) = modify.cache_name {
+                transaction.execute(
+                    "UPDATE hosts SET cache_name = ?2 WHERE id = ?1",
+                    params![host_id.0, new_cache_name],
+                )?;
+            }
+            if let Some(new_cache_mac) = modify.cache_mac {
+                transaction.execute(
+                    "UPDATE hosts SET cache_mac = ?2 WHERE id = ?1",
+                    params![host_id.0, new_cache_mac.map(|mac| mac.to_bytes())],
+                )?;
+            }
+
+            transaction.commit()?;
+
+            Ok(())
+        })
+        .await
+    }
+    async fn get_host(&self, host_id: HostId) -> Result<StorageHost, AppError> {
+        self.run(move |connection| {
+            connection
+                .query_row(
+                    &format!("SELECT {HOST_COLUMNS} FROM hosts WHERE id = ?1"),
+                    params![host_id.0],
+                    host_from_row,
+                )
+                .optional()?
+                .ok_or(AppError::HostNotFound)
+        })
+        .await
+    }
+    async fn remove_host(&self, host_id: HostId) -> Result<(), AppError> {
+        self.run(move |connection| {
+            let removed =
+                connection.execute("DELETE FROM hosts WHERE id = ?1", params![host_id.0])?;
+
+            if removed == 0 {
+                return Err(AppError::HostNotFound);
+            }
+
+            Ok(())
+        })
+        .await
+    }
+
+    async fn list_user_hosts(
+        &self,
+        query: StorageQueryHosts,
+    ) -> Result<Vec<(HostId, Option<StorageHost>)>, AppError> {
+        self.run(move |connection| {
+            let mut statement = connection.prepare(&format!(
+                "SELECT {HOST_COLUMNS} FROM hosts WHERE owner IS NULL OR owner = ?1 OR id IN (
+                    SELECT host_id FROM user_group_hosts JOIN user_group_members USING (group_id) WHERE user_id = ?1
+                )"
+            ))?;
+
+            let hosts = statement
+                .query_map(params![query.user_id.0], host_from_row)?
+                .map(|host| host.map(|host| (host.id, Some(host))))
+                .collect::<Result<Vec<_>, _>>()?;
+
+            Ok(hosts)
+        })
+        .await
+    }
+
+    async fn add_group(&self, group: StorageGroupAdd) -> Result<StorageGroup, AppError> {
+        self.run(move |connection| {
+            let transaction = connection.transaction()?;
+
+            let name_taken: bool = transaction.query_row(
+                "SELECT EXISTS(SELECT 1 FROM user_groups WHERE name = ?1)",
+                params![group.name],
+                |row| row.get(0),
+            )?;
+            if name_taken {
+                return Err(AppError::GroupAlreadyExists);
+            }
+
+            let id = new_id(&transaction, "user_groups")?;
+            transaction.execute(
+                "INSERT INTO user_groups (id, name) VALUES (?1, ?2)",
+                params![id, group.name],
+            )?;
+
+            transaction.commit()?;
+
+            Ok(StorageGroup {
+                id: GroupId(id),
+                name: group.name,
+                members: Vec::new(),
+                hosts: Vec::new(),
+            })
+        })
+        .await
+    }
+    async fn modify_group(
+        &self,
+        group_id: GroupId,
+        modify: StorageGroupModify,
+    ) -> Result<(), AppError> {
+        self.run(move |connection| {
+            let transaction = connection.transaction()?;
+
+            let exists: bool = transaction.query_row(
+                "SELECT EXISTS(SELECT 1 FROM user_groups WHERE id = ?1)",
+                params![group_id.0],
+                |row| row.get(0),
+            )?;
+            if !exists {
+                return Err(AppError::GroupNotFound);
+            }
+
+            if let Some(name) = modify.name {
+                let name_taken: bool = transaction.query_row(
+                    "SELECT EXISTS(SELECT 1 FROM user_groups WHERE name = ?1 AND id != ?2)",
+                    params![name, group_id.0],
+                    |row| row.get(0),
+                )?;
+                if name_taken {
+                    return Err(AppError::GroupAlreadyExists);
+                }
+
+                transaction.execute(
+                    "UPDATE user_groups SET name = ?2 WHERE id = ?1",
+                    params![group_id.0, name],
+                )?;
+            }
+            if let Some(members) = modify.members {
+                transaction.execute(
+                    "DELETE FROM user_group_members WHERE group_id = ?1",
+                    params![group_id.0],
+                )?;
+
+                for user_id in members {
+                    transaction.execute(
+                        "INSERT OR IGNORE INTO user_group_members (group_id, user_id) VALUES (?1, ?2)",
+                        params![group_id.0, user_id.0],
+                    )?;
+                }
+            }
+            if let Some(hosts) = modify.hosts {
+                transaction.execute(
+                    "DELETE FROM user_group_hosts WHERE group_id = ?1",
+                    params![group_id.0],
+                )?;
+
+                for host_id in hosts {
+                    transaction.execute(
+                        "INSERT OR IGNORE INTO user_group_hosts (group_id, host_id) VALUES (?1, ?2)",
+                        params![group_id.0, host_id.0],
+                    )?;
+                }
+            }
+
+            transaction.commit()?;
+
+            Ok(())
+        })
+        .await
+    }
+    async fn get_group(&self, group_id: GroupId) -> Result<StorageGroup, AppError> {
+        self.run(move |connection| {
+            let name: String = connection
+                .query_row(
+                    "SELECT name FROM user_groups WHERE id = ?1",
+                    params![group_id.0],
+                    |row| row.get(0),
+                )
+                .optional()?
+                .ok_or(AppError::GroupNotFound)?;
+
+            Ok(group_from_sql(connection, group_id.0, name)?)
+        })
+        .await
+    }
+    async fn remove_group(&self, group_id: GroupId) -> Result<(), AppError> {
+        self.run(move |connection| {
+            let removed =
+                connection.execute("DELETE FROM user_groups WHERE id = ?1", params![group_id.0])?;
+
+            if removed == 0 {
+                return Err(AppError::GroupNotFound);
+            }
+
+            Ok(())
+        })
+        .await
+    }
+    async fn list_groups(&self) -> Result<Vec<StorageGroup>, AppError> {
+        self.run(|connection| {
+            let groups = connection
+                .prepare("SELECT id, name FROM user_groups")?
+                .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+                .collect::<Result<Vec<(u32, String)>, _>>()?;
+
+            let groups = groups
+                .into_iter()
+                .map(|(id, name)| group_from_sql(connection, id, name))
+                .collect::<Result<Vec<_>, _>>()?;
+
+            Ok(groups)
+        })
+        .await
+    }
+
+    async fn is_host_shared_with_user(
+        &self,
+        host_id: HostId,
+        user_id: UserId,
+    ) -> Result<bool, AppError> {
+        self.run(move |connection| {
+            let shared = connection.query_row(
+                "SELECT EXISTS(SELECT 1 FROM user_group_hosts JOIN user_group_members USING (group_id) WHERE host_id = ?1 AND user_id = ?2)",
+                params![host_id.0, user_id.0],
+                |row| row.get(0),
+            )?;
+
+            Ok(shared)
+        })
+        .await
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use super::*;
+
+    const IP: IpAddr = IpAddr::V4(Ipv4Addr::new(192, 168, 0, 2));
+
+    async fn open() -> Arc<SqliteStorage> {
+        SqliteStorage::open(":memory:".into(), Duration::from_secs(3600), None)
+            .await
+            .unwrap()
+    }
+
+    async fn add_user(storage: &SqliteStorage, name: &str) -> UserId {
+        storage
+            .add_user(StorageUserAdd {
+                role: Role::User,
+                name: name.to_string(),
+                password: None,
+                client_unique_id: name.to_string(),
+                allow_clipboard: false,
+                oidc: None,
+            })
+            .await
+            .unwrap()
+            .id
+    }
+
+    #[actix_web::test]
+    async fn test_session_lookup() {
+        let storage = open().await;
+        let user_id = add_user(&storage, "alice").await;
+
+        let token = storage
+            .create_session_token(
+                user_id,
+                Duration::from_secs(3600),
+                StorageSessionAdd {
+                    user_agent: Some("browser".to_string()),
+                    ip: None,
+                },
+            )
+            .await
+            .unwrap();
+
+        let (found_id, _) = storage
+            .get_user_by_session_token(token, Some(IP))
+            .await
+            .unwrap();
+        assert_eq!(found_id, user_id);
+
+        // Only the hash of the token is stored and the ip of the last request is recorded
+        let sessions = storage.list_user_session_tokens(user_id).await.unwrap();
+        assert_eq!(sessions.len(), 1);
+        assert_eq!(sessions[0].token_hash, token.hash());
+        assert_eq!(sessions[0].user_agent.as_deref(), Some("browser"));
+        assert_eq!(sessions[0].last_seen_ip, Some(IP));
+    }
+
+    #[actix_web::test]
+    async fn test_session_expiry() {
+        let storage = open().await;
+        let user_id = add_user(&storage, "alice").await;
+
+        let token = storage
+            .create_session_token(user_id, Duration::ZERO, Default::default())
+            .await
+            .unwrap();
+
+        assert!(matches!(
+            storage.get_user_by_session_token(token, None).await,
+            Err(AppError::SessionTokenNotFound)
+        ));
+        assert!(
+            storage
+                .list_user_session_tokens(user_id)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[actix_web::test]
+    async fn test_session_revocation() {
+        let storage = open().await;
+        let alice = add_user(&storage, "alice").await;
+        let bob = add_user(&storage, "bob").await;
+
+        let expiration = Duration::from_secs(3600);
+        let first = storage
+            .create_session_token(alice, expiration, Default::default())
+            .await
+            .unwrap();
+        let second = storage
+            .create_session_token(alice, expiration, Default::default())
+            .await
+            .unwrap();
+        let third = storage
+            .create_session_token(alice, expiration, Default::default())
+            .await
+            .unwrap();
+        let bobs = storage
+            .create_session_token(bob, expiration, Default::default())
+            .await
+            .unwrap();
+
+        // Users can only revoke their own sessions
+        assert!(matches!(
+            storage.remove_user_session_token(bob, first.hash()).await,
+            Err(AppError::LoginSessionNotFound)
+        ));
+        storage
+            .remove_user_session_token(alice, first.hash())
+            .await
+            .unwrap();
+        assert!(matches!(
+            storage.get_user_by_session_token(first, None).await,
+            Err(AppError::SessionTokenNotFound)
+        ));
+        storage
+            .get_user_by_session_token(second, None)
+            .await
+            .unwrap();
+
+        // Logging out everywhere doesn't touch the sessions of other users
+        storage.remove_all_user_session_tokens(alice).await.unwrap();
+        for token in [second, third] {
+            assert!(matches!(
+                storage.get_user_by_session_token(token, None).await,
+                Err(AppError::SessionTokenNotFound)
+            ));
+        }
+        assert!(
+            storage
+                .list_user_session_tokens(alice)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+        storage.get_user_by_session_token(bobs, None).await.unwrap();
+    }
+
+    #[actix_web::test]
+    async fn test_api_token_revocation() {
+        let storage = open().await;
+        let alice = add_user(&storage, "alice").await;
+        let bob = add_user(&storage, "bob").await;
+
+        let (token, api_token) = storage
+            .create_api_token(
+                alice,
+                StorageApiTokenAdd {
+                    name: "automation".to_string(),
+                    scopes: vec![ApiTokenScope::ReadHosts, ApiTokenScope::WakeHosts],
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(api_token.token_hash, token.hash());
+
+        let (user_id, scopes) = storage.get_user_by_api_token(token).await.unwrap();
+        assert_eq!(user_id, alice);
+        assert_eq!(
+            scopes,
+            vec![ApiTokenScope::ReadHosts, ApiTokenScope::WakeHosts]
+        );
+
+        // Users can only revoke their own tokens
+        assert!(matches!(
+            storage.remove_user_api_token(bob, token.hash()).await,
+            Err(AppError::ApiTokenNotFound)
+        ));
+        storage
+            .remove_user_api_token(alice, token.hash())
+            .await
+            .unwrap();
+
+        assert!(matches!(
+            storage.get_user_by_api_token(token).await,
+            Err(AppError::Unauthorized)
+        ));
+        assert!(
+            storage
+                .list_user_api_tokens(alice)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+    }
+}
@@ -0,0 +1,505 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{
+        Arc,
+        atomic::{AtomicU32, Ordering},
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use actix_ws::{Closed, Session};
+use common::{
+    api_bindings::{
+        StreamClientMessage, StreamSeat, StreamServerMessage, StreamSession, StreamSessionSettings,
+        StreamSessionStats, StreamSpectator, StreamUserSeat, StreamerStatsUpdate, TransportType,
+    },
+    ipc::{IpcSender, ServerIpcMessage},
+    serialize_json,
+};
+use log::{info, warn};
+use thiserror::Error;
+use tokio::{
+    process::Child,
+    spawn,
+    sync::{Mutex, RwLock},
+    time::{sleep, timeout},
+};
+
+use crate::app::{
+    AppError,
+    host::{AppId, HostId},
+    user::UserId,
+};
+
+/// Moonlight supports up to 16 gamepads
+const MAX_GAMEPADS: u8 = 16;
+
+/// How long a streamer gets to stop itself before it's killed
+const STREAMER_STOP_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SpectatorId(pub u32);
+
+/// The configured limit that prevents a new stream from starting
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Error)]
+pub enum StreamLimitReached {
+    #[error("the server is already running the maximum amount of streams")]
+    Server,
+    #[error("you're already running the maximum amount of streams")]
+    User,
+    #[error("the host is busy with another stream")]
+    Host,
+}
+
+#[derive(Default)]
+struct OwnerConnection {
+    session: Option<Session>,
+    /// Increased for every new web socket of the owner
+    generation: u32,
+}
+
+/// What the owner requested and the streamer reported
+#[derive(Default)]
+struct ActiveStreamInfo {
+    transport: Option<TransportType>,
+    settings: Option<StreamSessionSettings>,
+    stats: StreamSessionStats,
+}
+
+struct ActiveSpectator {
+    user_id: UserId,
+    name: String,
+    session: Session,
+}
+
+/// A streamer which is currently running
+pub struct ActiveStream {
+    owner: UserId,
+    host_id: HostId,
+    app_id: AppId,
+    started_at: SystemTime,
+    child: Mutex<Option<Child>>,
+    ipc_sender: Mutex<IpcSender<ServerIpcMessage>>,
+    info: RwLock<ActiveStreamInfo>,
+    owner_connection: Mutex<OwnerConnection>,
+    next_spectator_id: AtomicU32,
+    invited: RwLock<HashSet<UserId>>,
+    spectators: RwLock<HashMap<SpectatorId, ActiveSpectator>>,
+    seats: RwLock<HashMap<UserId, StreamSeat>>,
+}
+
+impl ActiveStream {
+    pub(super) fn new(
+        owner: UserId,
+        host_id: HostId,
+        app_id: AppId,
+        child: Child,
+        ipc_sender: IpcSender<ServerIpcMessage>,
+    ) -> Self {
+        Self {
+            owner,
+            host_id,
+            app_id,
+            started_at: SystemTime::now(),
+            child: Mutex::new(Some(child)),
+            ipc_sender: Mutex::new(ipc_sender),
+            info: Default::default(),
+            owner_connection: Default::default(),
+            next_spectator_id: AtomicU32::new(0),
+            invited: Default::default(),
+            spectators: Default::default(),
+            seats: Default::default(),
+        }
+    }
+
+    pub fn owner(&self) -> UserId {
+        self.owner
+    }
+    pub fn host_id(&self) -> HostId {
+        self.host_id
+    }
+    pub fn app_id(&self) -> AppId {
+        self.app_id
+    }
+
+    pub async fn send_ipc(&self, message: ServerIpcMessage) {
+        let mut ipc_sender = self.ipc_sender.lock().await;
+
+        ipc_sender.send(message).await;
+    }
+
+    /// Asks the streamer to stop and kills it if it doesn't exit in time
+    pub async fn stop(&self) {
+        self.send_ipc(ServerIpcMessage::Stop).await;
+
+        let child = self.child.lock().await.take();
+        let Some(mut child) = child else {
+            return;
+        };
+
+        if timeout(STREAMER_STOP_TIMEOUT, child.wait()).await.is_err() {
+            warn!(
+                "streamer of {:?} on host {:?} didn't stop in time, killing it",
+                self.owner, self.host_id
+            );
+
+            if let Err(err) = child.kill().await {
+                warn!("failed to kill streamer child: {err}");
+            }
+        }
+    }
+
+    /// Kills the streamer if it's still running
+    pub async fn kill(&self) {
+        let child = self.child.lock().await.take();
+
+        if let Some(mut child) = child
+            && let Err(err) = child.kill().await
+        {
+            warn!("failed to kill streamer child: {err}");
+        }
+    }
+
+    // -- Session info
+    /// Remembers the transport and settings the owner requested
+    pub async fn on_owner_message(&self, message: &StreamClientMessage) {
+        match message {
+            StreamClientMessage::SetTransport(transport) => {
+                let mut info = self.info.write().await;
+                info.transport = Some(*transport);
+            }
+            StreamClientMessage::StartStream {
+                bitrate,
+                fps,
+                width,
+                height,
+                ..
+            } => {
+                let mut info = self.info.write().await;
+                info.settings = Some(StreamSessionSettings {
+                    bitrate: *bitrate,
+                    width: *width,
+                    height: *height,
+                    fps: *fps,
+                });
+            }
+            _ => {}
+        }
+    }
+
+    pub async fn on_stats(&self, stats: StreamerStatsUpdate) {
+        let mut info = self.info.write().await;
+
+        match stats {
+            StreamerStatsUpdate::Bandwidth {
+                incoming_kbps,
+                outgoing_kbps,
+            } => {
+                info.stats.incoming_kbps = Some(incoming_kbps);
+                info.stats.outgoing_kbps = Some(outgoing_kbps);
+            }
+            StreamerStatsUpdate::Rtt { rtt_ms, .. } => {
+                info.stats.rtt_ms = Some(rtt_ms);
+            }
+            _ => {}
+        }
+    }
+
+    pub async fn session(&self) -> StreamSession {
+        let (transport, settings, stats) = {
+            let info = self.info.read().await;
+            (info.transport, info.settings.clone(), info.stats.clone())
+        };
+
+        StreamSession {
+            user_id: self.owner.0,
+            host_id: self.host_id.0,
+            app_id: self.app_id.0,
+            started_at: self
+                .started_at
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs(),
+            transport,
+            settings,
+            stats,
+            owner_connected: self.owner_connection.lock().await.session.is_some(),
+            spectator_count: self.spectators.read().await.len(),
+        }
+    }
+
+    // -- Owner
+    /// Sets the web socket of the owner and closes the previous one.
+    /// Returns the generation of this web socket which is required to detach it again.
+    pub async fn attach_owner(&self, session: Session) -> u32 {
+        let (old_session, generation) = {
+            let mut connection = self.owner_connection.lock().await;
+            connection.generation = connection.generation.wrapping_add(1);
+
+            (connection.session.replace(session), connection.generation)
+        };
+
+        if let Some(old_session) = old_session
+            && let Err(err) = old_session.close(None).await
+        {
+            warn!("failed to close replaced web socket of the stream owner: {err}");
+        }
+
+        generation
+    }
+
+    /// Removes the web socket of the owner if it wasn't replaced in the meantime.
+    /// The streamer gets stopped if the owner doesn't reconnect within the grace period.
+    pub async fn detach_owner(self: &Arc<Self>, generation: u32, grace_period: Duration) {
+        {
+            let mut connection = self.owner_connection.lock().await;
+            if connection.generation != generation {
+                return;
+            }
+
+            connection.session = None;
+        }
+
+        let this = self.clone();
+        spawn(async move {
+            sleep(grace_period).await;
+
+            let reconnected = {
+                let connection = this.owner_connection.lock().await;
+                connection.generation != generation
+            };
+
+            if !reconnected {
+                info!(
+                    "stopping stream of {:?} on host {:?} because the owner didn't reconnect",
+                    this.owner, this.host_id
+                );
+
+                this.stop().await;
+            }
+        });
+    }
+
+    /// Sends the message to the owner if the owner is connected
+    pub async fn send_owner_message(&self, message: StreamServerMessage) -> Result<(), Closed> {
+        let Some(mut session) = self.owner_session().await else {
+            return Ok(());
+        };
+        let Some(json) = serialize_json(&message) else {
+            return Ok(());
+        };
+
+        session.text(json).await
+    }
+
+    pub async fn owner_session(&self) -> Option<Session> {
+        let connection = self.owner_connection.lock().await;
+
+        connection.session.clone()
+    }
+
+    /// Closes the web socket of the owner, the streamer is already stopped at this point
+    pub(super) async fn close_owner(&self) {
+        let session = {
+            let mut connection = self.owner_connection.lock().await;
+            connection.session.take()
+        };
+
+        if let Some(session) = session
+            && let Err(err) = session.close(None).await
+        {
+            warn!("failed to close streamer web socket: {err}");
+        }
+    }
+
+    // -- Spectators
+    pub async fn invite(&self, user_id: UserId) {
+        let mut invited = self.invited.write().await;
+
+        invited.insert(user_id);
+    }
+    pub async fn is_invited(&self, user_id: UserId) -> bool {
+        let invited = self.invited.read().await;
+
+        invited.contains(&user_id)
+    }
+    pub async fn invited(&self) -> Vec<UserId> {
+        let invited = self.invited.read().await;
+
+        invited.iter().copied().collect()
+    }
+
+    pub async fn spectators(&self) -> Vec<StreamSpectator> {
+        let spectators = self.spectators.read().await;
+
+        spectators
+            .iter()
+            .map(|(spectator_id, spectator)| StreamSpectator {
+                spectator_id: spectator_id.0,
+                user_id: spectator.user_id.0,
+                name: spectator.name.clone(),
+            })
+            .collect()
+    }
+
+    pub async fn spectator_session(&self, spectator_id: SpectatorId) -> Option<Session> {
+        let spectators = self.spectators.read().await;
+
+        spectators
+            .get(&spectator_id)
+            .map(|spectator| spectator.session.clone())
+    }
+
+    pub async fn add_spectator(
+        &self,
+        user_id: UserId,
+        name: String,
+        session: Session,
+    ) -> SpectatorId {
+        let spectator_id = SpectatorId(self.next_spectator_id.fetch_add(1, Ordering::Relaxed));
+
+        {
+            let mut spectators = self.spectators.write().await;
+            spectators.insert(
+                spectator_id,
+                ActiveSpectator {
+                    user_id,
+                    name,
+                    session,
+                },
+            );
+        }
+
+        self.send_ipc(ServerIpcMessage::SpectatorJoin {
+            spectator_id: spectator_id.0,
+        })
+        .await;
+
+        let seat = {
+            let seats = self.seats.read().await;
+            seats.get(&user_id).cloned()
+        };
+        if let Some(seat) = seat {
+            self.send_ipc(ServerIpcMessage::SpectatorSeat {
+                spectator_id: spectator_id.0,
+                seat,
+            })
+            .await;
+        }
+
+        spectator_id
+    }
+
+    /// Removes the spectator and closes its web socket
+    pub async fn remove_spectator(&self, spectator_id: SpectatorId) {
+        let spectator = {
+            let mut spectators = self.spectators.write().await;
+            spectators.remove(&spectator_id)
+        };
+
+        let Some(spectator) = spectator else {
+            return;
+        };
+
+        self.send_ipc(ServerIpcMessage::SpectatorLeave {
+            spectator_id: spectator_id.0,
+        })
+        .await;
+
+        if let Err(err) = spectator.session.close(None).await {
+            warn!("failed to close web socket of spectator {spectator_id:?}: {err}");
+        }
+    }
+
+    /// Revokes the invite of this user and kicks all of its spectators
+    pub async fn kick(&self, user_id: UserId) {
+        {
+            let mut invited = self.invited.write().await;
+            invited.remove(&user_id);
+        }
+        {
+            let mut seats = self.seats.write().await;
+            seats.remove(&user_id);
+        }
+
+        let spectator_ids = self.user_spectator_ids(user_id).await;
+
+        for spectator_id in spectator_ids {
+            self.remove_spectator(spectator_id).await;
+        }
+    }
+
+    async fn user_spectator_ids(&self, user_id: UserId) -> Vec<SpectatorId> {
+        let spectators = self.spectators.read().await;
+
+        spectators
+            .iter()
+            .filter(|(_, spectator)| spectator.user_id == user_id)
+            .map(|(spectator_id, _)| *spectator_id)
+            .collect()
+    }
+
+    // -- Seats
+    pub async fn seats(&self) -> Vec<StreamUserSeat> {
+        let seats = self.seats.read().await;
+
+        seats
+            .iter()
+            .map(|(user_id, seat)| StreamUserSeat {
+                user_id: user_id.0,
+                seat: seat.clone(),
+            })
+            .collect()
+    }
+
+    /// Gives the user the gamepad slots and keyboard / mouse of the seat, an empty seat revokes all input.
+    /// The seat gets applied to all spectators of this user immediately.
+    pub async fn set_seat(&self, user_id: UserId, seat: StreamSeat) -> Result<(), AppError> {
+        {
+            let mut seats = self.seats.write().await;
+
+            let mut used = HashSet::new();
+            for gamepad in &seat.gamepads {
+                if *gamepad >= MAX_GAMEPADS || !used.insert(*gamepad) {
+                    return Err(AppError::BadRequest);
+                }
+
+                let taken = seats.iter().any(|(other_user_id, other_seat)| {
+                    *other_user_id != user_id && other_seat.gamepads.contains(gamepad)
+                });
+                if taken {
+                    return Err(AppError::BadRequest);
+                }
+            }
+
+            if seat == StreamSeat::default() {
+                seats.remove(&user_id);
+            } else {
+                seats.insert(user_id, seat.clone());
+            }
+        }
+
+        self.invite(user_id).await;
+
+        for spectator_id in self.user_spectator_ids(user_id).await {
+            self.send_ipc(ServerIpcMessage::SpectatorSeat {
+                spectator_id: spectator_id.0,
+                seat: seat.clone(),
+            })
+            .await;
+        }
+
+        Ok(())
+    }
+
+    /// Closes the web sockets of all spectators, the streamer is already stopped at this point
+    pub(super) async fn close_spectators(&self) {
+        let mut spectators = self.spectators.write().await;
+
+        for (spectator_id, spectator) in spectators.drain() {
+            if let Err(err) = spectator.session.close(None).await {
+                warn!("failed to close web socket of spectator {spectator_id:?}: {err}");
+            }
+        }
+    }
+}
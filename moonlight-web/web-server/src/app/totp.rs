@@ -0,0 +1,245 @@
+use openssl::{
+    hash::MessageDigest, memcmp, pkey::PKey, rand::rand_bytes, sha::sha256, sign::Signer,
+};
+use url::Url;
+
+use crate::app::{AppError, storage::unix_now};
+
+const TOTP_ISSUER: &str = "Moonlight Web";
+const TOTP_STEP_SECONDS: u64 = 30;
+const TOTP_DIGITS: u32 = 6;
+/// How many steps a code may be off to account for clock drift
+const TOTP_ALLOWED_DRIFT: u64 = 1;
+
+const RECOVERY_CODE_COUNT: usize = 10;
+const RECOVERY_CODE_SIZE: usize = 5;
+
+/// Time based one time passwords (RFC 6238) with HMAC-SHA1 like every authenticator app supports
+#[derive(Clone)]
+pub struct StorageTotp {
+    pub secret: [u8; 20],
+    /// A totp is only enforced after the user confirmed it with a valid code
+    pub confirmed: bool,
+    /// The sha256 hashes of the recovery codes that weren't used yet
+    pub recovery_codes: Vec<[u8; 32]>,
+    /// The last time step a code was accepted for, so a code can't be used twice
+    pub last_used_step: u64,
+}
+
+fn base32_encode(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+    let mut out = String::new();
+
+    let mut buffer = 0u32;
+    let mut bits = 0;
+    for byte in bytes {
+        buffer = (buffer << 8) | *byte as u32;
+        bits += 8;
+
+        while bits >= 5 {
+            bits -= 5;
+            out.push(ALPHABET[((buffer >> bits) & 0x1f) as usize] as char);
+        }
+    }
+    if bits > 0 {
+        out.push(ALPHABET[((buffer << (5 - bits)) & 0x1f) as usize] as char);
+    }
+
+    out
+}
+
+fn normalize_recovery_code(code: &str) -> String {
+    code.chars()
+        .filter(char::is_ascii_alphanumeric)
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
+
+impl StorageTotp {
+    pub fn new() -> Result<Self, AppError> {
+        let mut secret = [0u8; 20];
+
+        rand_bytes(&mut secret)?;
+
+        Ok(Self {
+            secret,
+            confirmed: false,
+            recovery_codes: Vec::new(),
+            last_used_step: 0,
+        })
+    }
+
+    pub fn secret_base32(&self) -> String {
+        base32_encode(&self.secret)
+    }
+
+    /// The uri that authenticator apps understand, usually shown as a qr code
+    pub fn provisioning_uri(&self, username: &str) -> String {
+        let mut url = Url::parse("otpauth://totp/").expect("valid otpauth url");
+
+        if let Ok(mut path) = url.path_segments_mut() {
+            path.clear().push(&format!("{TOTP_ISSUER}:{username}"));
+        }
+        url.query_pairs_mut()
+            .append_pair("secret", &self.secret_base32())
+            .append_pair("issuer", TOTP_ISSUER)
+            .append_pair("digits", &TOTP_DIGITS.to_string())
+            .append_pair("period", &TOTP_STEP_SECONDS.to_string());
+
+        url.into()
+    }
+
+    fn code_at(&self, step: u64) -> Result<u32, AppError> {
+        let key = PKey::hmac(&self.secret)?;
+        let mut signer = Signer::new(MessageDigest::sha1(), &key)?;
+        signer.update(&step.to_be_bytes())?;
+        let hmac = signer.sign_to_vec()?;
+
+        // Dynamic truncation
+        let offset = (hmac[hmac.len() - 1] & 0xf) as usize;
+        let value = u32::from_be_bytes([
+            hmac[offset] & 0x7f,
+            hmac[offset + 1],
+            hmac[offset + 2],
+            hmac[offset + 3],
+        ]);
+
+        Ok(value % 10u32.pow(TOTP_DIGITS))
+    }
+
+    /// Checks the code of the authenticator app, a code is only accepted once
+    pub fn verify_code(&mut self, code: &str) -> Result<bool, AppError> {
+        let code = code.trim();
+        if code.len() != TOTP_DIGITS as usize {
+            return Ok(false);
+        }
+        let Ok(code) = code.parse::<u32>() else {
+            return Ok(false);
+        };
+
+        let current_step = unix_now() / TOTP_STEP_SECONDS;
+        for step in current_step.saturating_sub(TOTP_ALLOWED_DRIFT)
+            ..=current_step.saturating_add(TOTP_ALLOWED_DRIFT)
+        {
+            if step > self.last_used_step && self.code_at(step)? == code {
+                self.last_used_step = step;
+                return Ok(true);
+            }
+        }
+
+        Ok(false)
+    }
+
+    /// Accepts either a code of the authenticator app or one of the recovery codes, which is removed after use
+    pub fn verify(&mut self, code: &str) -> Result<bool, AppError> {
+        if self.verify_code(code)? {
+            return Ok(true);
+        }
+
+        let hash = sha256(normalize_recovery_code(code).as_bytes());
+        if let Some(index) = self
+            .recovery_codes
+            .iter()
+            .position(|recovery_code| memcmp::eq(recovery_code, &hash))
+        {
+            self.recovery_codes.remove(index);
+            return Ok(true);
+        }
+
+        Ok(false)
+    }
+
+    /// Replaces all recovery codes and returns the new ones, only their hashes are stored
+    pub fn new_recovery_codes(&mut self) -> Result<Vec<String>, AppError> {
+        let mut codes = Vec::with_capacity(RECOVERY_CODE_COUNT);
+
+        self.recovery_codes.clear();
+        for _ in 0..RECOVERY_CODE_COUNT {
+            let mut bytes = [0u8; RECOVERY_CODE_SIZE];
+            rand_bytes(&mut bytes)?;
+
+            let code = hex::encode(bytes);
+            self.recovery_codes.push(sha256(code.as_bytes()));
+
+            let (first, second) = code.split_at(code.len() / 2);
+            codes.push(format!("{first}-{second}"));
+        }
+
+        Ok(codes)
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    fn totp() -> StorageTotp {
+        StorageTotp {
+            secret: *b"12345678901234567890",
+            confirmed: true,
+            recovery_codes: Vec::new(),
+            last_used_step: 0,
+        }
+    }
+
+    #[test]
+    fn test_rfc6238_sha1() {
+        let totp = totp();
+
+        // The last 6 of the 8 digits in RFC 6238 Appendix B
+        for (time, code) in [
+            (59, 287082),
+            (1111111109, 81804),
+            (1111111111, 50471),
+            (1234567890, 5924),
+            (2000000000, 279037),
+            (20000000000, 353130),
+        ] {
+            assert_eq!(totp.code_at(time / TOTP_STEP_SECONDS).unwrap(), code);
+        }
+    }
+
+    #[test]
+    fn test_base32_encode() {
+        for (bytes, encoded) in [
+            ("", ""),
+            ("f", "MY"),
+            ("fo", "MZXQ"),
+            ("foo", "MZXW6"),
+            ("foob", "MZXW6YQ"),
+            ("fooba", "MZXW6YTB"),
+            ("foobar", "MZXW6YTBOI"),
+        ] {
+            assert_eq!(base32_encode(bytes.as_bytes()), encoded);
+        }
+    }
+
+    #[test]
+    fn test_reused_step_rejected() {
+        let mut totp = totp();
+
+        let step = unix_now() / TOTP_STEP_SECONDS;
+        let code = format!("{:06}", totp.code_at(step).unwrap());
+
+        assert!(totp.verify_code(&code).unwrap());
+        assert!(!totp.verify_code(&code).unwrap());
+
+        // Older steps aren't accepted anymore either
+        let previous_code = format!("{:06}", totp.code_at(step - 1).unwrap());
+        assert!(!totp.verify_code(&previous_code).unwrap());
+    }
+
+    #[test]
+    fn test_recovery_code_used_once() {
+        let mut totp = totp();
+
+        let codes = totp.new_recovery_codes().unwrap();
+        assert_eq!(codes.len(), RECOVERY_CODE_COUNT);
+
+        assert!(totp.verify(&codes[0].to_uppercase()).unwrap());
+        assert!(!totp.verify(&codes[0]).unwrap());
+        assert_eq!(totp.recovery_codes.len(), RECOVERY_CODE_COUNT - 1);
+    }
+}
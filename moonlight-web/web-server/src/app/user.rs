@@ -1,10 +1,12 @@
 use std::{
     fmt::{Debug, Formatter},
+    marker::PhantomData,
+    net::IpAddr,
     ops::{Deref, DerefMut},
     time::Duration,
 };
 
-use common::api_bindings::{self, DetailedUser};
+use common::api_bindings::{self, ApiTokenScope, DetailedUser, InputProfile, UserPermissions};
 use moonlight_common::network::{
     ApiError, ClientInfo, host_info,
     request_client::{RequestClient, RequestError},
@@ -14,12 +16,16 @@ use uuid::Uuid;
 
 use crate::app::{
     AppError, AppRef, MoonlightClient,
-    auth::{SessionToken, UserAuth},
-    host::{Host, HostId},
+    auth::{ApiToken, SessionToken, UserAuth},
+    host::{AppId, Host, HostId},
     password::StoragePassword,
+    permissions::is_global_host_allowed,
     storage::{
-        StorageHostAdd, StorageHostCache, StorageQueryHosts, StorageUser, StorageUserModify,
+        StorageApiToken, StorageApiTokenAdd, StorageHostAdd, StorageHostCache,
+        StorageInputProfiles, StorageQueryHosts, StorageSession, StorageSessionAdd, StorageUser,
+        StorageUserModify,
     },
+    totp::StorageTotp,
 };
 
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
@@ -109,6 +115,9 @@ impl User {
             name: storage.name,
             role: storage.role.into(),
             client_unique_id: storage.client_unique_id,
+            allow_clipboard: storage.allow_clipboard,
+            totp_enabled: storage.totp.is_some_and(|totp| totp.confirmed),
+            permissions: storage.permissions,
         })
     }
 
@@ -121,6 +130,23 @@ impl User {
 
         Ok(())
     }
+    /// Removes the two factor authentication, e.g. when the user lost their authenticator and recovery codes
+    pub async fn reset_totp(&mut self, admin: &Admin) -> Result<(), AppError> {
+        self.modify(
+            admin,
+            StorageUserModify {
+                totp: Some(None),
+                ..Default::default()
+            },
+        )
+        .await
+    }
+    /// Logs the user out on all devices
+    pub async fn revoke_login_sessions(&self, _: &Admin) -> Result<(), AppError> {
+        let app = self.app.access()?;
+
+        app.storage.remove_all_user_session_tokens(self.id).await
+    }
     pub async fn delete(self, _: &Admin) -> Result<(), AppError> {
         let app = self.app.access()?;
 
@@ -134,7 +160,11 @@ impl User {
             UserAuth::None if self.is_default_user().await? => {
                 Ok(AuthenticatedUser { inner: self })
             }
-            UserAuth::UserPassword { username, password } => {
+            UserAuth::UserPassword {
+                username,
+                password,
+                totp_code,
+            } => {
                 let storage = self.storage_user().await?;
 
                 if username.as_str() != storage.name.as_str() {
@@ -142,18 +172,64 @@ impl User {
                     return Err(AppError::Unauthorized);
                 }
 
-                if let Some(storage_password) = storage.password
-                    && storage_password.verify(password)?
+                let password_valid = match &storage.password {
+                    Some(storage_password) => storage_password.verify(password)?,
+                    None => false,
+                };
+                if !password_valid {
+                    return Err(AppError::CredentialsWrong);
+                }
+
+                if let Some(mut totp) = storage.totp.filter(|totp| totp.confirmed) {
+                    let Some(totp_code) = totp_code else {
+                        return Err(AppError::TotpRequired);
+                    };
+
+                    let valid = totp.verify(totp_code)?;
+
+                    // Always store it because the used code or recovery code must not work again
+                    let app = self.app.access()?;
+                    self.cache_storage = None;
+                    app.storage
+                        .modify_user(
+                            self.id,
+                            StorageUserModify {
+                                totp: Some(Some(totp)),
+                                ..Default::default()
+                            },
+                        )
+                        .await?;
+
+                    if !valid {
+                        return Err(AppError::CredentialsWrong);
+                    }
+                }
+
+                // Passwords hashed with an older algorithm are upgraded now that we know the plain password
+                if storage
+                    .password
+                    .as_ref()
+                    .is_some_and(StoragePassword::needs_rehash)
                 {
-                    Ok(AuthenticatedUser { inner: self })
-                } else {
-                    Err(AppError::CredentialsWrong)
+                    let app = self.app.access()?;
+                    self.cache_storage = None;
+                    app.storage
+                        .modify_user(
+                            self.id,
+                            StorageUserModify {
+                                password: Some(Some(StoragePassword::new(password)?)),
+                                ..Default::default()
+                            },
+                        )
+                        .await?;
                 }
+
+                Ok(AuthenticatedUser { inner: self })
             }
-            UserAuth::Session(session) => {
+            UserAuth::Session { token, ip } => {
                 let app = self.app.access()?;
 
-                let (id, user) = app.storage.get_user_by_session_token(*session).await?;
+                let (id, user) = app.storage.get_user_by_session_token(*token, *ip).await?;
 
                 if self.id != id {
                     return Err(AppError::SessionTokenNotFound);
@@ -211,6 +287,17 @@ impl AuthenticatedUser {
         Ok(storage.role)
     }
 
+    /// The permissions that restrict this user, admins are never restricted
+    pub async fn permissions(&mut self) -> Result<UserPermissions, AppError> {
+        let storage = self.storage_user().await?;
+
+        if storage.role == Role::Admin {
+            return Ok(UserPermissions::default());
+        }
+
+        Ok(storage.permissions)
+    }
+
     pub async fn set_password(&mut self, password: StoragePassword) -> Result<(), AppError> {
         let app = self.app.access()?;
 
@@ -229,24 +316,270 @@ impl AuthenticatedUser {
         Ok(())
     }
 
-    pub async fn new_session(&self, expiration: Duration) -> Result<SessionToken, AppError> {
+    pub async fn new_session(
+        &self,
+        expiration: Duration,
+        session: StorageSessionAdd,
+    ) -> Result<SessionToken, AppError> {
         let app = self.app.access()?;
 
         let token = app
             .storage
-            .create_session_token(self.id, expiration)
+            .create_session_token(self.id, expiration, session)
             .await?;
 
         Ok(token)
     }
 
+    pub async fn login_sessions(&self) -> Result<Vec<StorageSession>, AppError> {
+        let app = self.app.access()?;
+
+        app.storage.list_user_session_tokens(self.id).await
+    }
+
+    pub async fn remove_login_session(&self, token_hash: [u8; 32]) -> Result<(), AppError> {
+        let app = self.app.access()?;
+
+        app.storage
+            .remove_user_session_token(self.id, token_hash)
+            .await
+    }
+
+    pub async fn remove_all_login_sessions(&self) -> Result<(), AppError> {
+        let app = self.app.access()?;
+
+        app.storage.remove_all_user_session_tokens(self.id).await
+    }
+
+    pub async fn api_tokens(&self) -> Result<Vec<StorageApiToken>, AppError> {
+        let app = self.app.access()?;
+
+        app.storage.list_user_api_tokens(self.id).await
+    }
+
+    pub async fn new_api_token(
+        &self,
+        api_token: StorageApiTokenAdd,
+    ) -> Result<(ApiToken, StorageApiToken), AppError> {
+        if api_token.name.is_empty() {
+            return Err(AppError::NameEmpty);
+        }
+        if api_token.scopes.is_empty() {
+            return Err(AppError::BadRequest);
+        }
+
+        let app = self.app.access()?;
+
+        app.storage.create_api_token(self.id, api_token).await
+    }
+
+    pub async fn remove_api_token(&self, token_hash: [u8; 32]) -> Result<(), AppError> {
+        let app = self.app.access()?;
+
+        app.storage.remove_user_api_token(self.id, token_hash).await
+    }
+
+    /// Fails if the config requires two factor authentication but this user didn't set it up yet.
+    ///
+    /// Users without a password authenticate somewhere else and are never required to have it.
+    pub async fn check_totp_required(&mut self) -> Result<(), AppError> {
+        let require_totp = self.app.access()?.config.web_server.require_totp;
+        if !require_totp {
+            return Ok(());
+        }
+
+        let storage = self.storage_user().await?;
+        if storage.password.is_some() && !storage.totp.is_some_and(|totp| totp.confirmed) {
+            return Err(AppError::TotpSetupRequired);
+        }
+
+        Ok(())
+    }
+
+    /// Starts the two factor setup with a new secret, it's enforced after [Self::confirm_totp]
+    pub async fn start_totp(&mut self) -> Result<(StorageTotp, String), AppError> {
+        let storage = self.storage_user().await?;
+
+        if storage.totp.is_some_and(|totp| totp.confirmed) {
+            return Err(AppError::TotpAlreadyEnabled);
+        }
+
+        let totp = StorageTotp::new()?;
+        let provisioning_uri = totp.provisioning_uri(&storage.name);
+
+        let app = self.app.access()?;
+
+        self.cache_storage = None;
+        app.storage
+            .modify_user(
+                self.id,
+                StorageUserModify {
+                    totp: Some(Some(totp.clone())),
+                    ..Default::default()
+                },
+            )
+            .await?;
+
+        Ok((totp, provisioning_uri))
+    }
+
+    /// Enables two factor authentication if the code is valid and returns the recovery codes.
+    /// Wrong codes count as failed logins of the user, like in [Self::disable_totp]
+    pub async fn confirm_totp(
+        &mut self,
+        code: &str,
+        ip: Option<IpAddr>,
+    ) -> Result<Vec<String>, AppError> {
+        let storage = self.storage_user().await?;
+
+        let Some(mut totp) = storage.totp.filter(|totp| !totp.confirmed) else {
+            return Err(AppError::BadRequest);
+        };
+
+        let app = self.app.access()?;
+
+        let limiter = &app.login_limiter;
+        limiter.check(ip, &storage.name).await?;
+
+        match totp.verify_code(code) {
+            Ok(true) => {}
+            Ok(false) => {
+                let err = AppError::TotpCodeWrong;
+                limiter.login_failed(ip, &storage.name, &err).await;
+
+                return Err(err);
+            }
+            Err(err) => {
+                limiter.login_not_counted(ip, &storage.name).await;
+                return Err(err);
+            }
+        }
+        limiter.login_succeeded(ip, &storage.name).await;
+
+        totp.confirmed = true;
+        let recovery_codes = totp.new_recovery_codes()?;
+
+        self.cache_storage = None;
+        app.storage
+            .modify_user(
+                self.id,
+                StorageUserModify {
+                    totp: Some(Some(totp)),
+                    ..Default::default()
+                },
+            )
+            .await?;
+
+        Ok(recovery_codes)
+    }
+
+    /// Wrong codes count as failed logins of the user, else the code could be guessed with a stolen session
+    pub async fn disable_totp(&mut self, code: &str, ip: Option<IpAddr>) -> Result<(), AppError> {
+        let storage = self.storage_user().await?;
+
+        let app = self.app.access()?;
+        if app.config.web_server.require_totp {
+            return Err(AppError::Forbidden);
+        }
+
+        let Some(mut totp) = storage.totp.filter(|totp| totp.confirmed) else {
+            return Err(AppError::BadRequest);
+        };
+
+        let limiter = &app.login_limiter;
+        limiter.check(ip, &storage.name).await?;
+
+        let valid = match totp.verify(code) {
+            Ok(valid) => valid,
+            Err(err) => {
+                limiter.login_not_counted(ip, &storage.name).await;
+                return Err(err);
+            }
+        };
+
+        self.cache_storage = None;
+        app.storage
+            .modify_user(
+                self.id,
+                StorageUserModify {
+                    totp: Some(if valid { None } else { Some(totp) }),
+                    ..Default::default()
+                },
+            )
+            .await?;
+
+        if !valid {
+            let err = AppError::TotpCodeWrong;
+            limiter.login_failed(ip, &storage.name, &err).await;
+
+            return Err(err);
+        }
+
+        limiter.login_succeeded(ip, &storage.name).await;
+
+        Ok(())
+    }
+
+    pub async fn allow_clipboard(&mut self) -> Result<bool, AppError> {
+        let user = self.storage_user().await?;
+
+        Ok(user.allow_clipboard)
+    }
+
     pub async fn host_unique_id(&mut self) -> Result<String, AppError> {
         let user = self.storage_user().await?;
 
         Ok(user.client_unique_id.clone())
     }
 
+    pub async fn input_profiles(&self) -> Result<StorageInputProfiles, AppError> {
+        let app = self.app.access()?;
+
+        app.storage.get_input_profiles(self.id).await
+    }
+
+    /// The profile of this app or else the default profile of the user
+    pub async fn input_profile(
+        &self,
+        host_id: HostId,
+        app_id: AppId,
+    ) -> Result<InputProfile, AppError> {
+        let profiles = self.input_profiles().await?;
+
+        let app_profile = profiles
+            .apps
+            .into_iter()
+            .find(|app| app.host_id == host_id && app.app_id == app_id)
+            .map(|app| app.profile);
+
+        Ok(app_profile.or(profiles.default).unwrap_or_default())
+    }
+
+    /// Sets the profile of this app or the default profile if the app is none. A profile of none removes it.
+    pub async fn set_input_profile(
+        &mut self,
+        host_app: Option<(HostId, AppId)>,
+        profile: Option<InputProfile>,
+    ) -> Result<(), AppError> {
+        // A broken mouse sensitivity would invert, freeze or saturate the cursor
+        if profile.as_ref().is_some_and(|profile| !profile.is_valid()) {
+            return Err(AppError::BadRequest);
+        }
+
+        if let Some((host_id, _)) = host_app {
+            // Only allow profiles for hosts this user can access
+            self.host(host_id).await?;
+        }
+
+        let app = self.app.access()?;
+
+        app.storage
+            .set_input_profile(self.id, host_app, profile)
+            .await
+    }
+
     pub async fn hosts(&mut self) -> Result<Vec<Host>, AppError> {
+        let permissions = self.permissions().await?;
         let app = self.app.access()?;
 
         let hosts = app
@@ -254,6 +587,10 @@ impl AuthenticatedUser {
             .list_user_hosts(StorageQueryHosts { user_id: self.id })
             .await?
             .into_iter()
+            .filter(|(host_id, host)| match host {
+                Some(host) if host.owner.is_some() => true,
+                _ => is_global_host_allowed(&permissions, *host_id),
+            })
             .map(|(host_id, host)| Host {
                 app: self.app.clone(),
                 id: host_id,
@@ -270,7 +607,16 @@ impl AuthenticatedUser {
 
         let host = app.storage.get_host(host_id).await?;
 
-        if host.owner.is_none() || host.owner == Some(self.id) {
+        let allowed = match host.owner {
+            None => is_global_host_allowed(&self.permissions().await?, host_id),
+            Some(owner) if owner == self.id => true,
+            Some(_) => {
+                app.storage
+                    .is_host_shared_with_user(host_id, self.id)
+                    .await?
+            }
+        };
+        if allowed {
             Ok(Host {
                 app: self.app.clone(),
                 id: host.id,
@@ -366,3 +712,53 @@ impl Deref for Admin {
         &self.0
     }
 }
+
+/// An api token scope that is required by an endpoint
+pub trait RequiredScope {
+    const SCOPE: ApiTokenScope;
+}
+
+pub struct ReadHosts;
+impl RequiredScope for ReadHosts {
+    const SCOPE: ApiTokenScope = ApiTokenScope::ReadHosts;
+}
+
+pub struct WakeHosts;
+impl RequiredScope for WakeHosts {
+    const SCOPE: ApiTokenScope = ApiTokenScope::WakeHosts;
+}
+
+pub struct StartApps;
+impl RequiredScope for StartApps {
+    const SCOPE: ApiTokenScope = ApiTokenScope::StartApps;
+}
+
+/// A user that is either authenticated normally or with an api token that has the scope `S`.
+///
+/// Every other [AuthenticatedUser] rejects api tokens.
+pub struct ScopedUser<S: RequiredScope> {
+    user: AuthenticatedUser,
+    _scope: PhantomData<S>,
+}
+
+impl<S: RequiredScope> ScopedUser<S> {
+    pub fn new(
+        user: AuthenticatedUser,
+        scopes: Option<&[ApiTokenScope]>,
+    ) -> Result<Self, AppError> {
+        if let Some(scopes) = scopes
+            && !scopes.contains(&S::SCOPE)
+        {
+            return Err(AppError::ApiTokenNotAllowed);
+        }
+
+        Ok(Self {
+            user,
+            _scope: PhantomData,
+        })
+    }
+
+    pub fn into_inner(self) -> AuthenticatedUser {
+        self.user
+    }
+}
@@ -155,12 +155,11 @@ impl CliConfig {
         if let Some(forwarded_header) = self.forwarded_header {
             config.web_server.forwarded_header = Some(ForwardedHeaders {
                 username_header: forwarded_header,
-                auto_create_missing_user: config
+                ..config
                     .web_server
                     .forwarded_header
                     .clone()
                     .unwrap_or_default()
-                    .auto_create_missing_user,
             });
         }
         if let Some(log_level_filter) = self.log_level_filter {
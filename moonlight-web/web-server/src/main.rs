@@ -159,5 +159,8 @@ async fn start(config: Config) -> Result<(), anyhow::Error> {
         server.bind(bind_address)?.run().await?;
     }
 
+    info!("[Server]: Stopping all streams");
+    app.stop_all_streams().await;
+
     Ok(())
 }
@@ -23,6 +23,12 @@ async fn config_js(app: Data<App>) -> HttpResponse {
     let config_json = match serde_json::to_string(&ConfigJs {
         path_prefix: app.config().web_server.url_path_prefix.clone(),
         default_settings: app.config().default_settings.clone().map(TsAny::from),
+        oidc_login: app
+            .config()
+            .web_server
+            .oidc
+            .as_ref()
+            .map(|oidc| oidc.display_name.clone()),
     }) {
         Ok(value) => value,
         Err(err) => {
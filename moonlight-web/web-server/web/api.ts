@@ -1,6 +1,6 @@
-import { App, DeleteHostQuery, DeleteUserRequest, DetailedHost, DetailedUser, GetAppImageQuery, GetAppsQuery, GetAppsResponse, GetHostQuery, GetHostResponse, GetHostsResponse, GetUserQuery, GetUsersResponse, PatchUserRequest, PostCancelRequest, PostCancelResponse, PostLoginRequest, PostPairRequest, PostPairResponse1, PostPairResponse2, PostUserRequest, PostWakeUpRequest, PostHostRequest, PostHostResponse, UndetailedHost, PatchHostRequest } from "./api_bindings.js";
+import { App, DeleteHostQuery, DeleteUserRequest, DetailedHost, DetailedUser, GetAppImageQuery, GetAppsQuery, GetAppsResponse, GetHostQuery, GetHostResponse, GetHostsResponse, GetUserQuery, GetUsersResponse, PatchUserRequest, PostCancelRequest, PostCancelResponse, PostLoginRequest, PostPairRequest, PostPairResponse1, PostPairResponse2, PostUserRequest, PostWakeUpRequest, PostHostRequest, PostHostResponse, UndetailedHost, PatchHostRequest, PostTotpResponse, PostTotpConfirmResponse } from "./api_bindings.js";
 import { showErrorPopup } from "./component/error.js";
-import { showMessage, showModal } from "./component/modal/index.js";
+import { showMessage, showModal, showPrompt } from "./component/modal/index.js";
 import { ApiUserPasswordPrompt } from "./component/modal/login.js";
 import { buildUrl } from "./config_.js";
 
@@ -59,15 +59,16 @@ export async function tryLogin(): Promise<Api | null> {
         return null
     }
 
-    if (await apiLogin(api, userAuth)) {
+    const loggedIn = await apiLogin(api, userAuth)
+    if (loggedIn) {
         if (!await apiAuthenticate(api)) {
             showErrorPopup("Login was successful but authentication doesn't work!")
         }
         return api
-    } else {
+    } else if (loggedIn == false) {
         await showMessage("Credentials are not Valid")
-        return null
     }
+    return null
 }
 
 const GET = "GET"
@@ -230,7 +231,8 @@ export async function fetchApi(api: Api, endpoint: string, method: string = GET,
     }
 }
 
-export async function apiLogin(api: Api, request: PostLoginRequest): Promise<boolean> {
+// Returns null if the login failed for another reason than wrong credentials, the user was already informed about it
+export async function apiLogin(api: Api, request: PostLoginRequest): Promise<boolean | null> {
     let response
 
     try {
@@ -244,6 +246,10 @@ export async function apiLogin(api: Api, request: PostLoginRequest): Promise<boo
 
             if (response && (response.status == 401 || response.status == 404)) {
                 return false
+            } else if (response?.status == 429) {
+                // 429 = Too Many Requests, TooManyLoginAttempts
+                await showMessage(await response.text())
+                return null
             } else {
                 showErrorPopup(e.message)
                 return false
@@ -276,6 +282,9 @@ export async function apiAuthenticate(api: Api, retryOnFail?: boolean): Promise<
             const response = e.getResponse()
             if (response?.status == 401) {
                 return false
+            } else if (response?.status == 403 && retryOnFail_) {
+                // 403 = Forbidden, TotpSetupRequired -> the server requires two factor authentication
+                return await setupTotp(api) && await apiAuthenticate(api, false)
             } else if (response?.status == 409 && retryOnFail_) {
                 // 409 = Conflict, SessionTokenNotFound -> requires a new request
                 return await apiAuthenticate(api, false)
@@ -289,6 +298,32 @@ export async function apiAuthenticate(api: Api, retryOnFail?: boolean): Promise<
     return response != null
 }
 
+async function setupTotp(api: Api): Promise<boolean> {
+    const setup = await fetchApi(api, "/user/totp", POST) as PostTotpResponse
+
+    while (true) {
+        const code = await showPrompt(`Two factor authentication is required. Add this secret to your authenticator app and enter its code: ${setup.secret}`, {
+            name: "ml-totp-code"
+        })
+        if (code == null) {
+            return false
+        }
+
+        try {
+            const response = await fetchApi(api, "/user/totp/confirm", POST, { json: { code } }) as PostTotpConfirmResponse
+
+            await showMessage(`Store these recovery codes, each can be used once instead of a code: ${response.recovery_codes.join(", ")}`)
+            return true
+        } catch (e) {
+            if (e instanceof FetchError && e.getResponse()?.status == 400) {
+                await showMessage("The code is wrong")
+                continue
+            }
+            throw e
+        }
+    }
+}
+
 export async function apiGetUser(api: Api, query?: GetUserQuery): Promise<DetailedUser> {
     if (!query || (query.name == null && query.user_id == null)) {
         if (api.user) {
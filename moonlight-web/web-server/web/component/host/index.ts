@@ -80,6 +80,8 @@ export class Host implements Component {
             this.onContextMenu(event)
         } else if (this.cache?.paired == "Paired") {
             this.divElement.dispatchEvent(new ComponentEvent("ml-hostopen", this))
+        } else if (this.cache?.owner == "Group") {
+            await showMessage("This host is shared with you by a group, only its owner can pair it.")
         } else {
             await this.pair()
         }
@@ -110,7 +112,10 @@ export class Host implements Component {
             callback: async () => this.forceFetch()
         })
 
-        if (this.cache?.server_state != null && this.cache?.paired == "NotPaired") {
+        // Hosts shared by a group can only be changed by their owner or an admin
+        const canManage = this.cache?.owner != "Group" || this.userCache?.role == "Admin"
+
+        if (canManage && this.cache?.server_state != null && this.cache?.paired == "NotPaired") {
             elements.push({
                 name: "Pair",
                 callback: this.pair.bind(this)
@@ -162,6 +167,7 @@ export class Host implements Component {
         await showMessage(
             `Web Id: ${host.host_id}\n` +
             `Name: ${host.name}\n` +
+            `Owner: ${host.owner == "Group" ? "Shared by a group" : host.owner}\n` +
             `Pair Status: ${host.paired}\n` +
             `State: ${host.server_state}\n` +
             `Address: ${host.address}\n` +
@@ -296,7 +302,13 @@ export class Host implements Component {
         }
 
         // Update Elements
-        this.nameElement.innerText = this.cache.name
+        if (this.cache.owner == "Group") {
+            this.nameElement.innerText = `${this.cache.name} (Shared)`
+            this.nameElement.title = "Shared with you by a group"
+        } else {
+            this.nameElement.innerText = this.cache.name
+            this.nameElement.title = ""
+        }
 
         if (this.cache.server_state == null) {
             this.imageOverlayElement.src = HOST_OVERLAY_OFFLINE
@@ -1,10 +1,13 @@
+import CONFIG from "../../config.js"
+import { buildUrl } from "../../config_.js"
 import { ComponentEvent } from "../index.js"
 import { InputComponent } from "../input.js"
 import { FormModal } from "./form.js"
 
 export type UserAuth = {
     name: string,
-    password: string
+    password: string,
+    totp_code: string | null
 }
 
 export class ApiUserPasswordPrompt extends FormModal<UserAuth> {
@@ -14,6 +17,9 @@ export class ApiUserPasswordPrompt extends FormModal<UserAuth> {
     private name: InputComponent
     private password: InputComponent
     private passwordFile: InputComponent
+    private totpCode: InputComponent
+
+    private oidcButton: HTMLButtonElement | null = null
 
     constructor() {
         super()
@@ -30,6 +36,19 @@ export class ApiUserPasswordPrompt extends FormModal<UserAuth> {
 
         this.passwordFile = new InputComponent("ml-api-password-file", "file", "Password as File", { accept: ".txt" })
         this.passwordFile.addChangeListener(this.setFilePassword.bind(this))
+
+        this.totpCode = new InputComponent("ml-api-totp-code", "text", "Two Factor Code", {
+            placeholer: "Only if enabled"
+        })
+
+        if (CONFIG?.oidc_login) {
+            this.oidcButton = document.createElement("button")
+            this.oidcButton.type = "button"
+            this.oidcButton.innerText = `Login with ${CONFIG.oidc_login}`
+            this.oidcButton.addEventListener("click", () => {
+                window.location.href = buildUrl("/api/oidc/login")
+            })
+        }
     }
 
     private async setFilePassword(event: ComponentEvent<InputComponent>) {
@@ -56,13 +75,15 @@ export class ApiUserPasswordPrompt extends FormModal<UserAuth> {
         this.name.reset()
         this.password.reset()
         this.passwordFile.reset()
+        this.totpCode.reset()
     }
     submit(): UserAuth | null {
         const name = this.name.getValue()
         const password = this.password.getValue()
+        const totp_code = this.totpCode.getValue() || null
 
         if (name && password) {
-            return { name, password }
+            return { name, password, totp_code }
         } else {
             return null
         }
@@ -90,5 +111,10 @@ export class ApiUserPasswordPrompt extends FormModal<UserAuth> {
 
         this.password.mount(form)
         this.passwordFile.mount(form)
+        this.totpCode.mount(form)
+
+        if (this.oidcButton) {
+            form.appendChild(this.oidcButton)
+        }
     }
 }
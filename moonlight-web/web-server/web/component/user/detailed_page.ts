@@ -1,6 +1,6 @@
 import { Component, ComponentEvent } from "../index.js";
 import { Api, apiDeleteUser, apiPatchUser } from "../../api.js";
-import { DetailedUser, PatchUserRequest, UserRole } from "../../api_bindings.js";
+import { DetailedUser, PatchUserRequest, UserPermissions, UserRole } from "../../api_bindings.js";
 import { InputComponent, SelectComponent } from "../input.js";
 import { createSelectRoleInput } from "./role_select.js";
 import { tryDeleteUser, UserEventListener } from "./index.js";
@@ -18,6 +18,17 @@ export class DetailedUserPage implements Component {
     private password: InputComponent
     private role: SelectComponent
     private clientUniqueId: InputComponent
+    private allowClipboard: InputComponent
+    private resetTotp: InputComponent
+
+    // The app allow lists and time windows can only be changed using the api
+    private permissions: UserPermissions
+    private globalHosts: InputComponent
+    private canPairHosts: InputComponent
+    private canReencode: InputComponent
+    private maxBitrate: InputComponent
+    private maxWidth: InputComponent
+    private maxHeight: InputComponent
 
     private applyButton = document.createElement("button")
     private deleteButton = document.createElement("button")
@@ -56,6 +67,53 @@ export class DetailedUserPage implements Component {
         })
         this.clientUniqueId.mount(this.formRoot)
 
+        this.allowClipboard = new InputComponent("userAllowClipboard", "checkbox", "Allow Clipboard", {
+            checked: user.allow_clipboard,
+        })
+        this.allowClipboard.mount(this.formRoot)
+
+        this.resetTotp = new InputComponent("userResetTotp", "checkbox", "Reset Two Factor Authentication", {
+            checked: false,
+        })
+        this.resetTotp.setEnabled(user.totp_enabled)
+        this.resetTotp.mount(this.formRoot)
+
+        this.permissions = user.permissions
+
+        this.globalHosts = new InputComponent("userGlobalHosts", "text", "Allowed Global Host Ids", {
+            defaultValue: user.permissions.global_hosts?.join(", ") ?? "",
+            placeholer: "All Global Hosts"
+        })
+        this.globalHosts.mount(this.formRoot)
+
+        this.canPairHosts = new InputComponent("userCanPairHosts", "checkbox", "Allow Pairing Hosts", {
+            checked: user.permissions.can_pair_hosts,
+        })
+        this.canPairHosts.mount(this.formRoot)
+
+        this.canReencode = new InputComponent("userCanReencode", "checkbox", "Allow Reencoding", {
+            checked: user.permissions.can_reencode,
+        })
+        this.canReencode.mount(this.formRoot)
+
+        this.maxBitrate = new InputComponent("userMaxBitrate", "number", "Max Bitrate (kbps)", {
+            defaultValue: user.permissions.max_bitrate?.toString() ?? "",
+            placeholer: "Unlimited"
+        })
+        this.maxBitrate.mount(this.formRoot)
+
+        this.maxWidth = new InputComponent("userMaxWidth", "number", "Max Width", {
+            defaultValue: user.permissions.max_width?.toString() ?? "",
+            placeholer: "Unlimited"
+        })
+        this.maxWidth.mount(this.formRoot)
+
+        this.maxHeight = new InputComponent("userMaxHeight", "number", "Max Height", {
+            defaultValue: user.permissions.max_height?.toString() ?? "",
+            placeholer: "Unlimited"
+        })
+        this.maxHeight.mount(this.formRoot)
+
         this.applyButton.innerText = "Apply"
         this.applyButton.type = "submit"
         this.formRoot.appendChild(this.applyButton)
@@ -81,7 +139,18 @@ export class DetailedUserPage implements Component {
             id: this.id,
             role: this.role.getValue() as UserRole,
             password,
-            client_unique_id: this.clientUniqueId.getValue()
+            client_unique_id: this.clientUniqueId.getValue(),
+            allow_clipboard: this.allowClipboard.isChecked(),
+            reset_totp: this.resetTotp.isChecked() || null,
+            permissions: {
+                ...this.permissions,
+                global_hosts: parseIdList(this.globalHosts.getValue()),
+                can_pair_hosts: this.canPairHosts.isChecked(),
+                can_reencode: this.canReencode.isChecked(),
+                max_bitrate: parseOptionalNumber(this.maxBitrate.getValue()),
+                max_width: parseOptionalNumber(this.maxWidth.getValue()),
+                max_height: parseOptionalNumber(this.maxHeight.getValue()),
+            }
         };
 
         await apiPatchUser(this.api, request)
@@ -110,4 +179,18 @@ export class DetailedUserPage implements Component {
     unmount(parent: HTMLElement): void {
         parent.removeChild(this.formRoot)
     }
-}
\ No newline at end of file
+}
+
+function parseOptionalNumber(value: string): number | null {
+    const number = parseInt(value)
+
+    return isNaN(number) ? null : number
+}
+// An empty list means everything is allowed
+function parseIdList(value: string): Array<number> | null {
+    const ids = value.split(",")
+        .map(id => parseInt(id.trim()))
+        .filter(id => !isNaN(id))
+
+    return ids.length > 0 ? ids : null
+}
@@ -39,6 +39,85 @@ declare global {
         prototype: VideoTrackGenerator
         new(): VideoTrackGenerator
     }
+
+    // WebHID: https://developer.mozilla.org/en-US/docs/Web/API/WebHID_API
+    interface Navigator {
+        hid?: HID
+    }
+
+    interface HIDDeviceFilter {
+        vendorId?: number
+        productId?: number
+    }
+
+    interface HIDConnectionEvent extends Event {
+        readonly device: HIDDevice
+    }
+
+    interface HIDEventMap {
+        "connect": HIDConnectionEvent
+        "disconnect": HIDConnectionEvent
+    }
+
+    interface HID extends EventTarget {
+        getDevices(): Promise<Array<HIDDevice>>
+        requestDevice(options: { filters: Array<HIDDeviceFilter> }): Promise<Array<HIDDevice>>
+
+        addEventListener<K extends keyof HIDEventMap>(type: K, listener: (this: HID, event: HIDEventMap[K]) => any, options?: boolean | AddEventListenerOptions): void
+        addEventListener(type: string, listener: EventListenerOrEventListenerObject, options?: boolean | AddEventListenerOptions): void
+    }
+
+    interface HIDInputReportEvent extends Event {
+        readonly device: HIDDevice
+        readonly reportId: number
+        readonly data: DataView
+    }
+
+    interface HIDDeviceEventMap {
+        "inputreport": HIDInputReportEvent
+    }
+
+    interface HIDDevice extends EventTarget {
+        readonly opened: boolean
+        readonly vendorId: number
+        readonly productId: number
+        readonly productName: string
+
+        open(): Promise<void>
+        close(): Promise<void>
+        sendReport(reportId: number, data: BufferSource): Promise<void>
+        receiveFeatureReport(reportId: number): Promise<DataView>
+
+        addEventListener<K extends keyof HIDDeviceEventMap>(type: K, listener: (this: HIDDevice, event: HIDDeviceEventMap[K]) => any, options?: boolean | AddEventListenerOptions): void
+        addEventListener(type: string, listener: EventListenerOrEventListenerObject, options?: boolean | AddEventListenerOptions): void
+        removeEventListener<K extends keyof HIDDeviceEventMap>(type: K, listener: (this: HIDDevice, event: HIDDeviceEventMap[K]) => any, options?: boolean | EventListenerOptions): void
+        removeEventListener(type: string, listener: EventListenerOrEventListenerObject, options?: boolean | EventListenerOptions): void
+    }
+
+    // Generic Sensor API: https://developer.mozilla.org/en-US/docs/Web/API/Sensor_APIs
+    interface MotionSensor extends EventTarget {
+        readonly x: number | null
+        readonly y: number | null
+        readonly z: number | null
+
+        start(): void
+        stop(): void
+    }
+
+    type MotionSensorConstructor = {
+        prototype: MotionSensor
+        new(options?: { frequency?: number }): MotionSensor
+    }
+
+    // m/s² including gravity
+    var Accelerometer: MotionSensorConstructor | undefined
+    // rad/s
+    var Gyroscope: MotionSensorConstructor | undefined
+
+    // Touch Type: https://developer.mozilla.org/en-US/docs/Web/API/Touch/touchType
+    interface Touch {
+        readonly touchType?: "direct" | "stylus"
+    }
 }
 
 
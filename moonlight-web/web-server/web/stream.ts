@@ -14,6 +14,7 @@ import { ScreenKeyboard, TextEvent } from "./screen_keyboard.js";
 import { FormModal } from "./component/modal/form.js";
 import { streamStatsToText } from "./stream/stats.js";
 import { buildUrl } from "./config_.js";
+import { DualSense, DUALSENSE_HID_FILTERS } from "./stream/dualsense.js";
 
 async function startApp() {
     const api = await getApi()
@@ -248,6 +249,9 @@ class ViewerApp implements Component {
         window.addEventListener("blur", () => {
             this.stream?.getInput().raiseAllKeys()
         })
+        window.addEventListener("focus", () => {
+            this.stream?.getInput().syncClipboard()
+        })
         document.addEventListener("visibilitychange", () => {
             if (document.visibilityState !== "visible") {
                 this.stream?.getInput().raiseAllKeys()
@@ -265,6 +269,18 @@ class ViewerApp implements Component {
                 this.onGamepadAdd(gamepad)
             }
         }
+
+        // DualSense controllers that the user allowed previously
+        if (navigator.hid) {
+            navigator.hid.addEventListener("connect", event => this.onHidDeviceAdd(event.device))
+            navigator.hid.addEventListener("disconnect", event => this.onHidDeviceRemove(event.device))
+
+            navigator.hid.getDevices().then(devices => {
+                for (const device of devices) {
+                    this.onHidDeviceAdd(device)
+                }
+            })
+        }
     }
     private addListeners(element: GlobalEventHandlers) {
         element.addEventListener("keydown", this.onKeyDown.bind(this), { passive: false })
@@ -277,6 +293,12 @@ class ViewerApp implements Component {
         element.addEventListener("wheel", this.onMouseWheel.bind(this), { passive: false })
         element.addEventListener("contextmenu", this.onContextMenu.bind(this), { passive: false })
 
+        element.addEventListener("pointerdown", this.onPointerEvent.bind(this), { passive: false })
+        element.addEventListener("pointermove", this.onPointerEvent.bind(this), { passive: false })
+        element.addEventListener("pointerup", this.onPointerEvent.bind(this), { passive: false })
+        element.addEventListener("pointercancel", this.onPointerEvent.bind(this), { passive: false })
+        element.addEventListener("pointerleave", this.onPointerEvent.bind(this), { passive: false })
+
         element.addEventListener("touchstart", this.onTouchStart.bind(this), { passive: false })
         element.addEventListener("touchend", this.onTouchEnd.bind(this), { passive: false })
         element.addEventListener("touchcancel", this.onTouchCancel.bind(this), { passive: false })
@@ -483,6 +505,9 @@ class ViewerApp implements Component {
         this.onUserInteraction()
 
         event.preventDefault()
+        if (this.stream?.getInput().isPenInRange()) {
+            return
+        }
         this.stream?.getInput().onMouseDown(event, this.getStreamRect());
 
         event.stopPropagation()
@@ -491,12 +516,18 @@ class ViewerApp implements Component {
         this.onUserInteraction()
 
         event.preventDefault()
+        if (this.stream?.getInput().isPenInRange()) {
+            return
+        }
         this.stream?.getInput().onMouseUp(event)
 
         event.stopPropagation()
     }
     onMouseMove(event: MouseEvent) {
         event.preventDefault()
+        if (this.stream?.getInput().isPenInRange()) {
+            return
+        }
         this.stream?.getInput().onMouseMove(event, this.getStreamRect())
 
         event.stopPropagation()
@@ -513,11 +544,27 @@ class ViewerApp implements Component {
         event.stopPropagation()
     }
 
+    // Pen
+    onPointerEvent(event: PointerEvent) {
+        if (event.type == "pointerdown") {
+            this.onUserInteraction()
+        }
+
+        if (this.stream?.getInput().onPenEvent(event, this.getStreamRect())) {
+            // Also stops the mouse events the browser emits for compatibility
+            event.preventDefault()
+            event.stopPropagation()
+        }
+    }
+
     // Touch
     onTouchStart(event: TouchEvent) {
         this.onUserInteraction()
 
         event.preventDefault()
+        if (this.stream?.getInput().isPenTouchEvent(event)) {
+            return
+        }
         this.stream?.getInput().onTouchStart(event, this.getStreamRect())
 
         event.stopPropagation()
@@ -526,6 +573,9 @@ class ViewerApp implements Component {
         this.onUserInteraction()
 
         event.preventDefault()
+        if (this.stream?.getInput().isPenTouchEvent(event)) {
+            return
+        }
         this.stream?.getInput().onTouchEnd(event, this.getStreamRect())
 
         event.stopPropagation()
@@ -534,6 +584,9 @@ class ViewerApp implements Component {
         this.onUserInteraction()
 
         event?.preventDefault()
+        if (this.stream?.getInput().isPenTouchEvent(event)) {
+            return
+        }
         this.stream?.getInput().onTouchCancel(event, this.getStreamRect())
 
         event.stopPropagation()
@@ -545,6 +598,9 @@ class ViewerApp implements Component {
     }
     onTouchMove(event: TouchEvent) {
         event.preventDefault()
+        if (this.stream?.getInput().isPenTouchEvent(event)) {
+            return
+        }
         this.stream?.getInput().onTouchMove(event, this.getStreamRect())
 
         event.stopPropagation()
@@ -566,6 +622,30 @@ class ViewerApp implements Component {
         window.requestAnimationFrame(this.onGamepadUpdate.bind(this))
     }
 
+    // DualSense (WebHID)
+    async requestDualSense() {
+        if (!navigator.hid) {
+            return
+        }
+
+        const devices = await navigator.hid.requestDevice({ filters: DUALSENSE_HID_FILTERS })
+        for (const device of devices) {
+            this.onHidDeviceAdd(device)
+        }
+    }
+    private onHidDeviceAdd(device: HIDDevice) {
+        if (!DualSense.isDualSense(device)) {
+            return
+        }
+
+        this.stream?.getInput().addDualSense(device).catch(error => {
+            console.warn("[DualSense]: failed to open device", error)
+        })
+    }
+    private onHidDeviceRemove(device: HIDDevice) {
+        this.stream?.getInput().removeDualSense(device)
+    }
+
     // Fullscreen
     async requestFullscreen() {
         const body = document.body
@@ -972,6 +1052,7 @@ class ViewerSidebar implements Component, Sidebar {
 
     private statsButton = document.createElement("button")
     private touchControllerButton = document.createElement("button")
+    private dualSenseButton = document.createElement("button")
     private logButton = document.createElement("button")
     private bitrateButton = document.createElement("button")
     private exitStreamButton = document.createElement("button")
@@ -1051,6 +1132,15 @@ class ViewerSidebar implements Component, Sidebar {
         })
         this.buttonDiv.appendChild(this.touchControllerButton)
 
+        // DualSense, the Gamepad API doesn't expose its motion sensors
+        if (navigator.hid) {
+            this.dualSenseButton.innerText = "DualSense"
+            this.dualSenseButton.addEventListener("click", async () => {
+                await this.app.requestDualSense()
+            })
+            this.buttonDiv.appendChild(this.dualSenseButton)
+        }
+
         // Logs
         this.logButton.innerText = "Logs"
         this.logButton.addEventListener("click", () => {
@@ -0,0 +1,308 @@
+import { StreamBatteryState, StreamControllerCapabilities, StreamMotionType } from "../api_bindings.js"
+
+// Report layout: https://github.com/torvalds/linux/blob/master/drivers/hid/hid-playstation.c
+const SONY_VENDOR_ID = 0x054c
+const DUALSENSE_PRODUCT_IDS = [0x0ce6, 0x0df2]
+
+export const DUALSENSE_HID_FILTERS: Array<HIDDeviceFilter> = DUALSENSE_PRODUCT_IDS.map(productId => ({ vendorId: SONY_VENDOR_ID, productId }))
+
+const USB_INPUT_REPORT_ID = 0x01
+const USB_INPUT_REPORT_SIZE = 63
+const BLUETOOTH_INPUT_REPORT_ID = 0x31
+// Reading the calibration switches a bluetooth connection from the simple to the full input report
+const CALIBRATION_FEATURE_REPORT_ID = 0x05
+
+// Offsets in the input report data, the bluetooth report starts one byte later
+const INPUT_GYRO_OFFSET = 15
+const INPUT_ACCEL_OFFSET = 21
+const INPUT_TOUCH_POINT_OFFSETS = [32, 36]
+const INPUT_STATUS_OFFSET = 52
+
+const TOUCH_POINT_INACTIVE = 0x80
+const TOUCH_POINT_ID_MASK = 0x7f
+const TOUCHPAD_WIDTH = 1920
+const TOUCHPAD_HEIGHT = 1080
+
+const STATUS_BATTERY_CAPACITY_MASK = 0x0f
+const STATUS_CHARGING_SHIFT = 4
+
+const USB_OUTPUT_REPORT_ID = 0x02
+const BLUETOOTH_OUTPUT_REPORT_ID = 0x31
+const BLUETOOTH_OUTPUT_REPORT_SIZE = 77
+const BLUETOOTH_OUTPUT_TAG = 0x10
+// The checksum of bluetooth output reports includes this byte and the report id
+const BLUETOOTH_OUTPUT_CRC_SEED = 0xa2
+
+// Offsets in the part of the output report that's the same for usb and bluetooth
+const OUTPUT_COMMON_SIZE = 47
+const OUTPUT_VALID_FLAG0_OFFSET = 0
+const OUTPUT_VALID_FLAG1_OFFSET = 1
+const OUTPUT_RIGHT_TRIGGER_OFFSET = 10
+const OUTPUT_LEFT_TRIGGER_OFFSET = 21
+const OUTPUT_LIGHTBAR_OFFSET = 44
+
+// The parameters of a trigger effect, after its mode
+export const DUALSENSE_TRIGGER_EFFECT_SIZE = 10
+
+// Same values as the flags of the adaptive triggers packet
+const VALID_FLAG0_RIGHT_TRIGGER = 0x04
+const VALID_FLAG0_LEFT_TRIGGER = 0x08
+const VALID_FLAG1_LIGHTBAR = 0x04
+
+// The sensors aren't calibrated, these are the nominal ranges of +-2000 °/s and +-4 g
+const GYRO_DEGREES_PER_SECOND_PER_UNIT = 2000 / 32768
+const ACCEL_METERS_PER_SECOND_SQUARED_PER_UNIT = 4 * 9.80665 / 32768
+
+export const DUALSENSE_CAPABILITIES =
+    StreamControllerCapabilities.CAPABILITY_ACCEL | StreamControllerCapabilities.CAPABILITY_GYRO |
+    StreamControllerCapabilities.CAPABILITY_TOUCHPAD | StreamControllerCapabilities.CAPABILITY_BATTERY_STATE |
+    StreamControllerCapabilities.CAPABILITY_RGB_LED
+
+export type DualSenseListener = {
+    // Accelerometer in m/s², gyroscope in °/s
+    onMotion(motionType: number, x: number, y: number, z: number): void
+    // Event types of the controller touch packet: 0 = down, 1 = move, 3 = up, x and y are normalized
+    onTouch(eventType: number, pointerId: number, x: number, y: number, pressure: number): void
+    // Only called when the battery changes, a percentage of 0xFF is unknown
+    onBattery(batteryState: number, percentage: number): void
+}
+
+function hex4(value: number): string {
+    return ("0000" + value.toString(16)).slice(-4)
+}
+
+let crc32Table: Uint32Array | null = null
+function crc32(data: Uint8Array): number {
+    if (!crc32Table) {
+        crc32Table = new Uint32Array(256)
+        for (let i = 0; i < 256; i++) {
+            let crc = i
+            for (let bit = 0; bit < 8; bit++) {
+                crc = (crc & 1) != 0 ? (crc >>> 1) ^ 0xedb88320 : crc >>> 1
+            }
+            crc32Table[i] = crc
+        }
+    }
+
+    let crc = 0xffffffff
+    for (const byte of data) {
+        crc = crc32Table[(crc ^ byte) & 0xff] ^ (crc >>> 8)
+    }
+    return (crc ^ 0xffffffff) >>> 0
+}
+
+// A DualSense that the user allowed us to access with WebHID, the Gamepad API doesn't expose its sensors
+export class DualSense {
+    readonly device: HIDDevice
+
+    private listener: DualSenseListener | null = null
+
+    // The contact id of each touch point, null if it isn't touching
+    private touchPoints: Array<number | null> = INPUT_TOUCH_POINT_OFFSETS.map(() => null)
+    private batteryState: number | null = null
+    private batteryPercentage: number | null = null
+
+    // Output reports have to match the connection of the input reports
+    private bluetooth = false
+    private bluetoothOutputSequence = 0
+
+    private constructor(device: HIDDevice) {
+        this.device = device
+
+        this.device.addEventListener("inputreport", this.onInputReport.bind(this))
+    }
+
+    static isDualSense(device: HIDDevice): boolean {
+        return device.vendorId == SONY_VENDOR_ID && DUALSENSE_PRODUCT_IDS.indexOf(device.productId) != -1
+    }
+
+    static async open(device: HIDDevice): Promise<DualSense> {
+        if (!device.opened) {
+            await device.open()
+        }
+
+        try {
+            await device.receiveFeatureReport(CALIBRATION_FEATURE_REPORT_ID)
+        } catch (e) {
+            console.warn("[DualSense]: failed to read calibration, bluetooth controllers will only send simple reports", e)
+        }
+
+        return new DualSense(device)
+    }
+
+    // Chromium and Firefox put the vendor and product id into the id of the gamepad
+    matchesGamepad(gamepad: Gamepad): boolean {
+        const id = gamepad.id.toLowerCase()
+
+        return id.includes(hex4(this.device.vendorId)) && id.includes(hex4(this.device.productId))
+    }
+
+    setListener(listener: DualSenseListener | null) {
+        this.listener = listener
+
+        // The new listener needs the current state
+        this.touchPoints.fill(null)
+        this.batteryState = null
+        this.batteryPercentage = null
+    }
+
+    private onInputReport(event: HIDInputReportEvent) {
+        let offset
+        if (event.reportId == USB_INPUT_REPORT_ID && event.data.byteLength >= USB_INPUT_REPORT_SIZE) {
+            offset = 0
+        } else if (event.reportId == BLUETOOTH_INPUT_REPORT_ID) {
+            offset = 1
+            this.bluetooth = true
+        } else {
+            // The simple bluetooth report doesn't contain sensors
+            return
+        }
+
+        const listener = this.listener
+        if (!listener) {
+            return
+        }
+
+        const data = event.data
+
+        const gyro = offset + INPUT_GYRO_OFFSET
+        listener.onMotion(
+            StreamMotionType.GYRO,
+            data.getInt16(gyro, true) * GYRO_DEGREES_PER_SECOND_PER_UNIT,
+            data.getInt16(gyro + 2, true) * GYRO_DEGREES_PER_SECOND_PER_UNIT,
+            data.getInt16(gyro + 4, true) * GYRO_DEGREES_PER_SECOND_PER_UNIT,
+        )
+
+        const accel = offset + INPUT_ACCEL_OFFSET
+        listener.onMotion(
+            StreamMotionType.ACCEL,
+            data.getInt16(accel, true) * ACCEL_METERS_PER_SECOND_SQUARED_PER_UNIT,
+            data.getInt16(accel + 2, true) * ACCEL_METERS_PER_SECOND_SQUARED_PER_UNIT,
+            data.getInt16(accel + 4, true) * ACCEL_METERS_PER_SECOND_SQUARED_PER_UNIT,
+        )
+
+        for (let i = 0; i < INPUT_TOUCH_POINT_OFFSETS.length; i++) {
+            this.onTouchPoint(listener, i, data, offset + INPUT_TOUCH_POINT_OFFSETS[i])
+        }
+
+        this.onStatus(listener, data.getUint8(offset + INPUT_STATUS_OFFSET))
+    }
+
+    private onTouchPoint(listener: DualSenseListener, index: number, data: DataView, offset: number) {
+        const contact = data.getUint8(offset)
+        const previousId = this.touchPoints[index]
+
+        if ((contact & TOUCH_POINT_INACTIVE) != 0) {
+            if (previousId != null) {
+                this.touchPoints[index] = null
+                listener.onTouch(3, previousId, 0, 0, 0)
+            }
+            return
+        }
+
+        const id = contact & TOUCH_POINT_ID_MASK
+        if (previousId != null && previousId != id) {
+            // Another finger took this point
+            listener.onTouch(3, previousId, 0, 0, 0)
+        }
+
+        const b1 = data.getUint8(offset + 1)
+        const b2 = data.getUint8(offset + 2)
+        const b3 = data.getUint8(offset + 3)
+        const x = b1 | ((b2 & 0x0f) << 8)
+        const y = (b2 >> 4) | (b3 << 4)
+
+        this.touchPoints[index] = id
+        // The touchpad doesn't measure pressure
+        listener.onTouch(previousId == id ? 1 : 0, id, x / TOUCHPAD_WIDTH, y / TOUCHPAD_HEIGHT, 1)
+    }
+
+    private onStatus(listener: DualSenseListener, status: number) {
+        const capacity = Math.min((status & STATUS_BATTERY_CAPACITY_MASK) * 10 + 5, 100)
+
+        let batteryState: number
+        let percentage: number
+        switch (status >> STATUS_CHARGING_SHIFT) {
+            case 0x0:
+                batteryState = StreamBatteryState.DISCHARGING
+                percentage = capacity
+                break
+            case 0x1:
+                batteryState = StreamBatteryState.CHARGING
+                percentage = capacity
+                break
+            case 0x2:
+                batteryState = StreamBatteryState.FULL
+                percentage = 100
+                break
+            case 0xa:
+            case 0xb:
+                // Voltage or temperature out of range
+                batteryState = StreamBatteryState.NOT_CHARGING
+                percentage = 0xff
+                break
+            default:
+                batteryState = StreamBatteryState.UNKNOWN
+                percentage = 0xff
+                break
+        }
+
+        if (batteryState == this.batteryState && percentage == this.batteryPercentage) {
+            return
+        }
+        this.batteryState = batteryState
+        this.batteryPercentage = percentage
+
+        listener.onBattery(batteryState, percentage)
+    }
+
+    // The effects use the trigger effect format of the DualSense, the flags tell which triggers change
+    async setAdaptiveTriggers(eventFlags: number, typeLeft: number, typeRight: number, left: Uint8Array, right: Uint8Array) {
+        const report = new Uint8Array(OUTPUT_COMMON_SIZE)
+
+        if ((eventFlags & VALID_FLAG0_RIGHT_TRIGGER) != 0) {
+            report[OUTPUT_VALID_FLAG0_OFFSET] |= VALID_FLAG0_RIGHT_TRIGGER
+            report[OUTPUT_RIGHT_TRIGGER_OFFSET] = typeRight
+            report.set(right, OUTPUT_RIGHT_TRIGGER_OFFSET + 1)
+        }
+        if ((eventFlags & VALID_FLAG0_LEFT_TRIGGER) != 0) {
+            report[OUTPUT_VALID_FLAG0_OFFSET] |= VALID_FLAG0_LEFT_TRIGGER
+            report[OUTPUT_LEFT_TRIGGER_OFFSET] = typeLeft
+            report.set(left, OUTPUT_LEFT_TRIGGER_OFFSET + 1)
+        }
+
+        await this.sendOutputReport(report)
+    }
+
+    async setLed(r: number, g: number, b: number) {
+        const report = new Uint8Array(OUTPUT_COMMON_SIZE)
+
+        report[OUTPUT_VALID_FLAG1_OFFSET] = VALID_FLAG1_LIGHTBAR
+        report[OUTPUT_LIGHTBAR_OFFSET] = r
+        report[OUTPUT_LIGHTBAR_OFFSET + 1] = g
+        report[OUTPUT_LIGHTBAR_OFFSET + 2] = b
+
+        await this.sendOutputReport(report)
+    }
+
+    private async sendOutputReport(common: Uint8Array) {
+        if (!this.bluetooth) {
+            await this.device.sendReport(USB_OUTPUT_REPORT_ID, common)
+            return
+        }
+
+        const report = new Uint8Array(BLUETOOTH_OUTPUT_REPORT_SIZE)
+        report[0] = this.bluetoothOutputSequence << 4
+        report[1] = BLUETOOTH_OUTPUT_TAG
+        report.set(common, 2)
+        this.bluetoothOutputSequence = (this.bluetoothOutputSequence + 1) % 16
+
+        const checksummed = new Uint8Array(BLUETOOTH_OUTPUT_REPORT_SIZE - 2)
+        checksummed[0] = BLUETOOTH_OUTPUT_CRC_SEED
+        checksummed[1] = BLUETOOTH_OUTPUT_REPORT_ID
+        checksummed.set(report.subarray(0, BLUETOOTH_OUTPUT_REPORT_SIZE - 4), 2)
+        new DataView(report.buffer).setUint32(BLUETOOTH_OUTPUT_REPORT_SIZE - 4, crc32(checksummed), true)
+
+        await this.device.sendReport(BLUETOOTH_OUTPUT_REPORT_ID, report)
+    }
+}
@@ -726,7 +726,10 @@ export class Stream implements Component {
                     bitrate_kbps: this.settings.serverReencodeBitrateKbps,
                     preset: this.settings.serverReencodePreset === "default" ? null : this.settings.serverReencodePreset,
                     threads: this.settings.serverReencodeThreads > 0 ? this.settings.serverReencodeThreads : null,
-                }
+                },
+                gamepads_attached: this.input.getBufferedGamepadsMask(),
+                gamepads_persist: false,
+                horizontal_scroll: true,
             }
         }
         this.debugLog(`Starting stream with info: ${JSON.stringify(message)}`)
@@ -1,7 +1,9 @@
-import { StreamCapabilities, StreamControllerCapabilities, StreamMouseButton, TransportChannelId } from "../api_bindings.js"
+import { StreamCapabilities, StreamControllerCapabilities, StreamMouseButton, StreamPenButtons, StreamPenToolType, TransportChannelId } from "../api_bindings.js"
 import { ByteBuffer, I16_MAX, U16_MAX, U8_MAX } from "./buffer.js"
+import { DualSense, DUALSENSE_CAPABILITIES, DUALSENSE_TRIGGER_EFFECT_SIZE } from "./dualsense.js"
 import { ControllerConfig, emptyGamepadState, extractGamepadState, GamepadState, SUPPORTED_BUTTONS } from "./gamepad.js"
 import { convertToKey, convertToModifiers } from "./keyboard.js"
+import { DeviceMotionSensors } from "./motion.js"
 import { convertToButton } from "./mouse.js"
 import { DataTransportChannel, Transport, TransportChannelIdKey, TransportChannelIdValue } from "./transport/index.js"
 
@@ -20,6 +22,16 @@ const TOUCHES_AS_KEYBOARD_DISTANCE = 100
 
 const CONTROLLER_RUMBLE_INTERVAL_MS = 60
 
+const PEN_EVENT_DOWN = 0
+const PEN_EVENT_MOVE = 1
+const PEN_EVENT_CANCEL = 2
+const PEN_EVENT_UP = 3
+const PEN_EVENT_HOVER = 4
+const PEN_EVENT_HOVER_LEAVE = 5
+
+// The default max size of the streamer
+const CLIPBOARD_MAX_SIZE = 64 * 1024
+
 function trySendChannel(channel: DataTransportChannel | null, buffer: ByteBuffer) {
     if (!channel) {
         return
@@ -67,7 +79,7 @@ export class StreamInput {
 
     private connected = false
     private config: StreamInputConfig
-    private capabilities: StreamCapabilities = { touch: true }
+    private capabilities: StreamCapabilities = { touch: true, controller_touch: false, pen: false, clipboard: false }
     // Size of the streamer device
     private streamerSize: [number, number] = [0, 0]
 
@@ -76,6 +88,8 @@ export class StreamInput {
     private mouseAbsolute: DataTransportChannel | null = null
     private mouseRelative: DataTransportChannel | null = null
     private touch: DataTransportChannel | null = null
+    private pen: DataTransportChannel | null = null
+    private clipboard: DataTransportChannel | null = null
     private controllers: DataTransportChannel | null = null
     private controllerInputs: Array<DataTransportChannel | null> = []
 
@@ -122,6 +136,11 @@ export class StreamInput {
         this.touch = this.getDataChannel(transport, TransportChannelId.TOUCH)
         this.touch.addReceiveListener(this.onTouchData.bind(this))
 
+        this.pen = this.getDataChannel(transport, TransportChannelId.PEN)
+
+        this.clipboard = this.getDataChannel(transport, TransportChannelId.CLIPBOARD)
+        this.clipboard.addReceiveListener(this.onClipboardData.bind(this))
+
         if (this.controllers) {
             this.controllers.removeReceiveListener(this.onTouchData.bind(this))
         }
@@ -132,7 +151,11 @@ export class StreamInput {
         for (let i = 0; i < 16; i++) {
             const channelId = TransportChannelId[`CONTROLLER${i}` as TransportChannelIdKey]
 
-            this.controllerInputs[i] = this.getDataChannel(transport, channelId)
+            const channel = this.getDataChannel(transport, channelId)
+            // The host sends the outbound packets of a controller on its channel
+            channel.addReceiveListener(this.onControllerData.bind(this))
+
+            this.controllerInputs[i] = channel
         }
 
         this.flushPendingControllerUpdates()
@@ -187,7 +210,10 @@ export class StreamInput {
         console.debug("PASTE", data)
 
         const text = data.getData("text/plain")
-        if (text) {
+        if (text && this.capabilities.clipboard) {
+            // The host can paste it by itself
+            this.sendClipboard(text)
+        } else if (text) {
             console.debug("PASTE TEXT", text)
 
             // Before sending text raise all keys
@@ -258,6 +284,61 @@ export class StreamInput {
         trySendChannel(this.keyboard, this.buffer)
     }
 
+    // -- Clipboard
+    // The last text that is in both clipboards
+    private clipboardText: string | null = null
+
+    private onClipboardData(data: ArrayBuffer) {
+        const bytes = new Uint8Array(data)
+        if (bytes.length < 1 || bytes[0] != 0) {
+            return
+        }
+
+        const text = new TextDecoder().decode(bytes.subarray(1))
+        this.clipboardText = text
+
+        navigator.clipboard?.writeText(text).catch(error => {
+            console.debug("[Clipboard]: failed to write the host clipboard", error)
+        })
+    }
+
+    // Sends the clipboard of this device, e.g. when the window gets focused again after copying something
+    async syncClipboard() {
+        if (!this.capabilities.clipboard || !navigator.clipboard) {
+            return
+        }
+
+        const text = await navigator.clipboard.readText().catch(error => {
+            // The user didn't allow it
+            console.debug("[Clipboard]: failed to read the clipboard", error)
+            return null
+        })
+        if (text != null) {
+            this.sendClipboard(text)
+        }
+    }
+
+    sendClipboard(text: string) {
+        if (!this.capabilities.clipboard || text == this.clipboardText) {
+            return
+        }
+
+        const encoded = new TextEncoder().encode(text)
+        if (encoded.length > CLIPBOARD_MAX_SIZE) {
+            console.warn(`[Clipboard]: not sending clipboard of ${encoded.length} bytes because it exceeds the max size`)
+            return
+        }
+        this.clipboardText = text
+
+        // The clipboard might not fit into the shared buffer
+        const buffer = new ByteBuffer(encoded.length + 1)
+
+        buffer.putU8(0)
+        buffer.putU8Array(encoded)
+
+        trySendChannel(this.clipboard, buffer)
+    }
+
     // -- Mouse
     onMouseDown(event: MouseEvent, rect: DOMRect) {
         const button = convertToButton(event)
@@ -660,6 +741,103 @@ export class StreamInput {
         return this.touchMouseAction
     }
 
+    // -- Pen
+    private penInRange = false
+
+    // The browser also emits mouse events and on some devices touch events for a pen, ignore them while it's in range
+    isPenInRange(): boolean {
+        return this.penInRange
+    }
+    isPenTouchEvent(event: TouchEvent): boolean {
+        if (!this.capabilities.pen || event.changedTouches.length == 0) {
+            return false
+        }
+
+        for (const touch of event.changedTouches) {
+            if (touch.touchType != "stylus") {
+                return false
+            }
+        }
+        return true
+    }
+
+    // Returns true if the event was sent as a pen event
+    onPenEvent(event: PointerEvent, rect: DOMRect): boolean {
+        if (event.pointerType != "pen" || !this.capabilities.pen) {
+            return false
+        }
+
+        let eventType: number
+        if (event.type == "pointerdown") {
+            eventType = PEN_EVENT_DOWN
+        } else if (event.type == "pointermove") {
+            // The first button is the tip touching the surface
+            eventType = (event.buttons & 1) != 0 ? PEN_EVENT_MOVE : PEN_EVENT_HOVER
+        } else if (event.type == "pointerup") {
+            eventType = PEN_EVENT_UP
+        } else if (event.type == "pointercancel") {
+            eventType = PEN_EVENT_CANCEL
+        } else if (event.type == "pointerleave") {
+            eventType = PEN_EVENT_HOVER_LEAVE
+        } else {
+            return false
+        }
+        this.penInRange = eventType != PEN_EVENT_CANCEL && eventType != PEN_EVENT_HOVER_LEAVE
+
+        this.sendPen(eventType, event, rect)
+
+        return true
+    }
+
+    private sendPen(eventType: number, event: PointerEvent, rect: DOMRect) {
+        const position = this.calcNormalizedPosition(event.clientX, event.clientY, rect)
+        if (!position) {
+            return
+        }
+        const [x, y] = position
+
+        // The eraser end of the pen is the fifth button
+        const toolType = event.button == 5 || (event.buttons & 32) != 0 ? StreamPenToolType.ERASER : StreamPenToolType.PEN
+
+        let buttons = 0
+        if ((event.buttons & 2) != 0) {
+            buttons |= StreamPenButtons.PRIMARY
+        }
+        if ((event.buttons & 4) != 0) {
+            buttons |= StreamPenButtons.SECONDARY
+        }
+
+        // The tilt towards x and y gives the angle from the vertical and the direction the pen points to
+        const tanX = Math.tan(event.tiltX * Math.PI / 180)
+        const tanY = Math.tan(event.tiltY * Math.PI / 180)
+        const tilt = Math.round(Math.atan(Math.sqrt(tanX * tanX + tanY * tanY)) * 180 / Math.PI)
+
+        let rotation = U16_MAX
+        if (tilt != 0) {
+            // Degrees clockwise from up
+            rotation = Math.round(Math.atan2(tanX, -tanY) * 180 / Math.PI + 360) % 360
+        }
+
+        this.buffer.reset()
+
+        this.buffer.putU8(eventType)
+        this.buffer.putU8(toolType)
+        this.buffer.putU8(buttons)
+
+        this.buffer.putF32(x)
+        this.buffer.putF32(y)
+        // Browsers don't report the hover distance
+        this.buffer.putF32(eventType == PEN_EVENT_HOVER || eventType == PEN_EVENT_HOVER_LEAVE ? 0 : event.pressure)
+
+        this.buffer.putF32(event.width / rect.width)
+        this.buffer.putF32(event.height / rect.height)
+
+        this.buffer.putU16(rotation)
+        this.buffer.putU8(Math.min(tilt, 90))
+
+        trySendChannel(this.pen, this.buffer)
+    }
+
     // -- Controller
     // Wait for stream to connect and then send controllers
     private bufferedControllers: Array<number> = []
@@ -674,6 +852,14 @@ export class StreamInput {
         }
     }
 
+    // The gamepads that will get the first ids once the stream is connected
+    getBufferedGamepadsMask(): number {
+        const gamepads = navigator.getGamepads()
+        const count = Math.min(this.bufferedControllers.filter(index => gamepads[index]).length, 16)
+
+        return (1 << count) - 1
+    }
+
     private flushPendingControllerUpdates() {
         if (!this.controllers) {
             return
@@ -708,7 +894,7 @@ export class StreamInput {
         return actuators
     }
 
-    private gamepads: Array<{ gamepadIndex: number, oldState: GamepadState } | null> = []
+    private gamepads: Array<{ gamepadIndex: number, oldState: GamepadState, capabilities: number } | null> = []
     private gamepadRumbleInterval: number | null = null
 
     onGamepadConnect(gamepad: Gamepad) {
@@ -724,14 +910,14 @@ export class StreamInput {
         let id = -1
         for (let i = 0; i < this.gamepads.length; i++) {
             if (this.gamepads[i] == null) {
-                this.gamepads[i] = { gamepadIndex: gamepad.index, oldState: emptyGamepadState() }
+                this.gamepads[i] = { gamepadIndex: gamepad.index, oldState: emptyGamepadState(), capabilities: 0 }
                 id = i
                 break
             }
         }
         if (id == -1) {
             id = this.gamepads.length
-            this.gamepads.push({ gamepadIndex: gamepad.index, oldState: emptyGamepadState() })
+            this.gamepads.push({ gamepadIndex: gamepad.index, oldState: emptyGamepadState(), capabilities: 0 })
         }
 
         // Start Rumble interval
@@ -766,7 +952,16 @@ export class StreamInput {
             }
         }
 
-        this.sendControllerAdd(this.gamepads.length - 1, SUPPORTED_BUTTONS, capabilities)
+        // The Gamepad API doesn't expose motion sensors, a DualSense that was allowed with WebHID does
+        if (this.attachDualSense(id, gamepad)) {
+            capabilities |= this.getDualSenseCapabilities()
+        }
+
+        const entry = this.gamepads[id]
+        if (entry) {
+            entry.capabilities = capabilities
+        }
+        this.sendControllerAdd(id, SUPPORTED_BUTTONS, capabilities)
 
         if (gamepad.mapping != "standard") {
             console.warn(`[Gamepad]: Unable to read values of gamepad with mapping ${gamepad.mapping}`)
@@ -780,6 +975,9 @@ export class StreamInput {
                 this.sendControllerRemove(id)
             }
 
+            this.detachDualSense(index)
+            this.controllerMotion[index] = undefined
+
             this.gamepads[index] = null
         }
     }
@@ -851,6 +1049,180 @@ export class StreamInput {
             }
 
             this.setGamepadEffect(gamepadIndex, "trigger-rumble", { leftTrigger, rightTrigger })
+        } else if (ty == 2) {
+            // Motion Event State
+            const id = this.buffer.getU8()
+            const motionType = this.buffer.getU8()
+            const reportRateHz = this.buffer.getU16()
+
+            this.setControllerMotionReportRate(id, motionType, reportRateHz)
+        } else if (ty == 3) {
+            // Adaptive Triggers
+            const id = this.buffer.getU8()
+            const eventFlags = this.buffer.getU8()
+            const typeLeft = this.buffer.getU8()
+            const typeRight = this.buffer.getU8()
+
+            const left = new Uint8Array(DUALSENSE_TRIGGER_EFFECT_SIZE)
+            this.buffer.get(left, 0, left.length)
+            const right = new Uint8Array(DUALSENSE_TRIGGER_EFFECT_SIZE)
+            this.buffer.get(right, 0, right.length)
+
+            this.getDualSense(id)?.setAdaptiveTriggers(eventFlags, typeLeft, typeRight, left, right).catch(error => {
+                console.warn(`[DualSense]: failed to set adaptive triggers of id=${id}`, error)
+            })
+        } else if (ty == 4) {
+            // RGB LED
+            const id = this.buffer.getU8()
+            const r = this.buffer.getU8()
+            const g = this.buffer.getU8()
+            const b = this.buffer.getU8()
+
+            this.getDualSense(id)?.setLed(r, g, b).catch(error => {
+                console.warn(`[DualSense]: failed to set led of id=${id}`, error)
+            })
+        }
+    }
+
+    // -- Controller motion
+    // How often the host wants reports of a motion type for each controller, missing if it doesn't want any
+    private controllerMotion: Array<Map<number, { intervalMs: number, lastSent: number }> | undefined> = []
+
+    private deviceMotionControllerId: number | null = null
+    private deviceMotion = new DeviceMotionSensors((motionType, x, y, z) => {
+        if (this.deviceMotionControllerId != null) {
+            this.onControllerMotion(this.deviceMotionControllerId, motionType, x, y, z)
+        }
+    })
+
+    private setControllerMotionReportRate(id: number, motionType: number, reportRateHz: number) {
+        let motion = this.controllerMotion[id]
+        if (!motion) {
+            motion = new Map()
+            this.controllerMotion[id] = motion
+        }
+
+        if (reportRateHz == 0) {
+            motion.delete(motionType)
+        } else {
+            motion.set(motionType, { intervalMs: 1000 / reportRateHz, lastSent: 0 })
+        }
+        this.logDebug(`[Controller] Motion ${motionType} of id=${id} at ${reportRateHz}Hz`)
+
+        if (id == this.deviceMotionControllerId) {
+            this.deviceMotion.setReportRate(motionType, reportRateHz)
+        }
+    }
+
+    // Lets the motion sensors of this device act as the sensors of the controller, returns the capabilities of the sensors
+    useDeviceMotion(id: number): number {
+        this.deviceMotionControllerId = id
+
+        return DeviceMotionSensors.getCapabilities()
+    }
+    stopDeviceMotion() {
+        if (this.deviceMotionControllerId != null) {
+            this.controllerMotion[this.deviceMotionControllerId] = undefined
+        }
+        this.deviceMotionControllerId = null
+        this.deviceMotion.stop()
+    }
+
+    // Sensors usually report faster than the host wants them
+    private onControllerMotion(id: number, motionType: number, x: number, y: number, z: number) {
+        const state = this.controllerMotion[id]?.get(motionType)
+        if (!state) {
+            return
+        }
+
+        const now = performance.now()
+        if (now - state.lastSent < state.intervalMs) {
+            return
+        }
+        state.lastSent = now
+
+        this.sendControllerMotion(id, motionType, x, y, z)
+    }
+
+    // -- DualSense (WebHID)
+    private dualSenses: Array<{ dualSense: DualSense, controllerId: number | null }> = []
+
+    // The DualSense goes to the connected gamepad with the same vendor and product id
+    async addDualSense(device: HIDDevice) {
+        if (this.dualSenses.some(entry => entry.dualSense.device == device)) {
+            return
+        }
+
+        const dualSense = await DualSense.open(device)
+        this.dualSenses.push({ dualSense, controllerId: null })
+        this.logDebug(`[Controller] Added DualSense ${device.productName}`)
+
+        const gamepads = navigator.getGamepads()
+        for (let id = 0; id < this.gamepads.length; id++) {
+            const entry = this.gamepads[id]
+            const gamepad = entry ? gamepads[entry.gamepadIndex] : null
+            if (!entry || !gamepad || !this.attachDualSense(id, gamepad)) {
+                continue
+            }
+
+            // Tell the host about the new capabilities
+            entry.capabilities |= this.getDualSenseCapabilities()
+            this.sendControllerAdd(id, SUPPORTED_BUTTONS, entry.capabilities)
+            break
+        }
+    }
+    removeDualSense(device: HIDDevice) {
+        const index = this.dualSenses.findIndex(entry => entry.dualSense.device == device)
+        if (index == -1) {
+            return
+        }
+
+        this.dualSenses[index].dualSense.setListener(null)
+        this.dualSenses.splice(index, 1)
+    }
+
+    private getDualSense(id: number): DualSense | null {
+        return this.dualSenses.find(entry => entry.controllerId == id)?.dualSense ?? null
+    }
+
+    private getDualSenseCapabilities(): number {
+        let capabilities = DUALSENSE_CAPABILITIES
+        // Not every host accepts touchpad events
+        if (!this.capabilities.controller_touch) {
+            capabilities &= ~StreamControllerCapabilities.CAPABILITY_TOUCHPAD
+        }
+        return capabilities
+    }
+
+    private attachDualSense(id: number, gamepad: Gamepad): boolean {
+        if (this.dualSenses.some(entry => entry.controllerId == id)) {
+            return true
+        }
+
+        const entry = this.dualSenses.find(entry => entry.controllerId == null && entry.dualSense.matchesGamepad(gamepad))
+        if (!entry) {
+            return false
+        }
+
+        entry.controllerId = id
+        entry.dualSense.setListener({
+            onMotion: (motionType, x, y, z) => this.onControllerMotion(id, motionType, x, y, z),
+            onTouch: (eventType, pointerId, x, y, pressure) => {
+                if (this.capabilities.controller_touch) {
+                    this.sendControllerTouch(id, eventType, pointerId, x, y, pressure)
+                }
+            },
+            onBattery: (batteryState, percentage) => this.sendControllerBattery(id, batteryState, percentage),
+        })
+        this.logDebug(`[Controller] Using DualSense ${entry.dualSense.device.productName} for id=${id}`)
+
+        return true
+    }
+    private detachDualSense(id: number) {
+        const entry = this.dualSenses.find(entry => entry.controllerId == id)
+        if (entry) {
+            entry.dualSense.setListener(null)
+            entry.controllerId = null
         }
     }
 
@@ -1009,5 +1381,43 @@ export class StreamInput {
 
         trySendChannel(this.controllerInputs[id], this.buffer)
     }
+    // Values
+    // - Accelerometer: m/s²
+    // - Gyroscope: °/s
+    sendControllerMotion(id: number, motionType: number, x: number, y: number, z: number) {
+        this.buffer.reset()
+
+        this.buffer.putU8(1)
+        this.buffer.putU8(motionType)
+        this.buffer.putF32(x)
+        this.buffer.putF32(y)
+        this.buffer.putF32(z)
+
+        trySendChannel(this.controllerInputs[id], this.buffer)
+    }
+    // Event types: 0 = down, 1 = move, 2 = cancel, 3 = up
+    // x and y are normalized, pressure is between 0 and 1
+    sendControllerTouch(id: number, eventType: number, pointerId: number, x: number, y: number, pressure: number) {
+        this.buffer.reset()
+
+        this.buffer.putU8(2)
+        this.buffer.putU8(eventType)
+        this.buffer.putU32(pointerId)
+        this.buffer.putF32(x)
+        this.buffer.putF32(y)
+        this.buffer.putF32(pressure)
+
+        trySendChannel(this.controllerInputs[id], this.buffer)
+    }
+    // A percentage of 0xFF is unknown
+    sendControllerBattery(id: number, batteryState: number, percentage: number) {
+        this.buffer.reset()
+
+        this.buffer.putU8(3)
+        this.buffer.putU8(batteryState)
+        this.buffer.putU8(percentage)
+
+        trySendChannel(this.controllerInputs[id], this.buffer)
+    }
 
 }
@@ -0,0 +1,77 @@
+import { StreamControllerCapabilities, StreamMotionType } from "../api_bindings.js"
+
+const RADIANS_TO_DEGREES = 180 / Math.PI
+
+function createMotionSensor(motionType: number, frequency: number): MotionSensor | null {
+    try {
+        if (motionType == StreamMotionType.ACCEL && typeof Accelerometer != "undefined") {
+            return new Accelerometer({ frequency })
+        } else if (motionType == StreamMotionType.GYRO && typeof Gyroscope != "undefined") {
+            return new Gyroscope({ frequency })
+        }
+    } catch (e) {
+        // Blocked by a permissions policy
+        console.warn(`[DeviceMotion]: failed to create motion sensor ${motionType}`, e)
+    }
+    return null
+}
+
+// The motion sensors of this device with the Generic Sensor API, e.g. of a phone that shows the touch controller
+export class DeviceMotionSensors {
+    private sensors: Map<number, MotionSensor> = new Map()
+    private onMotion: (motionType: number, x: number, y: number, z: number) => void
+
+    constructor(onMotion: (motionType: number, x: number, y: number, z: number) => void) {
+        this.onMotion = onMotion
+    }
+
+    static getCapabilities(): number {
+        let capabilities = 0
+        if (typeof Accelerometer != "undefined") {
+            capabilities |= StreamControllerCapabilities.CAPABILITY_ACCEL
+        }
+        if (typeof Gyroscope != "undefined") {
+            capabilities |= StreamControllerCapabilities.CAPABILITY_GYRO
+        }
+        return capabilities
+    }
+
+    // A report rate of 0 stops the sensor
+    setReportRate(motionType: number, reportRateHz: number) {
+        this.sensors.get(motionType)?.stop()
+        this.sensors.delete(motionType)
+
+        if (reportRateHz == 0) {
+            return
+        }
+
+        const sensor = createMotionSensor(motionType, reportRateHz)
+        if (!sensor) {
+            return
+        }
+
+        // The host wants the gyroscope in °/s
+        const multiplier = motionType == StreamMotionType.GYRO ? RADIANS_TO_DEGREES : 1
+
+        sensor.addEventListener("reading", () => {
+            if (sensor.x == null || sensor.y == null || sensor.z == null) {
+                return
+            }
+
+            this.onMotion(motionType, sensor.x * multiplier, sensor.y * multiplier, sensor.z * multiplier)
+        })
+        sensor.addEventListener("error", (event) => {
+            console.warn(`[DeviceMotion]: motion sensor ${motionType} failed`, event)
+        })
+        sensor.start()
+
+        this.sensors.set(motionType, sensor)
+    }
+
+    stop() {
+        for (const sensor of this.sensors.values()) {
+            sensor.stop()
+        }
+        this.sensors.clear()
+    }
+}
@@ -67,11 +67,14 @@ export class TouchController {
 
         if (visible) {
             this.logDebug?.("[TouchController] Enabled")
-            this.input.sendControllerAdd(VIRTUAL_CONTROLLER_ID, SUPPORTED_BUTTONS, 0)
+            // The sensors of the phone or tablet showing the controller are its sensors
+            const capabilities = this.input.useDeviceMotion(VIRTUAL_CONTROLLER_ID)
+            this.input.sendControllerAdd(VIRTUAL_CONTROLLER_ID, SUPPORTED_BUTTONS, capabilities)
             this.sendState(true)
         } else {
             this.logDebug?.("[TouchController] Disabled")
             this.resetState()
+            this.input.stopDeviceMotion()
             this.input.sendControllerRemove(VIRTUAL_CONTROLLER_ID)
         }
     }